@@ -1 +1 @@
-cd ../rust && cargo build && cargo test && cd ../bootstrap && ./../rust/target/debug/mud mud.mud && ./mud.exe mud.mud
+cd ../rust && cargo build && cargo test && cd ../bootstrap && ./../rust/target/debug/mud build mud.mud && ./mud.exe mud.mud
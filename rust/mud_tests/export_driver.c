@@ -0,0 +1,11 @@
+#include <stdio.h>
+#include "export.h"
+
+int main(void) {
+    Point p = { 3, 4 };
+    Range r;
+    r._mud_int = 2;
+    r.mud_int = 9;
+    printf("%d %d %d\n", point_sum(&p), one(), range_width(&r));
+    return 0;
+}
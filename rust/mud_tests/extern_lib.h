@@ -0,0 +1,3 @@
+static int triple(int x) {
+    return 3 * x;
+}
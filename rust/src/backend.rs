@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::compiler::{self, OptLevel, Sources};
+use crate::ir::{self, Program, Type};
+use crate::lexer::error::{MudResult, ErrorType};
+use crate::trace::trace;
+
+mod c;
+mod llvm;
+mod wasm;
+mod x86_64;
+
+// the registers x86-64 passes integers and pointers in
+const INTEGER_REGISTERS: usize = 6;
+
+// where the System V ABI puts an argument, a struct of up to 16 bytes takes a register for each eightbyte if enough are left
+enum Location {
+    Registers(Vec<usize>),
+    Stack(u64),
+}
+
+// structs over 16 bytes are returned in memory the caller passes the address of in rdi
+fn returns_memory(program: &Program, return_type: Option<Type>) -> bool {
+    matches!(return_type, Some(ty @ Type::Struct(_)) if program.size(ty) > 16)
+}
+
+fn classify(program: &Program, args: &[Type], return_type: Option<Type>) -> (Vec<Location>, u64) {
+    let mut next = returns_memory(program, return_type) as usize;
+    let mut stack = 0;
+    let mut locations = Vec::new();
+
+    for &ty in args {
+        let words = program.size(ty).div_ceil(8) as usize;
+        if words <= 2 && next + words <= INTEGER_REGISTERS {
+            locations.push(Location::Registers((next..next + words).collect()));
+            next += words;
+        } else {
+            locations.push(Location::Stack(stack));
+            stack += words as u64 * 8;
+        }
+    }
+
+    (locations, stack)
+}
+
+// the C runtime as a file of its own for native code to link against, with what user headers define
+// static reached through pointers the native code calls
+pub fn runtime(program: &Program) -> String {
+    let mut output = "#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\ntypedef int i32;\n".to_string();
+    // the IR only holds what the program reaches, so the read_file prelude only comes along when it is called
+    if program.external("read_file").is_some() {
+        output += compiler::READ_FILE;
+    }
+    output += &compiler::RUNTIME.replace("static inline ", "");
+
+    for external in &program.externs {
+        if let Some((header, name)) = &external.header {
+            output += &format!("#include \"{}\"\n__typeof__({name})* {} = {name};\n", header.display(), external.name);
+        }
+    }
+
+    output
+}
+
+// how a program is built, the C compiler also assembles and links what the other backends produce
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub level: OptLevel,
+    pub cc: String,
+    pub cflags: Vec<String>,
+    pub sources: Sources,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { level: OptLevel::O0, cc: "gcc".to_string(), cflags: Vec::new(), sources: Sources::default() }
+    }
+}
+
+// the backends, each writes files the C compiler or a host turns into something that runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    C,
+    Asm,
+    Llvm,
+    Wasm,
+}
+
+// what a backend writes for a program, each file named by the extension it takes after the program's name
+pub fn emit(program: &Program, target: Target) -> Vec<(&'static str, String)> {
+    trace!(Codegen, Info, "{target:?} for {} functions", program.functions.len());
+    match target {
+        Target::C => vec![("c", c::emit(program))],
+        Target::Asm => vec![("s", x86_64::emit(program)), ("rt.c", runtime(program))],
+        Target::Llvm => vec![("ll", llvm::emit(program)), ("rt.c", runtime(program))],
+        Target::Wasm => vec![("wat", wasm::emit(program))],
+    }
+}
+
+// writes what a backend emits for the program at path next to output, and gives the paths it wrote
+fn emit_beside(path: &str, output: &str, target: Target, options: &Options) -> MudResult<Vec<String>> {
+    let program = ir::lower(&options.sources, path, options.level)?;
+    let base = output.strip_suffix(".exe").unwrap_or(output);
+
+    let mut paths = Vec::new();
+    for (extension, contents) in emit(&program, target) {
+        let file = format!("{base}.{extension}");
+        write(&file, contents)?;
+        paths.push(file);
+    }
+    Ok(paths)
+}
+
+// the program at path as C, compiled by the C compiler into the executable at output
+pub fn build_c(path: &str, output: &str, options: &Options) -> MudResult<()> {
+    let files = emit_beside(path, output, Target::C, options)?;
+    link(options, &files, output)
+}
+
+// the program at path as x86-64 assembly, assembled and linked with the C runtime into the executable at output
+pub fn build_asm(path: &str, output: &str, options: &Options) -> MudResult<()> {
+    let files = emit_beside(path, output, Target::Asm, options)?;
+    link(options, &files, output)
+}
+
+// the program at path as LLVM IR, optimized and compiled by the LLVM tools and linked into the executable at output
+pub fn build_llvm(path: &str, output: &str, options: &Options) -> MudResult<()> {
+    let files = emit_beside(path, output, Target::Llvm, options)?;
+    let (ir_path, runtime_path) = (&files[0], &files[1]);
+    let base = output.strip_suffix(".exe").unwrap_or(output);
+    let (optimized_path, object_path) = (format!("{base}.opt.ll"), format!("{base}.o"));
+
+    // LLVM 14 reads opaque pointers only when asked to, later versions always do
+    let version = Command::new("llc").arg("--version").output()
+        .map_err(|_| ErrorType::CompileError(format!("llc was not found, {ir_path} can be compiled where LLVM is installed")))?;
+    let version = String::from_utf8_lossy(&version.stdout).split("version ").nth(1)
+        .and_then(|version| version.split('.').next()?.parse::<u32>().ok())
+        .unwrap_or(0);
+    let flags: &[&str] = if version < 15 { &["-opaque-pointers"] } else { &[] };
+
+    run(Command::new("opt").args(flags).args(["-O2", "-S", ir_path, "-o", &optimized_path]))?;
+    run(Command::new("llc").args(flags).args(["-O2", "-relocation-model=pic", "-filetype=obj", &optimized_path, "-o", &object_path]))?;
+    link(options, &[object_path, runtime_path.clone()], output)
+}
+
+// the program at path as a WebAssembly text module at output, to be run by a host that provides its imports
+pub fn build_wat(path: &str, output: &str, options: &Options) -> MudResult<()> {
+    let program = ir::lower(&options.sources, path, options.level)?;
+    let (_, module) = emit(&program, Target::Wasm).remove(0);
+    write(output, module)
+}
+
+fn write(path: &str, contents: String) -> MudResult<()> {
+    fs::write(path, contents).map_err(|_| ErrorType::CompileError(format!("Unable to create file {path}")))
+}
+
+fn link(options: &Options, inputs: &[String], output: &str) -> MudResult<()> {
+    run(Command::new(&options.cc).args(&options.cflags).args(inputs).arg("-o").arg(Path::new(output)))
+}
+
+fn run(command: &mut Command) -> MudResult<()> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let result = command.output().map_err(|_| ErrorType::CompileError(format!("Failed to run {program}")))?;
+
+    match result.status.success() {
+        true => Ok(()),
+        false => Err(ErrorType::CompileError(format!("{program} failed: {}", String::from_utf8_lossy(&result.stderr)))),
+    }
+}
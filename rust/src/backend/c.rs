@@ -0,0 +1,326 @@
+use std::fmt::Write;
+
+use crate::ir::*;
+
+// C from the IR, one file with the runtime in it that gcc compiles on its own
+//
+// temporaries are locals, pointers among them intptr_t so the IR's pointer arithmetic is integer
+// arithmetic, and slots are locals of their type. blocks are labels and control flow is goto.
+// externs are called through the prototypes of their headers, with each argument and the result
+// cast to the C type the program gives it, so a declaration that does not match C fails the build.
+// the runtime's own functions take types only the runtime has, they are declared under names of
+// their own bound to the real symbol with an asm label instead
+
+// the symbol of a C name, with the prefix the platform gives C symbols
+const LABEL_MACROS: &str = "#define MUD_STR(x) #x\n#define MUD_XSTR(x) MUD_STR(x)\n#define MUD_LABEL(name) MUD_XSTR(__USER_LABEL_PREFIX__) name\n";
+
+// what -Wall says of code written this way, which is how it is meant to be
+const PRAGMAS: [&str; 4] = ["unused-label", "unused-variable", "unused-but-set-variable", "cast-function-type"];
+
+// what a program declaring an extern with types its C function does not take gets
+const ERRORS: [&str; 3] = ["conversion", "int-conversion", "incompatible-pointer-types"];
+
+pub fn emit(program: &Program) -> String {
+    // which function of the Mud source each symbol is, for reading the C and its backtraces
+    let mut out = String::from("/* mud symbol map:\n");
+    for (name, symbol) in &program.symbols {
+        writeln!(out, " * {name} -> {symbol}").unwrap();
+    }
+    out.push_str(" */\n");
+
+    let mut emitter = Emitter { program, out: out + &super::runtime(program) };
+    emitter.out.push_str("#include <stdint.h>\n");
+    let mut includes = Vec::new();
+    for include in program.externs.iter().filter_map(|external| external.include.as_ref()) {
+        if !includes.contains(include) {
+            writeln!(emitter.out, "#include <{include}>").unwrap();
+            includes.push(include.clone());
+        }
+    }
+    emitter.out.push_str(LABEL_MACROS);
+    for warning in PRAGMAS {
+        writeln!(emitter.out, "#pragma GCC diagnostic ignored \"-W{warning}\"").unwrap();
+    }
+    // after the runtime, which converts as it likes
+    for warning in ERRORS {
+        writeln!(emitter.out, "#pragma GCC diagnostic error \"-W{warning}\"").unwrap();
+    }
+
+    for id in 0..program.structs.len() {
+        let fields = program.structs[id].fields.iter().enumerate()
+            .map(|(index, &field)| format!("{} f{index};", emitter.ty(field)))
+            .collect::<Vec<_>>();
+        writeln!(emitter.out, "{} {{ {} }};", emitter.ty(Type::Struct(id)), fields.join(" ")).unwrap();
+    }
+
+    // statics of headers are reached through the pointers the runtime defines for them
+    for external in program.externs.iter().filter(|external| external.header.is_none() && external.include.is_none()) {
+        match &external.prototype {
+            Some(prototype) => {
+                let mut params = prototype.args.clone();
+                match (external.variadic, params.is_empty()) {
+                    (true, _) => params.push("...".to_string()),
+                    (false, true) => params.push("void".to_string()),
+                    _ => {}
+                }
+                writeln!(emitter.out, "extern {} {}({});", prototype.return_type, external.name, params.join(", ")).unwrap();
+            }
+            None => {
+                let (return_type, params) = emitter.signature(&external.args, external.return_type, external.variadic);
+                writeln!(emitter.out, "extern {return_type} mudext_{name}({params}) __asm__(MUD_LABEL(\"{name}\"));", name = external.name).unwrap();
+            }
+        }
+    }
+
+    // every function is declared first, as data and functions refer to each other
+    for function in &program.functions {
+        let prototype = emitter.prototype(function);
+        writeln!(emitter.out, "{prototype};").unwrap();
+    }
+    for data in &program.data {
+        emitter.data(data);
+    }
+    for function in &program.functions {
+        emitter.function(function);
+    }
+
+    emitter.out
+}
+
+// the escapes of bytes in a "..." literal, octal so a digit after one is not taken as part of it
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| match b {
+        b' '..=b'~' if b != b'"' && b != b'\\' && b != b'?' => (b as char).to_string(),
+        b => format!("\\{b:03o}"),
+    }).collect()
+}
+
+struct Emitter<'a> {
+    program: &'a Program,
+    out: String,
+}
+
+impl Emitter<'_> {
+    fn ty(&self, ty: Type) -> String {
+        match ty {
+            Type::I8 => "signed char".to_string(),
+            Type::I32 => "i32".to_string(),
+            Type::Ptr => "intptr_t".to_string(),
+            Type::Struct(id) => {
+                let name = self.program.structs[id].name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect::<String>();
+                format!("struct mudty_{name}_{id}")
+            }
+        }
+    }
+
+    // the unsigned type arithmetic of a type is done in, so it wraps instead of overflowing
+    fn unsigned(&self, ty: Type) -> &'static str {
+        match ty {
+            Type::Ptr => "uintptr_t",
+            _ => "uint32_t",
+        }
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        writeln!(self.out, "    {}", line.as_ref()).unwrap();
+    }
+
+    fn signature(&self, args: &[Type], return_type: Option<Type>, variadic: bool) -> (String, String) {
+        let return_type = return_type.map_or("void".to_string(), |ty| self.ty(ty));
+        let mut params = args.iter().map(|&ty| self.ty(ty)).collect::<Vec<_>>();
+        match (variadic, params.is_empty()) {
+            (true, _) => params.push("...".to_string()),
+            (false, true) => params.push("void".to_string()),
+            _ => {}
+        }
+        (return_type, params.join(", "))
+    }
+
+    // a function with its params named after their temporaries, struct params are values of their own
+    fn prototype(&self, function: &Function) -> String {
+        if function.name == "main" {
+            return "int main(int argc, char** argv)".to_string();
+        }
+        let return_type = function.return_type.map_or("void".to_string(), |ty| self.ty(ty));
+        let params = function.params.iter().map(|&(temp, ty)| match ty.is_scalar() {
+            true => format!("{} t{temp}", self.ty(ty)),
+            false => format!("{} p{temp}", self.ty(ty)),
+        }).collect::<Vec<_>>();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        let linkage = if function.export { "" } else { "static " };
+        format!("{linkage}{return_type} {}({params})", function.name)
+    }
+
+    // the C expression of the address of a symbol
+    fn symbol(&self, name: &str) -> String {
+        match self.program.external(name) {
+            Some(Extern { header: Some(_), .. }) => format!("(intptr_t){name}"),
+            Some(Extern { prototype: Some(_), .. }) => format!("(intptr_t)&{name}"),
+            Some(_) => format!("(intptr_t)&mudext_{name}"),
+            None => format!("(intptr_t)&{name}"),
+        }
+    }
+
+    fn data(&mut self, data: &Data) {
+        match &data.items[..] {
+            [DataItem::Bytes(bytes)] => {
+                writeln!(self.out, "static char {}[{}] = \"{}\";", data.name, bytes.len(), escape(bytes)).unwrap();
+            }
+            items => {
+                // packed so the items follow each other as they do in the IR
+                let mut fields = Vec::new();
+                let mut values = Vec::new();
+                for (index, item) in items.iter().enumerate() {
+                    match item {
+                        DataItem::Bytes(bytes) => {
+                            fields.push(format!("char d{index}[{}];", bytes.len()));
+                            values.push(format!("\"{}\"", escape(bytes)));
+                        }
+                        DataItem::Symbol(name) => {
+                            fields.push(format!("void* d{index};"));
+                            values.push(format!("(void*){}", self.symbol(name)));
+                        }
+                    }
+                }
+                writeln!(self.out, "static struct __attribute__((packed)) {{ {} }} {} = {{ {} }};", fields.join(" "), data.name, values.join(", ")).unwrap();
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        let prototype = self.prototype(function);
+        writeln!(self.out, "\n{prototype} {{").unwrap();
+
+        let params = function.params.iter().map(|&(temp, _)| temp).collect::<Vec<_>>();
+        for (temp, &ty) in function.temps.iter().enumerate() {
+            let param = function.params.iter().find(|&&(param, _)| param == temp);
+            match (param, function.name == "main") {
+                (Some(_), true) => {
+                    let value = if params[0] == temp { "argc" } else { "(intptr_t)argv" };
+                    self.line(format!("{} t{temp} = {value};", self.ty(ty)));
+                }
+                (Some(&(_, param_type)), false) if !param_type.is_scalar() => self.line(format!("intptr_t t{temp} = (intptr_t)&p{temp};")),
+                (Some(_), false) => {}
+                (None, _) => self.line(format!("{} t{temp};", self.ty(ty))),
+            }
+        }
+        for (slot, &ty) in function.slots.iter().enumerate() {
+            self.line(format!("{} s{slot};", self.ty(ty)));
+        }
+
+        for (index, Block { insts, terminator }) in function.blocks.iter().enumerate() {
+            writeln!(self.out, "b{index}:;").unwrap();
+            for inst in insts {
+                self.inst(function, inst);
+            }
+            self.terminator(function, terminator);
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn inst(&mut self, function: &Function, inst: &Inst) {
+        let program = self.program;
+        let temps = &function.temps;
+        match inst {
+            &Inst::Const { dest, value } => self.line(format!("t{dest} = ({}){value}LL;", self.ty(temps[dest]))),
+            Inst::Symbol { dest, name } => {
+                let symbol = self.symbol(name);
+                self.line(format!("t{dest} = {symbol};"));
+            }
+            &Inst::Slot { dest, slot } => self.line(format!("t{dest} = (intptr_t)&s{slot};")),
+            &Inst::Load { dest, addr } => self.line(format!("t{dest} = *({}*)t{addr};", self.ty(temps[dest]))),
+            &Inst::Store { addr, value } => self.line(format!("*({}*)t{addr} = t{value};", self.ty(temps[value]))),
+            &Inst::Copy { dest, src, ty } => self.line(format!("__builtin_memmove((void*)t{dest}, (void*)t{src}, {});", program.size(ty))),
+            &Inst::Zero { dest, ty } => self.line(format!("__builtin_memset((void*)t{dest}, 0, {});", program.size(ty))),
+            &Inst::Field { dest, base, ty, index } => self.line(format!("t{dest} = t{base} + {};", program.offset(ty, index))),
+            &Inst::Element { dest, base, ty, index } => self.line(format!("t{dest} = t{base} + t{index} * {};", program.size(ty))),
+            &Inst::Unary { dest, op, value } => {
+                let line = match op {
+                    UnaryOp::Neg => format!("t{dest} = ({})(0 - ({})t{value});", self.ty(temps[dest]), self.unsigned(temps[value])),
+                    UnaryOp::Not => format!("t{dest} = !t{value};"),
+                };
+                self.line(line);
+            }
+            &Inst::Binary { dest, op, lhs, rhs } => {
+                let operator = match op {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Sub => "-",
+                    BinaryOp::Mul => "*",
+                    BinaryOp::Lt => "<",
+                    BinaryOp::Gt => ">",
+                    BinaryOp::Eq => "==",
+                    BinaryOp::Ne => "!=",
+                };
+                let line = match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                        let unsigned = self.unsigned(temps[lhs]);
+                        format!("t{dest} = ({})(({unsigned})t{lhs} {operator} ({unsigned})t{rhs});", self.ty(temps[dest]))
+                    }
+                    _ => format!("t{dest} = t{lhs} {operator} t{rhs};"),
+                };
+                self.line(line);
+            }
+            &Inst::Cast { dest, value } => self.line(format!("t{dest} = ({})t{value};", self.ty(temps[dest]))),
+            Inst::Call { dest, callee, args, fixed } => self.call(function, *dest, callee, args, *fixed),
+        }
+    }
+
+    fn call(&mut self, function: &Function, dest: Dest, callee: &Callee, args: &[(Temp, Type)], fixed: Option<usize>) {
+        let return_type = match dest {
+            Dest::None => None,
+            Dest::Value(temp) => Some(function.temps[temp]),
+            Dest::Memory(_, ty) => Some(ty),
+        };
+        let prototype = match callee {
+            Callee::Direct(name) => self.program.external(name).and_then(|external| external.prototype.as_ref()),
+            Callee::Indirect(_) => None,
+        };
+        let values = args.iter().enumerate().map(|(i, &(temp, ty))| match (prototype.and_then(|prototype| prototype.args.get(i)), ty.is_scalar()) {
+            (Some(c_type), true) => format!("({c_type})t{temp}"),
+            (Some(c_type), false) => format!("*({c_type}*)t{temp}"),
+            (None, true) => format!("t{temp}"),
+            (None, false) => format!("*({}*)t{temp}", self.ty(ty)),
+        }).collect::<Vec<_>>();
+
+        // through a pointer the callee is cast to the function type of the call
+        let pointer = |emitter: &Self, pointer: String| {
+            let arg_types = args[..fixed.unwrap_or(args.len())].iter().map(|&(_, ty)| ty).collect::<Vec<_>>();
+            let (return_type, params) = emitter.signature(&arg_types, return_type, fixed.is_some());
+            format!("(({return_type} (*)({params})){pointer})")
+        };
+        let target = match callee {
+            Callee::Direct(name) => match self.program.external(name) {
+                Some(Extern { prototype: Some(_), .. }) => name.clone(),
+                Some(Extern { header: Some(_), .. }) => pointer(self, name.clone()),
+                Some(_) => format!("mudext_{name}"),
+                None => name.clone(),
+            },
+            Callee::Indirect(temp) => pointer(self, format!("t{temp}")),
+        };
+        let call = format!("{target}({})", values.join(", "));
+
+        match (dest, prototype) {
+            (Dest::None, _) => self.line(format!("{call};")),
+            // the result is taken in the C type the program gives it first, so C checks that too
+            (Dest::Value(temp), Some(prototype)) => {
+                self.line(format!("{{ {} r = {call}; t{temp} = ({})r; }}", prototype.return_type, self.ty(function.temps[temp])));
+            }
+            (Dest::Value(temp), None) => self.line(format!("t{temp} = {call};")),
+            (Dest::Memory(addr, _), Some(prototype)) => self.line(format!("*({}*)t{addr} = {call};", prototype.return_type)),
+            (Dest::Memory(addr, ty), None) => self.line(format!("*({}*)t{addr} = {call};", self.ty(ty))),
+        }
+    }
+
+    fn terminator(&mut self, function: &Function, terminator: &Terminator) {
+        match *terminator {
+            Terminator::Jump(block) => self.line(format!("goto b{block};")),
+            Terminator::Branch { condition, then, otherwise } => self.line(format!("if (t{condition}) goto b{then}; else goto b{otherwise};")),
+            Terminator::Return(value) => match (value, function.return_type) {
+                (Some(value), Some(ty @ Type::Struct(_))) => self.line(format!("return *({}*)t{value};", self.ty(ty))),
+                (Some(value), Some(_)) => self.line(format!("return t{value};")),
+                _ => self.line("return;"),
+            },
+            Terminator::Unreachable => self.line("__builtin_unreachable();"),
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+use crate::ir::*;
+
+use super::{classify, returns_memory, Location};
+
+// textual LLVM IR with opaque pointers
+//
+// temporaries are SSA values and slots are allocas, structs cross calls as the System V ABI passes them
+// the way clang lowers C: small ones as integers, large ones byval and returned through sret
+
+// how a value crosses a call
+enum Pass {
+    Scalar(Type),
+    // a struct in registers, as an integer or a pair of them of its size
+    Coerced(String, u64),
+    ByVal(Type),
+    Skip,
+}
+
+// a param of a function's LLVM type, from the IR arg at index
+struct Param {
+    ty: String,
+    attributes: String,
+    index: usize,
+}
+
+pub fn emit(program: &Program) -> String {
+    let mut emitter = Emitter { program, out: String::new(), entry: String::new(), next: 0 };
+
+    for (id, structure) in program.structs.iter().enumerate() {
+        let fields = structure.fields.iter().map(|&field| emitter.ty(field)).collect::<Vec<_>>();
+        writeln!(emitter.out, "{} = type {{ {} }}", emitter.ty(Type::Struct(id)), fields.join(", ")).unwrap();
+    }
+    emitter.out.push('\n');
+
+    for data in &program.data {
+        emitter.data(data);
+    }
+    emitter.out.push('\n');
+
+    for external in &program.externs {
+        match external.header {
+            Some(_) => writeln!(emitter.out, "@{} = external global ptr", external.name).unwrap(),
+            None => {
+                let (return_type, params, _) = emitter.signature(&external.args, external.return_type);
+                let mut params = params.into_iter().map(|param| param.ty + &param.attributes).collect::<Vec<_>>();
+                if external.variadic {
+                    params.push("...".to_string());
+                }
+                writeln!(emitter.out, "declare {return_type} @{}({})", external.name, params.join(", ")).unwrap();
+            }
+        }
+    }
+    emitter.out.push_str("declare void @llvm.memmove.p0.p0.i64(ptr, ptr, i64, i1)\n");
+    emitter.out.push_str("declare void @llvm.memset.p0.i64(ptr, i8, i64, i1)\n");
+
+    for function in &program.functions {
+        emitter.function(function);
+    }
+
+    emitter.out
+}
+
+// the escapes of bytes in a c"..." constant
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| match b {
+        b' '..=b'~' if b != b'"' && b != b'\\' => (b as char).to_string(),
+        b => format!("\\{b:02X}"),
+    }).collect()
+}
+
+struct Emitter<'a> {
+    program: &'a Program,
+    out: String,
+    // the allocas of the function being emitted, which go at the start of its entry block
+    entry: String,
+    next: usize,
+}
+
+impl Emitter<'_> {
+    fn ty(&self, ty: Type) -> String {
+        match ty {
+            Type::I8 => "i8".to_string(),
+            Type::I32 => "i32".to_string(),
+            Type::Ptr => "ptr".to_string(),
+            Type::Struct(id) => format!("%{}.{id}", self.program.structs[id].name),
+        }
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        writeln!(self.out, "  {}", line.as_ref()).unwrap();
+    }
+
+    // a fresh name for a value the IR has no temporary for
+    fn value(&mut self) -> String {
+        self.next += 1;
+        format!("%v{}", self.next)
+    }
+
+    fn alloca(&mut self, ty: &str) -> String {
+        let name = self.value();
+        writeln!(self.entry, "  {name} = alloca {ty}, align 8").unwrap();
+        name
+    }
+
+    fn data(&mut self, data: &Data) {
+        let items = data.items.iter().map(|item| match item {
+            DataItem::Bytes(bytes) => (format!("[{} x i8]", bytes.len()), format!("c\"{}\"", escape(bytes))),
+            DataItem::Symbol(name) => ("ptr".to_string(), format!("@{name}")),
+        }).collect::<Vec<_>>();
+
+        let (ty, value) = match &items[..] {
+            [(ty, value)] if ty != "ptr" => (ty.clone(), value.clone()),
+            items => {
+                let types = items.iter().map(|(ty, _)| ty.clone()).collect::<Vec<_>>();
+                let values = items.iter().map(|(ty, value)| format!("{ty} {value}")).collect::<Vec<_>>();
+                (format!("<{{ {} }}>", types.join(", ")), format!("<{{ {} }}>", values.join(", ")))
+            }
+        };
+        writeln!(self.out, "@{} = private unnamed_addr constant {ty} {value}, align 8", data.name).unwrap();
+    }
+
+    fn pass(&self, ty: Type, location: &Location) -> Pass {
+        let size = self.program.size(ty);
+        match (ty, location) {
+            (Type::Struct(_), _) if size == 0 => Pass::Skip,
+            (Type::Struct(_), Location::Registers(_)) if size <= 8 => Pass::Coerced(format!("i{}", size * 8), size),
+            (Type::Struct(_), Location::Registers(_)) => Pass::Coerced(format!("{{ i64, i{} }}", (size - 8) * 8), size),
+            (Type::Struct(_), Location::Stack(_)) => Pass::ByVal(ty),
+            (ty, _) => Pass::Scalar(ty),
+        }
+    }
+
+    fn return_pass(&self, return_type: Option<Type>) -> Option<Pass> {
+        match return_type {
+            None => None,
+            Some(ty) if returns_memory(self.program, Some(ty)) => Some(Pass::ByVal(ty)),
+            Some(ty) => Some(self.pass(ty, &Location::Registers(Vec::new()))),
+        }
+    }
+
+    // the return type and params of a function, and how each IR arg is passed
+    fn signature(&self, args: &[Type], return_type: Option<Type>) -> (String, Vec<Param>, Vec<Pass>) {
+        let (locations, _) = classify(self.program, args, return_type);
+        let mut params = Vec::new();
+
+        let return_type = match self.return_pass(return_type) {
+            Some(Pass::ByVal(ty)) => {
+                params.push(Param { ty: "ptr".to_string(), attributes: format!(" sret({}) align 8", self.ty(ty)), index: 0 });
+                "void".to_string()
+            }
+            Some(Pass::Scalar(ty)) => self.ty(ty),
+            Some(Pass::Coerced(coerced, _)) => coerced,
+            _ => "void".to_string(),
+        };
+
+        let mut passes = Vec::new();
+        for (index, (&ty, location)) in args.iter().zip(&locations).enumerate() {
+            let pass = self.pass(ty, location);
+            match &pass {
+                Pass::Scalar(Type::I8) => params.push(Param { ty: "i8".to_string(), attributes: " signext".to_string(), index }),
+                &Pass::Scalar(ty) => params.push(Param { ty: self.ty(ty), attributes: String::new(), index }),
+                Pass::Coerced(coerced, _) => params.push(Param { ty: coerced.clone(), attributes: String::new(), index }),
+                &Pass::ByVal(ty) => params.push(Param { ty: "ptr".to_string(), attributes: format!(" byval({}) align 8", self.ty(ty)), index }),
+                Pass::Skip => {}
+            }
+            passes.push(pass);
+        }
+
+        (return_type, params, passes)
+    }
+
+    fn function(&mut self, function: &Function) {
+        let program = self.program;
+        self.next = 0;
+        self.entry.clear();
+        let header = std::mem::take(&mut self.out);
+
+        for (slot, &ty) in function.slots.iter().enumerate() {
+            writeln!(self.entry, "  %s{slot} = alloca {}, align 8", self.ty(ty)).unwrap();
+        }
+
+        let arg_types = function.params.iter().map(|&(_, ty)| ty).collect::<Vec<_>>();
+        let (return_type, params, passes) = self.signature(&arg_types, function.return_type);
+        let sret = returns_memory(program, function.return_type);
+
+        // params are named after their temporaries, structs passed in registers are stored to memory of their own
+        let mut names = Vec::new();
+        if sret {
+            names.push("%sret".to_string());
+        }
+        for (&(temp, _), pass) in function.params.iter().zip(&passes) {
+            match pass {
+                Pass::Coerced(coerced, _) => {
+                    let name = self.value();
+                    writeln!(self.entry, "  %t{temp} = alloca {coerced}, align 8").unwrap();
+                    writeln!(self.entry, "  store {coerced} {name}, ptr %t{temp}").unwrap();
+                    names.push(name);
+                }
+                Pass::Skip => {
+                    writeln!(self.entry, "  %t{temp} = alloca i8, align 8").unwrap();
+                }
+                _ => names.push(format!("%t{temp}")),
+            }
+        }
+        let params = params.iter().zip(names).map(|(param, name)| format!("{}{} {name}", param.ty, param.attributes)).collect::<Vec<_>>();
+
+        for (block, Block { insts, terminator }) in function.blocks.iter().enumerate() {
+            writeln!(self.out, "b{block}:").unwrap();
+            for inst in insts {
+                self.inst(function, inst);
+            }
+            self.terminator(function, terminator);
+        }
+
+        let body = std::mem::replace(&mut self.out, header);
+        let linkage = if function.export || function.name == "main" { "" } else { "internal " };
+        writeln!(self.out, "\ndefine {linkage}{return_type} @{}({}) {{\nentry:", function.name, params.join(", ")).unwrap();
+        self.out.push_str(&self.entry);
+        self.out.push_str("  br label %b0\n");
+        self.out.push_str(&body);
+        self.out.push_str("}\n");
+    }
+
+    fn inst(&mut self, function: &Function, inst: &Inst) {
+        let program = self.program;
+        let temps = &function.temps;
+        match inst {
+            &Inst::Const { dest, value } => match temps[dest] {
+                Type::Ptr => self.line(format!("%t{dest} = inttoptr i64 {value} to ptr")),
+                ty => {
+                    let value = if ty == Type::I8 { value as i8 as i64 } else { value as i32 as i64 };
+                    self.line(format!("%t{dest} = add {} 0, {value}", self.ty(ty)));
+                }
+            },
+            Inst::Symbol { dest, name } => match program.external(name) {
+                Some(Extern { header: Some(_), .. }) => self.line(format!("%t{dest} = load ptr, ptr @{name}")),
+                _ => self.line(format!("%t{dest} = getelementptr i8, ptr @{name}, i64 0")),
+            },
+            &Inst::Slot { dest, slot } => self.line(format!("%t{dest} = getelementptr i8, ptr %s{slot}, i64 0")),
+            &Inst::Load { dest, addr } => self.line(format!("%t{dest} = load {}, ptr %t{addr}", self.ty(temps[dest]))),
+            &Inst::Store { addr, value } => self.line(format!("store {} %t{value}, ptr %t{addr}", self.ty(temps[value]))),
+            &Inst::Copy { dest, src, ty } => self.copy(&format!("%t{dest}"), &format!("%t{src}"), program.size(ty)),
+            &Inst::Zero { dest, ty } => {
+                self.line(format!("call void @llvm.memset.p0.i64(ptr %t{dest}, i8 0, i64 {}, i1 false)", program.size(ty)));
+            }
+            &Inst::Field { dest, base, ty, index } => {
+                self.line(format!("%t{dest} = getelementptr {}, ptr %t{base}, i32 0, i32 {index}", self.ty(Type::Struct(ty))));
+            }
+            &Inst::Element { dest, base, ty, index } => {
+                let index = self.index(temps, index);
+                self.line(format!("%t{dest} = getelementptr {}, ptr %t{base}, i64 {index}", self.ty(ty)));
+            }
+            &Inst::Unary { dest, op, value } => {
+                let ty = temps[value];
+                let result = self.value();
+                match op {
+                    UnaryOp::Neg => {
+                        let (value, width) = self.integer(temps, value);
+                        self.line(format!("{result} = sub {width} 0, {value}"));
+                        self.result(temps, dest, result, &width);
+                    }
+                    UnaryOp::Not => {
+                        let zero = if ty == Type::Ptr { "null" } else { "0" };
+                        self.line(format!("{result} = icmp eq {} %t{value}, {zero}", self.ty(ty)));
+                        self.result(temps, dest, result, "i1");
+                    }
+                }
+            }
+            &Inst::Binary { dest, op, lhs, rhs } => {
+                let ty = temps[lhs];
+                let predicate = match op {
+                    BinaryOp::Lt => Some("slt"),
+                    BinaryOp::Gt => Some("sgt"),
+                    BinaryOp::Eq => Some("eq"),
+                    BinaryOp::Ne => Some("ne"),
+                    _ => None,
+                };
+                let result = self.value();
+                match predicate {
+                    Some(predicate) => {
+                        self.line(format!("{result} = icmp {predicate} {} %t{lhs}, %t{rhs}", self.ty(ty)));
+                        self.result(temps, dest, result, "i1");
+                    }
+                    None => {
+                        let instruction = match op {
+                            BinaryOp::Add => "add",
+                            BinaryOp::Sub => "sub",
+                            _ => "mul",
+                        };
+                        let ((l, width), (r, _)) = (self.integer(temps, lhs), self.integer(temps, rhs));
+                        self.line(format!("{result} = {instruction} {width} {l}, {r}"));
+                        self.result(temps, dest, result, &width);
+                    }
+                }
+            }
+            &Inst::Cast { dest, value } => {
+                let from = self.ty(temps[value]);
+                self.result(temps, dest, format!("%t{value}"), &from);
+            }
+            Inst::Call { dest, callee, args, fixed } => self.call(function, *dest, callee, args, *fixed),
+        }
+    }
+
+    fn copy(&mut self, dest: &str, src: &str, size: u64) {
+        self.line(format!("call void @llvm.memmove.p0.p0.i64(ptr {dest}, ptr {src}, i64 {size}, i1 false)"));
+    }
+
+    // a temporary as an integer and its type, arithmetic on pointers is done in i64
+    fn integer(&mut self, temps: &[Type], temp: Temp) -> (String, String) {
+        match temps[temp] {
+            Type::Ptr => {
+                let value = self.value();
+                self.line(format!("{value} = ptrtoint ptr %t{temp} to i64"));
+                (value, "i64".to_string())
+            }
+            ty => (format!("%t{temp}"), self.ty(ty)),
+        }
+    }
+
+    // a temporary as the i64 a getelementptr indexes by
+    fn index(&mut self, temps: &[Type], temp: Temp) -> String {
+        let (value, ty) = self.integer(temps, temp);
+        if ty == "i64" {
+            return value;
+        }
+        let wide = self.value();
+        self.line(format!("{wide} = sext {ty} {value} to i64"));
+        wide
+    }
+
+    // a value of LLVM type from converted to the type of dest, i1 is zero extended and other integers sign extended
+    fn result(&mut self, temps: &[Type], dest: Temp, value: String, from: &str) {
+        let to = temps[dest];
+        let width = |ty: &str| ty.trim_start_matches('i').parse::<u32>().unwrap_or(64);
+        let extend = if from == "i1" { "zext" } else { "sext" };
+
+        let line = match (from, to) {
+            ("ptr", Type::Ptr) => format!("%t{dest} = getelementptr i8, ptr {value}, i64 0"),
+            ("ptr", to) => format!("%t{dest} = ptrtoint ptr {value} to {}", self.ty(to)),
+            (from, Type::Ptr) => {
+                let wide = match width(from) {
+                    64 => value,
+                    _ => {
+                        let wide = self.value();
+                        self.line(format!("{wide} = {extend} {from} {value} to i64"));
+                        wide
+                    }
+                };
+                format!("%t{dest} = inttoptr i64 {wide} to ptr")
+            }
+            (from, to) => {
+                let to = self.ty(to);
+                match width(&to).cmp(&width(from)) {
+                    Ordering::Equal => format!("%t{dest} = add {from} {value}, 0"),
+                    Ordering::Less => format!("%t{dest} = trunc {from} {value} to {to}"),
+                    Ordering::Greater => format!("%t{dest} = {extend} {from} {value} to {to}"),
+                }
+            }
+        };
+        self.line(line);
+    }
+
+    fn call(&mut self, function: &Function, dest: Dest, callee: &Callee, args: &[(Temp, Type)], fixed: Option<usize>) {
+        let program = self.program;
+        let return_type = match dest {
+            Dest::None => None,
+            Dest::Value(temp) => Some(function.temps[temp]),
+            Dest::Memory(_, ty) => Some(ty),
+        };
+        let arg_types = args.iter().map(|&(_, ty)| ty).collect::<Vec<_>>();
+        let (return_ty, params, passes) = self.signature(&arg_types, return_type);
+
+        let mut values = Vec::new();
+        if let (Dest::Memory(addr, _), true) = (dest, returns_memory(program, return_type)) {
+            values.push(format!("%t{addr}"));
+        }
+        for (&(temp, _), pass) in args.iter().zip(&passes) {
+            match pass {
+                Pass::Coerced(coerced, size) => {
+                    let memory = self.alloca(coerced);
+                    self.copy(&memory, &format!("%t{temp}"), *size);
+                    let value = self.value();
+                    self.line(format!("{value} = load {coerced}, ptr {memory}"));
+                    values.push(value);
+                }
+                Pass::Skip => {}
+                _ => values.push(format!("%t{temp}")),
+            }
+        }
+        let values = params.iter().zip(values).map(|(param, value)| format!("{}{} {value}", param.ty, param.attributes)).collect::<Vec<_>>();
+
+        // a variadic callee's type only has the params before the ...
+        let mut types = params.iter().filter(|param| fixed.is_none_or(|fixed| param.index < fixed)).map(|param| param.ty.clone()).collect::<Vec<_>>();
+        if fixed.is_some() {
+            types.push("...".to_string());
+        }
+
+        let target = match callee {
+            Callee::Direct(name) => match program.external(name) {
+                Some(Extern { header: Some(_), .. }) => {
+                    let pointer = self.value();
+                    self.line(format!("{pointer} = load ptr, ptr @{name}"));
+                    pointer
+                }
+                _ => format!("@{name}"),
+            },
+            Callee::Indirect(temp) => format!("%t{temp}"),
+        };
+        let call = format!("call {return_ty} ({}) {target}({})", types.join(", "), values.join(", "));
+
+        match dest {
+            Dest::Value(temp) => self.line(format!("%t{temp} = {call}")),
+            Dest::Memory(addr, ty) if !returns_memory(program, Some(ty)) && program.size(ty) > 0 => {
+                let result = self.value();
+                self.line(format!("{result} = {call}"));
+                let memory = self.alloca(&return_ty);
+                self.line(format!("store {return_ty} {result}, ptr {memory}"));
+                self.copy(&format!("%t{addr}"), &memory, program.size(ty));
+            }
+            _ => self.line(call),
+        }
+    }
+
+    fn terminator(&mut self, function: &Function, terminator: &Terminator) {
+        let program = self.program;
+        match *terminator {
+            Terminator::Jump(block) => self.line(format!("br label %b{block}")),
+            Terminator::Branch { condition, then, otherwise } => {
+                let ty = function.temps[condition];
+                let zero = if ty == Type::Ptr { "null" } else { "0" };
+                let value = self.value();
+                self.line(format!("{value} = icmp ne {} %t{condition}, {zero}", self.ty(ty)));
+                self.line(format!("br i1 {value}, label %b{then}, label %b{otherwise}"));
+            }
+            Terminator::Return(value) => match (value, function.return_type) {
+                (Some(value), Some(ty @ Type::Struct(_))) if returns_memory(program, Some(ty)) => {
+                    self.copy("%sret", &format!("%t{value}"), program.size(ty));
+                    self.line("ret void");
+                }
+                (Some(value), Some(ty @ Type::Struct(_))) => match self.return_pass(Some(ty)) {
+                    Some(Pass::Coerced(coerced, size)) => {
+                        let memory = self.alloca(&coerced);
+                        self.copy(&memory, &format!("%t{value}"), size);
+                        let result = self.value();
+                        self.line(format!("{result} = load {coerced}, ptr {memory}"));
+                        self.line(format!("ret {coerced} {result}"));
+                    }
+                    _ => self.line("ret void"),
+                },
+                (Some(value), Some(ty)) => self.line(format!("ret {} %t{value}", self.ty(ty))),
+                _ => self.line("ret void"),
+            },
+            Terminator::Unreachable => self.line("unreachable"),
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::ir::*;
+
+mod runtime;
+
+// a WebAssembly text module over one linear memory
+//
+// temporaries are i32 locals, pointers too, and keep the layout the IR gives them in memory: 8 bytes
+// with the address in the low 4. slots live in a frame on a stack in memory, structs are passed by
+// the address of the caller's value and copied by the callee, returned through the address of the
+// caller's memory in a first param, and the args after the fixed ones of a variadic call are 8 byte
+// values in memory whose address is the last param
+//
+// the host provides the "mud" imports the runtime is built on, and the "env" ones for the program's
+// own externs, which it can leave out if it does not run such programs
+
+// the first bytes of memory are kept from data so no address is null
+const DATA_START: u64 = 16;
+const STACK_SIZE: u64 = 1 << 20;
+const PAGE_SIZE: u64 = 1 << 16;
+
+// what the runtime writes to stderr, NUL terminated
+const MESSAGES: [(&str, &str); 5] = [
+    ("mudrt_index_message", "index "),
+    ("mudrt_range_message", "range "),
+    ("mudrt_dots_message", ".."),
+    ("mudrt_length_message", " is out of bounds for length "),
+    ("mudrt_newline_message", "\n"),
+];
+
+pub fn emit(program: &Program) -> String {
+    let mut emitter = Emitter { program, out: String::new(), addresses: HashMap::new(), table: HashMap::new() };
+    let defined = runtime::functions();
+
+    emitter.out.push_str("(module\n");
+    emitter.out.push_str(runtime::IMPORTS);
+    for external in program.externs.iter().filter(|external| !defined.contains(&external.name.as_str())) {
+        let import = match &external.header {
+            Some((_, name)) => name,
+            None => &external.name,
+        };
+        let (params, result) = emitter.signature(&external.args, external.return_type, external.variadic);
+        emitter.line(format!("(import \"env\" \"{import}\" (func ${}{params}{result}))", external.name));
+    }
+
+    // data, then the stack growing down towards it, then the heap
+    let mut address = DATA_START;
+    let mut segments = String::new();
+    for (name, message) in MESSAGES {
+        emitter.addresses.insert(name.to_string(), address);
+        writeln!(segments, "  (global ${name} i32 (i32.const {address}))").unwrap();
+        writeln!(segments, "  (data (i32.const {address}) \"{}\\00\")", escape(message.as_bytes())).unwrap();
+        address = (address + message.len() as u64 + 1).next_multiple_of(8);
+    }
+    for data in &program.data {
+        emitter.addresses.insert(data.name.clone(), address);
+        address += data.items.iter().map(|item| match item {
+            DataItem::Bytes(bytes) => bytes.len() as u64,
+            DataItem::Symbol(_) => 8,
+        }).sum::<u64>();
+        address = address.next_multiple_of(8);
+    }
+    let heap = address.next_multiple_of(16) + STACK_SIZE;
+
+    // functions are called through a table, 0 is left as the null function pointer
+    let functions = emitter.table_functions();
+    for (index, name) in functions.iter().enumerate() {
+        emitter.table.insert(name.clone(), index as u64 + 1);
+    }
+
+    writeln!(emitter.out, "  (memory (export \"memory\") {})", heap / PAGE_SIZE + 1).unwrap();
+    writeln!(emitter.out, "  (global $sp (mut i32) (i32.const {heap}))").unwrap();
+    writeln!(emitter.out, "  (global $heap (mut i32) (i32.const {heap}))").unwrap();
+    if !functions.is_empty() {
+        let names = functions.iter().map(|name| format!("${name}")).collect::<Vec<_>>();
+        writeln!(emitter.out, "  (table {} funcref)", functions.len() + 1).unwrap();
+        writeln!(emitter.out, "  (elem (i32.const 1) func {})", names.join(" ")).unwrap();
+    }
+    emitter.out.push_str(&segments);
+    for data in &program.data {
+        emitter.data(data);
+    }
+
+    emitter.out.push_str(runtime::RUNTIME);
+    for function in &program.functions {
+        emitter.function(function);
+    }
+    emitter.out.push_str(")\n");
+
+    emitter.out
+}
+
+// the escapes of bytes in a "..." string
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| match b {
+        b' '..=b'~' if b != b'"' && b != b'\\' => (b as char).to_string(),
+        b => format!("\\{b:02x}"),
+    }).collect()
+}
+
+struct Emitter<'a> {
+    program: &'a Program,
+    out: String,
+    // where each data symbol and runtime message is in memory
+    addresses: HashMap<String, u64>,
+    // the index of each function whose address is taken
+    table: HashMap<String, u64>,
+}
+
+// the memory of a function's frame
+struct Frame {
+    slots: Vec<u64>,
+    // the copies of struct params, by param index
+    params: HashMap<usize, u64>,
+    // the args of variadic calls, shared by all of them
+    varargs: u64,
+    size: u64,
+}
+
+impl Emitter<'_> {
+    fn line(&mut self, line: impl AsRef<str>) {
+        writeln!(self.out, "  {}", line.as_ref()).unwrap();
+    }
+
+    fn data(&mut self, data: &Data) {
+        let mut bytes = Vec::new();
+        for item in &data.items {
+            match item {
+                DataItem::Bytes(item) => bytes.extend(item),
+                DataItem::Symbol(name) => {
+                    let value = self.table.get(name).or(self.addresses.get(name)).copied().unwrap_or(0);
+                    bytes.extend(value.to_le_bytes());
+                }
+            }
+        }
+        writeln!(self.out, "  (data (i32.const {}) \"{}\")", self.addresses[&data.name], escape(&bytes)).unwrap();
+    }
+
+    // the functions whose address is taken, by an instruction or in data
+    fn table_functions(&self) -> Vec<String> {
+        let program = self.program;
+        let mut names: Vec<String> = Vec::new();
+        let symbols = program.functions.iter()
+            .flat_map(|function| function.blocks.iter().flat_map(|block| &block.insts))
+            .filter_map(|inst| match inst {
+                Inst::Symbol { name, .. } => Some(name),
+                _ => None,
+            })
+            .chain(program.data.iter().flat_map(|data| &data.items).filter_map(|item| match item {
+                DataItem::Symbol(name) => Some(name),
+                _ => None,
+            }));
+
+        for name in symbols {
+            if !self.addresses.contains_key(name) && !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+
+    // the params and result of a function type, with an sret param for a struct result
+    fn signature(&self, args: &[Type], return_type: Option<Type>, variadic: bool) -> (String, String) {
+        let sret = matches!(return_type, Some(Type::Struct(_)));
+        let count = sret as usize + args.len() + variadic as usize;
+        let params = " (param i32)".repeat(count);
+        let result = match return_type {
+            Some(ty) if ty.is_scalar() => " (result i32)",
+            _ => "",
+        };
+        (params, result.to_string())
+    }
+
+    fn frame(&self, function: &Function) -> Frame {
+        let program = self.program;
+        let mut size: u64 = 0;
+        let mut place = |ty: Type| {
+            let offset = size.next_multiple_of(program.align(ty).max(1));
+            size = offset + program.size(ty);
+            offset
+        };
+
+        let slots = function.slots.iter().map(|&ty| place(ty)).collect();
+        let params = function.params.iter().enumerate()
+            .filter(|(_, (_, ty))| !ty.is_scalar())
+            .map(|(index, &(_, ty))| (index, place(ty)))
+            .collect();
+
+        let varargs_size = function.blocks.iter().flat_map(|block| &block.insts).filter_map(|inst| match inst {
+            Inst::Call { args, fixed: Some(fixed), .. } => Some(args[*fixed..].iter().map(|&(_, ty)| program.size(ty).next_multiple_of(8)).sum()),
+            _ => None,
+        }).max().unwrap_or(0);
+        let varargs = size.next_multiple_of(8);
+        let size = (varargs + varargs_size).next_multiple_of(16);
+
+        Frame { slots, params, varargs, size }
+    }
+
+    fn function(&mut self, function: &Function) {
+        let program = self.program;
+        let frame = self.frame(function);
+        let sret = matches!(function.return_type, Some(Type::Struct(_)));
+
+        let export = match function.export {
+            true => format!(" (export \"{}\")", function.name),
+            false => String::new(),
+        };
+        let mut params = String::new();
+        if sret {
+            params.push_str(" (param $sret i32)");
+        }
+        for &(temp, _) in &function.params {
+            write!(params, " (param $t{temp} i32)").unwrap();
+        }
+        let (_, result) = self.signature(&[], function.return_type, false);
+        writeln!(self.out, "  (func ${}{export}{params}{result}", function.name).unwrap();
+
+        self.line("(local $fp i32) (local $pc i32)");
+        let locals = (0..function.temps.len())
+            .filter(|temp| !function.params.iter().any(|&(param, _)| param == *temp))
+            .map(|temp| format!("(local $t{temp} i32)"))
+            .collect::<Vec<_>>();
+        for chunk in locals.chunks(8) {
+            self.line(chunk.join(" "));
+        }
+
+        self.line(format!("global.get $sp\n  i32.const {}\n  i32.sub\n  local.tee $fp\n  global.set $sp", frame.size));
+        for (index, &(temp, ty)) in function.params.iter().enumerate() {
+            if let Some(offset) = frame.params.get(&index) {
+                self.line(format!("local.get $fp\n  i32.const {offset}\n  i32.add\n  local.get $t{temp}\n  i32.const {}\n  memory.copy", program.size(ty)));
+                self.line(format!("local.get $fp\n  i32.const {offset}\n  i32.add\n  local.set $t{temp}"));
+            }
+        }
+
+        // blocks are reached through a br_table on $pc, the code of each follows the end of its wasm block
+        let count = function.blocks.len();
+        self.line("loop $dispatch");
+        for block in (0..count).rev() {
+            self.line(format!("block $b{block}"));
+        }
+        let labels = (0..count).map(|block| format!("$b{block}")).collect::<Vec<_>>();
+        self.line(format!("local.get $pc\n  br_table {}\n  end", labels.join(" ")));
+        for (index, Block { insts, terminator }) in function.blocks.iter().enumerate() {
+            for inst in insts {
+                self.inst(function, &frame, inst);
+            }
+            self.terminator(function, &frame, terminator);
+            if index + 1 < count {
+                self.line("end");
+            }
+        }
+        self.line("end\n  unreachable\n  )");
+    }
+
+    fn inst(&mut self, function: &Function, frame: &Frame, inst: &Inst) {
+        let program = self.program;
+        let temps = &function.temps;
+        match inst {
+            &Inst::Const { dest, value } => {
+                let value = if temps[dest] == Type::I8 { value as i8 as i32 } else { value as i32 };
+                self.line(format!("i32.const {value}\n  local.set $t{dest}"));
+            }
+            Inst::Symbol { dest, name } => {
+                let value = self.table.get(name).or(self.addresses.get(name)).copied().unwrap_or(0);
+                self.line(format!("i32.const {value}\n  local.set $t{dest}"));
+            }
+            &Inst::Slot { dest, slot } => self.line(format!("local.get $fp\n  i32.const {}\n  i32.add\n  local.set $t{dest}", frame.slots[slot])),
+            &Inst::Load { dest, addr } => {
+                let load = if temps[dest] == Type::I8 { "i32.load8_s" } else { "i32.load" };
+                self.line(format!("local.get $t{addr}\n  {load}\n  local.set $t{dest}"));
+            }
+            &Inst::Store { addr, value } => {
+                let store = match temps[value] {
+                    Type::I8 => "i32.store8",
+                    Type::Ptr => "i64.extend_i32_u\n  i64.store",
+                    _ => "i32.store",
+                };
+                self.line(format!("local.get $t{addr}\n  local.get $t{value}\n  {store}"));
+            }
+            &Inst::Copy { dest, src, ty } => self.line(format!("local.get $t{dest}\n  local.get $t{src}\n  i32.const {}\n  memory.copy", program.size(ty))),
+            &Inst::Zero { dest, ty } => self.line(format!("local.get $t{dest}\n  i32.const 0\n  i32.const {}\n  memory.fill", program.size(ty))),
+            &Inst::Field { dest, base, ty, index } => {
+                self.line(format!("local.get $t{base}\n  i32.const {}\n  i32.add\n  local.set $t{dest}", program.offset(ty, index)));
+            }
+            &Inst::Element { dest, base, ty, index } => {
+                self.line(format!("local.get $t{base}\n  local.get $t{index}\n  i32.const {}\n  i32.mul\n  i32.add\n  local.set $t{dest}", program.size(ty)));
+            }
+            &Inst::Unary { dest, op, value } => {
+                let instructions = match op {
+                    UnaryOp::Neg => format!("i32.const 0\n  local.get $t{value}\n  i32.sub"),
+                    UnaryOp::Not => format!("local.get $t{value}\n  i32.eqz"),
+                };
+                self.line(instructions);
+                self.result(temps, dest);
+            }
+            &Inst::Binary { dest, op, lhs, rhs } => {
+                let instruction = match op {
+                    BinaryOp::Add => "i32.add",
+                    BinaryOp::Sub => "i32.sub",
+                    BinaryOp::Mul => "i32.mul",
+                    BinaryOp::Lt => "i32.lt_s",
+                    BinaryOp::Gt => "i32.gt_s",
+                    BinaryOp::Eq => "i32.eq",
+                    BinaryOp::Ne => "i32.ne",
+                };
+                self.line(format!("local.get $t{lhs}\n  local.get $t{rhs}\n  {instruction}"));
+                self.result(temps, dest);
+            }
+            &Inst::Cast { dest, value } => {
+                self.line(format!("local.get $t{value}"));
+                self.result(temps, dest);
+            }
+            Inst::Call { dest, callee, args, fixed } => self.call(function, frame, *dest, callee, args, *fixed),
+        }
+    }
+
+    // sets dest to the i32 on the stack, an I8 is kept sign extended
+    fn result(&mut self, temps: &[Type], dest: Temp) {
+        if temps[dest] == Type::I8 {
+            self.line("i32.extend8_s");
+        }
+        self.line(format!("local.set $t{dest}"));
+    }
+
+    fn call(&mut self, function: &Function, frame: &Frame, dest: Dest, callee: &Callee, args: &[(Temp, Type)], fixed: Option<usize>) {
+        let program = self.program;
+        let return_type = match dest {
+            Dest::None => None,
+            Dest::Value(temp) => Some(function.temps[temp]),
+            Dest::Memory(_, ty) => Some(ty),
+        };
+
+        if let Dest::Memory(addr, _) = dest {
+            self.line(format!("local.get $t{addr}"));
+        }
+        let count = fixed.unwrap_or(args.len());
+        for &(temp, _) in &args[..count] {
+            self.line(format!("local.get $t{temp}"));
+        }
+
+        if fixed.is_some() {
+            let mut offset = frame.varargs;
+            for &(temp, ty) in &args[count..] {
+                match ty.is_scalar() {
+                    true => self.line(format!("local.get $fp\n  local.get $t{temp}\n  i64.extend_i32_s\n  i64.store offset={offset}")),
+                    false => self.line(format!("local.get $fp\n  i32.const {offset}\n  i32.add\n  local.get $t{temp}\n  i32.const {}\n  memory.copy", program.size(ty))),
+                }
+                offset += program.size(ty).next_multiple_of(8);
+            }
+            self.line(format!("local.get $fp\n  i32.const {}\n  i32.add", frame.varargs));
+        }
+
+        match callee {
+            Callee::Direct(name) => self.line(format!("call ${name}")),
+            Callee::Indirect(temp) => {
+                let arg_types = args[..count].iter().map(|&(_, ty)| ty).collect::<Vec<_>>();
+                let (params, result) = self.signature(&arg_types, return_type, fixed.is_some());
+                self.line(format!("local.get $t{temp}\n  call_indirect{params}{result}"));
+            }
+        }
+
+        if let Dest::Value(temp) = dest {
+            self.line(format!("local.set $t{temp}"));
+        }
+    }
+
+    fn epilogue(&mut self, frame: &Frame) {
+        self.line(format!("local.get $fp\n  i32.const {}\n  i32.add\n  global.set $sp", frame.size));
+    }
+
+    fn terminator(&mut self, function: &Function, frame: &Frame, terminator: &Terminator) {
+        let program = self.program;
+        match *terminator {
+            Terminator::Jump(block) => self.line(format!("i32.const {block}\n  local.set $pc\n  br $dispatch")),
+            Terminator::Branch { condition, then, otherwise } => {
+                self.line(format!("i32.const {then}\n  i32.const {otherwise}\n  local.get $t{condition}\n  select\n  local.set $pc\n  br $dispatch"));
+            }
+            Terminator::Return(value) => {
+                match (value, function.return_type) {
+                    (Some(value), Some(ty @ Type::Struct(_))) => {
+                        self.line(format!("local.get $sret\n  local.get $t{value}\n  i32.const {}\n  memory.copy", program.size(ty)));
+                    }
+                    (Some(value), Some(_)) => self.line(format!("local.get $t{value}")),
+                    _ => {}
+                }
+                self.epilogue(frame);
+                self.line("return");
+            }
+            Terminator::Unreachable => self.line("unreachable"),
+        }
+    }
+}
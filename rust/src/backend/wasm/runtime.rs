@@ -0,0 +1,254 @@
+// what the host provides, output to a file descriptor, stdin, exiting and reading whole files
+pub const IMPORTS: &str = r#"  (import "mud" "write" (func $mud_write (param i32 i32 i32)))
+  (import "mud" "read" (func $mud_read (param i32 i32) (result i32)))
+  (import "mud" "exit" (func $mud_exit (param i32)))
+  (import "mud" "file_size" (func $mud_file_size (param i32) (result i32)))
+  (import "mud" "file_read" (func $mud_file_read (param i32 i32)))
+"#;
+
+// the C runtime and the parts of libc it uses, in wat. memory is never reused, free does nothing
+pub const RUNTIME: &str = r#"  (func $malloc (export "malloc") (param $size i32) (result i32)
+    (local $block i32)
+    (local.set $block (global.get $heap))
+    (global.set $heap (i32.and (i32.add (i32.add (local.get $block) (local.get $size)) (i32.const 15)) (i32.const -8)))
+    (if (i32.gt_u (global.get $heap) (i32.mul (memory.size) (i32.const 65536)))
+      (then
+        (if (i32.eq (memory.grow (i32.sub (i32.shr_u (i32.add (global.get $heap) (i32.const 65535)) (i32.const 16)) (memory.size))) (i32.const -1))
+          (then (unreachable)))))
+    ;; a block starts with its size, for realloc
+    (i32.store (local.get $block) (local.get $size))
+    (i32.add (local.get $block) (i32.const 8)))
+  (func $calloc (param $count i32) (param $size i32) (result i32)
+    (local $memory i32)
+    (local.set $memory (call $malloc (i32.mul (local.get $count) (local.get $size))))
+    (memory.fill (local.get $memory) (i32.const 0) (i32.mul (local.get $count) (local.get $size)))
+    (local.get $memory))
+  (func $realloc (param $memory i32) (param $size i32) (result i32)
+    (local $new i32) (local $old i32)
+    (local.set $new (call $malloc (local.get $size)))
+    (if (local.get $memory)
+      (then
+        (local.set $old (i32.load (i32.sub (local.get $memory) (i32.const 8))))
+        (if (i32.lt_u (local.get $size) (local.get $old)) (then (local.set $old (local.get $size))))
+        (memory.copy (local.get $new) (local.get $memory) (local.get $old))))
+    (local.get $new))
+  (func $free (param $memory i32))
+  (func $exit (param $code i32)
+    (call $mud_exit (local.get $code))
+    (unreachable))
+  (func $strlen (param $s i32) (result i32)
+    (local $len i32)
+    (block $done (loop $next
+      (br_if $done (i32.eqz (i32.load8_u (i32.add (local.get $s) (local.get $len)))))
+      (local.set $len (i32.add (local.get $len) (i32.const 1)))
+      (br $next)))
+    (local.get $len))
+  (func $strcmp (param $a i32) (param $b i32) (result i32)
+    (local $x i32) (local $y i32)
+    (loop $next
+      (local.set $x (i32.load8_u (local.get $a)))
+      (local.set $y (i32.load8_u (local.get $b)))
+      (if (i32.and (i32.eq (local.get $x) (local.get $y)) (i32.ne (local.get $x) (i32.const 0)))
+        (then
+          (local.set $a (i32.add (local.get $a) (i32.const 1)))
+          (local.set $b (i32.add (local.get $b) (i32.const 1)))
+          (br $next))))
+    (i32.sub (local.get $x) (local.get $y)))
+  (func $memcmp (param $a i32) (param $b i32) (param $n i32) (result i32)
+    (local $i i32) (local $x i32) (local $y i32)
+    (block $done (loop $next
+      (br_if $done (i32.ge_s (local.get $i) (local.get $n)))
+      (local.set $x (i32.load8_u (i32.add (local.get $a) (local.get $i))))
+      (local.set $y (i32.load8_u (i32.add (local.get $b) (local.get $i))))
+      (if (i32.ne (local.get $x) (local.get $y)) (then (return (i32.sub (local.get $x) (local.get $y)))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $next)))
+    (i32.const 0))
+  (func $memcpy (param $dest i32) (param $src i32) (param $n i32) (result i32)
+    (memory.copy (local.get $dest) (local.get $src) (local.get $n))
+    (local.get $dest))
+  (func $memmove (param $dest i32) (param $src i32) (param $n i32) (result i32)
+    (memory.copy (local.get $dest) (local.get $src) (local.get $n))
+    (local.get $dest))
+  (func $memset (param $dest i32) (param $c i32) (param $n i32) (result i32)
+    (memory.fill (local.get $dest) (local.get $c) (local.get $n))
+    (local.get $dest))
+  (func $read_file (param $filename i32) (result i32)
+    (local $size i32) (local $buffer i32)
+    (local.set $size (call $mud_file_size (local.get $filename)))
+    (if (i32.lt_s (local.get $size) (i32.const 0)) (then (return (i32.const 0))))
+    (local.set $buffer (call $malloc (i32.add (local.get $size) (i32.const 1))))
+    (call $mud_file_read (local.get $filename) (local.get $buffer))
+    (i32.store8 (i32.add (local.get $buffer) (local.get $size)) (i32.const 0))
+    (local.get $buffer))
+  ;; writes a str of ptr and len to memory at s
+  (func $mudrt_str (param $s i32) (param $ptr i32) (param $len i32)
+    (i64.store (local.get $s) (i64.extend_i32_u (local.get $ptr)))
+    (i32.store offset=8 (local.get $s) (local.get $len)))
+  ;; the decimal digits of value in buffer, and how many bytes they take
+  (func $mudrt_format_int (param $value i32) (param $buffer i32) (result i32)
+    (local $len i32) (local $magnitude i32) (local $start i32) (local $end i32) (local $c i32)
+    (local.set $magnitude (local.get $value))
+    (if (i32.lt_s (local.get $value) (i32.const 0))
+      (then
+        (i32.store8 (local.get $buffer) (i32.const 45))
+        (local.set $len (i32.const 1))
+        (local.set $magnitude (i32.sub (i32.const 0) (local.get $value)))))
+    (local.set $start (local.get $len))
+    (loop $digit
+      (i32.store8 (i32.add (local.get $buffer) (local.get $len)) (i32.add (i32.const 48) (i32.rem_u (local.get $magnitude) (i32.const 10))))
+      (local.set $len (i32.add (local.get $len) (i32.const 1)))
+      (local.set $magnitude (i32.div_u (local.get $magnitude) (i32.const 10)))
+      (br_if $digit (local.get $magnitude)))
+    ;; the digits were written lowest first
+    (local.set $end (i32.sub (local.get $len) (i32.const 1)))
+    (block $done (loop $swap
+      (br_if $done (i32.ge_s (local.get $start) (local.get $end)))
+      (local.set $c (i32.load8_u (i32.add (local.get $buffer) (local.get $start))))
+      (i32.store8 (i32.add (local.get $buffer) (local.get $start)) (i32.load8_u (i32.add (local.get $buffer) (local.get $end))))
+      (i32.store8 (i32.add (local.get $buffer) (local.get $end)) (local.get $c))
+      (local.set $start (i32.add (local.get $start) (i32.const 1)))
+      (local.set $end (i32.sub (local.get $end) (i32.const 1)))
+      (br $swap)))
+    (local.get $len))
+  ;; the buffers below are under the stack pointer, nothing is called while they are used
+  (func $mudrt_write_int (param $fd i32) (param $value i32)
+    (local $buffer i32)
+    (local.set $buffer (i32.sub (global.get $sp) (i32.const 16)))
+    (call $mud_write (local.get $fd) (local.get $buffer) (call $mudrt_format_int (local.get $value) (local.get $buffer))))
+  (func $mudrt_write_cstr (param $fd i32) (param $s i32)
+    (call $mud_write (local.get $fd) (local.get $s) (call $strlen (local.get $s))))
+  (func $mudrt_getchar (result i32)
+    (local $buffer i32)
+    (local.set $buffer (i32.sub (global.get $sp) (i32.const 16)))
+    (if (i32.lt_s (call $mud_read (local.get $buffer) (i32.const 1)) (i32.const 1)) (then (return (i32.const -1))))
+    (i32.load8_u (local.get $buffer)))
+  (func $mudrt_exit (param $code i32)
+    (call $exit (local.get $code)))
+  (func $mudrt_bounds (param $index i32) (param $len i32) (result i32)
+    (if (i32.or (i32.lt_s (local.get $index) (i32.const 0)) (i32.ge_s (local.get $index) (local.get $len)))
+      (then
+        (call $mudrt_write_cstr (i32.const 2) (global.get $mudrt_index_message))
+        (call $mudrt_write_int (i32.const 2) (local.get $index))
+        (call $mudrt_write_cstr (i32.const 2) (global.get $mudrt_length_message))
+        (call $mudrt_write_int (i32.const 2) (local.get $len))
+        (call $mudrt_write_cstr (i32.const 2) (global.get $mudrt_newline_message))
+        (call $exit (i32.const 101))))
+    (local.get $index))
+  (func $mudrt_range (param $start i32) (param $end i32) (param $len i32) (result i32)
+    (if (i32.or (i32.or (i32.lt_s (local.get $start) (i32.const 0)) (i32.gt_s (local.get $start) (local.get $end))) (i32.gt_s (local.get $end) (local.get $len)))
+      (then
+        (call $mudrt_write_cstr (i32.const 2) (global.get $mudrt_range_message))
+        (call $mudrt_write_int (i32.const 2) (local.get $start))
+        (call $mudrt_write_cstr (i32.const 2) (global.get $mudrt_dots_message))
+        (call $mudrt_write_int (i32.const 2) (local.get $end))
+        (call $mudrt_write_cstr (i32.const 2) (global.get $mudrt_length_message))
+        (call $mudrt_write_int (i32.const 2) (local.get $len))
+        (call $mudrt_write_cstr (i32.const 2) (global.get $mudrt_newline_message))
+        (call $exit (i32.const 101))))
+    (local.get $start))
+  (func $mudrt_print_str (param $s i32)
+    (call $mud_write (i32.const 1) (i32.load (local.get $s)) (i32.load offset=8 (local.get $s))))
+  (func $mudrt_args (param $sret i32) (param $argc i32) (param $argv i32)
+    (local $args i32) (local $i i32) (local $arg i32)
+    (local.set $args (call $calloc (local.get $argc) (i32.const 16)))
+    (block $done (loop $next
+      (br_if $done (i32.ge_s (local.get $i) (local.get $argc)))
+      (local.set $arg (i32.load (i32.add (local.get $argv) (i32.shl (local.get $i) (i32.const 3)))))
+      (call $mudrt_str (i32.add (local.get $args) (i32.shl (local.get $i) (i32.const 4))) (local.get $arg) (call $strlen (local.get $arg)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $next)))
+    (call $mudrt_str (local.get $sret) (local.get $args) (local.get $argc)))
+  (func $mudrt_read_line (param $line i32) (result i32)
+    (local $capacity i32) (local $len i32) (local $buffer i32) (local $c i32)
+    (local.set $capacity (i32.const 128))
+    (local.set $buffer (call $malloc (local.get $capacity)))
+    (block $done (loop $next
+      (local.set $c (call $mudrt_getchar))
+      (br_if $done (i32.or (i32.eq (local.get $c) (i32.const -1)) (i32.eq (local.get $c) (i32.const 10))))
+      (if (i32.eq (i32.add (local.get $len) (i32.const 1)) (local.get $capacity))
+        (then
+          (local.set $capacity (i32.shl (local.get $capacity) (i32.const 1)))
+          (local.set $buffer (call $realloc (local.get $buffer) (local.get $capacity)))))
+      (i32.store8 (i32.add (local.get $buffer) (local.get $len)) (local.get $c))
+      (local.set $len (i32.add (local.get $len) (i32.const 1)))
+      (br $next)))
+    (i32.store8 (i32.add (local.get $buffer) (local.get $len)) (i32.const 0))
+    (call $mudrt_str (local.get $line) (local.get $buffer) (local.get $len))
+    (i32.or (i32.ne (local.get $c) (i32.const -1)) (i32.gt_s (local.get $len) (i32.const 0))))
+  (func $mudrt_read_all (param $sret i32)
+    (local $capacity i32) (local $len i32) (local $buffer i32) (local $n i32)
+    (local.set $capacity (i32.const 256))
+    (local.set $buffer (call $malloc (local.get $capacity)))
+    (block $done (loop $next
+      (local.set $n (call $mud_read (i32.add (local.get $buffer) (local.get $len)) (i32.sub (i32.sub (local.get $capacity) (local.get $len)) (i32.const 1))))
+      (br_if $done (i32.le_s (local.get $n) (i32.const 0)))
+      (local.set $len (i32.add (local.get $len) (local.get $n)))
+      (if (i32.eq (i32.add (local.get $len) (i32.const 1)) (local.get $capacity))
+        (then
+          (local.set $capacity (i32.shl (local.get $capacity) (i32.const 1)))
+          (local.set $buffer (call $realloc (local.get $buffer) (local.get $capacity)))))
+      (br $next)))
+    (i32.store8 (i32.add (local.get $buffer) (local.get $len)) (i32.const 0))
+    (call $mudrt_str (local.get $sret) (local.get $buffer) (local.get $len)))
+  (func $mudrt_str_cmp (param $a i32) (param $b i32) (result i32)
+    (local $x i32) (local $y i32) (local $c i32)
+    (local.set $x (i32.load offset=8 (local.get $a)))
+    (local.set $y (i32.load offset=8 (local.get $b)))
+    (local.set $c (call $memcmp (i32.load (local.get $a)) (i32.load (local.get $b)) (select (local.get $x) (local.get $y) (i32.lt_s (local.get $x) (local.get $y)))))
+    (if (local.get $c) (then (return (local.get $c))))
+    (i32.sub (i32.gt_s (local.get $x) (local.get $y)) (i32.lt_s (local.get $x) (local.get $y))))
+  (func $mudrt_str_eq (param $a i32) (param $b i32) (result i32)
+    (if (i32.ne (i32.load offset=8 (local.get $a)) (i32.load offset=8 (local.get $b))) (then (return (i32.const 0))))
+    (i32.eqz (call $memcmp (i32.load (local.get $a)) (i32.load (local.get $b)) (i32.load offset=8 (local.get $a)))))
+  (func $mudrt_str_to_cstr (param $s i32) (result i32)
+    (local $len i32) (local $c i32)
+    (local.set $len (i32.load offset=8 (local.get $s)))
+    (local.set $c (call $malloc (i32.add (local.get $len) (i32.const 1))))
+    (memory.copy (local.get $c) (i32.load (local.get $s)) (local.get $len))
+    (i32.store8 (i32.add (local.get $c) (local.get $len)) (i32.const 0))
+    (local.get $c))
+  (func $mudrt_str_concat (param $sret i32) (param $a i32) (param $b i32)
+    (local $x i32) (local $y i32) (local $c i32)
+    (local.set $x (i32.load offset=8 (local.get $a)))
+    (local.set $y (i32.load offset=8 (local.get $b)))
+    (local.set $c (call $malloc (i32.add (i32.add (local.get $x) (local.get $y)) (i32.const 1))))
+    (memory.copy (local.get $c) (i32.load (local.get $a)) (local.get $x))
+    (memory.copy (i32.add (local.get $c) (local.get $x)) (i32.load (local.get $b)) (local.get $y))
+    (i32.store8 (i32.add (local.get $c) (i32.add (local.get $x) (local.get $y))) (i32.const 0))
+    (call $mudrt_str (local.get $sret) (local.get $c) (i32.add (local.get $x) (local.get $y))))
+  (func $mudrt_str_from_cstr (param $sret i32) (param $c i32)
+    (call $mudrt_str (local.get $sret) (local.get $c) (call $strlen (local.get $c))))
+  (func $mudrt_str_from_int (param $sret i32) (param $i i32)
+    (local $c i32) (local $len i32)
+    (local.set $c (call $malloc (i32.const 12)))
+    (local.set $len (call $mudrt_format_int (local.get $i) (local.get $c)))
+    (i32.store8 (i32.add (local.get $c) (local.get $len)) (i32.const 0))
+    (call $mudrt_str (local.get $sret) (local.get $c) (local.get $len)))
+  ;; an optional sign and the digits that follow it, anything after them is ignored
+  (func $mudrt_str_to_int (param $s i32) (result i32)
+    (local $ptr i32) (local $len i32) (local $i i32) (local $value i32) (local $sign i32) (local $c i32)
+    (local.set $ptr (i32.load (local.get $s)))
+    (local.set $len (i32.load offset=8 (local.get $s)))
+    (local.set $sign (i32.const 1))
+    (if (i32.gt_s (local.get $len) (i32.const 0))
+      (then
+        (local.set $c (i32.load8_u (local.get $ptr)))
+        (if (i32.or (i32.eq (local.get $c) (i32.const 45)) (i32.eq (local.get $c) (i32.const 43)))
+          (then
+            (if (i32.eq (local.get $c) (i32.const 45)) (then (local.set $sign (i32.const -1))))
+            (local.set $i (i32.const 1))))))
+    (block $done (loop $next
+      (br_if $done (i32.ge_s (local.get $i) (local.get $len)))
+      (local.set $c (i32.sub (i32.load8_u (i32.add (local.get $ptr) (local.get $i))) (i32.const 48)))
+      (br_if $done (i32.gt_u (local.get $c) (i32.const 9)))
+      (local.set $value (i32.add (i32.mul (local.get $value) (i32.const 10)) (local.get $c)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $next)))
+    (i32.mul (local.get $sign) (local.get $value)))
+"#;
+
+// the functions the runtime defines, externs of these names are not imported
+pub fn functions() -> Vec<&'static str> {
+    RUNTIME.split("(func $").skip(1).filter_map(|rest| rest.split([' ', ')']).next()).collect()
+}
@@ -0,0 +1,442 @@
+use std::fmt::Write;
+
+use crate::ir::*;
+
+use super::{classify, returns_memory, Location};
+
+// GNU as for x86-64 Linux, calls follow the System V ABI so C code can call and be called
+//
+// every temporary has 8 bytes in the frame and holds its value sign extended to 64 bits,
+// so temporaries move between registers and memory whole
+
+const ARGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+// the instruction that sign extends rax from the width of a type
+fn extend(ty: Type) -> Option<&'static str> {
+    match ty {
+        Type::I8 => Some("movsbq %al, %rax"),
+        Type::I32 => Some("cltq"),
+        _ => None,
+    }
+}
+
+pub fn emit(program: &Program) -> String {
+    let mut emitter = Emitter { program, out: String::new(), sret: 0 };
+
+    emitter.out.push_str("\t.text\n");
+    for (index, function) in program.functions.iter().enumerate() {
+        emitter.function(index, function);
+    }
+
+    for data in &program.data {
+        let is_constant = data.items.iter().all(|item| matches!(item, DataItem::Bytes(_)));
+        emitter.out.push_str(if is_constant { "\t.section .rodata\n" } else { "\t.data\n" });
+        emitter.out.push_str(&format!("\t.p2align 3\n{}:\n", data.name));
+        for item in &data.items {
+            match item {
+                DataItem::Bytes(bytes) => {
+                    let bytes = bytes.iter().map(u8::to_string).collect::<Vec<_>>();
+                    emitter.line(format!(".byte {}", bytes.join(", ")));
+                }
+                DataItem::Symbol(name) => emitter.line(format!(".quad {name}")),
+            }
+        }
+    }
+
+    emitter.out.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    emitter.out
+}
+
+struct Emitter<'a> {
+    program: &'a Program,
+    out: String,
+    // where the address a struct is returned to is kept
+    sret: i64,
+}
+
+impl Emitter<'_> {
+    fn line(&mut self, line: impl AsRef<str>) {
+        writeln!(self.out, "\t{}", line.as_ref()).unwrap();
+    }
+
+    fn temp(&self, temp: Temp) -> String {
+        format!("{}(%rbp)", -8 * (temp as i64 + 1))
+    }
+
+    fn load_temp(&mut self, temp: Temp, register: &str) {
+        self.line(format!("movq {}, {register}", self.temp(temp)));
+    }
+
+    fn store_temp(&mut self, temp: Temp) {
+        self.line(format!("movq %rax, {}", self.temp(temp)));
+    }
+
+    fn label(&self, function: usize, block: BlockId) -> String {
+        format!(".L{function}_{block}")
+    }
+
+    fn function(&mut self, index: usize, function: &Function) {
+        let program = self.program;
+        // slots and the copies of struct params passed in registers go below the temporaries
+        let mut size = 8 * function.temps.len() as i64;
+        let mut slots = Vec::new();
+        for &slot in &function.slots {
+            size += program.size(slot).next_multiple_of(8) as i64;
+            slots.push(-size);
+        }
+        size += 8;
+        self.sret = -size;
+        let arg_types = function.params.iter().map(|&(_, ty)| ty).collect::<Vec<_>>();
+        let (locations, _) = classify(program, &arg_types, function.return_type);
+        let mut copies = Vec::new();
+        for (&ty, location) in arg_types.iter().zip(&locations) {
+            if let (Type::Struct(_), Location::Registers(_)) = (ty, location) {
+                size += program.size(ty).next_multiple_of(8) as i64;
+                copies.push(-size);
+            }
+        }
+        let size = (size + 15) / 16 * 16;
+
+        if function.export || function.name == "main" {
+            writeln!(self.out, "\t.globl {}", function.name).unwrap();
+        }
+        writeln!(self.out, "\t.type {}, @function\n{}:", function.name, function.name).unwrap();
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        self.line(format!("subq ${size}, %rsp"));
+
+        if returns_memory(program, function.return_type) {
+            self.line(format!("movq %rdi, {}(%rbp)", self.sret));
+        }
+        let mut copies = copies.into_iter();
+        for (&(temp, ty), location) in function.params.iter().zip(&locations) {
+            match (ty, location) {
+                (Type::Struct(_), Location::Registers(registers)) => {
+                    let copy = copies.next().unwrap();
+                    for (word, &register) in registers.iter().enumerate() {
+                        self.line(format!("movq {}, {}(%rbp)", ARGS[register], copy + 8 * word as i64));
+                    }
+                    self.line(format!("leaq {copy}(%rbp), %rax"));
+                }
+                (Type::Struct(_), Location::Stack(offset)) => self.line(format!("leaq {}(%rbp), %rax", 16 + offset)),
+                (ty, location) => {
+                    match location {
+                        Location::Registers(registers) => self.line(format!("movq {}, %rax", ARGS[registers[0]])),
+                        Location::Stack(offset) => self.line(format!("movq {}(%rbp), %rax", 16 + offset)),
+                    }
+                    if let Some(extend) = extend(ty) {
+                        self.line(extend);
+                    }
+                }
+            }
+            self.store_temp(temp);
+        }
+
+        for (block, Block { insts, terminator }) in function.blocks.iter().enumerate() {
+            writeln!(self.out, "{}:", self.label(index, block)).unwrap();
+            for inst in insts {
+                self.inst(function, &slots, inst);
+            }
+            self.terminator(index, function, terminator);
+        }
+    }
+
+    fn inst(&mut self, function: &Function, slots: &[i64], inst: &Inst) {
+        let program = self.program;
+        match inst {
+            &Inst::Const { dest, value } => {
+                let value = match function.temps[dest] {
+                    Type::I8 => value as i8 as i64,
+                    Type::I32 => value as i32 as i64,
+                    _ => value,
+                };
+                self.line(format!("movabsq ${value}, %rax"));
+                self.store_temp(dest);
+            }
+            Inst::Symbol { dest, name } => {
+                self.address(name);
+                self.store_temp(*dest);
+            }
+            &Inst::Slot { dest, slot } => {
+                self.line(format!("leaq {}(%rbp), %rax", slots[slot]));
+                self.store_temp(dest);
+            }
+            &Inst::Load { dest, addr } => {
+                self.load_temp(addr, "%r11");
+                match function.temps[dest] {
+                    Type::I8 => self.line("movsbq (%r11), %rax"),
+                    Type::I32 => self.line("movslq (%r11), %rax"),
+                    _ => self.line("movq (%r11), %rax"),
+                }
+                self.store_temp(dest);
+            }
+            &Inst::Store { addr, value } => {
+                self.load_temp(addr, "%r11");
+                self.load_temp(value, "%rax");
+                match function.temps[value] {
+                    Type::I8 => self.line("movb %al, (%r11)"),
+                    Type::I32 => self.line("movl %eax, (%r11)"),
+                    _ => self.line("movq %rax, (%r11)"),
+                }
+            }
+            &Inst::Copy { dest, src, ty } => {
+                self.load_temp(src, "%rsi");
+                self.load_temp(dest, "%rdi");
+                self.line(format!("movq ${}, %rcx", program.size(ty)));
+                self.line("rep movsb");
+            }
+            &Inst::Zero { dest, ty } => {
+                self.load_temp(dest, "%rdi");
+                self.line("xorl %eax, %eax");
+                self.line(format!("movq ${}, %rcx", program.size(ty)));
+                self.line("rep stosb");
+            }
+            &Inst::Field { dest, base, ty, index } => {
+                self.load_temp(base, "%rax");
+                self.line(format!("addq ${}, %rax", program.offset(ty, index)));
+                self.store_temp(dest);
+            }
+            &Inst::Element { dest, base, ty, index } => {
+                self.load_temp(index, "%rax");
+                self.line(format!("imulq ${}, %rax, %rax", program.size(ty)));
+                self.line(format!("addq {}, %rax", self.temp(base)));
+                self.store_temp(dest);
+            }
+            &Inst::Unary { dest, op, value } => {
+                self.load_temp(value, "%rax");
+                match op {
+                    UnaryOp::Neg => self.line("negq %rax"),
+                    UnaryOp::Not => {
+                        self.line("testq %rax, %rax");
+                        self.line("sete %al");
+                        self.line("movzbq %al, %rax");
+                    }
+                }
+                if let Some(extend) = extend(function.temps[dest]) {
+                    self.line(extend);
+                }
+                self.store_temp(dest);
+            }
+            &Inst::Binary { dest, op, lhs, rhs } => {
+                self.load_temp(lhs, "%rax");
+                self.load_temp(rhs, "%rcx");
+                let set = match op {
+                    BinaryOp::Add => Some("addq %rcx, %rax"),
+                    BinaryOp::Sub => Some("subq %rcx, %rax"),
+                    BinaryOp::Mul => Some("imulq %rcx, %rax"),
+                    _ => None,
+                };
+                match set {
+                    Some(arithmetic) => self.line(arithmetic),
+                    None => {
+                        let condition = match op {
+                            BinaryOp::Lt => "l",
+                            BinaryOp::Gt => "g",
+                            BinaryOp::Eq => "e",
+                            _ => "ne",
+                        };
+                        self.line("cmpq %rcx, %rax");
+                        self.line(format!("set{condition} %al"));
+                        self.line("movzbq %al, %rax");
+                    }
+                }
+                if let Some(extend) = extend(function.temps[dest]) {
+                    self.line(extend);
+                }
+                self.store_temp(dest);
+            }
+            &Inst::Cast { dest, value } => {
+                match function.temps[dest] {
+                    Type::I8 => self.line(format!("movsbq {}, %rax", self.temp(value))),
+                    Type::I32 => self.line(format!("movslq {}, %rax", self.temp(value))),
+                    _ => self.load_temp(value, "%rax"),
+                }
+                self.store_temp(dest);
+            }
+            Inst::Call { dest, callee, args, fixed } => self.call(function, *dest, callee, args, fixed.is_some()),
+        }
+    }
+
+    // the address of a function or data symbol in rax, those of other objects through the GOT
+    fn address(&mut self, name: &str) {
+        match self.program.external(name) {
+            Some(Extern { header: Some(_), .. }) => {
+                self.line(format!("movq {name}@GOTPCREL(%rip), %rax"));
+                self.line("movq (%rax), %rax");
+            }
+            Some(_) => self.line(format!("movq {name}@GOTPCREL(%rip), %rax")),
+            None => self.line(format!("leaq {name}(%rip), %rax")),
+        }
+    }
+
+    // n bytes from offset of the memory at r11 into rax, without reading past them
+    fn load_bytes(&mut self, offset: u64, n: u64) {
+        if n >= 8 {
+            self.line(format!("movq {offset}(%r11), %rax"));
+            return;
+        }
+        self.line("xorl %eax, %eax");
+        for i in (0..n).rev() {
+            self.line("shlq $8, %rax");
+            self.line(format!("movzbl {}(%r11), %r10d", offset + i));
+            self.line("orq %r10, %rax");
+        }
+    }
+
+    // n bytes of register to offset of the memory at r11
+    fn store_bytes(&mut self, register: &str, offset: u64, n: u64) {
+        if n >= 8 {
+            self.line(format!("movq {register}, {offset}(%r11)"));
+            return;
+        }
+        self.line(format!("movq {register}, %r10"));
+        for i in 0..n {
+            self.line(format!("movb %r10b, {}(%r11)", offset + i));
+            self.line("shrq $8, %r10");
+        }
+    }
+
+    fn call(&mut self, function: &Function, dest: Dest, callee: &Callee, args: &[(Temp, Type)], variadic: bool) {
+        let program = self.program;
+        let return_type = match dest {
+            Dest::Memory(_, ty) => Some(ty),
+            _ => None,
+        };
+        let arg_types = args.iter().map(|&(_, ty)| ty).collect::<Vec<_>>();
+        let (locations, stack) = classify(program, &arg_types, return_type);
+
+        // arguments on the stack are written first, as copying structs there uses argument registers
+        let stack = stack.next_multiple_of(16);
+        if stack > 0 {
+            self.line(format!("subq ${stack}, %rsp"));
+        }
+        for (&(temp, ty), location) in args.iter().zip(&locations) {
+            let Location::Stack(offset) = location else { continue };
+            match ty {
+                Type::Struct(_) => {
+                    self.load_temp(temp, "%rsi");
+                    self.line(format!("leaq {offset}(%rsp), %rdi"));
+                    self.line(format!("movq ${}, %rcx", program.size(ty)));
+                    self.line("rep movsb");
+                }
+                _ => {
+                    self.load_temp(temp, "%rax");
+                    self.line(format!("movq %rax, {offset}(%rsp)"));
+                }
+            }
+        }
+        for (&(temp, ty), location) in args.iter().zip(&locations) {
+            let Location::Registers(registers) = location else { continue };
+            match ty {
+                Type::Struct(_) => {
+                    self.load_temp(temp, "%r11");
+                    let size = program.size(ty);
+                    for (word, &register) in registers.iter().enumerate() {
+                        let offset = 8 * word as u64;
+                        self.load_bytes(offset, (size - offset).min(8));
+                        self.line(format!("movq %rax, {}", ARGS[register]));
+                    }
+                }
+                _ => self.load_temp(temp, ARGS[registers[0]]),
+            }
+        }
+        if let Dest::Memory(addr, _) = dest {
+            if returns_memory(program, return_type) {
+                self.load_temp(addr, "%rdi");
+            }
+        }
+
+        // variadic callees learn how many vector registers hold arguments from al
+        match callee {
+            Callee::Direct(name) => match program.external(name) {
+                Some(Extern { header: Some(_), .. }) => {
+                    self.line(format!("movq {name}@GOTPCREL(%rip), %r10"));
+                    self.line("movq (%r10), %r10");
+                    if variadic {
+                        self.line("xorl %eax, %eax");
+                    }
+                    self.line("call *%r10");
+                }
+                external => {
+                    if variadic {
+                        self.line("xorl %eax, %eax");
+                    }
+                    match external {
+                        Some(_) => self.line(format!("call {name}@PLT")),
+                        None => self.line(format!("call {name}")),
+                    }
+                }
+            },
+            &Callee::Indirect(target) => {
+                self.load_temp(target, "%r10");
+                if variadic {
+                    self.line("xorl %eax, %eax");
+                }
+                self.line("call *%r10");
+            }
+        }
+        if stack > 0 {
+            self.line(format!("addq ${stack}, %rsp"));
+        }
+
+        match dest {
+            Dest::None => {}
+            Dest::Value(temp) => {
+                if let Some(extend) = extend(function.temps[temp]) {
+                    self.line(extend);
+                }
+                self.store_temp(temp);
+            }
+            Dest::Memory(_, ty) if returns_memory(program, Some(ty)) => {}
+            Dest::Memory(addr, ty) => {
+                self.load_temp(addr, "%r11");
+                let size = program.size(ty);
+                for (word, register) in ["%rax", "%rdx"].into_iter().enumerate() {
+                    let offset = 8 * word as u64;
+                    if offset < size {
+                        self.store_bytes(register, offset, (size - offset).min(8));
+                    }
+                }
+            }
+        }
+    }
+
+    fn terminator(&mut self, index: usize, function: &Function, terminator: &Terminator) {
+        let program = self.program;
+        match *terminator {
+            Terminator::Jump(block) => self.line(format!("jmp {}", self.label(index, block))),
+            Terminator::Branch { condition, then, otherwise } => {
+                self.load_temp(condition, "%rax");
+                self.line("testq %rax, %rax");
+                self.line(format!("jne {}", self.label(index, then)));
+                self.line(format!("jmp {}", self.label(index, otherwise)));
+            }
+            Terminator::Return(value) => {
+                match (value, function.return_type) {
+                    (Some(value), Some(ty @ Type::Struct(_))) if returns_memory(program, Some(ty)) => {
+                        self.load_temp(value, "%rsi");
+                        self.line(format!("movq {}(%rbp), %rdi", self.sret));
+                        self.line(format!("movq ${}, %rcx", program.size(ty)));
+                        self.line("rep movsb");
+                        self.line(format!("movq {}(%rbp), %rax", self.sret));
+                    }
+                    // the second eightbyte goes through rax to rdx first, as loading it uses rax
+                    (Some(value), Some(ty @ Type::Struct(_))) => {
+                        self.load_temp(value, "%r11");
+                        let size = program.size(ty);
+                        if size > 8 {
+                            self.load_bytes(8, size - 8);
+                            self.line("movq %rax, %rdx");
+                        }
+                        self.load_bytes(0, size.min(8));
+                    }
+                    (Some(value), _) => self.load_temp(value, "%rax"),
+                    (None, _) => {}
+                }
+                self.line("leave");
+                self.line("ret");
+            }
+            Terminator::Unreachable => self.line("ud2"),
+        }
+    }
+}
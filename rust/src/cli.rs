@@ -0,0 +1,275 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use crate::{backend, compiler, ir, lexer, parser, repl, trace, Compilation, Diagnostic, DiagnosticKind, Io, Target};
+use crate::lexer::error::{ErrorType, MudResult};
+
+const USAGE: &str = "\
+usage: mud <command> <file.mud> [options]
+
+commands:
+  build    compile the program into an executable, or with --header into a C library
+  run      build the program and run it, arguments after -- are passed on to it
+  check    check the program without building anything
+  emit     write one stage of the compiler, chosen with --emit
+  repl     start an interactive session
+
+options:
+  -o <path>                    where build and emit write, next to the input by default and stdout for emit
+  --emit=tokens|ast|c|ir       the stage emit writes
+  -O0, -O1, -O2                the optimization level, -O is -O1
+  --cc <compiler>              the C compiler that builds and links, gcc by default
+  --cflags <flags>             flags passed on to the C compiler
+  --asm, --llvm, --wasm        build through the native, LLVM or WebAssembly backend instead of C
+  --header                     build a library with a header for C code to link against
+  --interpret                  run in the interpreter instead of building
+  --trace=<spec>               report what the compiler does on stderr, like --trace=parse,codegen=trace,
+                               for the categories lex, parse, resolve and codegen at info, debug or trace.
+                               MUD_TRACE takes the same spec";
+
+// the mud command exits with these, a program it runs exits with its own code
+pub(crate) const EXIT_ERROR: i32 = 1;
+pub(crate) const EXIT_USAGE: i32 = 2;
+// what compiled programs exit with when they fault, the interpreter does the same
+pub(crate) const EXIT_RUNTIME_ERROR: i32 = 101;
+
+// the command line, parsed
+pub(crate) struct Options {
+    pub(crate) command: String,
+    pub(crate) input: String,
+    pub(crate) output: Option<String>,
+    pub(crate) emit: Option<String>,
+    pub(crate) target: Target,
+    pub(crate) header: bool,
+    pub(crate) interpret: bool,
+    pub(crate) trace: Option<String>,
+    pub(crate) build: backend::Options,
+    pub(crate) program_args: Vec<String>,
+}
+
+pub(crate) fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or("no command given")?;
+    let mut options = Options {
+        command, input: String::new(), output: None, emit: None, target: Target::C, header: false, interpret: false, trace: None,
+        build: backend::Options::default(), program_args: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| value.clone().or_else(|| args.next()).ok_or(format!("{name} expects a value"));
+
+        match &flag[..] {
+            "-o" => options.output = Some(value("-o")?),
+            "--emit" => options.emit = Some(value("--emit")?),
+            "--cc" => options.build.cc = value("--cc")?,
+            "--cflags" => options.build.cflags.extend(value("--cflags")?.split_whitespace().map(String::from)),
+            "--asm" => options.target = Target::Asm,
+            "--llvm" => options.target = Target::Llvm,
+            "--wasm" => options.target = Target::Wasm,
+            "--header" => options.header = true,
+            "--interpret" => options.interpret = true,
+            "--trace" => options.trace = Some(value("--trace")?),
+            "--" => options.program_args.extend(args.by_ref()),
+            flag if flag.starts_with("-O") => {
+                options.build.level = compiler::OptLevel::from_flag(flag).ok_or(format!("unknown optimization level {flag}"))?;
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+            _ if options.input.is_empty() => options.input = arg,
+            _ => return Err(format!("unexpected argument {arg}, only one file is compiled at a time")),
+        }
+    }
+
+    if options.input.is_empty() {
+        return Err(format!("{} expects a file to compile", options.command));
+    }
+
+    Ok(options)
+}
+
+// the path next to the input with another extension
+pub(crate) fn beside(input: &str, extension: &str) -> String {
+    Path::new(input).with_extension(extension).to_string_lossy().to_string()
+}
+
+fn write(path: &str, contents: impl AsRef<[u8]>) -> MudResult<()> {
+    fs::write(path, contents).map_err(|_| ErrorType::CompileError(format!("Unable to create file {path}")))
+}
+
+// a library for C code to link against, the C at output and with emit_header its header next to it
+pub(crate) fn compile_file(input: &str, output: &str, emit_header: bool, level: compiler::OptLevel) -> MudResult<()> {
+    let module_name = Path::new(output).file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let (program, header) = ir::library(&compiler::Sources::default(), input, level, &module_name)?;
+
+    for (_, contents) in backend::emit(&program, Target::C) {
+        write(output, contents)?;
+    }
+    if emit_header {
+        write(&beside(output, "h"), header)?;
+    }
+
+    Ok(())
+}
+
+fn compilation(options: &Options) -> Compilation {
+    let mut compilation = Compilation::new(&options.input[..]);
+    compilation.set_opt_level(options.build.level);
+    compilation
+}
+
+// the executable or module a build writes
+fn build(options: &Options) -> MudResult<String> {
+    let (input, level) = (&options.input[..], options.build.level);
+
+    if options.header {
+        let output = options.output.clone().unwrap_or_else(|| beside(input, "c"));
+        compile_file(input, &output, true, level)?;
+        return Ok(output);
+    }
+
+    let extension = if options.target == Target::Wasm { "wat" } else { "exe" };
+    let output = options.output.clone().unwrap_or_else(|| beside(input, extension));
+    let build = match options.target {
+        Target::C => backend::build_c,
+        Target::Asm => backend::build_asm,
+        Target::Llvm => backend::build_llvm,
+        Target::Wasm => backend::build_wat,
+    };
+    build(input, &output, &options.build)?;
+    Ok(output)
+}
+
+fn emit(options: &Options) -> Result<Vec<u8>, Diagnostic> {
+    let (input, level) = (&options.input[..], options.build.level);
+    let source = || fs::read(input).map_err(|_| ErrorType::CompileError(format!("Unable to open file {input}")));
+
+    match options.emit.as_deref() {
+        Some("tokens") => {
+            let mut lexer = lexer::Lexer::new(source()?);
+            let mut output = String::new();
+            loop {
+                let lexeme = lexer.next().map_err(|e| e.at(lexer.location()).in_file(input))?;
+                output += &format!("{lexeme:?}\n");
+                if let lexer::Lexeme::Eof = lexeme {
+                    return Ok(output.into_bytes());
+                }
+            }
+        }
+        Some("ast") => {
+            let program = parser::Parser::new(source()?).parse().map_err(|e| e.in_file(input))?;
+            let program = compiler::optimize(program, level);
+            Ok(format!("{program:#?}\n").into_bytes())
+        }
+        // the C build compiles, libraries come from the compiler rather than the IR
+        Some("c") if options.header => Ok(compilation(options).library()?.remove(0).contents),
+        Some("c") => Ok(compilation(options).emit(Target::C)?.remove(0).contents),
+        Some("ir") => Ok(compilation(options).ir()?.into_bytes()),
+        _ => unreachable!("the stage is checked before emitting"),
+    }
+}
+
+// prints why the program did not compile or run, and gives the exit code for it
+fn report(error: impl Into<Diagnostic>) -> i32 {
+    let diagnostic = error.into();
+    match diagnostic.kind {
+        // a fault of the interpreted program reads like one of the compiled program
+        DiagnosticKind::Runtime => {
+            let message = diagnostic.to_string();
+            eprintln!("{}", message.strip_prefix("runtime error: ").unwrap_or(&message));
+            EXIT_RUNTIME_ERROR
+        }
+        _ => {
+            eprintln!("{diagnostic}");
+            EXIT_ERROR
+        }
+    }
+}
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("mud: {message}\n\n{USAGE}");
+    EXIT_USAGE
+}
+
+pub(crate) fn run(options: Options) -> i32 {
+    match &options.command[..] {
+        "check" => compilation(&options).check().map_or_else(report, |_| 0),
+        "build" => build(&options).map_or_else(report, |_| 0),
+        "emit" => {
+            if !matches!(options.emit.as_deref(), Some("tokens" | "ast" | "c" | "ir")) {
+                return usage_error("emit expects --emit=tokens, --emit=ast, --emit=c or --emit=ir");
+            }
+            let output = match emit(&options) {
+                Ok(output) => output,
+                Err(error) => return report(error),
+            };
+            let written = match &options.output {
+                Some(path) => write(path, output),
+                None => std::io::Write::write_all(&mut std::io::stdout(), &output)
+                    .map_err(|_| ErrorType::CompileError("Unable to write to stdout".to_string())),
+            };
+            written.map_or_else(report, |_| 0)
+        }
+        "run" if options.interpret => {
+            match compilation(&options).interpret(&options.program_args, Io::std()) {
+                Ok(code) => code,
+                Err(error) => report(error),
+            }
+        }
+        "run" => {
+            if options.header || options.target == Target::Wasm {
+                return usage_error("run needs an executable, which --header and --wasm do not build");
+            }
+            let executable = match build(&options) {
+                Ok(executable) => executable,
+                Err(error) => return report(error),
+            };
+            // a bare file name would be looked up on the PATH
+            let executable = match Path::new(&executable).components().count() {
+                1 => format!("./{executable}"),
+                _ => executable,
+            };
+            match Command::new(&executable).args(&options.program_args).status() {
+                Ok(status) => exit_code(status),
+                Err(_) => report(ErrorType::CompileError(format!("Unable to run {executable}"))),
+            }
+        }
+        command => usage_error(&format!("unknown command {command}")),
+    }
+}
+
+// the code a program exited with, one killed by a signal exits the way a shell reports it
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(EXIT_RUNTIME_ERROR)
+}
+
+// runs the mud command with its arguments, giving the code to exit with
+pub fn main(args: Vec<String>) -> i32 {
+    if let Ok(spec) = std::env::var("MUD_TRACE") {
+        if let Err(message) = trace::enable(&spec) {
+            return usage_error(&format!("MUD_TRACE: {message}"));
+        }
+    }
+
+    match args.first().map(String::as_str) {
+        Some("repl") => repl::run(),
+        Some("help" | "-h" | "--help") => {
+            println!("{USAGE}");
+            0
+        }
+        _ => match parse_args(args) {
+            Ok(options) => match options.trace.as_deref().map(trace::enable) {
+                Some(Err(message)) => usage_error(&message),
+                _ => run(options),
+            },
+            Err(message) => usage_error(&message),
+        },
+    }
+}
@@ -1,17 +1,131 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use crate::parser::*;
 use crate::lexer::error::{MudResult, ErrorType};
+use crate::trace::trace;
+
+mod closures;
+mod const_eval;
+mod ffi;
+mod format;
+mod function_types;
+mod generics;
+mod interfaces;
+mod methods;
+mod names;
+mod modules;
+mod optimize;
+mod repl;
+mod runtime;
+mod slices;
+mod sources;
+mod strings;
+mod stdlib;
+pub(crate) mod typed;
+use const_eval::ConstValue;
+use closures::{Borrow, ClosureFrame};
+use modules::Module;
+use generics::GenericDef;
+use interfaces::{Impl, InterfaceDef};
+use methods::MethodDef;
+use typed::{Expr, ExprKind, FunctionId, LocalId};
+
+// the parts of the compiler the interpreter shares, so both agree on what a program means
+pub(crate) use ffi::{extern_c_type, header, libc_header};
+pub(crate) use format::Piece;
+use names::module_prefix;
+pub(crate) use optimize::optimize;
+pub(crate) use repl::Statement;
+pub use optimize::OptLevel;
+pub use sources::Sources;
+pub(crate) use slices::view_fields;
+pub(crate) use strings::unescape;
+pub(crate) use runtime::{READ_FILE, RUNTIME};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Clone)]
 pub enum ValueType {
     I32,
     U8,
+    // C's size_t, only for calling C, it converts to and from i32 where it is used
+    Usize,
     Void,
     Pointer(Box<ValueType>),
     Unknown,
-    Function { args: Vec<ValueType>, return_type: Box<ValueType> },
-    Struct (HashMap<String, ValueType>),
+    Function { args: Vec<ValueType>, return_type: Box<ValueType>, variadic: bool },
+    // structs are told apart by the symbol of their declaration, anonymous ones have none
+    Struct { symbol: String, fields: Vec<(String, ValueType)> },
+    Opaque (String),
+    Module (String),
+    Str,
+    Slice (Box<ValueType>),
+    Param (String),
+    Generic (String),
+    Interface (String),
+    Closure { args: Vec<ValueType>, return_type: Box<ValueType> },
+}
+
+// a named struct is the same type wherever it is referred to, even as the fieldless stand-in its own fields refer to
+impl PartialEq for ValueType {
+    fn eq(&self, other: &Self) -> bool {
+        use ValueType::*;
+        match (self, other) {
+            (Struct { symbol: a, fields: a_fields }, Struct { symbol: b, fields: b_fields }) => match a.is_empty() && b.is_empty() {
+                true => a_fields == b_fields,
+                false => a == b,
+            },
+            (Pointer(a), Pointer(b)) | (Slice(a), Slice(b)) => a == b,
+            (Function { args: a, return_type: a_return, variadic: a_variadic }, Function { args: b, return_type: b_return, variadic: b_variadic }) => {
+                a == b && a_return == b_return && a_variadic == b_variadic
+            }
+            (Closure { args: a, return_type: a_return }, Closure { args: b, return_type: b_return }) => a == b && a_return == b_return,
+            (Opaque(a), Opaque(b)) | (Module(a), Module(b)) | (Param(a), Param(b)) | (Generic(a), Generic(b)) | (Interface(a), Interface(b)) => a == b,
+            (I32, I32) | (U8, U8) | (Usize, Usize) | (Void, Void) | (Unknown, Unknown) | (Str, Str) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ValueType {}
+
+impl ValueType {
+    // sizes and alignments follow the C layout rules of the x86-64 targets we emit for
+    pub fn size(&self) -> MudResult<u64> {
+        match self {
+            ValueType::I32 => Ok(4),
+            ValueType::U8 => Ok(1),
+            ValueType::Usize | ValueType::Pointer(_) | ValueType::Function { .. } => Ok(8),
+            ValueType::Str | ValueType::Slice(_) | ValueType::Interface(_) => Ok(16),
+            ValueType::Closure { .. } => Ok(24),
+            // type parameters only have a layout once instantiated, this one is for checking generic code
+            ValueType::Param(_) => Ok(1),
+            ValueType::Struct { fields, .. } => {
+                let mut size: u64 = 0;
+                for (_, field) in fields {
+                    let align = field.align()?;
+                    size = size.div_ceil(align) * align + field.size()?;
+                }
+                Ok(size.div_ceil(self.align()?) * self.align()?)
+            }
+            ValueType::Opaque(name) | ValueType::Module(name) => Err(ErrorType::CompileError(format!("Type {name} has no size"))),
+            ValueType::Generic(_) => Err(ErrorType::CompileError("A generic type has no size without its type arguments".to_string())),
+            ValueType::Void | ValueType::Unknown => Err(ErrorType::CompileError("Type void has no size".to_string())),
+        }
+    }
+
+    pub fn align(&self) -> MudResult<u64> {
+        match self {
+            ValueType::Struct { fields, .. } => {
+                let mut align = 1;
+                for (_, field) in fields {
+                    align = align.max(field.align()?);
+                }
+                Ok(align)
+            }
+            ValueType::Str | ValueType::Slice(_) | ValueType::Interface(_) | ValueType::Closure { .. } => Ok(8),
+            t => t.size(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -19,9 +133,13 @@ pub enum ExprType {
     Literal,
     FunctionLiteral { args: Vec<Expression>, return_type: Box<Expression>, body: Box<Expression> },
     StructLiteral {fields: Vec<Expression>},
-    Identifier,
+    Identifier(String),
     Type,
     Expression,
+    // a closure that must not outlive what it borrows
+    Borrow(Borrow),
+    // the method at this index of an interface, called through the vtable of the receiver
+    Dynamic(usize),
 }
 
 #[derive(Clone, Debug)]
@@ -32,92 +150,211 @@ pub struct  Type {
 
 #[derive(Debug, Clone)]
 struct CompiledAtom {
-    source: String,
+    // what the atom evaluates to, types and names that are not values have none
+    node: Option<Expr>,
     atom_type: Type,
 }
 
+#[derive(Clone)]
 pub struct Compiler {
     scope_stack: Vec<HashMap<String, ValueType>>,
-    forward_decls: String,
+    // the globals of the module that are values, such as functions and constants
+    values: HashMap<String, Expr>,
+    // the local each name in scope stands for, by the depth of its scope
+    local_ids: HashMap<(usize, String), LocalId>,
+    program: typed::Program,
     is_decl: bool,
+    is_export: bool,
+    constants: HashMap<String, ConstValue>,
+    modules: HashMap<String, Module>,
+    import_stack: Vec<PathBuf>,
+    public: Vec<(String, bool)>,
+    module_prefix: String,
+    current_function: Option<FunctionId>,
+    current_return: Option<ValueType>,
+    generics: HashMap<String, GenericDef>,
+    // each instance of a generic by its type arguments, and the function of an instance of a generic fn
+    instances: HashMap<String, (ValueType, Option<FunctionId>)>,
+    type_params: HashMap<String, ValueType>,
+    checking_generic: bool,
+    interfaces: HashMap<String, InterfaceDef>,
+    impls: Vec<Impl>,
+    param_bounds: HashMap<String, String>,
+    methods: Vec<MethodDef>,
+    closure_frames: Vec<ClosureFrame>,
+    borrows: HashMap<(usize, String), Borrow>,
+    // the fields of each named struct by its symbol, for the values reached through the pointers in its own fields
+    structs: HashMap<String, Vec<(String, ValueType)>>,
+    opt_level: OptLevel,
+    sources: Sources,
+    // the file being compiled as it was given, for diagnostics
+    file: String,
 }
 
 impl CompiledAtom {
-    fn new(source: String, value: ValueType, expr: ExprType) -> Self {
+    fn new(node: Option<Expr>, value: ValueType, expr: ExprType) -> Self {
         Self {
-            source,
+            node,
             atom_type: Type { value, expr },
         }
     }
+
+    fn expr(kind: ExprKind, value: ValueType) -> Self {
+        Self::new(Some(Expr::new(kind, value.clone())), value, ExprType::Expression)
+    }
+
+    fn of_type(value: ValueType) -> Self {
+        Self::new(None, value, ExprType::Type)
+    }
+
+    fn void() -> Self {
+        Self::expr(ExprKind::Void, ValueType::Void)
+    }
 }
 
-macro_rules! program_fmt {
-    () => ("#include <stdio.h>\n\
-            #include <stdlib.h>\n\
-            typedef int i32;\n\
-            char* read_file(char *filename){{\n\
-                char * buffer = 0;\n\
-                long length;\n\
-                FILE * f = fopen (filename, \"rb\");\n\
-                if (f)\n\
-                {{\n\
-                    fseek (f, 0, SEEK_END);\n\
-                    length = ftell (f);\n\
-                    fseek (f, 0, SEEK_SET);\n\
-                    buffer = malloc (length);\n\
-                    if (buffer)\n\
-                    {{\n\
-                        fread (buffer, 1, length, f);\n\
-                    }}\n\
-                    fclose (f);\n\
-                }}\n\
-                return buffer;\n\
-            }}\n\
-            {}\n\
-            {}");
+// what an atom is, for diagnostics about atoms that are not values or names
+fn describe(expr: &ExprType) -> String {
+    match expr {
+        ExprType::Literal => "a literal".to_string(),
+        ExprType::FunctionLiteral { .. } => "a fn literal".to_string(),
+        ExprType::StructLiteral { .. } => "a struct literal".to_string(),
+        ExprType::Identifier(name) => name.clone(),
+        ExprType::Borrow(_) => "a closure".to_string(),
+        ExprType::Type => "a type".to_string(),
+        ExprType::Expression => "an expression".to_string(),
+        ExprType::Dynamic(_) => "an interface method".to_string(),
+    }
+}
+
+// the C runtime calls main, so it can only take the program arguments and must return the exit code
+fn is_main_signature(args: &[ValueType], return_type: &ValueType) -> bool {
+    let takes_args = match args {
+        [] => true,
+        [ValueType::Slice(element)] => **element == ValueType::Str,
+        _ => false,
+    };
+
+    takes_args && *return_type == ValueType::I32
+}
+
+// a statement whose value nobody uses
+fn statement(expr: Expr) -> Expr {
+    match expr.value_type {
+        ValueType::Void => expr,
+        _ => Expr::new(ExprKind::Convert(typed::Conversion::Discard, Box::new(expr)), ValueType::Void),
+    }
 }
 
 impl Compiler {
     pub fn new() -> Self {
-        let mut globals = HashMap::new();
+        let program = typed::Program { externs: Compiler::builtins(), ..typed::Program::default() };
 
-        globals.insert("calloc".to_string(), ValueType::Function { args: vec![ValueType::I32], return_type: Box::new(ValueType::Pointer(Box::new(ValueType::Void))) });
-        globals.insert("read_file".to_string(), ValueType::Function { args: vec![ValueType::Pointer(Box::new(ValueType::U8))], return_type: Box::new(ValueType::Pointer(Box::new(ValueType::U8))) });
+        Self { scope_stack: vec![Compiler::globals()], values: Compiler::global_values(), local_ids: HashMap::new(), program, is_decl:false, is_export: false,
+               constants: HashMap::new(), modules: HashMap::new(), import_stack: Vec::new(), public: Vec::new(), module_prefix: String::new(),
+               current_function: None, current_return: None, generics: HashMap::new(), instances: HashMap::new(), type_params: HashMap::new(), checking_generic: false,
+               interfaces: HashMap::new(), impls: Vec::new(), param_bounds: HashMap::new(), methods: Vec::new(),
+               closure_frames: Vec::new(), borrows: HashMap::new(), structs: HashMap::new(), opt_level: OptLevel::O0, sources: Sources::default(), file: String::new() }
+    }
+
+    // the C functions every module can call, they are the first externs of every program
+    fn builtins() -> Vec<typed::Extern> {
+        let c_string = ValueType::Pointer(Box::new(ValueType::U8));
+
+        vec![
+            typed::Extern {
+                name: "calloc".to_string(), value_type: ValueType::Function { args: vec![ValueType::Usize, ValueType::Usize], return_type: Box::new(ValueType::Pointer(Box::new(ValueType::Void))), variadic: false },
+                header: None, include: Some("stdlib.h".to_string()),
+            },
+            typed::Extern { name: "read_file".to_string(), value_type: ValueType::Function { args: vec![c_string.clone()], return_type: Box::new(c_string), variadic: false }, header: None, include: None },
+        ]
+    }
+
+    // the global scope every module starts with
+    fn globals() -> HashMap<String, ValueType> {
+        Compiler::builtins().into_iter().map(|builtin| (builtin.name, builtin.value_type)).collect()
+    }
+
+    fn global_values() -> HashMap<String, Expr> {
+        Compiler::builtins().into_iter().enumerate()
+            .map(|(id, builtin)| (builtin.name, Expr::new(ExprKind::Extern(id), builtin.value_type)))
+            .collect()
+    }
+
+    pub fn set_opt_level(&mut self, level: OptLevel) {
+        self.opt_level = level;
+    }
 
-        Self { scope_stack: vec![globals], forward_decls: String::new(), is_decl:false}
+    // the files imports are read from, set before the file itself
+    pub fn set_sources(&mut self, sources: Sources) {
+        self.sources = sources;
     }
 
-    pub fn compile_full(&mut self, program: Vec<u8>) -> MudResult<Vec<u8>>{
-        let output = self.compile(program)?;
+    // checks a program and gives it back typed, as the lowering reads it
+    pub fn check(&mut self, program: Vec<u8>) -> MudResult<typed::Program> {
+        self.compile(program)?;
         assert!(self.scope_stack.len() == 1);
+        trace!(Codegen, Info, "{} functions, {} externs", self.program.functions.len(), self.program.externs.len());
+
+        Ok(std::mem::take(&mut self.program))
+    }
 
-        Ok(format!(program_fmt!(), self.forward_decls, String::from_utf8(output).unwrap()).into_bytes())
+    pub fn compile(&mut self, program: Vec<u8>) -> MudResult<()> {
+        let file = self.file.clone();
+        self.compile_program(program).map_err(|e| e.in_file(&file))
     }
 
-    pub fn compile(&mut self, program: Vec<u8>) -> MudResult<Vec<u8>> {
+    fn compile_program(&mut self, program: Vec<u8>) -> MudResult<()> {
         let mut parser = Parser::new(program);
         let expression = parser.parse()?;
 
-        let output = self.convert(expression)?.source.as_bytes().to_owned();
-        Ok(output)
-    }
+        // optimizing drops code that never runs, which still has to check, so the program is checked as written first
+        if self.opt_level > OptLevel::O0 {
+            let mut checker = Compiler::new();
+            checker.sources = self.sources.clone();
+            checker.import_stack = self.import_stack.clone();
+            checker.file = self.file.clone();
+            checker.convert(expression.clone())?;
+        }
+        let expression = optimize(expression, self.opt_level);
 
-    fn struct_assign(&mut self, lhs: CompiledAtom, rhs: Expression) -> MudResult<CompiledAtom>{
-        todo!("Havent implemented this yet");
+        self.convert(expression)?;
+        Ok(())
     }
 
     fn binary_op_transpile(&mut self, op: Operator, lhs: Expression, rhs: Expression) -> MudResult<CompiledAtom> {
 
-        self.is_decl = op == Operator::Colon;
-        
+        // `*module.Type` in a declaration is still a type
+        if op != Operator::Dot {
+            self.is_decl = op == Operator::Colon;
+        }
+
         let lhs = self.convert(lhs)?;
-        if let ExprType::Identifier = lhs.atom_type.expr {
-            if let Expression::Block(inner) = rhs {
-                return self.struct_assign(lhs, *inner);
+        if let (Operator::ColonEquals, ExprType::Identifier(name), Expression::Generic { params, body }) = (op, &lhs.atom_type.expr, &rhs) {
+            return self.generic_definition(name.clone(), params.clone(), (**body).clone());
+        }
+        if let (Operator::ColonEquals, ExprType::Identifier(name), Expression::Interface { methods }) = (op, &lhs.atom_type.expr, &rhs) {
+            return self.interface_definition(name.clone(), methods.clone());
+        }
+        if let (Operator::ColonEquals, ExprType::Identifier(name), Expression::Function { args, return_type, body }) = (op, &lhs.atom_type.expr, &rhs) {
+            if self.scope_stack.len() != 1 {
+                return self.nested_function(name.clone(), args.clone(), (**return_type).clone(), (**body).clone());
             }
         }
+        // there are no struct literals, a block is not a value
+        if let (ExprType::Identifier(name), Expression::Block(_)) = (&lhs.atom_type.expr, &rhs) {
+            return Err(ErrorType::CompileError(format!("Cannot use a block as the value of {name}")));
+        }
+        if op == Operator::ColonEquals && !matches!(rhs, Expression::Function { .. } | Expression::Struct { .. }) {
+            return self.constant(lhs, rhs);
+        }
         let rhs = self.convert(rhs)?;
 
+        if matches!(op, Operator::Plus | Operator::DoubleEquals | Operator::ExclaimEquals | Operator::LessThan | Operator::GreaterThan)
+            && (self.resolve_type(&lhs)? == ValueType::Str || self.resolve_type(&rhs)? == ValueType::Str
+                || strings::is_literal(&lhs) && strings::is_literal(&rhs)) {
+            return self.str_operation(op, lhs, rhs);
+        }
+
         match op {
             Operator::Plus => self.add(lhs, rhs),
             Operator::Minus => self.sub(lhs, rhs),
@@ -134,39 +371,32 @@ impl Compiler {
             Operator::ColonEquals=> self.assign_func_struct_const(lhs, rhs),
             Operator::Dot=> self.dot(lhs, rhs),
 
-            _ => Err(ErrorType::CompileError(format!("Binary operator {:?} cannot be transpiled", op))),
+            _ => Err(ErrorType::CompileError(format!("Binary operator {op} cannot be transpiled"))),
         }
     }
 
-    fn unary_op_transpile(&mut self, op: Operator, oprand: Expression) -> MudResult<CompiledAtom> {
+    fn unary_op_transpile(&mut self, op: Operator, oprand: Expression, location: Location) -> MudResult<CompiledAtom> {
         let oprand = self.convert(oprand)?;
 
-        dbg!(&oprand.atom_type.expr);
-        dbg!(&oprand.atom_type);
+        trace!(Codegen, Trace, "unary {op:?} on {:?}", oprand.atom_type);
         match (&oprand.atom_type.expr, &op, self.is_decl) {
             (ExprType::Type, _, _ ) => {
                 match op {
                     Operator::Asterisk => self.pointer_type(oprand),
-                    _ => {
-                        dbg!(oprand);
-                        Err(ErrorType::CompileError(format!("Unary operator {:?} on type cannot be transpiled", op)))
-                    },
+                    _ => Err(ErrorType::CompileError(format!("Unary operator {op} on type cannot be transpiled"))),
                 }
             },
-            (ExprType::Identifier, Operator::Asterisk, true) => {
-                // dbg!(&self.resolve_type(&oprand));
-                // dbg!(oprand.source);
-                // Err(ErrorType::CompileError(format!("Unary operator {:?} on type cannot be transpiled", op)))
+            (ExprType::Identifier(_), Operator::Asterisk, true) => {
                 self.pointer_type(oprand)
             },
             _ => {
                 match op {
                     Operator::Exclaim => self.not(oprand),
                     Operator::Minus => self.negate(oprand),
-                    Operator::LessThan => self.print(oprand),
-                    Operator::Asterisk => self.deref(oprand),
+                    Operator::LessThan => self.print(oprand, location),
+                    Operator::Asterisk => self.deref(oprand, location),
                     Operator::Ampersand => self.adressof(oprand),
-                    _ => Err(ErrorType::CompileError(format!("Unary operator {:?} cannot be transpiled", op))),
+                    _ => Err(ErrorType::CompileError(format!("Unary operator {op} cannot be transpiled"))),
                 }
             }
         }
@@ -174,96 +404,222 @@ impl Compiler {
 
     fn block(&mut self, expression: Expression) -> MudResult<CompiledAtom> {
         self.scope_stack.push(HashMap::new());
-        let atom = CompiledAtom { source: format!("{{\n{};\n}}", self.convert(expression)?.source), atom_type: Type { value: ValueType::Void, expr: ExprType::Expression } };
+        let body = self.convert(expression).and_then(|body| self.value(body));
         self.scope_stack.pop();
-        Ok(atom)
-    }
 
-    fn supressed_block(&mut self, expression: Expression) -> MudResult<CompiledAtom> {
-        // scope has been created for us
-        let atom = CompiledAtom { source: format!("{{\n{};\n}}", self.convert(expression)?.source), atom_type: Type { value: ValueType::Void, expr: ExprType::Expression } };
-        self.scope_stack.pop();
-        Ok(atom)
+        let body = statement(body?);
+        Ok(CompiledAtom::new(Some(body), ValueType::Void, ExprType::Expression))
     }
 
     fn if_else(&mut self, condition: Expression, on_if: Expression, on_else: Expression) -> MudResult<CompiledAtom> {
-        Ok(CompiledAtom {
-            source: format!("if ({}) {} else {}", self.convert(condition)?.source, self.convert(on_if)?.source, self.convert(on_else)?.source),
-            atom_type: Type { value: ValueType::Void, expr: ExprType::Expression
-        }})
+        let condition = self.convert(condition)?;
+        let condition = self.value(condition)?;
+        let then = self.convert(on_if)?;
+        let then = statement(self.value(then)?);
+        let otherwise = self.convert(on_else)?;
+        let otherwise = statement(self.value(otherwise)?);
+
+        Ok(CompiledAtom::expr(ExprKind::If { condition: Box::new(condition), then: Box::new(then), otherwise: Box::new(otherwise) }, ValueType::Void))
     }
 
     fn while_loop(&mut self, condition: Expression, body: Expression) -> MudResult<CompiledAtom> {
-        Ok(CompiledAtom {
-            source: format!("while ({}) {}", self.convert(condition)?.source, self.convert(body)?.source),
-            atom_type: Type { value: ValueType::Void, expr: ExprType::Expression
-        }})
+        let condition = self.convert(condition)?;
+        let condition = self.value(condition)?;
+        let body = self.convert(body)?;
+        let body = statement(self.value(body)?);
+
+        Ok(CompiledAtom::expr(ExprKind::While { condition: Box::new(condition), body: Box::new(body) }, ValueType::Void))
     }
 
     fn function(&mut self, args: Vec<Expression>, return_type: Box<Expression>, body: Box<Expression>) -> MudResult<CompiledAtom> {
-        Ok(CompiledAtom {
-            source: "_mud_uncompiled_function".to_string(),
-            atom_type: Type { value: ValueType::Unknown, expr: ExprType::FunctionLiteral { args, return_type, body } },
-        })
+        // inside a function a fn literal is a value, at the top level it is named with :=
+        if self.scope_stack.len() != 1 {
+            return self.closure(None, args, *return_type, *body);
+        }
+
+        Ok(CompiledAtom::new(None, ValueType::Unknown, ExprType::FunctionLiteral { args, return_type, body }))
     }
 
     fn r#struct(&mut self, fields: Vec<Expression>) -> MudResult<CompiledAtom>{
-        Ok(CompiledAtom {
-            source: "_mud_uncompiled_struct".to_string(),
-            atom_type: Type { value: ValueType::Unknown, expr: ExprType::StructLiteral {fields}},
-        })
+        Ok(CompiledAtom::new(None, ValueType::Unknown, ExprType::StructLiteral {fields}))
     }
 
-    fn function_call(&mut self, function: Expression, args: Vec<Expression>) -> MudResult<CompiledAtom> {
-        let function = self.convert(function)?;
+    fn function_call(&mut self, function: Expression, args: Vec<Expression>, location: Location) -> MudResult<CompiledAtom> {
+        if let Expression::Identifier(name) = &function {
+            if self.is_builtin(name) {
+                return self.builtin_call(name.clone(), args, location);
+            }
+        }
 
-        let mut source = String::new();
+        // `value.method(args)` passes the value as the first argument, `Type.method(args)` does not
+        let mut arg_atoms = Vec::new();
+        let mut callee_name = match &function {
+            Expression::Identifier(name) => name.clone(),
+            _ => "the function".to_string(),
+        };
+        let mut function = match function {
+            Expression::BinaryOperation { op: Operator::Dot, lhs, rhs } => {
+                let receiver = self.convert(*lhs)?;
+                let method = match &*rhs {
+                    Expression::Identifier(name) => {
+                        callee_name = name.clone();
+                        self.method(&receiver, name, location)?
+                    }
+                    _ => None,
+                };
 
-        match self.resolve_type(&function)? { // NOTE: not type checking here at all
-            ValueType::Function { args: _, return_type } => {
-                source.push_str(&function.source);
-                source.push('(');
+                match method {
+                    Some((method, receiver)) => {
+                        arg_atoms.extend(receiver);
+                        method
+                    }
+                    None => {
+                        let rhs = self.convert(*rhs)?;
+                        self.dot(receiver, rhs)?
+                    }
+                }
+            }
+            function => self.convert(function)?,
+        };
+        for arg in args {
+            arg_atoms.push(self.convert(arg)?);
+        }
+
+        if let ValueType::Generic(key) = self.resolve_type(&function)? {
+            function = self.infer_instance(&key, &arg_atoms)?;
+        }
+
+        let (arg_types, return_type, variadic) = match self.resolve_type(&function)? {
+            ValueType::Function { args, return_type, variadic } => (args, return_type, variadic),
+            ValueType::Closure { args, return_type } => (args, return_type, false),
+            t => return Err(ErrorType::CompileError(format!("Cannot call a {}", self.type_name(&t)))),
+        };
 
-                let mut delim = ' ';
+        if arg_types.len() != arg_atoms.len() && !(variadic && arg_atoms.len() > arg_types.len()) {
+            return Err(ErrorType::CompileError(format!("Function {} expects {} arguments but got {}", callee_name, arg_types.len(), arg_atoms.len())));
+        }
+
+        let mut args = Vec::new();
+        for (i, arg) in arg_atoms.into_iter().enumerate() {
+            let arg = match arg_types.get(i) {
+                Some(arg_type) => self.coerce(arg, arg_type)?,
+                // C promotes the arguments after the params of a variadic fn
+                None if self.resolve_type(&arg)? == ValueType::U8 => self.coerce(arg, &ValueType::I32)?,
+                None => arg,
+            };
+            args.push(self.value(arg)?);
+        }
+
+        let kind = match function.atom_type.expr {
+            ExprType::Dynamic(method) => ExprKind::Dynamic { method, args, location },
+            _ => ExprKind::Call { callee: Box::new(self.value(function)?), args, location },
+        };
+        Ok(CompiledAtom::expr(kind, *return_type))
+    }
+
+    // builtins that are not C functions, they are only used when not shadowed by a user definition
+    fn is_builtin(&self, name: &str) -> bool {
+        matches!(name, "sizeof" | "alignof" | "alloc" | "free" | "print" | "println" | "len" | "exit" | "read_line" | "read_all" | "str" | "i32" | "cstr")
+            && !self.scope_stack.iter().any(|scope| scope.contains_key(name))
+    }
 
-                for arg in args {
-                    source.push(delim);
-                    source.push_str(&self.convert(arg)?.source);
-                    delim=',';
+    fn builtin_call(&mut self, name: String, mut args: Vec<Expression>, location: Location) -> MudResult<CompiledAtom> {
+        if name == "print" || name == "println" {
+            return self.format_print(&name, args, location);
+        }
+
+        let expected_args = match &name[..] {
+            "alloc" => 2,
+            "read_all" => 0,
+            _ => 1,
+        };
+        if args.len() != expected_args {
+            return Err(ErrorType::CompileError(format!("{name} expects {expected_args} arguments but got {}", args.len())));
+        }
+
+        let builtin = |builtin, args, value_type| CompiledAtom::expr(ExprKind::Builtin { builtin, args, location }, value_type);
+
+        match &name[..] {
+            "sizeof" => {
+                let size = self.type_expr(args.remove(0))?.size()?;
+                Ok(CompiledAtom::new(Some(Expr::new(ExprKind::Integer(size as i64), ValueType::I32)), ValueType::I32, ExprType::Literal))
+            }
+            "alignof" => {
+                let align = self.type_expr(args.remove(0))?.align()?;
+                Ok(CompiledAtom::new(Some(Expr::new(ExprKind::Integer(align as i64), ValueType::I32)), ValueType::I32, ExprType::Literal))
+            }
+            "alloc" => {
+                let value_type = self.type_expr(args.remove(0))?;
+                let count = self.convert(args.remove(0))?;
+
+                match self.resolve_type(&count)? {
+                    ValueType::I32 | ValueType::U8 => {
+                        let size = value_type.size()?;
+                        let count = self.coerce(count, &ValueType::I32)?;
+                        Ok(builtin(typed::Builtin::Alloc(size), vec![self.value(count)?], ValueType::Pointer(Box::new(value_type))))
+                    }
+                    t => Err(ErrorType::CompileError(format!("Cannot allocate {} elements", self.type_name(&t)))),
                 }
+            }
+            "free" => {
+                let pointer = self.convert(args.remove(0))?;
 
-                source.push(')');
+                match self.resolve_type(&pointer)? {
+                    ValueType::Pointer(_) => Ok(builtin(typed::Builtin::Free, vec![self.value(pointer)?], ValueType::Void)),
+                    t => Err(ErrorType::CompileError(format!("Cannot free type {}", self.type_name(&t)))),
+                }
+            }
+            "len" => self.len(args.remove(0)),
+            "str" | "i32" | "cstr" => self.str_conversion(&name, args.remove(0), location),
+            "exit" => {
+                let code = self.convert(args.remove(0))?;
+
+                match self.resolve_type(&code)? {
+                    ValueType::I32 | ValueType::U8 => {
+                        let code = self.coerce(code, &ValueType::I32)?;
+                        Ok(builtin(typed::Builtin::Exit, vec![self.value(code)?], ValueType::Void))
+                    }
+                    t => Err(ErrorType::CompileError(format!("Exit code must be an integer but is {}", self.type_name(&t)))),
+                }
+            }
+            // read_line(&line) is 0 once stdin is exhausted
+            "read_line" => {
+                let line = self.convert(args.remove(0))?;
 
-                Ok(CompiledAtom { source, atom_type: Type { expr: ExprType::Expression, value: *return_type } })
-            },
-            t => Err(ErrorType::CompileError(format!("Cannot call a {:?}", t)))
+                match self.resolve_type(&line)? {
+                    ValueType::Pointer(inner) if *inner == ValueType::Str => Ok(builtin(typed::Builtin::ReadLine, vec![self.value(line)?], ValueType::I32)),
+                    t => Err(ErrorType::CompileError(format!("read_line expects a *str but got {}", self.type_name(&t)))),
+                }
+            }
+            "read_all" => Ok(builtin(typed::Builtin::ReadAll, Vec::new(), ValueType::Str)),
+            _ => unreachable!("{name} is not a builtin"),
         }
     }
 
     fn return_statement(&mut self, value: Expression) -> MudResult<CompiledAtom> {
-        Ok(CompiledAtom { source: format!("return {}", self.convert(value)?.source), atom_type: Type { value: ValueType::Unknown, expr: ExprType::Expression } })
+        let mut value = self.convert(value)?;
+        self.check_return_borrow(&value)?;
+        if let Some(return_type) = self.current_return.clone() {
+            value = self.coerce(value, &return_type)?;
+        }
+
+        Ok(CompiledAtom::expr(ExprKind::Return(Box::new(self.value(value)?)), ValueType::Void))
     }
 
     fn convert(&mut self, expression: Expression) -> MudResult<CompiledAtom> {
         match expression {
             Expression::Integer(val) => {
-                Ok(CompiledAtom::new(val.to_string(), ValueType::I32, ExprType::Literal))
+                Ok(CompiledAtom::new(Some(Expr::new(ExprKind::Integer(val as i64), ValueType::I32)), ValueType::I32, ExprType::Literal))
             }
             Expression::Identifier(s) => {
-                if s == "i32" { //todo fix this placeholder
-                    Ok(CompiledAtom::new("int".to_string(), ValueType::Unknown, ExprType::Type))
-                } else if s == "u8" {
-                    Ok(CompiledAtom::new("char".to_string(), ValueType::Unknown, ExprType::Type))
-                }
-                else {
-                    Ok(CompiledAtom::new(s, ValueType::Unknown, ExprType::Identifier))
-                }
+                Ok(self.identifier(s))
             }
             Expression::String(s) => {
-                Ok(CompiledAtom::new("\"".to_string() + &s + &"\"", ValueType::Pointer(Box::new(ValueType::U8)), ExprType::Literal))
+                let value_type = ValueType::Pointer(Box::new(ValueType::U8));
+                Ok(CompiledAtom::new(Some(Expr::new(ExprKind::String(unescape(&s)), value_type.clone())), value_type, ExprType::Literal))
             }
-            Expression::UnaryOperation { op, oprand: expr } => {
-                self.unary_op_transpile(op, *expr)
+            Expression::UnaryOperation { op, oprand: expr, location } => {
+                self.unary_op_transpile(op, *expr, location).map_err(|e| e.at(location))
             }
             Expression::BinaryOperation { op, lhs, rhs } => {
                 self.binary_op_transpile(op, *lhs, *rhs)
@@ -283,399 +639,601 @@ impl Compiler {
             Expression::Struct {fields} => {
                 self.r#struct(fields)
             }
-            Expression::FunctionCall { function, args } => {
-                self.function_call(*function, args)
+            Expression::FunctionType { args, return_type } => {
+                self.function_type(args, *return_type)
+            }
+            Expression::ClosureType { args, return_type } => {
+                self.closure_type(args, *return_type)
+            }
+            Expression::FunctionCall { function, args, location } => {
+                self.function_call(*function, args, location).map_err(|e| e.at(location))
+            }
+            Expression::Index { target, index, location } => {
+                self.index(*target, *index, location).map_err(|e| e.at(location))
+            }
+            Expression::SliceType(element) => {
+                self.slice_type(*element)
+            }
+            Expression::Slice { target, start, end, location } => {
+                self.subslice(*target, *start, *end, location).map_err(|e| e.at(location))
+            }
+            Expression::Instantiate { target, args } => {
+                self.instantiate_expr(*target, args)
+            }
+            Expression::Interface { .. } => {
+                Err(ErrorType::CompileError("Interfaces have to be given a name with :=".to_string()))
+            }
+            Expression::Impl { target, interface: Some(interface), methods } => {
+                self.impl_definition(*target, *interface, methods)
+            }
+            Expression::Impl { target, interface: None, methods } => {
+                self.inherent_impl(*target, methods)
+            }
+            Expression::Generic { .. } => {
+                Err(ErrorType::CompileError("Generic fn and struct have to be given a name with :=".to_string()))
             }
             Expression::Return(value) => {
                 self.return_statement(*value)
             }
-            Expression::Null => Ok(CompiledAtom::new(String::new(), ValueType::Void, ExprType::Literal)),
+            Expression::ExternFunction { name, args, variadic, return_type } => {
+                self.extern_function(name, args, variadic, *return_type, None)
+            }
+            Expression::ExternStruct { name, fields } => {
+                self.extern_struct(name, fields, None)
+            }
+            Expression::Attribute { name, args, target } => {
+                self.attribute(name, args, *target)
+            }
+            Expression::Import(path) => {
+                self.import(path)
+            }
+            Expression::Pub(target) => {
+                self.public(*target)
+            }
+            Expression::Null => Ok(CompiledAtom::new(Some(Expr::void()), ValueType::Void, ExprType::Literal)),
+        }
+    }
+
+    // a name as an atom, the builtin types and type parameters are types
+    fn identifier(&self, name: String) -> CompiledAtom {
+        if let Some(value_type) = self.type_params.get(&name) {
+            return CompiledAtom::of_type(value_type.clone());
+        }
+
+        match &name[..] {
+            "i32" => CompiledAtom::of_type(ValueType::I32),
+            "u8" => CompiledAtom::of_type(ValueType::U8),
+            "usize" => CompiledAtom::of_type(ValueType::Usize),
+            "void" => CompiledAtom::of_type(ValueType::Void),
+            "str" => CompiledAtom::of_type(ValueType::Str),
+            _ => {
+                let node = self.scope_stack.iter().rev().find_map(|scope| scope.get(&name))
+                    .and_then(|value_type| self.name_value(&name, value_type));
+                CompiledAtom::new(node, ValueType::Unknown, ExprType::Identifier(name))
+            }
+        }
+    }
+
+    // what a name in scope refers to as a value, a local, a capture or a global of the module
+    fn name_value(&self, name: &str, value_type: &ValueType) -> Option<Expr> {
+        let depth = self.depth_of(name)?;
+        if depth == 0 {
+            return self.values.get(name).cloned();
+        }
+        if self.closure_frames.last().is_some_and(|frame| depth < frame.base) {
+            return self.captured(name).map(|index| Expr::new(ExprKind::Capture(index), value_type.clone()));
+        }
+
+        let local = self.local_ids.get(&(depth, name.to_string()))?;
+        Some(Expr::new(ExprKind::Local(*local), value_type.clone()))
+    }
+
+    // the expression an atom evaluates to
+    fn value(&self, atom: CompiledAtom) -> MudResult<Expr> {
+        if let Some(node) = atom.node {
+            return Ok(node);
+        }
+
+        match atom.atom_type.expr {
+            ExprType::Identifier(name) if self.depth_of(&name).is_none() => Err(ErrorType::CompileError(format!("Undefined variable: {}", name))),
+            ExprType::Identifier(name) => Err(ErrorType::CompileError(format!("{name} is not a value"))),
+            ExprType::Type => Err(ErrorType::CompileError(format!("{} is a type, not a value", self.type_name(&atom.atom_type.value)))),
+            ExprType::FunctionLiteral { .. } | ExprType::StructLiteral { .. } => {
+                Err(ErrorType::CompileError("fn and struct literals at the top level have to be given a name with :=".to_string()))
+            }
+            ExprType::Dynamic(_) => Err(ErrorType::CompileError("A method of an interface can only be called".to_string())),
+            e => Err(ErrorType::CompileError(format!("{} is not a value", describe(&e)))),
+        }
+    }
+
+    fn attribute(&mut self, name: String, args: Vec<Expression>, target: Expression) -> MudResult<CompiledAtom> {
+        match (&name[..], &args[..], target) {
+            ("header", [Expression::String(header)], Expression::ExternFunction { name, args, variadic, return_type }) => {
+                self.extern_function(name, args, variadic, *return_type, Some(header.clone()))
+            }
+            ("header", [Expression::String(header)], Expression::ExternStruct { name, fields }) => {
+                self.extern_struct(name, fields, Some(header.clone()))
+            }
+            ("export", [], target @ Expression::BinaryOperation { op: Operator::ColonEquals, .. }) => {
+                self.is_export = true;
+                let result = self.convert(target);
+                self.is_export = false;
+                result
+            }
+            (name @ ("header" | "export"), _, _) => Err(ErrorType::CompileError(format!("Attribute @{name} cannot be applied here"))),
+            (name, _, _) => Err(ErrorType::CompileError(format!("Unknown attribute @{name}"))),
         }
     }
 
+    fn binary(&self, op: Operator, lhs: CompiledAtom, rhs: CompiledAtom, value_type: ValueType) -> MudResult<CompiledAtom> {
+        Ok(CompiledAtom::expr(ExprKind::Binary(op, Box::new(self.value(lhs)?), Box::new(self.value(rhs)?)), value_type))
+    }
+
     fn add(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}+{})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (ValueType::Pointer(inner), ValueType::I32) => Ok(CompiledAtom::new(format!("({}+{})", lhs.source, rhs.source), ValueType::Pointer(inner), ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot add types {:?} and {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => self.binary(Operator::Plus, lhs, rhs, ValueType::I32),
+            (ValueType::Pointer(inner), ValueType::I32) => self.binary(Operator::Plus, lhs, rhs, ValueType::Pointer(inner)),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot add types {} and {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn sub(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}-{})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot subtract types {:?} and {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => self.binary(Operator::Minus, lhs, rhs, ValueType::I32),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot subtract types {} and {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn mul(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}*{})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot multiply types {:?} and {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => self.binary(Operator::Asterisk, lhs, rhs, ValueType::I32),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot multiply types {} and {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn lt(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}<{})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot compare order of  types {:?} and {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => self.binary(Operator::LessThan, lhs, rhs, ValueType::I32),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot compare order of types {} and {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn gt(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}>{})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot compare order of types {:?} and {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => self.binary(Operator::GreaterThan, lhs, rhs, ValueType::I32),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot compare order of types {} and {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn eq(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}=={})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (ValueType::Pointer(_), ValueType::Pointer(_)) => Ok(CompiledAtom::new(format!("({}=={})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot compare types {:?} and {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => self.binary(Operator::DoubleEquals, lhs, rhs, ValueType::I32),
+            (ValueType::Pointer(_), ValueType::Pointer(_)) => self.binary(Operator::DoubleEquals, lhs, rhs, ValueType::I32),
+            (l @ ValueType::Function { .. }, r) if l == r => self.binary(Operator::DoubleEquals, lhs, rhs, ValueType::I32),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot compare types {} and {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn ne(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}!={})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (ValueType::Pointer(_), ValueType::Pointer(_)) => Ok(CompiledAtom::new(format!("({}!={})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot compare types {:?} and {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => self.binary(Operator::ExclaimEquals, lhs, rhs, ValueType::I32),
+            (ValueType::Pointer(_), ValueType::Pointer(_)) => self.binary(Operator::ExclaimEquals, lhs, rhs, ValueType::I32),
+            (l @ ValueType::Function { .. }, r) if l == r => self.binary(Operator::ExclaimEquals, lhs, rhs, ValueType::I32),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot compare types {} and {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn and(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}&&{})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot do {:?} && {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::expr(
+                ExprKind::Logical(Operator::DoubleAmpersand, Box::new(self.value(lhs)?), Box::new(self.value(rhs)?)), ValueType::I32)),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot do {} && {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn or(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
         match (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?) {
-            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::new(format!("({}||{})", lhs.source, rhs.source), ValueType::I32, ExprType::Expression)),
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot do {:?} || {:?}", l, r))),
+            (ValueType::I32, ValueType::I32) => Ok(CompiledAtom::expr(
+                ExprKind::Logical(Operator::DoubleBar, Box::new(self.value(lhs)?), Box::new(self.value(rhs)?)), ValueType::I32)),
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot do {} || {}", self.type_name(&l), self.type_name(&r)))),
         }
     }
 
     fn comp(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
-        Ok(CompiledAtom::new(format!("{};\n{}", lhs.source, rhs.source), ValueType::Void, ExprType::Expression))
+        let (lhs, rhs) = (statement(self.value(lhs)?), statement(self.value(rhs)?));
+        Ok(CompiledAtom::expr(ExprKind::Sequence(Box::new(lhs), Box::new(rhs)), ValueType::Void))
     }
 
-    fn dot(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
-        match (self.resolve_type(&lhs)?, rhs.atom_type.expr){
-            (ValueType::Struct(fields), ExprType::Identifier) => {
-                let field_type = fields.get(&rhs.source);
-                if let Some(field_type) = field_type{
-                    Ok(CompiledAtom::new(format!("{}.{}", lhs.source, rhs.source), field_type.to_owned(), ExprType::Expression))
+    fn dot(&mut self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
+        let lhs_type = self.resolve_type(&lhs)?;
+        let fields_type = slices::view_fields(&lhs_type).map(|fields| ValueType::Struct { symbol: String::new(), fields }).unwrap_or(lhs_type.clone());
+
+        match (fields_type, rhs.atom_type.expr){
+            (ValueType::Module(module), ExprType::Identifier(member)) => {
+                self.module_access(&module, &member)
+            }
+            // `*module.Type` parses as `(*module).Type`
+            (ValueType::Pointer(inner), ExprType::Identifier(member)) if matches!(lhs.atom_type.expr, ExprType::Type) && matches!(*inner, ValueType::Module(_)) => {
+                let ValueType::Module(module) = *inner else { unreachable!() };
+                let atom = self.module_access(&module, &member)?;
+                if !matches!(atom.atom_type.expr, ExprType::Type) {
+                    return MudResult::Err(ErrorType::CompileError(format!("{member} is not a type")));
+                }
+                Ok(CompiledAtom::of_type(ValueType::Pointer(Box::new(atom.atom_type.value))))
+            }
+            (ValueType::Struct { fields, .. }, ExprType::Identifier(field)) => {
+                let index = fields.iter().position(|(name, _)| *name == field);
+                if let Some(index) = index {
+                    let field_type = fields[index].1.clone();
+                    Ok(CompiledAtom::expr(ExprKind::Field(Box::new(self.value(lhs)?), index), field_type))
                 } else {
-                    dbg!(fields);
-                    return MudResult::Err(ErrorType::CompileError(format!("field \"{}\" not found on struct {:?}", rhs.source, lhs)));
+                    MudResult::Err(ErrorType::CompileError(format!("field \"{}\" not found on struct {}", field, self.type_name(&lhs_type))))
                 }
             }
-            // (ExprType::Identifier, rhs) => {
-            //
-            //     Ok(CompiledAtom::new(format!("{}.{}", lhs.source, rhs.source), ValueType::Unknown, ExprType::Expression))
-            //         // dbg!
-            // }
-            (bad_type, rhs) => MudResult::Err(ErrorType::CompileError(format!("lhs must be a struct but is {:?}, and rhs must be something but is {:?} ", bad_type, rhs))),
+            (bad_type, ExprType::Identifier(member)) => MudResult::Err(ErrorType::CompileError(format!("{} has no field {member}", self.type_name(&bad_type)))),
+            (_, rhs) => MudResult::Err(ErrorType::CompileError(format!("Expected a field name after . but got {}", describe(&rhs)))),
         }
     }
 
     fn decl(&mut self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
-                dbg!(&lhs);
-                dbg!(&rhs);
-        match (&lhs.atom_type.expr, &rhs.atom_type.expr) {
-            (ExprType::Identifier, ExprType::Type) => {
-                let res = CompiledAtom::new(format!("{} {}", rhs.source, lhs.source), ValueType::Void, ExprType::Expression);
-
-                let rhs_type = self.find_type(&rhs);
-
-                if self.scope_stack.last_mut().unwrap().insert(lhs.source, rhs_type?).is_some() {
-                    return MudResult::Err(ErrorType::CompileError("Variable redelcaration".to_string()));
+        trace!(Resolve, Debug, "declare {:?} as {:?}", lhs.atom_type.expr, rhs.atom_type);
+        let value_type = match (&lhs.atom_type.expr, &rhs.atom_type.expr) {
+            (ExprType::Identifier(_), ExprType::Type) => rhs.atom_type.value.clone(),
+            (ExprType::Identifier(_), ExprType::Identifier(_)) => {
+                let rhs_type = self.resolve_type(&rhs)?;
+                match rhs_type {
+                    ValueType::Struct { .. } | ValueType::Interface(_) if rhs.node.is_none() => rhs_type,
+                    _ => return MudResult::Err(ErrorType::CompileError("Declaring between invalid identifiers, you're doing something weird".to_string())),
                 }
+            }
+            (l, r) => return MudResult::Err(ErrorType::CompileError(format!("Cannot declare {} as {}", describe(l), describe(r)))),
+        };
+        let ExprType::Identifier(name) = lhs.atom_type.expr else { unreachable!() };
 
+        if self.scope_stack.last().unwrap().contains_key(&name) {
+            return MudResult::Err(ErrorType::CompileError("Variable redelcaration".to_string()));
+        }
+        let local = self.declare_local(&name, value_type)?;
 
-                Ok(res)
-            },
-            (ExprType::Identifier, ExprType::Identifier) => {
-                let rhs_type = self.resolve_type(&rhs)?;
-                if let ValueType::Struct(_) = rhs_type{
-                    let res = CompiledAtom::new(format!("{} {}", rhs.source, lhs.source), ValueType::Void, ExprType::Expression);
+        Ok(CompiledAtom::expr(ExprKind::Declare(local, None), ValueType::Void))
+    }
 
-                    if self.scope_stack.last_mut().unwrap().insert(lhs.source, rhs_type).is_some() {
-                        return MudResult::Err(ErrorType::CompileError("Variable redelcaration".to_string()));
-                    }
+    // a new local of the function being checked, in the innermost scope
+    fn declare_local(&mut self, name: &str, value_type: ValueType) -> MudResult<LocalId> {
+        let Some(function) = self.current_function else {
+            return Err(ErrorType::CompileError(format!("Variable {name} is not allowed outside a function")));
+        };
+
+        let depth = self.scope_stack.len() - 1;
+        let locals = &mut self.program.functions[function].locals;
+        locals.push(value_type.clone());
+        let local = locals.len() - 1;
+
+        self.borrows.remove(&(depth, name.to_string()));
+        self.local_ids.insert((depth, name.to_string()), local);
+        self.scope_stack.last_mut().unwrap().insert(name.to_string(), value_type);
+        Ok(local)
+    }
 
-                    return Ok(res)
-                } else {
-                    return MudResult::Err(ErrorType::CompileError(format!("Declaring between invalid identifiers, you're doing something weird")))
-                }
+    fn assign(&mut self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
+        let target = match &lhs.atom_type.expr {
+            ExprType::Identifier(name) if self.is_constant(name) => {
+                return MudResult::Err(ErrorType::CompileError(format!("Cannot assign to constant {name}")));
             }
-            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot declare between types {:?} and {:?}", l, r))),
+            ExprType::Identifier(name) => Some(name.clone()),
+            ExprType::Expression => None,
+            e => return MudResult::Err(ErrorType::CompileError(format!("Cannot assign to {}", describe(e)))),
+        };
+
+        self.check_borrow(target.as_deref(), &rhs)?;
+        let lhs_type = self.resolve_type(&lhs)?;
+        let rhs = self.coerce(rhs, &lhs_type)?;
+        let place = self.value(lhs)?;
+        if !place.is_place() {
+            return MudResult::Err(ErrorType::CompileError("Only variables, fields, indexing and dereferences can be assigned to".to_string()));
         }
+
+        Ok(CompiledAtom::expr(ExprKind::Assign(Box::new(place), Box::new(self.value(rhs)?)), ValueType::Void))
     }
 
-    fn assign(&self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
-        fn ensure_types_equal(lhs: &ValueType, rhs: &ValueType) -> MudResult<()> {
-            if *lhs == ValueType::U8 || *lhs == ValueType::I32 && *rhs == ValueType::U8 || *rhs == ValueType::I32 {
-                return Ok(());
-            }
+    fn assign_func_struct_const(&mut self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
+        let is_export = std::mem::take(&mut self.is_export);
 
-            if let ValueType::Pointer(t) = rhs {
-                if **t == ValueType::Void {
-                    if let ValueType::Pointer(_) = lhs {
-                        return Ok(());
-                    }
+        match (lhs.atom_type.expr, rhs.atom_type.expr) {
+            (ExprType::Identifier(name), ExprType::FunctionLiteral { args, return_type, body }) => {
+                trace!(Codegen, Debug, "fn {name}");
+                if self.scope_stack.len() != 1 {
+                    return MudResult::Err(ErrorType::CompileError("Functions are not allowed outside the top level".to_string()));
                 }
-            }
 
-            if lhs == rhs {
-                return Ok(())
-            }
+                let return_value_type = self.type_expr(*return_type)?;
+                let params = self.resolve_args(args)?;
+                let types = params.iter().map(|(_, value_type)| value_type.clone()).collect::<Vec<_>>();
+                let is_main = name == "main" && self.module_prefix.is_empty();
+                let signature_ok = is_main_signature(&types, &return_value_type);
+                let f_type = ValueType::Function { args: types, return_type: Box::new(return_value_type.clone()), variadic: false };
+                if is_main && !signature_ok {
+                    return Err(ErrorType::CompileError(format!(
+                        "main must be `fn() -> i32` or `fn(args: []str) -> i32`, but is {}", self.type_name(&f_type))));
+                }
 
-            MudResult::Err(ErrorType::CompileError(format!("Expected type {lhs:?} but got type {rhs:?} in assignment")))
-        }
+                if self.scope_stack.last_mut().unwrap().insert(name.clone(), f_type.clone()).is_some() {
+                    return MudResult::Err(ErrorType::CompileError("Function redelcaration".to_string()));
+                }
 
-        match &lhs.atom_type.expr {
-            ExprType::Identifier => {
-                let lhs_type = self.resolve_type(&lhs)?;
-                let rhs_type = self.resolve_type(&rhs)?;
+                // C callers expect exports by their Mud names
+                let symbol = if is_export { name.clone() } else { self.mangle(&name) };
+                let function = self.declare_function(symbol, is_export, return_value_type);
+                self.program.symbols.push((self.qualify(&name), function));
+                self.values.insert(name.clone(), Expr::new(ExprKind::Function(function), f_type.clone()));
+                if is_main {
+                    self.program.main = Some(function);
+                }
+                if is_export {
+                    self.check_export(&f_type)?;
+                    self.program.exports.push((name, f_type));
+                }
 
-                ensure_types_equal(&lhs_type, &rhs_type)?;
+                self.function_body(function, params, None, *body)?;
+                Ok(CompiledAtom::void())
+            },
+            (ExprType::Identifier(name), ExprType::StructLiteral{fields}) => {
+                trace!(Codegen, Debug, "struct {name}");
+                if self.scope_stack.len() != 1 {
+                    return MudResult::Err(ErrorType::CompileError("Structs are not allowed outside the top level".to_string()));
+                }
 
-                Ok(CompiledAtom::new(format!("{} = {}", lhs.source, rhs.source), ValueType::Void, ExprType::Expression))
-            }
-            ExprType::Expression => {
-                let lhs_type = self.resolve_type(&lhs)?;
-                let rhs_type = self.resolve_type(&rhs)?;
+                if self.scope_stack[0].contains_key(&name) {
+                    return MudResult::Err(ErrorType::CompileError("Struct redelcaration".to_string()));
+                }
 
-                ensure_types_equal(&lhs_type, &rhs_type)?;
+                // the fields can point to the struct, which they see without fields until it is complete
+                let symbol = self.mangle(&name);
+                self.scope_stack[0].insert(name.clone(), ValueType::Struct { symbol: symbol.clone(), fields: Vec::new() });
+                let s_type = self.struct_fields(symbol, fields).inspect_err(|_| {
+                    self.scope_stack[0].remove(&name);
+                })?;
+                self.scope_stack[0].insert(name.clone(), s_type.clone());
+
+                self.program.struct_names.push((s_type.clone(), name.clone()));
+                if is_export {
+                    self.check_export(&s_type)?;
+                    self.program.exports.push((name, s_type));
+                }
 
-                Ok(CompiledAtom::new(format!("{} = {}", lhs.source, rhs.source), ValueType::Void, ExprType::Expression))
-            }
-            e => {
-                dbg!(&lhs);
-                dbg!(self.resolve_type(&lhs));
-                dbg!(&rhs);
-                // Ok(CompiledAtom::new(format!("{} = {}", lhs.source, rhs.source), ValueType::Void, ExprType::Expression))
-                MudResult::Err(ErrorType::CompileError(format!("Invalid lhs of assignment {:?}", e)))
+                Ok(CompiledAtom::void())
             },
+            (l, r) => MudResult::Err(ErrorType::CompileError(format!("Cannot define {} as {}", describe(&l), describe(&r)))),
         }
     }
 
-    fn assign_func_struct_const(&mut self, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
-        fn resolve_args(this: &mut Compiler, args: Vec<Expression>, scope: &mut HashMap<String, ValueType>) -> MudResult<(Vec<String>, Vec<ValueType>)> {
-            let mut strs = Vec::new();
-            let mut types = Vec::new();
-
-            for arg in args {
-                if let Expression::BinaryOperation { op, lhs, rhs } = arg {
-                    this.is_decl = true;
-                    let rhs = this.convert(*rhs)?;
-                    if let (Operator::Colon, Expression::Identifier(ident), ExprType::Type) = (op, *lhs, &rhs.atom_type.expr) {
-                        strs.push(format!("{} {ident}", &rhs.source));
-                        types.push(this.find_type(&rhs)?);
-                        scope.insert(ident, types.last().unwrap().clone());
-                        continue;
-                    }
-                }
+    // a new function of the program, its body is filled in once it is checked
+    fn declare_function(&mut self, name: String, export: bool, return_type: ValueType) -> FunctionId {
+        let file = match &self.import_stack[..] {
+            [_, .., module] => module.display().to_string(),
+            _ => self.file.clone(),
+        };
+        self.program.functions.push(typed::Function {
+            name, export, file, params: Vec::new(), locals: Vec::new(), return_type, captures: Vec::new(), self_local: None, body: Expr::void(),
+        });
+        self.program.functions.len() - 1
+    }
 
-                return Err(ErrorType::CompileError("Malformed function arguments".to_string()));
-            }
+    // checks a function body in a scope of its own, which holds the params and what a nested fn calls itself
+    fn function_body(&mut self, function: FunctionId, params: Vec<(String, ValueType)>, self_value: Option<(String, ValueType)>, body: Expression) -> MudResult<()> {
+        let outer_function = self.current_function.replace(function);
+        let outer_return = self.current_return.replace(self.program.functions[function].return_type.clone());
+        self.scope_stack.push(HashMap::new());
 
-            Ok((strs, types))
-        }
+        let body = self.declare_params(function, params, self_value)
+            .and_then(|_| self.convert(body))
+            .and_then(|body| self.value(body));
 
-        fn resolve_fields(this: &mut Compiler, args: Vec<Expression>, scope: &mut HashMap<String, ValueType>) -> MudResult<(Vec<String>, Vec<ValueType>)> {
-            let mut strs = Vec::new();
-            let mut types = Vec::new();
+        self.scope_stack.pop();
+        self.current_function = outer_function;
+        self.current_return = outer_return;
 
-            for arg in args {
-                if let Expression::BinaryOperation { op, lhs, rhs } = arg {
-                    let rhs = this.convert(*rhs)?;
-                    if let (Operator::Colon, Expression::Identifier(ident), ExprType::Type) = (op, *lhs, &rhs.atom_type.expr) {
-                        strs.push(format!("{}", ident));
-                        types.push(this.find_type(&rhs)?);
-                        scope.insert(ident, types.last().unwrap().clone());
-                        continue;
-                    }
-                }
+        self.program.functions[function].body = statement(body?);
+        Ok(())
+    }
 
-                return Err(ErrorType::CompileError("Malformed function arguments".to_string()));
+    fn declare_params(&mut self, function: FunctionId, params: Vec<(String, ValueType)>, self_value: Option<(String, ValueType)>) -> MudResult<()> {
+        for (name, value_type) in params {
+            let is_closure = matches!(value_type, ValueType::Closure { .. });
+            let local = self.declare_local(&name, value_type)?;
+            self.program.functions[function].params.push(local);
+            if is_closure {
+                self.borrow_argument(&name);
             }
+        }
+        if let Some((name, value_type)) = self_value {
+            self.program.functions[function].self_local = Some(self.declare_local(&name, value_type)?);
+        }
+
+        Ok(())
+    }
+
+    fn struct_fields(&mut self, symbol: String, fields: Vec<Expression>) -> MudResult<ValueType> {
+        let fields = self.resolve_fields(fields)?;
 
-            Ok((strs, types))
+        let mut fields_list: Vec<(String, ValueType)> = Vec::new();
+        for (field, ftype) in fields {
+            if fields_list.iter().any(|(name, _)| *name == field){
+                return MudResult::Err(ErrorType::CompileError("Duplicate field in struct".to_string()));
+            }
+            if matches!(&ftype, ValueType::Struct { symbol: inner, .. } if *inner == symbol) {
+                return MudResult::Err(ErrorType::CompileError(format!("Field {field} cannot hold the struct it is in, only a pointer to it")));
+            }
+            fields_list.push((field, ftype));
         }
 
-        match (lhs.atom_type.expr, rhs.atom_type.expr) {
-            (ExprType::Identifier, ExprType::FunctionLiteral { args, return_type, body }) => {
-                if dbg!(self.scope_stack.len()) != 1 {
-                    return MudResult::Err(ErrorType::CompileError("Functions are not allowed outside the top level".to_string()));
-                }
+        self.structs.insert(symbol.clone(), fields_list.clone());
+        Ok(ValueType::Struct { symbol, fields: fields_list })
+    }
 
-                let return_converted = self.convert(*return_type)?;
-                let return_type_string = &return_converted.source;
+    // a struct referred to in its own fields has none there, the value such a pointer points to has them all
+    pub(super) fn complete(&self, value_type: ValueType) -> ValueType {
+        match value_type {
+            ValueType::Struct { symbol, fields } if fields.is_empty() && self.structs.contains_key(&symbol) => {
+                let fields = self.structs[&symbol].clone();
+                ValueType::Struct { symbol, fields }
+            }
+            value_type => value_type,
+        }
+    }
 
-                let mut fn_scope = HashMap::new();
-                let (strs, types) = resolve_args(self, args, &mut fn_scope)?;
-                let f_type = ValueType::Function { args: types, return_type: Box::new(self.find_type(&return_converted)?) };
+    // the name and type of each argument of a fn
+    fn resolve_args(&mut self, args: Vec<Expression>) -> MudResult<Vec<(String, ValueType)>> {
+        let mut resolved = Vec::new();
 
-                if self.scope_stack.last_mut().unwrap().insert(lhs.source.clone(), f_type).is_some() {
-                    return MudResult::Err(ErrorType::CompileError("Function redelcaration".to_string()));
+        for arg in args {
+            if let Expression::BinaryOperation { op: Operator::Colon, lhs, rhs } = arg {
+                if let Expression::Identifier(ident) = *lhs {
+                    resolved.push((ident, self.type_expr(*rhs)?));
+                    continue;
                 }
+            }
 
-                self.scope_stack.push(fn_scope);
+            return Err(ErrorType::CompileError("Malformed function arguments".to_string()));
+        }
 
-                let header = format!("{} {}({})", return_type_string, lhs.source, strs.join(", "));
-                self.forward_decls.push_str(&header);
-                self.forward_decls.push_str(";\n");
+        Ok(resolved)
+    }
 
-                let result = Ok(CompiledAtom::new(format!("{}{}", header, self.convert(*body)?.source), ValueType::Void, ExprType::Expression));
-                self.scope_stack.pop();
-                result
-            },
-            (ExprType::Identifier, ExprType::StructLiteral{fields}) => {
-                if dbg!(self.scope_stack.len()) != 1 {
-                    return MudResult::Err(ErrorType::CompileError("Structs are not allowed outside the top level".to_string()));
+    // the name and type of each field
+    fn resolve_fields(&mut self, fields: Vec<Expression>) -> MudResult<Vec<(String, ValueType)>> {
+        let mut resolved = Vec::new();
+
+        for field in fields {
+            if let Expression::BinaryOperation { op: Operator::Colon, lhs, rhs } = field {
+                if let Expression::Identifier(ident) = *lhs {
+                    resolved.push((ident, self.type_expr(*rhs)?));
+                    continue;
                 }
+            }
 
-                let mut fn_scope = HashMap::new(); //this is a dummy scope
-                let (strs, types) = resolve_fields(self, fields.clone(), &mut fn_scope)?;
+            return Err(ErrorType::CompileError("Malformed struct fields".to_string()));
+        }
 
-                let mut fields_map = HashMap::new();
-                    for (str, ftype) in strs.iter().zip(types.into_iter()){
-                        if fields_map.insert(str.to_owned(), ftype).is_some(){
-                            return MudResult::Err(ErrorType::CompileError("Duplicate field in struct".to_string()));
-                        }
-                    }
-                let s_type = ValueType::Struct(fields_map);
+        Ok(resolved)
+    }
 
-                if self.scope_stack.last_mut().unwrap().insert(lhs.source.clone(), s_type).is_some() {
-                    return MudResult::Err(ErrorType::CompileError("Struct redelcaration".to_string()));
-                }
+    fn constant(&mut self, lhs: CompiledAtom, rhs: Expression) -> MudResult<CompiledAtom> {
+        let ExprType::Identifier(name) = lhs.atom_type.expr else {
+            return MudResult::Err(ErrorType::CompileError(format!("Cannot define {} as a constant", describe(&lhs.atom_type.expr))));
+        };
 
-                let (strs, _types) = resolve_args(self, fields, &mut fn_scope)?;
-                let result = Ok(CompiledAtom::new(
-                        format!("typedef struct {{ {} }} {};",
-                                strs.join("; ") + &";",
-                                lhs.source),
-                        ValueType::Void,
-                        ExprType::Expression
-                        ));
-                result
-            },
-            (ExprType::Identifier, ExprType::Literal) => {
+        if self.scope_stack.len() != 1 {
+            return MudResult::Err(ErrorType::CompileError("Constants are not allowed outside the top level".to_string()));
+        }
 
-                let c_type = ValueType::I32;
-                if self.scope_stack.last_mut().unwrap().insert(lhs.source.clone(), c_type).is_some() {
-                    return MudResult::Err(ErrorType::CompileError("Struct redelcaration".to_string()));
-                }
+        let value = self.const_eval(&rhs)?;
+        let value_type = value.value_type();
 
-                let result = Ok(CompiledAtom::new(
-                        format!("i32 {} = {}", lhs.source, rhs.source),
-                        ValueType::Void,
-                        ExprType::Expression
-                        ));
-                result
-            }
-            e => MudResult::Err(ErrorType::CompileError(format!("Invalid lhs of assignment {:?}", e))),
+        if self.scope_stack.last_mut().unwrap().insert(name.clone(), value_type.clone()).is_some() {
+            return MudResult::Err(ErrorType::CompileError("Constant redelcaration".to_string()));
         }
+        let node = match &value {
+            ConstValue::Integer(i) => ExprKind::Integer(*i),
+            ConstValue::String(s) => ExprKind::String(unescape(s)),
+        };
+        self.values.insert(name.clone(), Expr::new(node, value_type));
+        self.constants.insert(name, value);
+
+        Ok(CompiledAtom::void())
     }
 
     fn not(&self, oprand: CompiledAtom) -> MudResult<CompiledAtom> {
         match self.resolve_type(&oprand)? {
-            ValueType::I32 => Ok(CompiledAtom::new(format!("(!{})", oprand.source), ValueType::I32, ExprType::Expression)),
-            e => MudResult::Err(ErrorType::CompileError(format!("Cannot do !{:?}", e))),
+            ValueType::I32 => Ok(CompiledAtom::expr(ExprKind::Unary(Operator::Exclaim, Box::new(self.value(oprand)?)), ValueType::I32)),
+            e => MudResult::Err(ErrorType::CompileError(format!("Cannot do !{}", self.type_name(&e)))),
         }
     }
 
     fn negate(&self, oprand: CompiledAtom) -> MudResult<CompiledAtom> {
         match self.resolve_type(&oprand)? {
-            ValueType::I32 => Ok(CompiledAtom::new(format!("(-{})", oprand.source), ValueType::I32, ExprType::Expression)),
-            e => MudResult::Err(ErrorType::CompileError(format!("Cannot negate type {:?}", e))),
+            ValueType::I32 => Ok(CompiledAtom::expr(ExprKind::Unary(Operator::Minus, Box::new(self.value(oprand)?)), ValueType::I32)),
+            e => MudResult::Err(ErrorType::CompileError(format!("Cannot negate type {}", self.type_name(&e)))),
         }
     }
 
     fn adressof(&self, oprand: CompiledAtom) -> MudResult<CompiledAtom> {
-        //todo should probably check if you can actually wrap this thing but whatever
-        Ok(CompiledAtom::new(format!("(&{})", oprand.source), ValueType::Pointer(Box::new(self.resolve_type(&oprand)?)), ExprType::Expression))
-        // match self.resolve_type(&oprand)? {
-        //     ValueType::Pointer(inner) => Ok(CompiledAtom::new(format!("(&{})", oprand.source), ValueType::Pointer(Box::new()), ExprType::Expression)),
-        //     ValueType::Integer => Ok(CompiledAtom::new(format!("(&{})", oprand.source), ValueType::Integer, ExprType::Expression)),
-        //     e => MudResult::Err(ErrorType::CompileError(format!("Cannot negate type {:?}", e))),
-        // }
+        let value = self.value(oprand)?;
+        if !value.is_place() {
+            return MudResult::Err(ErrorType::CompileError("cannot take the address of a temporary value".to_string()));
+        }
+
+        let value_type = ValueType::Pointer(Box::new(value.value_type.clone()));
+        Ok(CompiledAtom::expr(ExprKind::AddressOf(Box::new(value)), value_type))
     }
 
-    fn deref(&self, oprand: CompiledAtom) -> MudResult<CompiledAtom> {
-        dbg!("called deref");
-        match self.resolve_type(&oprand)? {
-            ValueType::Pointer(inner) => Ok(CompiledAtom::new(format!("(*{})", oprand.source), *inner, ExprType::Expression)),
-            e => MudResult::Err(ErrorType::CompileError(format!("Cannot deref type {:?}", e))),
+    fn deref(&self, oprand: CompiledAtom, location: Location) -> MudResult<CompiledAtom> {
+        let oprand_type = self.resolve_type(&oprand)?;
+        trace!(Resolve, Trace, "deref of {oprand_type:?}");
+        match oprand_type {
+            ValueType::Pointer(inner) => Ok(CompiledAtom::expr(ExprKind::Deref(Box::new(self.value(oprand)?), location), self.complete(*inner))),
+            e => MudResult::Err(ErrorType::CompileError(format!("Cannot deref type {}", self.type_name(&e)))),
         }
     }
 
     fn pointer_type(&self, oprand: CompiledAtom) -> MudResult<CompiledAtom> {
-        dbg!(self.resolve_type(&oprand)?);
-        dbg!(&oprand);
-        Ok::<CompiledAtom, ErrorType>(CompiledAtom::new(format!("{}*", oprand.source), ValueType::Pointer(Box::new(self.resolve_type(&oprand)?)), ExprType::Type))
+        let inner = self.resolve_type(&oprand)?;
+        trace!(Resolve, Trace, "pointer to {inner:?}");
+        Ok(CompiledAtom::of_type(ValueType::Pointer(Box::new(inner))))
     }
 
-    fn print(&self, oprand: CompiledAtom) -> MudResult<CompiledAtom> {
-        match self.resolve_type(&oprand)? {
-            ValueType::I32 => Ok(CompiledAtom::new(format!("printf(\"%d\", {})", oprand.source), ValueType::Void, ExprType::Expression)),
-            ValueType::U8 => Ok(CompiledAtom::new(format!("printf(\"%c\", {})", oprand.source), ValueType::Void, ExprType::Expression)),
-            //todo fix
-            ValueType::Pointer(inner) => Ok(CompiledAtom::new(format!("printf(\"%s\", {})", oprand.source), ValueType::Void, ExprType::Expression)),
-            // ValueType::StringLiteral => Ok(CompiledAtom::new(format!("printf(\"%s\", {})", oprand.source), ValueType::Void, ExprType::Expression)),
-            e => MudResult::Err(ErrorType::CompileError(format!("Cannot print type {:?}", e))),
+    fn print(&self, oprand: CompiledAtom, location: Location) -> MudResult<CompiledAtom> {
+        let value_type = self.resolve_type(&oprand)?;
+
+        match format::default_conversion(&value_type) {
+            Some(conversion) => Ok(CompiledAtom::expr(ExprKind::Print {
+                pieces: vec![Piece::Placeholder(String::new())],
+                args: vec![(conversion, self.value(oprand)?)],
+                location,
+            }, ValueType::Void)),
+            None => MudResult::Err(ErrorType::CompileError(format!("Cannot print type {}", self.type_name(&value_type)))),
         }
     }
 
+    // converts an expression that names a type, such as `*Cat`, into its ValueType
+    fn type_expr(&mut self, expression: Expression) -> MudResult<ValueType> {
+        let was_decl = self.is_decl;
+        self.is_decl = true;
+        let atom = self.convert(expression);
+        self.is_decl = was_decl;
+        let atom = atom?;
+
+        match &atom.atom_type.expr {
+            ExprType::Type => Ok(atom.atom_type.value),
+            ExprType::Identifier(name) => match self.resolve_type(&atom)? {
+                t @ (ValueType::Struct { .. } | ValueType::Opaque(_) | ValueType::Interface(_)) if atom.node.is_none() => Ok(t),
+                _ => Err(ErrorType::CompileError(format!("{name} is not a type"))),
+            },
+            e => Err(ErrorType::CompileError(format!("Expected a type but got {}", describe(e)))),
+        }
+    }
 
-    fn find_type(&self, atom: &CompiledAtom) -> MudResult<ValueType> {
-
-        match atom.atom_type.expr {
-            ExprType::Type => {
-                if let ValueType::Pointer(inner) = &atom.atom_type.value{
-                    let source = atom.source[..atom.source.len()-1].to_string();
-                    let mut atom_type = atom.atom_type.clone();
-                    atom_type.value = *inner.clone();
-
-                    let fake_atom = CompiledAtom{source, atom_type};
-
-                    return Ok(ValueType::Pointer(Box::new(self.find_type(&fake_atom)?)));
-                }
-                // return Ok(atom.atom_type.value.clone());
-                match &atom.source[..] {
-                    "int" => {
-                        return Ok(ValueType::I32)
-                    }
-                    "char" => {
-                        return Ok(ValueType::U8)
-                    }
-                    &_ => {
-                        return Ok(atom.atom_type.value.clone());
-                        // dbg!(atom);
-                        // todo!("add more types")
-                    }
-                }
+    // constants live in the global scope, so a name is constant if it is not shadowed there
+    fn is_constant(&self, name: &str) -> bool {
+        for (depth, scope) in self.scope_stack.iter().enumerate().rev() {
+            if scope.contains_key(name) {
+                return depth == 0 && self.constants.contains_key(name);
             }
-            _ => Ok(atom.atom_type.value.clone()),
         }
+
+        false
     }
 
     fn resolve_type(&self, atom: &CompiledAtom) -> MudResult<ValueType> {
         match atom.atom_type.expr {
-            ExprType::Identifier => {
+            ExprType::Identifier(ref name) => {
                 for scope in self.scope_stack.iter().rev() {
-                    if let Some(v) = scope.get(&atom.source) {
+                    if let Some(v) = scope.get(name) {
                         return Ok(v.clone());
                     }
                 }
 
-                Err(ErrorType::CompileError(format!("Undefined variable: {}", atom.source)))
+                Err(ErrorType::CompileError(format!("Undefined variable: {}", name)))
             }
             _ => Ok(atom.atom_type.value.clone()),
         }
@@ -0,0 +1,294 @@
+use std::collections::HashSet;
+
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, CompiledAtom, ExprType, ValueType};
+use super::typed::{self, Expr, ExprKind};
+
+// a fn literal whose body is being compiled, locals of the functions around it are reached through its environment
+#[derive(Clone)]
+pub struct ClosureFrame {
+    // scopes below this one belong to the enclosing functions
+    pub base: usize,
+    captures: Vec<typed::Capture>,
+}
+
+// what a closure depends on that lives in the scope at depth, a local it captured by reference or a closure it was
+// passed as an argument. A closure argument may borrow the caller's locals, so it is only used for the call
+#[derive(Debug, Clone)]
+pub struct Borrow {
+    depth: usize,
+    name: String,
+    argument: bool,
+}
+
+impl Borrow {
+    fn closure(&self) -> String {
+        match self.argument {
+            true => format!("a closure passed as {}", self.name),
+            false => format!("a closure that captures {} by reference", self.name),
+        }
+    }
+}
+
+fn children(expression: &Expression) -> Vec<&Expression> {
+    match expression {
+        Expression::BinaryOperation { lhs, rhs, .. } => vec![lhs, rhs],
+        Expression::UnaryOperation { oprand, .. } => vec![oprand],
+        Expression::FunctionCall { function, args, .. } => std::iter::once(&**function).chain(args).collect(),
+        Expression::Index { target, index, .. } => vec![target, index],
+        Expression::Slice { target, start, end, .. } => vec![target, start, end],
+        Expression::Instantiate { target, args } => std::iter::once(&**target).chain(args).collect(),
+        Expression::Return(value) | Expression::Block(value) | Expression::SliceType(value) | Expression::Pub(value) => vec![value],
+        Expression::IfElse { condition, on_if, on_else } => vec![condition, on_if, on_else],
+        Expression::While { condition, body } => vec![condition, body],
+        Expression::Function { args, return_type, body } => args.iter().chain([&**return_type, &**body]).collect(),
+        Expression::FunctionType { args, return_type } | Expression::ClosureType { args, return_type } => args.iter().chain([&**return_type]).collect(),
+        Expression::Generic { body, .. } => vec![body],
+        Expression::Attribute { target, .. } => vec![target],
+        _ => Vec::new(),
+    }
+}
+
+// the names an expression mentions, and the ones it declares itself
+fn identifiers(expression: &Expression, used: &mut Vec<String>, declared: &mut HashSet<String>) {
+    match expression {
+        Expression::Identifier(name) => used.push(name.clone()),
+        Expression::BinaryOperation { op: Operator::Colon | Operator::ColonEquals, lhs, .. } if matches!(**lhs, Expression::Identifier(_)) => {
+            let Expression::Identifier(name) = &**lhs else { unreachable!() };
+            declared.insert(name.clone());
+        }
+        _ => {}
+    }
+
+    for child in children(expression) {
+        identifiers(child, used, declared);
+    }
+}
+
+// the variable an assignment writes to or `&` takes the address of
+fn root(expression: &Expression) -> Option<&String> {
+    match expression {
+        Expression::Identifier(name) => Some(name),
+        Expression::BinaryOperation { op: Operator::Dot, lhs, .. } => root(lhs),
+        _ => None,
+    }
+}
+
+// the variables a closure changes have to be captured by reference, the rest are copied
+fn mutated(expression: &Expression, names: &mut HashSet<String>) {
+    let target = match expression {
+        Expression::BinaryOperation { op: Operator::Equals, lhs, .. } => root(lhs),
+        Expression::UnaryOperation { op: Operator::Ampersand, oprand, .. } => root(oprand),
+        _ => None,
+    };
+    names.extend(target.cloned());
+
+    for child in children(expression) {
+        mutated(child, names);
+    }
+}
+
+// the names a fn literal uses without declaring them, and whether it changes each one
+pub fn free_names(name: Option<&str>, args: &[Expression], body: &Expression) -> Vec<(String, bool)> {
+    let mut used = Vec::new();
+    let mut declared = HashSet::new();
+    for arg in args {
+        identifiers(arg, &mut used, &mut declared);
+    }
+    identifiers(body, &mut used, &mut declared);
+    declared.extend(name.map(str::to_string));
+
+    let mut by_ref = HashSet::new();
+    mutated(body, &mut by_ref);
+
+    let mut names: Vec<(String, bool)> = Vec::new();
+    for name in used {
+        if !declared.contains(&name) && !names.iter().any(|(n, _)| *n == name) {
+            names.push((name.clone(), by_ref.contains(&name)));
+        }
+    }
+
+    names
+}
+
+impl Compiler {
+    // `closure(i32) -> i32`, a function pointer together with the environment it is called with
+    pub(super) fn closure_type(&mut self, args: Vec<Expression>, return_type: Expression) -> MudResult<CompiledAtom> {
+        let (types, return_type) = self.signature_types(args, return_type)?;
+
+        Ok(CompiledAtom::of_type(ValueType::Closure { args: types, return_type: Box::new(return_type) }))
+    }
+
+    // a plain fn can be passed where a closure is expected, a closure cannot become a fn pointer
+    pub(super) fn coerce_closure(&mut self, atom: CompiledAtom, target: &ValueType) -> MudResult<CompiledAtom> {
+        let ValueType::Closure { args, return_type } = target else { unreachable!() };
+
+        match self.resolve_type(&atom)? {
+            t if t == *target => Ok(atom),
+            ValueType::Function { args: fn_args, return_type: fn_return, variadic: false } if fn_args == *args && fn_return == *return_type => {
+                Ok(CompiledAtom::expr(ExprKind::Convert(typed::Conversion::FunctionToClosure, Box::new(self.value(atom)?)), target.clone()))
+            }
+            t => Err(ErrorType::CompileError(format!("Expected a closure of type {} but got {}", self.type_name(target), self.type_name(&t)))),
+        }
+    }
+
+    // `name := fn(...) -> T {...}` inside a function, a local that holds the lifted fn or closure
+    pub(super) fn nested_function(&mut self, name: String, args: Vec<Expression>, return_type: Expression, body: Expression) -> MudResult<CompiledAtom> {
+        if self.scope_stack.last().unwrap().contains_key(&name) {
+            return Err(ErrorType::CompileError(format!("Function redeclaration of {name}")));
+        }
+
+        let atom = self.closure(Some(&name), args, return_type, body)?;
+        let borrow = self.borrowed(&atom);
+        let local = self.declare_local(&name, atom.atom_type.value.clone())?;
+        if let Some(borrow) = borrow {
+            self.borrows.insert((self.scope_stack.len() - 1, name), borrow);
+        }
+
+        Ok(CompiledAtom::expr(ExprKind::Declare(local, Some(Box::new(self.value(atom)?))), ValueType::Void))
+    }
+
+    // lifts a fn literal to a function of its own, it only becomes a closure if it captures locals of the functions around it
+    pub(super) fn closure(&mut self, name: Option<&str>, args: Vec<Expression>, return_type: Expression, body: Expression) -> MudResult<CompiledAtom> {
+        let outer = match self.current_function.map(|function| &self.program.functions[function].name) {
+            Some(outer) if outer.starts_with("mud_") => outer.clone(),
+            Some(outer) => format!("mud_{outer}"),
+            None => self.mangle(""),
+        };
+        let symbol = format!("{outer}__{}", name.unwrap_or("fn"));
+
+        let captures = self.captures(name, &args, &body)?;
+        let return_value_type = self.type_expr(return_type)?;
+        let params = self.resolve_args(args)?;
+        let types = params.iter().map(|(_, value_type)| value_type.clone()).collect();
+
+        let value_type = if captures.is_empty() {
+            ValueType::Function { args: types, return_type: Box::new(return_value_type.clone()), variadic: false }
+        } else {
+            ValueType::Closure { args: types, return_type: Box::new(return_value_type.clone()) }
+        };
+
+        let function = self.declare_function(symbol, false, return_value_type);
+        self.program.functions[function].captures = captures.clone();
+
+        // a nested fn sees itself, so it can recurse
+        let self_value = name.map(|name| (name.to_string(), value_type.clone()));
+        self.closure_frames.push(ClosureFrame { base: self.scope_stack.len(), captures: captures.clone() });
+        let result = self.function_body(function, params, self_value, body);
+        self.closure_frames.pop();
+        result?;
+
+        if captures.is_empty() {
+            return Ok(CompiledAtom::expr(ExprKind::Function(function), value_type));
+        }
+
+        // the environment is copied to the heap, so a closure that only copies its captures can outlive the function
+        let mut places = Vec::new();
+        let mut borrow: Option<Borrow> = None;
+        for capture in captures {
+            let atom = self.identifier(capture.name.clone());
+            let depth = self.depth_of(&capture.name).unwrap();
+            let captured_borrow = match capture.by_ref {
+                true => Some(Borrow { depth, name: capture.name, argument: false }),
+                false => self.borrowed(&atom),
+            };
+            if captured_borrow.as_ref().is_some_and(|captured| borrow.as_ref().is_none_or(|deepest| captured.depth > deepest.depth)) {
+                borrow = captured_borrow;
+            }
+
+            places.push(self.value(atom)?);
+        }
+
+        let expr = borrow.map(ExprType::Borrow).unwrap_or(ExprType::Expression);
+        let node = Expr::new(ExprKind::Closure { function, captures: places }, value_type.clone());
+
+        Ok(CompiledAtom::new(Some(node), value_type, expr))
+    }
+
+    // the locals of enclosing functions a fn literal uses, found before its body is checked so its type is known
+    fn captures(&self, name: Option<&str>, args: &[Expression], body: &Expression) -> MudResult<Vec<typed::Capture>> {
+        let mut captures = Vec::new();
+        for (name, by_ref) in free_names(name, args, body) {
+            if self.type_params.contains_key(&name) {
+                continue;
+            }
+            match self.depth_of(&name) {
+                Some(depth) if depth > 0 => {}
+                _ => continue,
+            }
+
+            let value_type = self.scope_stack.iter().rev().find_map(|scope| scope.get(&name)).unwrap().clone();
+            if let ValueType::Module(_) = value_type {
+                continue;
+            }
+            captures.push(typed::Capture { name, value_type, by_ref });
+        }
+
+        Ok(captures)
+    }
+
+    // inside a closure, a captured variable is in the environment at this index
+    pub(super) fn captured(&self, name: &str) -> Option<usize> {
+        let frame = self.closure_frames.last()?;
+        let depth = self.depth_of(name)?;
+        if depth == 0 || depth >= frame.base {
+            return None;
+        }
+
+        frame.captures.iter().position(|c| c.name == name)
+    }
+
+    pub(super) fn depth_of(&self, name: &str) -> Option<usize> {
+        self.scope_stack.iter().rposition(|scope| scope.contains_key(name))
+    }
+
+    // a closure argument of the function being checked can be called and passed on, but not kept
+    pub(super) fn borrow_argument(&mut self, name: &str) {
+        let depth = self.scope_stack.len() - 1;
+        self.borrows.insert((depth, name.to_string()), Borrow { depth, name: name.to_string(), argument: true });
+    }
+
+    // what a value borrows, if it is a closure that must not outlive something
+    pub(super) fn borrowed(&self, atom: &CompiledAtom) -> Option<Borrow> {
+        match &atom.atom_type.expr {
+            ExprType::Borrow(borrow) => Some(borrow.clone()),
+            ExprType::Identifier(name) => self.borrows.get(&(self.depth_of(name)?, name.clone())).cloned(),
+            _ => None,
+        }
+    }
+
+    // a closure that borrows a variable must not be kept after the variable is gone
+    pub(super) fn check_borrow(&mut self, target: Option<&str>, value: &CompiledAtom) -> MudResult<()> {
+        let borrow = self.borrowed(value);
+
+        let Some(target) = target else {
+            return match borrow {
+                Some(borrow) => Err(ErrorType::CompileError(format!("Only a local variable can hold {}", borrow.closure()))),
+                None => Ok(()),
+            };
+        };
+
+        let Some(depth) = self.depth_of(target) else { return Ok(()) };
+        match borrow {
+            Some(borrow) if borrow.depth > depth => Err(ErrorType::CompileError(format!(
+                "{target} outlives {}, so it cannot hold {}", borrow.name, borrow.closure()))),
+            Some(borrow) => {
+                self.borrows.insert((depth, target.to_string()), borrow);
+                Ok(())
+            }
+            None => {
+                self.borrows.remove(&(depth, target.to_string()));
+                Ok(())
+            }
+        }
+    }
+
+    pub(super) fn check_return_borrow(&self, value: &CompiledAtom) -> MudResult<()> {
+        match self.borrowed(value) {
+            Some(borrow) => Err(ErrorType::CompileError(format!("Cannot return {}, {} does not outlive the call", borrow.closure(), borrow.name))),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, ValueType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstValue {
+    Integer(i64),
+    String(String),
+}
+
+impl ConstValue {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            ConstValue::Integer(_) => ValueType::I32,
+            ConstValue::String(_) => ValueType::Pointer(Box::new(ValueType::U8)),
+        }
+    }
+
+}
+
+fn checked_i32(value: Option<i64>) -> MudResult<ConstValue> {
+    match value {
+        Some(v) if i32::try_from(v).is_ok() => Ok(ConstValue::Integer(v)),
+        _ => Err(ErrorType::CompileError("Constant expression overflows i32".to_string())),
+    }
+}
+
+impl Compiler {
+    pub(super) fn const_eval(&mut self, expression: &Expression) -> MudResult<ConstValue> {
+        use ConstValue::*;
+
+        match expression {
+            Expression::Integer(i) => checked_i32(i64::try_from(*i).ok()),
+            Expression::String(s) => Ok(String(s.clone())),
+            Expression::Identifier(name) => {
+                if !self.is_constant(name) {
+                    return Err(ErrorType::CompileError(format!("{name} is not a compile-time constant")));
+                }
+
+                Ok(self.constants[name].clone())
+            }
+            Expression::UnaryOperation { op, oprand, .. } => {
+                match (op, self.const_eval(oprand)?) {
+                    (Operator::Minus, Integer(i)) => checked_i32(i.checked_neg()),
+                    (Operator::Exclaim, Integer(i)) => Ok(Integer((i == 0) as i64)),
+                    (op, v) => Err(ErrorType::CompileError(format!("Unary operator {op} cannot be applied to a constant {}", self.type_name(&v.value_type())))),
+                }
+            }
+            Expression::BinaryOperation { op: Operator::Dot, lhs, rhs } => {
+                let (Expression::Identifier(module), Expression::Identifier(member)) = (&**lhs, &**rhs) else {
+                    return Err(ErrorType::CompileError("Field access is not allowed in constant expressions".to_string()));
+                };
+                let Some(ValueType::Module(module)) = self.scope_stack[0].get(module).cloned() else {
+                    return Err(ErrorType::CompileError(format!("{module} is not a module")));
+                };
+
+                self.module_member(&module, member)?.constant.clone()
+                    .ok_or_else(|| ErrorType::CompileError(format!("{member} is not a compile-time constant")))
+            }
+            Expression::BinaryOperation { op, lhs, rhs } => {
+                let lhs = self.const_eval(lhs)?;
+                let rhs = self.const_eval(rhs)?;
+
+                match (op, lhs, rhs) {
+                    (Operator::Plus, Integer(l), Integer(r)) => checked_i32(l.checked_add(r)),
+                    (Operator::Minus, Integer(l), Integer(r)) => checked_i32(l.checked_sub(r)),
+                    (Operator::Asterisk, Integer(l), Integer(r)) => checked_i32(l.checked_mul(r)),
+                    (Operator::LessThan, Integer(l), Integer(r)) => Ok(Integer((l < r) as i64)),
+                    (Operator::GreaterThan, Integer(l), Integer(r)) => Ok(Integer((l > r) as i64)),
+                    (Operator::DoubleAmpersand, Integer(l), Integer(r)) => Ok(Integer((l != 0 && r != 0) as i64)),
+                    (Operator::DoubleBar, Integer(l), Integer(r)) => Ok(Integer((l != 0 || r != 0) as i64)),
+                    (Operator::DoubleEquals, l, r) if l.value_type() == r.value_type() => Ok(Integer((l == r) as i64)),
+                    (Operator::ExclaimEquals, l, r) if l.value_type() == r.value_type() => Ok(Integer((l != r) as i64)),
+                    (Operator::Plus, String(l), String(r)) => Ok(String(l + &r)),
+                    (op, l, r) => Err(ErrorType::CompileError(format!("Binary operator {op} cannot be applied to constants {} and {}", self.type_name(&l.value_type()), self.type_name(&r.value_type())))),
+                }
+            }
+            Expression::FunctionCall { function, args, .. } => {
+                match (&**function, &args[..]) {
+                    (Expression::Identifier(name), [arg]) if name == "sizeof" => {
+                        let size = self.type_expr(arg.clone())?.size()?;
+                        checked_i32(i64::try_from(size).ok())
+                    }
+                    (Expression::Identifier(name), [arg]) if name == "alignof" => {
+                        let align = self.type_expr(arg.clone())?.align()?;
+                        checked_i32(i64::try_from(align).ok())
+                    }
+                    _ => Err(ErrorType::CompileError("Function calls are not allowed in constant expressions".to_string())),
+                }
+            }
+            _ => Err(ErrorType::CompileError("Expression is not a compile-time constant".to_string())),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::path::PathBuf;
+
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, CompiledAtom, ValueType};
+use super::names::escape_field;
+use super::typed::{self, Expr, ExprKind};
+
+// headers for the parts of libc people reach for most, so most externs need no `@header`
+pub(crate) fn libc_header(name: &str) -> Option<&'static str> {
+    match name {
+        "printf" | "puts" | "putchar" | "getchar" | "fopen" | "fclose" | "fgets" | "fputs"
+            | "fprintf" | "remove" | "fread" | "fwrite" | "fflush" | "sprintf" | "snprintf" | "FILE" => Some("stdio.h"),
+        "malloc" | "calloc" | "realloc" | "free" | "exit" | "abs" | "atoi" | "rand" | "srand" | "getenv" | "div" | "div_t" => Some("stdlib.h"),
+        "strlen" | "strcmp" | "strncmp" | "strcpy" | "strncpy" | "strcat" | "memcpy" | "memmove"
+            | "memset" | "memcmp" => Some("string.h"),
+        "isdigit" | "isalpha" | "isalnum" | "isspace" | "toupper" | "tolower" => Some("ctype.h"),
+        "time" | "clock" => Some("time.h"),
+        _ => None,
+    }
+}
+
+// the C spelling of a type in the header of a library, exports are checked to only use these
+fn c_type(program: &typed::Program, value_type: &ValueType) -> String {
+    match value_type {
+        ValueType::I32 => "i32".to_string(),
+        ValueType::U8 => "char".to_string(),
+        ValueType::Void => "void".to_string(),
+        ValueType::Str => "mudrt_str".to_string(),
+        ValueType::Pointer(inner) => c_type(program, inner) + "*",
+        ValueType::Struct { .. } => program.struct_names.iter().find(|(t, _)| t == value_type).map(|(_, name)| name.clone())
+            .expect("exported structs are named"),
+        t => unreachable!("exports cannot use {t:?}"),
+    }
+}
+
+// the C spelling of a param or return type of an extern, which C holds against the prototype it has.
+// pointers to what C does not know of are void*, Mud's own types have none
+pub(crate) fn extern_c_type(value_type: &ValueType) -> Option<String> {
+    match value_type {
+        ValueType::I32 => Some("i32".to_string()),
+        ValueType::U8 => Some("char".to_string()),
+        ValueType::Usize => Some("size_t".to_string()),
+        ValueType::Void => Some("void".to_string()),
+        ValueType::Pointer(inner) => match &**inner {
+            ValueType::Opaque(name) => Some(format!("{name}*")),
+            inner => Some(extern_c_type(inner).map_or("void*".to_string(), |inner| inner + "*")),
+        },
+        // every struct Mud declares has a mangled symbol, an extern struct has its C name
+        ValueType::Struct { symbol, .. } if !symbol.is_empty() && !symbol.starts_with("mud_") => Some(symbol.clone()),
+        ValueType::Function { .. } => Some("void*".to_string()),
+        _ => None,
+    }
+}
+
+// the header C callers of a library include, declaring what it exports under their Mud names
+pub fn header(program: &typed::Program, module_name: &str) -> String {
+    let guard = format!("MUD_{}_H", module_name.to_uppercase());
+    let mut declarations = String::new();
+
+    for (name, value_type) in &program.exports {
+        match value_type {
+            ValueType::Function { args, return_type, .. } => {
+                let args = args.iter().map(|arg| c_type(program, arg)).collect::<Vec<_>>();
+                let args = if args.is_empty() { "void".to_string() } else { args.join(", ") };
+                declarations.push_str(&format!("{} {name}({args});\n", c_type(program, return_type)));
+            }
+            ValueType::Struct { fields, .. } => {
+                let fields = fields.iter().map(|(field, t)| format!("{} {}; ", c_type(program, t), escape_field(field))).collect::<String>();
+                declarations.push_str(&format!("typedef struct {{ {fields}}} {name};\n"));
+            }
+            t => unreachable!("only fn and struct are exported, not {t:?}"),
+        }
+    }
+
+    // str is a view of its bytes, laid out as the runtime lays it out
+    let str_type = match declarations.contains("mudrt_str") {
+        true => "typedef struct { char* ptr; i32 len; } mudrt_str;\n",
+        false => "",
+    };
+
+    format!("#ifndef {guard}\n#define {guard}\ntypedef int i32;\n{str_type}{declarations}#endif\n")
+}
+
+impl Compiler {
+    // what is exported has to be spelled in the header, so it can only use types C has a name for
+    pub(super) fn check_export(&self, value_type: &ValueType) -> MudResult<()> {
+        match value_type {
+            ValueType::Function { args, return_type, .. } => {
+                args.iter().chain([&**return_type]).try_for_each(|t| self.check_export(t))
+            }
+            ValueType::Struct { fields, .. } if self.program.struct_names.iter().any(|(t, _)| t == value_type) => {
+                fields.iter().try_for_each(|(_, t)| self.check_export(t))
+            }
+            ValueType::I32 | ValueType::U8 | ValueType::Void | ValueType::Str => Ok(()),
+            ValueType::Pointer(inner) => self.check_export(inner),
+            ValueType::Struct { .. } => Err(ErrorType::CompileError(format!("An export cannot use the unnamed struct {}", self.type_name(value_type)))),
+            t => Err(ErrorType::CompileError(format!("An export cannot use {}", self.type_name(t)))),
+        }
+    }
+
+    pub(super) fn extern_function(&mut self, name: String, args: Vec<Expression>, variadic: bool, return_type: Expression, header: Option<String>) -> MudResult<CompiledAtom> {
+        if self.scope_stack.len() != 1 {
+            return Err(ErrorType::CompileError("Extern functions are not allowed outside the top level".to_string()));
+        }
+
+        let return_type = self.type_expr(return_type)?;
+        let types = self.resolve_args(args)?.into_iter().map(|(_, t)| t).collect();
+
+        let f_type = ValueType::Function { args: types, return_type: Box::new(return_type), variadic };
+        if self.scope_stack.last_mut().unwrap().insert(name.clone(), f_type.clone()).is_some() {
+            return Err(ErrorType::CompileError(format!("Extern function {name} redeclares an existing name")));
+        }
+
+        // a relative header is the program's own C, which may only define the function static, any other is the C library's
+        let (header, include) = match header {
+            Some(header) if header.starts_with('.') => {
+                let path = self.import_stack.last().and_then(|file| file.parent()).map(PathBuf::from).unwrap_or_default().join(header);
+                (Some(path.canonicalize().unwrap_or(path)), None)
+            }
+            Some(header) => (None, Some(header)),
+            None => (None, libc_header(&name).map(String::from)),
+        };
+
+        self.program.externs.push(typed::Extern { name: name.clone(), value_type: f_type.clone(), header, include });
+        self.values.insert(name, Expr::new(ExprKind::Extern(self.program.externs.len() - 1), f_type));
+
+        Ok(CompiledAtom::void())
+    }
+
+    pub(super) fn extern_struct(&mut self, name: String, fields: Option<Vec<Expression>>, header: Option<String>) -> MudResult<CompiledAtom> {
+        if self.scope_stack.len() != 1 {
+            return Err(ErrorType::CompileError("Extern structs are not allowed outside the top level".to_string()));
+        }
+
+        let s_type = match fields {
+            Some(fields) => {
+                let fields = self.resolve_fields(fields)?;
+                self.structs.insert(name.clone(), fields.clone());
+                ValueType::Struct { symbol: name.clone(), fields }
+            }
+            None => ValueType::Opaque(name.clone()),
+        };
+
+        if self.scope_stack.last_mut().unwrap().insert(name.clone(), s_type.clone()).is_some() {
+            return Err(ErrorType::CompileError(format!("Extern struct {name} redeclares an existing name")));
+        }
+        if let ValueType::Struct { .. } = s_type {
+            self.program.struct_names.push((s_type, name.clone()));
+        }
+
+        // the layout has to come from C, so a header is required
+        if header.is_none() && libc_header(&name).is_none() {
+            return Err(ErrorType::CompileError(format!("No header known for extern struct {name}, add @header(\"...\")")));
+        }
+
+        Ok(CompiledAtom::void())
+    }
+}
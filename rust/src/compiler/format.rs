@@ -0,0 +1,116 @@
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, CompiledAtom, ValueType};
+use super::const_eval::ConstValue;
+use super::strings::unescape;
+use super::typed::ExprKind;
+
+// the printf conversion `{}` and `<` use for a value of this type
+pub fn default_conversion(value_type: &ValueType) -> Option<&'static str> {
+    match value_type {
+        ValueType::I32 => Some("%d"),
+        ValueType::U8 => Some("%c"),
+        // strings are not NUL terminated, so they are written with mudrt_print_str
+        ValueType::Str => Some("%.*s"),
+        ValueType::Pointer(inner) if **inner == ValueType::U8 => Some("%s"),
+        ValueType::Pointer(_) => Some("%p"),
+        _ => None,
+    }
+}
+
+// the printf conversion for an explicit placeholder such as `{x}`, if it can print this type
+pub fn conversion(spec: &str, value_type: &ValueType) -> Option<&'static str> {
+    match (spec, value_type) {
+        ("", t) => default_conversion(t),
+        ("d", ValueType::I32 | ValueType::U8) => Some("%d"),
+        ("x", ValueType::I32 | ValueType::U8) => Some("%x"),
+        ("c", ValueType::I32 | ValueType::U8) => Some("%c"),
+        ("s", ValueType::Pointer(inner)) if **inner == ValueType::U8 => Some("%s"),
+        ("s", ValueType::Str) => Some("%.*s"),
+        ("p", ValueType::Pointer(_)) => Some("%p"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Piece {
+    Text(String),
+    Placeholder(String),
+}
+
+// splits a format string into text and `{spec}` placeholders, `{{` and `}}` are literal braces
+pub fn parse_format(format: &str) -> MudResult<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err(ErrorType::CompileError(format!("Unclosed placeholder in format string \"{format}\""))),
+                    }
+                }
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                pieces.push(Piece::Placeholder(spec));
+            }
+            '}' => return Err(ErrorType::CompileError(format!("Unmatched }} in format string \"{format}\", use }}}} for a literal brace"))),
+            c => text.push(c),
+        }
+    }
+    pieces.push(Piece::Text(text));
+
+    Ok(pieces)
+}
+
+impl Compiler {
+    // print("x = {}, y = {}", x, y), the format has to be known at compile time so it can be checked
+    pub(super) fn format_print(&mut self, name: &str, mut args: Vec<Expression>, location: Location) -> MudResult<CompiledAtom> {
+        if args.is_empty() {
+            return Err(ErrorType::CompileError(format!("{name} expects a format string")));
+        }
+
+        let format = match self.const_eval(&args.remove(0)) {
+            Ok(ConstValue::String(format)) => format,
+            _ => return Err(ErrorType::CompileError(format!("The format of {name} must be a constant string"))),
+        };
+
+        let mut pieces = parse_format(&String::from_utf8_lossy(&unescape(&format)))?;
+        let placeholders = pieces.iter().filter(|piece| matches!(piece, Piece::Placeholder(_))).count();
+        if placeholders != args.len() {
+            return Err(ErrorType::CompileError(format!("Format \"{format}\" has {placeholders} placeholders but {name} got {} arguments", args.len())));
+        }
+
+        let mut values = Vec::new();
+        let specs = pieces.iter().filter_map(|piece| match piece {
+            Piece::Placeholder(spec) => Some(spec.clone()),
+            Piece::Text(_) => None,
+        }).collect::<Vec<_>>();
+        for (index, (spec, arg)) in specs.into_iter().zip(args).enumerate() {
+            let arg = self.convert(arg)?;
+            let value_type = self.resolve_type(&arg)?;
+
+            let conversion = conversion(&spec, &value_type).ok_or_else(|| ErrorType::CompileError(
+                format!("Placeholder {{{spec}}} cannot print argument {} of type {}", index + 1, self.type_name(&value_type))))?;
+            values.push((conversion, self.value(arg)?));
+        }
+
+        if name == "println" {
+            pieces.push(Piece::Text("\n".to_string()));
+        }
+        Ok(CompiledAtom::expr(ExprKind::Print { pieces, args: values, location }, ValueType::Void))
+    }
+}
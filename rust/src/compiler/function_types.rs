@@ -0,0 +1,29 @@
+use crate::parser::*;
+use crate::lexer::error::MudResult;
+
+use super::{Compiler, CompiledAtom, ValueType};
+
+impl Compiler {
+    // `fn(i32, *u8) -> i32`, a pointer to a function with exactly this signature
+    pub(super) fn function_type(&mut self, args: Vec<Expression>, return_type: Expression) -> MudResult<CompiledAtom> {
+        let (types, return_type) = self.signature_types(args, return_type)?;
+
+        Ok(CompiledAtom::of_type(ValueType::Function { args: types, return_type: Box::new(return_type), variadic: false }))
+    }
+
+    // the types of the arguments and return of a fn or closure type
+    pub(super) fn signature_types(&mut self, args: Vec<Expression>, return_type: Expression) -> MudResult<(Vec<ValueType>, ValueType)> {
+        let mut types = Vec::new();
+        for arg in args {
+            // names are allowed but mean nothing in a type
+            let arg = match arg {
+                Expression::BinaryOperation { op: Operator::Colon, lhs, rhs } if matches!(*lhs, Expression::Identifier(_)) => *rhs,
+                arg => arg,
+            };
+            types.push(self.type_expr(arg)?);
+        }
+        let return_type = self.type_expr(return_type)?;
+
+        Ok((types, return_type))
+    }
+}
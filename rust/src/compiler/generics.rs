@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, CompiledAtom, ValueType};
+use super::strings;
+use super::typed::{ExprKind, FunctionId};
+
+// a generic fn or struct, kept as syntax and checked again for every set of type arguments
+#[derive(Debug, Clone)]
+pub struct GenericDef {
+    pub name: String,
+    pub symbol: String,
+    pub params: Vec<String>,
+    // the interface each parameter is bounded by, if any
+    pub bounds: Vec<Option<String>>,
+    pub definition: Expression,
+    pub module_prefix: String,
+    // the argument types of a generic fn, in terms of ValueType::Param
+    pub arg_types: Vec<ValueType>,
+}
+
+// a type name as part of a symbol
+pub(super) fn suffix(type_name: &str) -> String {
+    type_name.replace('*', "ptr_").chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect()
+}
+
+impl Compiler {
+    // binds the type parameters in pattern so that it matches actual, the arguments are checked against the instance afterwards
+    fn unify(&self, pattern: &ValueType, actual: &ValueType, bindings: &mut HashMap<String, ValueType>) -> MudResult<()> {
+        match (pattern, actual) {
+            (ValueType::Param(param), actual) => match bindings.get(param) {
+                Some(bound) if bound != actual => Err(ErrorType::CompileError(format!("{param} is both {} and {}", self.type_name(bound), self.type_name(actual)))),
+                _ => {
+                    bindings.insert(param.clone(), actual.clone());
+                    Ok(())
+                }
+            },
+            (ValueType::Pointer(pattern), ValueType::Pointer(actual)) | (ValueType::Slice(pattern), ValueType::Slice(actual)) => self.unify(pattern, actual, bindings),
+            (ValueType::Function { args: pattern, return_type: pattern_return, .. }, ValueType::Function { args: actual, return_type: actual_return, .. }) if pattern.len() == actual.len() => {
+                for (pattern, actual) in pattern.iter().zip(actual) {
+                    self.unify(pattern, actual, bindings)?;
+                }
+                self.unify(pattern_return, actual_return, bindings)
+            }
+            // a plain fn passed for a closure binds the same way
+            (ValueType::Closure { args: pattern, return_type: pattern_return }, ValueType::Closure { args: actual, return_type: actual_return })
+            | (ValueType::Closure { args: pattern, return_type: pattern_return }, ValueType::Function { args: actual, return_type: actual_return, .. }) if pattern.len() == actual.len() => {
+                for (pattern, actual) in pattern.iter().zip(actual) {
+                    self.unify(pattern, actual, bindings)?;
+                }
+                self.unify(pattern_return, actual_return, bindings)
+            }
+            (ValueType::Struct { fields: pattern, .. }, ValueType::Struct { fields: actual, .. }) if pattern.len() == actual.len() => {
+                for ((_, pattern), (_, actual)) in pattern.iter().zip(actual) {
+                    self.unify(pattern, actual, bindings)?;
+                }
+                Ok(())
+            }
+            // a struct reached through its own fields is left without them, only one side is completed so this stops
+            (ValueType::Struct { symbol, fields }, ValueType::Struct { .. }) if fields.is_empty() && self.structs.contains_key(symbol) => self.unify(&self.complete(pattern.clone()), actual, bindings),
+            (ValueType::Struct { .. }, ValueType::Struct { symbol, fields }) if fields.is_empty() && self.structs.contains_key(symbol) => self.unify(pattern, &self.complete(actual.clone()), bindings),
+            _ => Ok(()),
+        }
+    }
+
+    // `name := fn[T](...)` or `name := struct[T]{...}`, checked once with placeholder types
+    pub(super) fn generic_definition(&mut self, name: String, params: Vec<(String, Option<Expression>)>, definition: Expression) -> MudResult<CompiledAtom> {
+        if self.scope_stack.len() != 1 {
+            return Err(ErrorType::CompileError("Generic definitions are not allowed outside the top level".to_string()));
+        }
+        if std::mem::take(&mut self.is_export) {
+            return Err(ErrorType::CompileError(format!("Generic {name} cannot be exported, export an instance of it instead")));
+        }
+
+        let mut bounds = Vec::new();
+        for (param, bound) in &params {
+            bounds.push(match bound {
+                Some(bound) => match self.type_expr(bound.clone())? {
+                    ValueType::Interface(key) => Some(key),
+                    t => return Err(ErrorType::CompileError(format!("Bound {} of {param} in {name} is not an interface", self.type_name(&t)))),
+                },
+                None => None,
+            });
+        }
+        let params = params.into_iter().map(|(param, _)| param).collect();
+
+        let symbol = self.mangle(&name);
+        let generic = GenericDef { name: name.clone(), symbol: symbol.clone(), params, bounds, definition, module_prefix: self.module_prefix.clone(), arg_types: Vec::new() };
+
+        // declared before it is checked, so it can refer to itself
+        if self.scope_stack[0].insert(name.clone(), ValueType::Generic(symbol.clone())).is_some() {
+            return Err(ErrorType::CompileError(format!("Generic {name} redeclares an existing name")));
+        }
+        self.generics.insert(symbol.clone(), generic.clone());
+
+        let placeholders: Vec<(String, ValueType)> = generic.params.iter()
+            .map(|param| (param.clone(), ValueType::Param(param.clone())))
+            .collect();
+        let param_bounds = generic.params.iter().cloned().zip(generic.bounds.iter().cloned())
+            .filter_map(|(param, bound)| Some((param, bound?)))
+            .collect();
+
+        // the check leaves no functions behind, only instances are lowered
+        let functions = self.program.functions.len();
+        let outer_checking = std::mem::replace(&mut self.checking_generic, true);
+        let checked = self.with_type_args(placeholders, param_bounds, |comp| match generic.definition.clone() {
+            Expression::Function { args, return_type, body } => {
+                let return_type = comp.type_expr(*return_type)?;
+                let params = comp.resolve_args(args)?;
+                // the argument types are known before the body, so a call of itself can infer its type arguments
+                comp.generics.get_mut(&symbol).unwrap().arg_types = params.iter().map(|(_, value_type)| value_type.clone()).collect();
+
+                let function = comp.declare_function(symbol.clone(), false, return_type);
+                comp.function_body(function, params, None, *body)
+            }
+            Expression::Struct { fields } => comp.struct_fields(symbol.clone(), fields).map(|_| ()),
+            _ => Err(ErrorType::CompileError("Only fn and struct can be generic".to_string())),
+        });
+        self.checking_generic = outer_checking;
+        self.program.functions.truncate(functions);
+
+        if let Err(e) = checked {
+            self.scope_stack[0].remove(&name);
+            self.generics.remove(&symbol);
+            return Err(match e {
+                ErrorType::CompileError(message) => ErrorType::CompileError(format!("In generic {name}: {message}")),
+                e => e,
+            });
+        }
+
+        Ok(CompiledAtom::void())
+    }
+
+    // `List[i32]` or `push[i32]`, with the type arguments given explicitly
+    pub(super) fn instantiate_with(&mut self, key: &str, args: Vec<Expression>) -> MudResult<CompiledAtom> {
+        let mut type_args = Vec::new();
+        for arg in args {
+            type_args.push(self.type_expr(arg)?);
+        }
+
+        self.instantiate(key, type_args)
+    }
+
+    pub(super) fn instantiate_expr(&mut self, target: Expression, args: Vec<Expression>) -> MudResult<CompiledAtom> {
+        let target = self.convert(target)?;
+
+        match self.resolve_type(&target)? {
+            ValueType::Generic(key) => self.instantiate_with(&key, args),
+            t => Err(ErrorType::CompileError(format!("{} is not generic", self.type_name(&t)))),
+        }
+    }
+
+    // a call to a generic fn, with the type arguments inferred from the arguments
+    pub(super) fn infer_instance(&mut self, key: &str, args: &[CompiledAtom]) -> MudResult<CompiledAtom> {
+        let generic = self.generics[key].clone();
+
+        // string literals go last, they are a str if another argument already made T one
+        let mut bindings = HashMap::new();
+        let (literals, others): (Vec<_>, Vec<_>) = generic.arg_types.iter().zip(args).partition(|(_, arg)| strings::is_literal(arg));
+        for (pattern, arg) in others.into_iter().chain(literals) {
+            let target = match pattern {
+                ValueType::Param(param) => bindings.get(param).unwrap_or(pattern),
+                _ => pattern,
+            };
+            let arg = strings::coerce(arg.clone(), target);
+            self.unify(pattern, &self.resolve_type(&arg)?, &mut bindings)?;
+        }
+
+        let mut type_args = Vec::new();
+        for param in &generic.params {
+            let value_type = bindings.get(param).ok_or_else(|| ErrorType::CompileError(
+                format!("Cannot infer {param} for {}, give it explicitly as {}[...](...)", generic.name, generic.name)))?;
+            type_args.push(value_type.clone());
+        }
+
+        self.instantiate(key, type_args)
+    }
+
+    fn instantiate(&mut self, key: &str, type_args: Vec<ValueType>) -> MudResult<CompiledAtom> {
+        let generic = self.generics[key].clone();
+
+        if type_args.len() != generic.params.len() {
+            return Err(ErrorType::CompileError(format!("{} expects {} type arguments but got {}", generic.name, generic.params.len(), type_args.len())));
+        }
+
+        for ((param, bound), value_type) in generic.params.iter().zip(&generic.bounds).zip(&type_args) {
+            if let ValueType::Unknown | ValueType::Module(_) | ValueType::Generic(_) = value_type {
+                return Err(ErrorType::CompileError(format!("Type {} cannot be a type argument", self.type_name(value_type))));
+            }
+            if let Some(bound) = bound {
+                if !self.implements(value_type, bound) {
+                    return Err(ErrorType::CompileError(format!("{} does not implement {}, which {param} of {} requires",
+                        self.type_name(value_type), self.interfaces[bound].name, generic.name)));
+                }
+            }
+        }
+
+        let instance = format!("{key}[{type_args:?}]");
+        let is_struct = matches!(generic.definition, Expression::Struct { .. });
+
+        if let Some((value_type, function)) = self.instances.get(&instance) {
+            return Ok(self.instance_atom(value_type.clone(), *function, is_struct));
+        }
+
+        let type_names = type_args.iter().map(|t| self.type_name(t)).collect::<Vec<_>>().join(", ");
+        let bindings = generic.params.iter().cloned().zip(type_args).collect::<Vec<_>>();
+
+        let (value_type, function) = self.in_module_of(&generic.module_prefix, |comp| comp.with_type_args(bindings, HashMap::new(), |comp| {
+            match generic.definition.clone() {
+                Expression::Struct { fields } => {
+                    // the fields can point to the instance, which they see without fields until it is complete
+                    comp.instances.insert(instance.clone(), (ValueType::Struct { symbol: instance.clone(), fields: Vec::new() }, None));
+                    let s_type = comp.struct_fields(instance.clone(), fields);
+
+                    match (&s_type, comp.checking_generic) {
+                        (Ok(s_type), false) => {
+                            comp.program.struct_names.push((s_type.clone(), format!("{}[{type_names}]", generic.name)));
+                            comp.instances.insert(instance.clone(), (s_type.clone(), None));
+                        }
+                        _ => {
+                            comp.instances.remove(&instance);
+                        }
+                    }
+
+                    Ok((s_type?, None))
+                }
+                Expression::Function { args, return_type, body } => {
+                    let return_type = comp.type_expr(*return_type)?;
+                    let params = comp.resolve_args(args)?;
+                    let arg_types = params.iter().map(|(_, value_type)| value_type.clone()).collect();
+                    let f_type = ValueType::Function { args: arg_types, return_type: Box::new(return_type.clone()), variadic: false };
+
+                    // while checking another generic only the signature matters
+                    if comp.checking_generic {
+                        return Ok((f_type, None));
+                    }
+
+                    // cached before the body is checked, so a generic fn can call itself
+                    let function = comp.declare_function(generic.symbol.clone(), false, return_type);
+                    comp.program.symbols.push((comp.qualify(&format!("{}[{type_names}]", generic.name)), function));
+                    comp.instances.insert(instance.clone(), (f_type.clone(), Some(function)));
+                    comp.function_body(function, params, None, *body)?;
+
+                    Ok((f_type, Some(function)))
+                }
+                _ => unreachable!("generics are only fn and struct"),
+            }
+        }))?;
+
+        Ok(self.instance_atom(value_type, function, is_struct))
+    }
+
+    fn instance_atom(&self, value_type: ValueType, function: Option<FunctionId>, is_struct: bool) -> CompiledAtom {
+        match (is_struct, function) {
+            (true, _) => CompiledAtom::of_type(value_type),
+            (false, Some(function)) => CompiledAtom::expr(ExprKind::Function(function), value_type),
+            // only the signature of an instance made while checking a generic is known, and it is never lowered
+            (false, None) => CompiledAtom::expr(ExprKind::Void, value_type),
+        }
+    }
+
+    // checks with each type parameter standing for a type, in a scope of its own
+    pub(super) fn with_type_args<R>(&mut self, args: Vec<(String, ValueType)>, bounds: HashMap<String, String>, f: impl FnOnce(&mut Compiler) -> MudResult<R>) -> MudResult<R> {
+        let outer_params = std::mem::replace(&mut self.type_params, args.into_iter().collect());
+        let outer_bounds = std::mem::replace(&mut self.param_bounds, bounds);
+        let globals = self.scope_stack[0].clone();
+        let outer_scopes = std::mem::replace(&mut self.scope_stack, vec![globals]);
+        let outer_locals = std::mem::take(&mut self.local_ids);
+        let outer_function = self.current_function.take();
+        let outer_return = self.current_return.take();
+        let outer_decl = std::mem::take(&mut self.is_decl);
+        let outer_frames = std::mem::take(&mut self.closure_frames);
+        let outer_borrows = std::mem::take(&mut self.borrows);
+
+        let result = f(self);
+
+        self.type_params = outer_params;
+        self.param_bounds = outer_bounds;
+        self.scope_stack = outer_scopes;
+        self.local_ids = outer_locals;
+        self.current_function = outer_function;
+        self.current_return = outer_return;
+        self.is_decl = outer_decl;
+        self.closure_frames = outer_frames;
+        self.borrows = outer_borrows;
+
+        result
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, CompiledAtom, ExprType, ValueType};
+use super::generics::suffix;
+use super::strings;
+use super::typed::{Conversion, ExprKind, FunctionId};
+
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub name: String,
+    pub args: Vec<ValueType>,
+    pub return_type: ValueType,
+}
+
+// an interface value is a fat pointer, the data and a vtable with a function per method
+#[derive(Debug, Clone)]
+pub struct InterfaceDef {
+    pub name: String,
+    pub key: String,
+    pub methods: Vec<Method>,
+}
+
+// the methods a type provides for an interface, its vtable has the same index in the program
+#[derive(Debug, Clone)]
+pub struct Impl {
+    pub self_type: ValueType,
+    pub interface: String,
+    pub methods: Vec<FunctionId>,
+}
+
+impl InterfaceDef {
+    fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+}
+
+impl Compiler {
+    // `Writer := interface{ write(s: str) -> i32 }`
+    pub(super) fn interface_definition(&mut self, name: String, signatures: Vec<MethodSignature>) -> MudResult<CompiledAtom> {
+        if self.scope_stack.len() != 1 {
+            return Err(ErrorType::CompileError("Interfaces are not allowed outside the top level".to_string()));
+        }
+        if std::mem::take(&mut self.is_export) {
+            return Err(ErrorType::CompileError(format!("Interface {name} cannot be exported")));
+        }
+
+        let key = self.mangle(&name);
+        let mut methods: Vec<Method> = Vec::new();
+
+        for MethodSignature { name: method, args, return_type } in signatures {
+            if methods.iter().any(|m| m.name == method) {
+                return Err(ErrorType::CompileError(format!("Interface {name} declares {method} twice")));
+            }
+
+            let args = self.resolve_fields(args)?;
+            let return_type = self.type_expr(return_type)?;
+            methods.push(Method {
+                name: method,
+                args: args.into_iter().map(|(_, t)| t).collect(),
+                return_type,
+            });
+        }
+
+        if self.scope_stack[0].insert(name.clone(), ValueType::Interface(key.clone())).is_some() {
+            return Err(ErrorType::CompileError(format!("Interface {name} redeclares an existing name")));
+        }
+        self.interfaces.insert(key.clone(), InterfaceDef { name, key, methods });
+
+        Ok(CompiledAtom::void())
+    }
+
+    // `impl Console: Writer { write := fn(self: *Console, s: str) -> i32 {...} }`
+    pub(super) fn impl_definition(&mut self, target: Expression, interface: Expression, methods: Vec<(String, Expression)>) -> MudResult<CompiledAtom> {
+        if self.scope_stack.len() != 1 {
+            return Err(ErrorType::CompileError("impl is not allowed outside the top level".to_string()));
+        }
+
+        let self_type = self.type_expr(target)?;
+        let interface = match self.type_expr(interface)? {
+            ValueType::Interface(key) => self.interfaces[&key].clone(),
+            t => return Err(ErrorType::CompileError(format!("{} is not an interface", self.type_name(&t)))),
+        };
+        let type_name = self.type_name(&self_type);
+        let title = format!("impl {type_name}: {}", interface.name);
+
+        if matches!(self_type, ValueType::Interface(_) | ValueType::Param(_) | ValueType::Void) {
+            return Err(ErrorType::CompileError(format!("{title}: only concrete types can implement an interface")));
+        }
+        if self.implements(&self_type, &interface.key) {
+            return Err(ErrorType::CompileError(format!("{title}: the type already implements {}", interface.name)));
+        }
+
+        let mut methods: HashMap<String, Expression> = methods.into_iter().collect();
+        if let Some(extra) = methods.keys().find(|name| interface.method(name).is_none()) {
+            return Err(ErrorType::CompileError(format!("{title}: {extra} is not a method of {}", interface.name)));
+        }
+
+        // every method is declared before the bodies, so the methods can call each other
+        let mut bodies = Vec::new();
+        for method in &interface.methods {
+            let Some(Expression::Function { args, return_type, body }) = methods.remove(&method.name) else {
+                return Err(ErrorType::CompileError(format!("{title}: {} is missing or is not a fn", method.name)));
+            };
+
+            let return_type = self.type_expr(*return_type)?;
+            let params = self.resolve_args(args)?;
+            let f_type = ValueType::Function {
+                args: params.iter().map(|(_, t)| t.clone()).collect(),
+                return_type: Box::new(return_type.clone()),
+                variadic: false,
+            };
+
+            let receiver = ValueType::Pointer(Box::new(self_type.clone()));
+            let expected = ValueType::Function {
+                args: std::iter::once(receiver).chain(method.args.iter().cloned()).collect(),
+                return_type: Box::new(method.return_type.clone()),
+                variadic: false,
+            };
+            if f_type != expected {
+                return Err(ErrorType::CompileError(format!("{title}: {} must be {} but is {}", method.name, self.type_name(&expected), self.type_name(&f_type))));
+            }
+
+            let symbol = self.mangle(&format!("{}__{}", suffix(&type_name), method.name));
+            let function = self.declare_function(symbol, false, return_type);
+            self.program.symbols.push((format!("{type_name}.{}", method.name), function));
+            bodies.push((function, params, *body));
+        }
+
+        let functions: Vec<FunctionId> = bodies.iter().map(|(function, _, _)| *function).collect();
+        self.impls.push(Impl { self_type, interface: interface.key.clone(), methods: functions.clone() });
+        self.program.vtables.push(functions);
+
+        for (function, params, body) in bodies {
+            self.function_body(function, params, None, body)?;
+        }
+
+        Ok(CompiledAtom::void())
+    }
+
+    // whether a type argument satisfies a bound, a bounded parameter and the interface itself both do
+    pub(super) fn implements(&self, value_type: &ValueType, interface: &str) -> bool {
+        match value_type {
+            ValueType::Param(param) => self.param_bounds.get(param).is_some_and(|bound| bound == interface),
+            ValueType::Interface(key) => key == interface,
+            t => self.impls.iter().any(|i| i.self_type == *t && i.interface == interface),
+        }
+    }
+
+    // an interface method named name on base, and the receiver type it takes
+    pub(super) fn interface_method(&mut self, base: &ValueType, name: &str) -> MudResult<Option<(CompiledAtom, ValueType)>> {
+        let method_type = |self_type: ValueType, method: &Method| ValueType::Function {
+            args: std::iter::once(self_type).chain(method.args.iter().cloned()).collect(),
+            return_type: Box::new(method.return_type.clone()),
+            variadic: false,
+        };
+
+        match base {
+            // dynamic dispatch, the interface value is passed on by value
+            ValueType::Interface(key) => {
+                let interface = &self.interfaces[key];
+                let index = interface.methods.iter().position(|method| method.name == name)
+                    .ok_or_else(|| ErrorType::CompileError(format!("{name} is not a method of {}", interface.name)))?;
+
+                let atom = CompiledAtom::new(None, method_type(base.clone(), &interface.methods[index]), ExprType::Dynamic(index));
+                Ok(Some((atom, base.clone())))
+            }
+
+            // inside a generic only the bound is known, each instance calls the method directly
+            ValueType::Param(param) => {
+                let Some(method) = self.param_bounds.get(param).and_then(|bound| self.interfaces[bound].method(name)) else {
+                    return Ok(None);
+                };
+                let receiver_type = ValueType::Pointer(Box::new(base.clone()));
+
+                Ok(Some((CompiledAtom::expr(ExprKind::Void, method_type(receiver_type.clone(), method)), receiver_type)))
+            }
+
+            // static dispatch
+            t => {
+                let found: Vec<(FunctionId, Method)> = self.impls.iter()
+                    .filter(|i| i.self_type == *t)
+                    .filter_map(|i| {
+                        let interface = &self.interfaces[&i.interface];
+                        let index = interface.methods.iter().position(|method| method.name == name)?;
+                        Some((i.methods[index], interface.methods[index].clone()))
+                    })
+                    .collect();
+
+                match &found[..] {
+                    [] => Ok(None),
+                    [(function, method)] => {
+                        let receiver_type = ValueType::Pointer(Box::new(base.clone()));
+
+                        Ok(Some((CompiledAtom::expr(ExprKind::Function(*function), method_type(receiver_type.clone(), method)), receiver_type)))
+                    }
+                    _ => Err(ErrorType::CompileError(format!("{name} is ambiguous, {} implements it for several interfaces", self.type_name(t)))),
+                }
+            }
+        }
+    }
+
+    // string literals become str, pointers to implementing types become interface values, and function pointers must match
+    pub(super) fn coerce(&mut self, atom: CompiledAtom, target: &ValueType) -> MudResult<CompiledAtom> {
+        let atom = strings::coerce(atom, target);
+        if let ValueType::Closure { .. } = target {
+            return self.coerce_closure(atom, target);
+        }
+
+        let actual = self.resolve_type(&atom)?;
+        if actual == *target {
+            return Ok(atom);
+        }
+
+        if let ValueType::Function { .. } = target {
+            match actual {
+                ValueType::Function { .. } => return Err(ErrorType::CompileError(format!(
+                    "Expected a function of type {} but got {}", self.type_name(target), self.type_name(&actual)))),
+                ValueType::Closure { .. } => return Err(ErrorType::CompileError(format!(
+                    "A closure that captures variables cannot be used as a function of type {}", self.type_name(target)))),
+                _ => {}
+            }
+        }
+        if let ValueType::Interface(key) = target {
+            return self.coerce_interface(atom, actual, key);
+        }
+
+        let conversion = match (target, &actual) {
+            (ValueType::I32, ValueType::U8) | (ValueType::U8, ValueType::I32) | (ValueType::Pointer(_), ValueType::I32) => Conversion::Int,
+            (ValueType::Usize, ValueType::I32 | ValueType::U8) | (ValueType::I32, ValueType::Usize) => Conversion::Int,
+            (ValueType::Void, _) => Conversion::Discard,
+            // values laid out the same are the same value, such as a *void as a *T or a str as a []u8
+            (target, actual) if layout(target).is_some() && layout(target) == layout(actual) => Conversion::Retype,
+            (target, actual) => return Err(ErrorType::CompileError(format!("Cannot use a {} as a {}", self.type_name(actual), self.type_name(target)))),
+        };
+
+        Ok(CompiledAtom::expr(ExprKind::Convert(conversion, Box::new(self.value(atom)?)), target.clone()))
+    }
+
+    fn coerce_interface(&mut self, atom: CompiledAtom, actual: ValueType, key: &str) -> MudResult<CompiledAtom> {
+        let target = ValueType::Interface(key.to_string());
+
+        let conversion = match actual {
+            // while checking a generic there is no vtable yet, only the bound
+            ValueType::Pointer(inner) if matches!(*inner, ValueType::Param(_)) && self.implements(&inner, key) => Conversion::Retype,
+            ValueType::Pointer(inner) if self.implements(&inner, key) && !matches!(*inner, ValueType::Interface(_)) => {
+                Conversion::Interface(self.impls.iter().position(|i| i.self_type == *inner && i.interface == key).unwrap())
+            }
+            t => return Err(ErrorType::CompileError(format!("{} cannot be used as {}, pass a pointer to a type that implements it",
+                self.type_name(&t), self.interfaces[key].name))),
+        };
+
+        Ok(CompiledAtom::expr(ExprKind::Convert(conversion, Box::new(self.value(atom)?)), target))
+    }
+}
+
+// the kind of value a type is at run time, the lowering cannot tell apart types of the same kind
+fn layout(value_type: &ValueType) -> Option<&'static str> {
+    match value_type {
+        ValueType::I32 => Some("i32"),
+        ValueType::U8 => Some("u8"),
+        ValueType::Usize => Some("usize"),
+        ValueType::Pointer(_) | ValueType::Function { .. } => Some("ptr"),
+        ValueType::Str | ValueType::Slice(_) => Some("view"),
+        _ => None,
+    }
+}
@@ -0,0 +1,179 @@
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, CompiledAtom, ExprType, ValueType};
+use super::generics::suffix;
+use super::strings;
+use super::typed::{self, ExprKind, FunctionId};
+
+// a fn from an impl block, it is a method if its first argument is the type or a pointer to it
+#[derive(Debug, Clone)]
+pub struct MethodDef {
+    pub self_type: ValueType,
+    pub name: String,
+    pub function: FunctionId,
+    pub f_type: ValueType,
+    pub is_method: bool,
+}
+
+fn pointer_depth(value_type: &ValueType) -> usize {
+    match value_type {
+        ValueType::Pointer(inner) => 1 + pointer_depth(inner),
+        _ => 0,
+    }
+}
+
+impl Compiler {
+    // `impl Lexer { next := fn(self: *Lexer) -> i32 {...} }`
+    pub(super) fn inherent_impl(&mut self, target: Expression, functions: Vec<(String, Expression)>) -> MudResult<CompiledAtom> {
+        if self.scope_stack.len() != 1 {
+            return Err(ErrorType::CompileError("impl is not allowed outside the top level".to_string()));
+        }
+
+        let self_type = self.type_expr(target)?;
+        let target_name = self.type_name(&self_type);
+
+        if matches!(self_type, ValueType::Interface(_) | ValueType::Param(_) | ValueType::Void) {
+            return Err(ErrorType::CompileError(format!("impl {target_name}: only concrete types can have methods")));
+        }
+
+        // every signature is known before the bodies are checked, so methods can call each other
+        let mut bodies = Vec::new();
+        for (name, function) in functions {
+            let Expression::Function { args, return_type, body } = function else {
+                return Err(ErrorType::CompileError(format!("impl {target_name}: {name} must be a fn")));
+            };
+            if self.methods.iter().any(|m| m.self_type == self_type && m.name == name) {
+                return Err(ErrorType::CompileError(format!("impl {target_name}: {name} is already defined")));
+            }
+
+            let return_value_type = self.type_expr(*return_type)?;
+            let params = self.resolve_args(args)?;
+            let types: Vec<ValueType> = params.iter().map(|(_, t)| t.clone()).collect();
+            let receiver = ValueType::Pointer(Box::new(self_type.clone()));
+            let is_method = types.first().is_some_and(|first| *first == self_type || *first == receiver);
+
+            let symbol = self.mangle(&format!("{}__{name}", suffix(&target_name)));
+            let function = self.declare_function(symbol, false, return_value_type.clone());
+            self.program.symbols.push((format!("{target_name}.{name}"), function));
+            self.methods.push(MethodDef {
+                self_type: self_type.clone(),
+                name,
+                function,
+                f_type: ValueType::Function { args: types, return_type: Box::new(return_value_type), variadic: false },
+                is_method,
+            });
+
+            bodies.push((function, params, *body));
+        }
+
+        for (function, params, body) in bodies {
+            self.function_body(function, params, None, body)?;
+        }
+
+        Ok(CompiledAtom::void())
+    }
+
+    // the function and receiver for `value.name(...)`, there is no receiver in `Type.name(...)`
+    pub(super) fn method(&mut self, receiver: &CompiledAtom, name: &str, location: Location) -> MudResult<Option<(CompiledAtom, Option<CompiledAtom>)>> {
+        if let Some(self_type) = self.named_type(receiver)? {
+            return self.associated(&self_type, name).map(|function| Some((function, None)));
+        }
+
+        // a string literal is a str when str has the method
+        let receiver = &match strings::is_literal(receiver) && self.methods.iter().any(|m| m.self_type == ValueType::Str && m.name == name) {
+            true => strings::coerce(receiver.clone(), &ValueType::Str),
+            false => receiver.clone(),
+        };
+
+        let mut base = self.resolve_type(receiver)?;
+        while let ValueType::Pointer(inner) = base {
+            base = self.complete(*inner);
+        }
+
+        // fields and module members are reached through dot
+        match &base {
+            ValueType::Struct { fields, .. } if fields.iter().any(|(field, _)| field == name) => return Ok(None),
+            ValueType::Module(_) => return Ok(None),
+            _ => {}
+        }
+
+        let inherent = self.methods.iter().find(|m| m.self_type == base && m.name == name && m.is_method).cloned();
+
+        let (function, receiver_type) = if let Some(method) = inherent {
+            let ValueType::Function { args, .. } = &method.f_type else { unreachable!() };
+            let receiver_type = args[0].clone();
+
+            (CompiledAtom::expr(ExprKind::Function(method.function), method.f_type), receiver_type)
+        } else if let Some(found) = self.interface_method(&base, name)? {
+            found
+        } else if let Some(found) = self.free_function(name) {
+            found
+        } else if let ValueType::Param(param) = base {
+            return Err(ErrorType::CompileError(format!("{param} has no method {name}, it needs a bound that declares it")));
+        } else {
+            return Ok(None);
+        };
+
+        let receiver = self.adjust_receiver(receiver.clone(), &receiver_type, location)?;
+        Ok(Some((function, Some(receiver))))
+    }
+
+    // `x.f(y)` calls a function in scope as `f(x, y)` when no method is named f
+    fn free_function(&mut self, name: &str) -> Option<(CompiledAtom, ValueType)> {
+        let value_type = self.scope_stack.iter().rev().find_map(|scope| scope.get(name))?.clone();
+        let receiver_type = match &value_type {
+            ValueType::Function { args, .. } => args.first()?.clone(),
+            ValueType::Generic(key) => self.generics[key].arg_types.first()?.clone(),
+            _ => return None,
+        };
+
+        Some((self.identifier(name.to_string()), receiver_type))
+    }
+
+    // the receiver has its address taken or is dereferenced until it is as much of a pointer as the method wants
+    fn adjust_receiver(&self, receiver: CompiledAtom, wanted: &ValueType, location: Location) -> MudResult<CompiledAtom> {
+        let mut value_type = self.resolve_type(&receiver)?;
+        let (have, want) = (pointer_depth(&value_type), pointer_depth(wanted));
+
+        if want == have + 1 {
+            let value = self.value(receiver)?;
+            return Ok(CompiledAtom::expr(ExprKind::AddressOf(Box::new(value)), ValueType::Pointer(Box::new(value_type))));
+        }
+        if want > have {
+            return Err(ErrorType::CompileError(format!("Cannot pass {} as a receiver of type {}", self.type_name(&value_type), self.type_name(wanted))));
+        }
+
+        let mut value = self.value(receiver)?;
+        for _ in want..have {
+            let ValueType::Pointer(inner) = value_type else { unreachable!() };
+            value_type = self.complete(*inner);
+            value = typed::Expr::new(ExprKind::Deref(Box::new(value), location), value_type.clone());
+        }
+
+        Ok(CompiledAtom::expr(value.kind, value_type))
+    }
+
+    // the type a receiver names, for calls such as `Lexer.new(program)`
+    fn named_type(&self, receiver: &CompiledAtom) -> MudResult<Option<ValueType>> {
+        match receiver.atom_type.expr {
+            ExprType::Type => Ok(Some(receiver.atom_type.value.clone())),
+            ExprType::Identifier(_) if receiver.node.is_none() => match self.resolve_type(receiver)? {
+                t @ ValueType::Struct { .. } => Ok(Some(t)),
+                _ => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn associated(&mut self, self_type: &ValueType, name: &str) -> MudResult<CompiledAtom> {
+        if let Some(method) = self.methods.iter().find(|m| m.self_type == *self_type && m.name == name).cloned() {
+            return Ok(CompiledAtom::expr(ExprKind::Function(method.function), method.f_type));
+        }
+
+        match self.interface_method(self_type, name)? {
+            Some((function, _)) if !matches!(self_type, ValueType::Interface(_) | ValueType::Param(_)) => Ok(function),
+            _ => Err(ErrorType::CompileError(format!("{} has no function {name}", self.type_name(self_type)))),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+use crate::trace::trace;
+
+use super::{module_prefix, optimize, Compiler, CompiledAtom, ExprType, ValueType};
+use super::const_eval::ConstValue;
+use super::stdlib;
+use super::typed::Expr;
+
+#[derive(Debug, Clone)]
+pub struct ModuleSymbol {
+    pub value_type: ValueType,
+    // what the member evaluates to, types and generics have nothing
+    pub value: Option<Expr>,
+    pub is_type: bool,
+    pub constant: Option<ConstValue>,
+}
+
+// the `pub` interface of a compiled module
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub symbols: HashMap<String, ModuleSymbol>,
+    // what generics of the module need to be instantiated later
+    prefix: String,
+    globals: HashMap<String, ValueType>,
+    values: HashMap<String, Expr>,
+    constants: HashMap<String, ConstValue>,
+}
+
+// the state that belongs to the module currently being compiled
+struct ModuleState {
+    scope_stack: Vec<HashMap<String, ValueType>>,
+    values: HashMap<String, Expr>,
+    constants: HashMap<String, ConstValue>,
+    public: Vec<(String, bool)>,
+    module_prefix: String,
+}
+
+pub fn module_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect())
+        .unwrap_or_default()
+}
+
+// the name a `pub` is attached to, looking through any attributes on the declaration
+pub(super) fn declared_name(target: &Expression) -> Option<(String, bool)> {
+    match target {
+        Expression::BinaryOperation { op: Operator::ColonEquals, lhs, rhs } => match &**lhs {
+            Expression::Identifier(name) => Some((name.clone(), match &**rhs {
+                Expression::Generic { body, .. } => matches!(**body, Expression::Struct { .. }),
+                rhs => matches!(rhs, Expression::Struct { .. } | Expression::Interface { .. }),
+            })),
+            _ => None,
+        },
+        Expression::Attribute { target, .. } => declared_name(target),
+        _ => None,
+    }
+}
+
+impl Compiler {
+    // the file imports are resolved relative to
+    pub fn set_file(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        self.file = path.display().to_string();
+        self.import_stack = vec![self.sources.resolve(&path).unwrap_or(path)];
+    }
+
+    fn resolve_import(&self, path: &str) -> MudResult<PathBuf> {
+        if let Some(std_path) = stdlib::std_module(path) {
+            return self.sources.resolve(&std_path)
+                .ok_or_else(|| ErrorType::CompileError(format!("Cannot find {path} in the standard library at {}", stdlib::std_dir().display())));
+        }
+
+        let base = self.import_stack.last()
+            .and_then(|file| file.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        self.sources.resolve(&base.join(path))
+            .ok_or_else(|| ErrorType::CompileError(format!("Cannot find module {path}")))
+    }
+
+    pub(super) fn import(&mut self, path: String) -> MudResult<CompiledAtom> {
+        if self.scope_stack.len() != 1 {
+            return Err(ErrorType::CompileError("Imports are not allowed outside the top level".to_string()));
+        }
+
+        let full_path = self.resolve_import(&path)?;
+        trace!(Resolve, Info, "import {path} from {}", full_path.display());
+
+        if let Some(index) = self.import_stack.iter().position(|p| *p == full_path) {
+            let cycle: Vec<String> = self.import_stack[index..].iter()
+                .chain(std::iter::once(&full_path))
+                .map(|p| p.file_name().unwrap_or_default().to_string_lossy().to_string())
+                .collect();
+            return Err(ErrorType::CompileError(format!("Cyclic import: {}", cycle.join(" -> "))));
+        }
+
+        // a module is only compiled the first time it is imported, later imports just bind its name
+        let key = full_path.to_string_lossy().to_string();
+        if !self.modules.contains_key(&key) {
+            self.compile_module(&full_path)?;
+        }
+
+        let name = self.modules[&key].name.clone();
+        if self.scope_stack.last_mut().unwrap().insert(name.clone(), ValueType::Module(key)).is_some() {
+            return Err(ErrorType::CompileError(format!("Import of {path} redeclares {name}")));
+        }
+
+        Ok(CompiledAtom::void())
+    }
+
+    fn compile_module(&mut self, path: &Path) -> MudResult<()> {
+        let program = self.sources.read(path)
+            .ok_or_else(|| ErrorType::CompileError(format!("Unable to read module {}", path.display())))?;
+
+        let name = module_name(path);
+        // modules of the same name from different directories are told apart by a number, which no name starts with
+        let mut prefix = module_prefix(&name);
+        let mut n = 1;
+        while self.modules.values().any(|module| module.prefix == prefix) {
+            n += 1;
+            prefix = format!("{}{name}{n}_", name.len());
+        }
+
+        let outer = self.enter_module(prefix);
+        self.import_stack.push(path.to_path_buf());
+
+        let result = Parser::new(program).parse().and_then(|expression| self.convert(optimize(expression, self.opt_level)))
+            .map_err(|e| e.in_file(&path.display().to_string()));
+
+        self.import_stack.pop();
+        let inner = self.leave_module(outer);
+        result?;
+
+        let mut symbols = HashMap::new();
+        for (symbol, is_type) in inner.public {
+            let value_type = inner.scope_stack[0].get(&symbol).cloned()
+                .ok_or_else(|| ErrorType::CompileError(format!("pub {symbol} is not declared")))?;
+            let value = inner.values.get(&symbol).cloned();
+
+            symbols.insert(symbol.clone(), ModuleSymbol { value_type, value, is_type, constant: inner.constants.get(&symbol).cloned() });
+        }
+
+        let globals = inner.scope_stack.into_iter().next().unwrap_or_default();
+        self.modules.insert(path.to_string_lossy().to_string(), Module {
+            name, symbols, prefix: inner.module_prefix, globals, values: inner.values, constants: inner.constants,
+        });
+
+        Ok(())
+    }
+
+    fn enter_module(&mut self, module_prefix: String) -> ModuleState {
+        ModuleState {
+            scope_stack: std::mem::replace(&mut self.scope_stack, vec![Compiler::globals()]),
+            values: std::mem::replace(&mut self.values, Compiler::global_values()),
+            constants: std::mem::take(&mut self.constants),
+            public: std::mem::take(&mut self.public),
+            module_prefix: std::mem::replace(&mut self.module_prefix, module_prefix),
+        }
+    }
+
+    fn leave_module(&mut self, outer: ModuleState) -> ModuleState {
+        ModuleState {
+            scope_stack: std::mem::replace(&mut self.scope_stack, outer.scope_stack),
+            values: std::mem::replace(&mut self.values, outer.values),
+            constants: std::mem::replace(&mut self.constants, outer.constants),
+            public: std::mem::replace(&mut self.public, outer.public),
+            module_prefix: std::mem::replace(&mut self.module_prefix, outer.module_prefix),
+        }
+    }
+
+    // runs f as if it were compiling the top level of the module with this prefix
+    pub(super) fn in_module_of<R>(&mut self, prefix: &str, f: impl FnOnce(&mut Compiler) -> MudResult<R>) -> MudResult<R> {
+        if prefix == self.module_prefix {
+            return f(self);
+        }
+
+        let module = self.modules.values().find(|module| module.prefix == prefix)
+            .ok_or_else(|| ErrorType::CompileError(format!("No module is compiled with prefix {prefix}")))?;
+        let (globals, values, constants) = (module.globals.clone(), module.values.clone(), module.constants.clone());
+
+        let outer = self.enter_module(prefix.to_string());
+        self.scope_stack = vec![globals];
+        self.values = values;
+        self.constants = constants;
+
+        let result = f(self);
+        self.leave_module(outer);
+
+        result
+    }
+
+    pub(super) fn public(&mut self, target: Expression) -> MudResult<CompiledAtom> {
+        if self.scope_stack.len() != 1 {
+            return Err(ErrorType::CompileError("pub is only allowed on top level declarations".to_string()));
+        }
+
+        let (name, is_type) = declared_name(&target)
+            .ok_or_else(|| ErrorType::CompileError("pub can only be applied to := definitions".to_string()))?;
+
+        let atom = self.convert(target)?;
+        self.public.push((name, is_type));
+
+        Ok(atom)
+    }
+
+    pub(super) fn module_member(&self, module: &str, member: &str) -> MudResult<&ModuleSymbol> {
+        let module = &self.modules[module];
+
+        module.symbols.get(member)
+            .ok_or_else(|| ErrorType::CompileError(format!("{member} is not a pub member of module {}", module.name)))
+    }
+
+    // `module.member` as an atom, members that are structs act as types
+    pub(super) fn module_access(&mut self, module: &str, member: &str) -> MudResult<CompiledAtom> {
+        let symbol = self.module_member(module, member)?.clone();
+        if symbol.is_type {
+            return Ok(CompiledAtom::of_type(symbol.value_type));
+        }
+
+        Ok(CompiledAtom::new(symbol.value, symbol.value_type, ExprType::Expression))
+    }
+}
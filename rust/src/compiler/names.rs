@@ -0,0 +1,42 @@
+use super::Compiler;
+
+// C keywords, plus names the prelude or libc headers may define as macros
+const C_RESERVED: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum",
+    "extern", "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return",
+    "short", "signed", "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void",
+    "volatile", "while", "_Bool", "_Complex", "_Imaginary", "bool", "true", "false",
+    "i32", "NULL", "EOF", "errno", "stdin", "stdout", "stderr", "assert", "offsetof",
+];
+
+// struct fields live in their own namespace in C, so only reserved names need escaping.
+// Mud identifiers start with a letter, so the escaped name can never be another field
+pub fn escape_field(name: &str) -> String {
+    if C_RESERVED.contains(&name) {
+        format!("_mud_{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+// what the symbols of a module start with after `mud_`. The name is length prefixed, so it never reads
+// as a name of the root module, which starts with a letter, or as the prefix of another module
+pub fn module_prefix(name: &str) -> String {
+    format!("{}{name}_", name.len())
+}
+
+impl Compiler {
+    // every symbol Mud defines gets a prefix, so it can never collide with C, the prelude or another module
+    pub(super) fn mangle(&self, name: &str) -> String {
+        format!("mud_{}{name}", self.module_prefix)
+    }
+
+    // a name as the code importing the module it is defined in writes it
+    pub(super) fn qualify(&self, name: &str) -> String {
+        let digits = self.module_prefix.chars().take_while(char::is_ascii_digit).count();
+        match self.module_prefix[..digits].parse::<usize>() {
+            Ok(len) => format!("{}.{name}", &self.module_prefix[digits..digits + len]),
+            Err(_) => name.to_string(),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::*;
+
+// how much a program is rewritten after it is parsed, the compiler, the interpreter and the IR all see the result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    // the program as written
+    #[default]
+    O0,
+    // integer constants folded and propagated, and code after a return dropped
+    O1,
+    // and the functions, structs and runtime helpers nothing uses left out
+    O2,
+}
+
+impl OptLevel {
+    // `-O0` to `-O2`, and `-O` for `-O1` as gcc takes it
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "-O0" => Some(OptLevel::O0),
+            "-O" | "-O1" => Some(OptLevel::O1),
+            "-O2" => Some(OptLevel::O2),
+            _ => None,
+        }
+    }
+}
+
+pub fn optimize(program: Expression, level: OptLevel) -> Expression {
+    if level == OptLevel::O0 {
+        return program;
+    }
+
+    let mut constants = HashMap::new();
+    let mut items: Vec<Expression> = sequence(program).into_iter().map(|item| top_level(item, &mut constants)).collect();
+    if level >= OptLevel::O2 {
+        items = used(items);
+    }
+
+    chain(items)
+}
+
+// the statements of `a; b; c`, which parses as `(a; b); c`
+fn sequence(expression: Expression) -> Vec<Expression> {
+    match expression {
+        Expression::BinaryOperation { op: Operator::Semicolon, lhs, rhs } => {
+            let mut items = sequence(*lhs);
+            items.push(*rhs);
+            items
+        }
+        expression => vec![expression],
+    }
+}
+
+fn chain(items: Vec<Expression>) -> Expression {
+    items.into_iter()
+        .reduce(|lhs, rhs| Expression::BinaryOperation { op: Operator::Semicolon, lhs: Box::new(lhs), rhs: Box::new(rhs) })
+        .unwrap_or(Expression::Null)
+}
+
+fn children(expression: &Expression) -> Vec<&Expression> {
+    match expression {
+        Expression::Null | Expression::Integer(_) | Expression::Identifier(_) | Expression::String(_) | Expression::Import(_) => Vec::new(),
+        Expression::BinaryOperation { lhs, rhs, .. } => vec![lhs, rhs],
+        Expression::UnaryOperation { oprand, .. } => vec![oprand],
+        Expression::FunctionCall { function, args, .. } => std::iter::once(&**function).chain(args).collect(),
+        Expression::Index { target, index, .. } => vec![target, index],
+        Expression::Slice { target, start, end, .. } => vec![target, start, end],
+        Expression::Instantiate { target, args } => std::iter::once(&**target).chain(args).collect(),
+        Expression::Generic { params, body } => params.iter().filter_map(|(_, bound)| bound.as_ref()).chain([&**body]).collect(),
+        Expression::SliceType(value) | Expression::Return(value) | Expression::Block(value) | Expression::Pub(value) => vec![value],
+        Expression::IfElse { condition, on_if, on_else } => vec![condition, on_if, on_else],
+        Expression::While { condition, body } => vec![condition, body],
+        Expression::Function { args, return_type, body } => args.iter().chain([&**return_type, &**body]).collect(),
+        Expression::FunctionType { args, return_type } | Expression::ClosureType { args, return_type } => args.iter().chain([&**return_type]).collect(),
+        Expression::Struct { fields } => fields.iter().collect(),
+        Expression::Interface { methods } => methods.iter().flat_map(|method| method.args.iter().chain([&method.return_type])).collect(),
+        Expression::Impl { target, interface, methods } => std::iter::once(&**target).chain(interface.as_deref()).chain(methods.iter().map(|(_, method)| method)).collect(),
+        Expression::ExternFunction { args, return_type, .. } => args.iter().chain([&**return_type]).collect(),
+        Expression::ExternStruct { fields, .. } => fields.iter().flatten().collect(),
+        Expression::Attribute { args, target, .. } => args.iter().chain([&**target]).collect(),
+    }
+}
+
+// every name an expression mentions, fields and shadowed names included, so more is kept rather than less
+fn names(expression: &Expression, found: &mut HashSet<String>) {
+    if let Expression::Identifier(name) = expression {
+        found.insert(name.clone());
+    }
+    for child in children(expression) {
+        names(child, found);
+    }
+}
+
+// the names a function declares for itself, its args, locals and nested fns, which hide constants of the same name
+fn locals(expression: &Expression, found: &mut HashSet<String>) {
+    if let Expression::BinaryOperation { op: Operator::Colon | Operator::ColonEquals, lhs, .. } = expression {
+        if let Expression::Identifier(name) = &**lhs {
+            found.insert(name.clone());
+        }
+    }
+    if let Expression::Generic { params, .. } = expression {
+        found.extend(params.iter().map(|(param, _)| param.clone()));
+    }
+    for child in children(expression) {
+        locals(child, found);
+    }
+}
+
+fn top_level(item: Expression, constants: &mut HashMap<String, i64>) -> Expression {
+    match item {
+        Expression::Pub(target) => Expression::Pub(Box::new(top_level(*target, constants))),
+        Expression::Attribute { name, args, target } => Expression::Attribute { name, args, target: Box::new(top_level(*target, constants)) },
+        Expression::BinaryOperation { op: Operator::ColonEquals, lhs, rhs } => {
+            let rhs = match *rhs {
+                rhs @ (Expression::Function { .. } | Expression::Generic { .. }) => function(rhs, constants),
+                rhs @ (Expression::Struct { .. } | Expression::Interface { .. }) => rhs,
+                rhs => {
+                    let rhs = rewrite(rhs, constants);
+                    if let (Expression::Identifier(name), Some(value)) = (&*lhs, integer(&rhs)) {
+                        constants.insert(name.clone(), value);
+                    }
+                    rhs
+                }
+            };
+            Expression::BinaryOperation { op: Operator::ColonEquals, lhs, rhs: Box::new(rhs) }
+        }
+        Expression::Impl { target, interface, methods } => Expression::Impl {
+            target,
+            interface,
+            methods: methods.into_iter().map(|(name, method)| (name, function(method, constants))).collect(),
+        },
+        item => item,
+    }
+}
+
+fn function(expression: Expression, constants: &HashMap<String, i64>) -> Expression {
+    let mut shadowed = HashSet::new();
+    locals(&expression, &mut shadowed);
+    let constants = constants.iter()
+        .filter(|(name, _)| !shadowed.contains(*name))
+        .map(|(name, &value)| (name.clone(), value))
+        .collect();
+
+    rewrite(expression, &constants)
+}
+
+// the value of an integer literal, negative ones are negated literals
+fn integer(expression: &Expression) -> Option<i64> {
+    match expression {
+        Expression::Integer(value) => i32::try_from(*value).ok().map(i64::from),
+        Expression::UnaryOperation { op: Operator::Minus, oprand, .. } => match **oprand {
+            Expression::Integer(value) => i64::try_from(value).ok().map(|value| -value).filter(|&value| value >= i32::MIN as i64),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// a folded value as a literal, as long as it fits the i32 the operation would have given
+fn literal(value: Option<i64>, location: Location) -> Option<Expression> {
+    let value = value.filter(|&value| i32::try_from(value).is_ok())?;
+    match value {
+        value if value < 0 => Some(Expression::UnaryOperation { op: Operator::Minus, oprand: Box::new(Expression::Integer(value.unsigned_abs())), location }),
+        value => Some(Expression::Integer(value as u64)),
+    }
+}
+
+fn fold_unary(op: Operator, oprand: Expression, location: Location) -> Expression {
+    let folded = match (op, integer(&oprand)) {
+        (Operator::Minus, Some(value)) => literal(Some(-value), location),
+        (Operator::Exclaim, Some(value)) => literal(Some((value == 0) as i64), location),
+        _ => None,
+    };
+
+    folded.unwrap_or(Expression::UnaryOperation { op, oprand: Box::new(oprand), location })
+}
+
+fn fold_binary(op: Operator, lhs: Expression, rhs: Expression) -> Expression {
+    let location = Location::default();
+    let folded = match (op, integer(&lhs), integer(&rhs)) {
+        // the side that decides `&&` and `||` makes the other one dead, whatever it does
+        (Operator::DoubleAmpersand, Some(0), _) => literal(Some(0), location),
+        (Operator::DoubleBar, Some(l), _) if l != 0 => literal(Some(1), location),
+        (_, Some(l), Some(r)) => match op {
+            Operator::Plus => literal(l.checked_add(r), location),
+            Operator::Minus => literal(l.checked_sub(r), location),
+            Operator::Asterisk => literal(l.checked_mul(r), location),
+            Operator::LessThan => literal(Some((l < r) as i64), location),
+            Operator::GreaterThan => literal(Some((l > r) as i64), location),
+            Operator::DoubleEquals => literal(Some((l == r) as i64), location),
+            Operator::ExclaimEquals => literal(Some((l != r) as i64), location),
+            Operator::DoubleAmpersand => literal(Some((l != 0 && r != 0) as i64), location),
+            Operator::DoubleBar => literal(Some((l != 0 || r != 0) as i64), location),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    folded.unwrap_or(Expression::BinaryOperation { op, lhs: Box::new(lhs), rhs: Box::new(rhs) })
+}
+
+// whether control never gets past a statement
+fn returns(expression: &Expression) -> bool {
+    match expression {
+        Expression::Return(_) => true,
+        Expression::Block(inner) => returns(inner),
+        Expression::BinaryOperation { op: Operator::Semicolon, lhs, rhs } => returns(lhs) || returns(rhs),
+        Expression::IfElse { on_if, on_else, .. } => returns(on_if) && returns(on_else),
+        _ => false,
+    }
+}
+
+// folds and propagates constants through code, only where a value is expected rather than a name or a type
+fn rewrite(expression: Expression, constants: &HashMap<String, i64>) -> Expression {
+    let rewrite_box = |expression: Box<Expression>| Box::new(rewrite(*expression, constants));
+
+    match expression {
+        Expression::Identifier(name) => match constants.get(&name) {
+            Some(&value) => literal(Some(value), Location::default()).unwrap_or(Expression::Identifier(name)),
+            None => Expression::Identifier(name),
+        },
+        // a constant keeps its address
+        expression @ Expression::UnaryOperation { op: Operator::Ampersand, .. } => expression,
+        Expression::UnaryOperation { op, oprand, location } => fold_unary(op, rewrite(*oprand, constants), location),
+        Expression::BinaryOperation { op: Operator::Semicolon, lhs, rhs } => {
+            let mut statements = Vec::new();
+            for statement in sequence(Expression::BinaryOperation { op: Operator::Semicolon, lhs, rhs }) {
+                let statement = rewrite(statement, constants);
+                let done = returns(&statement);
+                statements.push(statement);
+                if done {
+                    break;
+                }
+            }
+            chain(statements)
+        }
+        expression @ Expression::BinaryOperation { op: Operator::Colon, .. } => expression,
+        Expression::BinaryOperation { op: op @ (Operator::Dot | Operator::ColonEquals), lhs, rhs } => {
+            let (lhs, rhs) = match op {
+                Operator::Dot => (rewrite_box(lhs), rhs),
+                _ => (lhs, rewrite_box(rhs)),
+            };
+            Expression::BinaryOperation { op, lhs, rhs }
+        }
+        // assigning to a constant stays an error
+        Expression::BinaryOperation { op: Operator::Equals, lhs, rhs } => {
+            let lhs = match *lhs {
+                Expression::Identifier(name) => Box::new(Expression::Identifier(name)),
+                lhs => Box::new(rewrite(lhs, constants)),
+            };
+            Expression::BinaryOperation { op: Operator::Equals, lhs, rhs: rewrite_box(rhs) }
+        }
+        Expression::BinaryOperation { op, lhs, rhs } => fold_binary(op, rewrite(*lhs, constants), rewrite(*rhs, constants)),
+        Expression::FunctionCall { function, args, location } => {
+            // sizeof, alignof and alloc take a type first
+            let skip = match &*function {
+                Expression::Identifier(name) if name == "sizeof" || name == "alignof" || name == "alloc" => 1,
+                _ => 0,
+            };
+            let args = args.into_iter().enumerate()
+                .map(|(i, arg)| if i < skip { arg } else { rewrite(arg, constants) })
+                .collect();
+            Expression::FunctionCall { function: rewrite_box(function), args, location }
+        }
+        Expression::Index { target, index, location } => Expression::Index { target: rewrite_box(target), index: rewrite_box(index), location },
+        Expression::Slice { target, start, end, location } => Expression::Slice { target: rewrite_box(target), start: rewrite_box(start), end: rewrite_box(end), location },
+        Expression::Return(value) => Expression::Return(rewrite_box(value)),
+        Expression::Block(inner) => Expression::Block(rewrite_box(inner)),
+        Expression::IfElse { condition, on_if, on_else } => {
+            let condition = rewrite(*condition, constants);
+            match integer(&condition) {
+                Some(0) => rewrite(*on_else, constants),
+                Some(_) => rewrite(*on_if, constants),
+                None => Expression::IfElse { condition: Box::new(condition), on_if: rewrite_box(on_if), on_else: rewrite_box(on_else) },
+            }
+        }
+        Expression::While { condition, body } => {
+            let condition = rewrite(*condition, constants);
+            match integer(&condition) {
+                Some(0) => Expression::Null,
+                _ => Expression::While { condition: Box::new(condition), body: rewrite_box(body) },
+            }
+        }
+        Expression::Function { args, return_type, body } => Expression::Function { args, return_type, body: rewrite_box(body) },
+        Expression::Generic { params, body } => Expression::Generic { params, body: rewrite_box(body) },
+        expression => expression,
+    }
+}
+
+// the items main, exports, pub declarations and everything else that stays reaches, and the rest of the
+// functions and structs are left out
+fn used(items: Vec<Expression>) -> Vec<Expression> {
+    let declared = |item: &Expression| match item {
+        Expression::BinaryOperation { op: Operator::ColonEquals, lhs, rhs } => match (&**lhs, &**rhs) {
+            (Expression::Identifier(name), Expression::Function { .. } | Expression::Struct { .. } | Expression::Generic { .. }) if name != "main" => Some(name.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    let mut reached = HashSet::new();
+    let mut pending = Vec::new();
+    for item in &items {
+        if declared(item).is_none() {
+            names(item, &mut reached);
+        }
+    }
+    pending.extend(reached.iter().cloned());
+
+    let definitions: HashMap<String, &Expression> = items.iter().filter_map(|item| Some((declared(item)?, item))).collect();
+    while let Some(name) = pending.pop() {
+        if let Some(definition) = definitions.get(&name) {
+            let mut found = HashSet::new();
+            names(definition, &mut found);
+            for name in found {
+                if reached.insert(name.clone()) {
+                    pending.push(name);
+                }
+            }
+        }
+    }
+
+    items.into_iter().filter(|item| declared(item).is_none_or(|name| reached.contains(&name))).collect()
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use super::typed::{self, Expr, FunctionId, LocalId};
+use super::modules::declared_name;
+use super::{Compiler, ValueType};
+use crate::lexer::error::MudResult;
+use crate::parser::Expression;
+
+// a statement typed into the REPL, checked as the body of a function of its own
+pub struct Statement {
+    pub function: FunctionId,
+    pub value_type: ValueType,
+    // the locals the REPL keeps, with those the statement declares, by their names
+    pub locals: Vec<(String, LocalId)>,
+}
+
+impl Compiler {
+    // checking an input can declare names, functions and instances before it fails, an input that fails leaves none of them
+    fn or_restore<T>(&mut self, check: impl FnOnce(&mut Self) -> MudResult<T>) -> MudResult<T> {
+        let saved = self.clone();
+        let result = check(self);
+        if result.is_err() {
+            *self = saved;
+        }
+        result
+    }
+
+    // a declaration typed into the REPL is checked as if it were at the top level of a file. A name can be
+    // defined again, what was checked before keeps using the old definition
+    pub fn check_declaration(&mut self, expression: Expression) -> MudResult<()> {
+        self.or_restore(|compiler| {
+            if let Some((name, _)) = declared_name(&expression) {
+                // instances of a generic are kept by its symbol, which the new definition gets as well
+                if let Some(ValueType::Generic(key)) = compiler.scope_stack[0].remove(&name) {
+                    compiler.instances.retain(|instance, _| !instance.starts_with(&format!("{key}[")));
+                }
+                compiler.values.remove(&name);
+                compiler.constants.remove(&name);
+            }
+
+            compiler.is_decl = false;
+            let result = compiler.convert(expression);
+            compiler.scope_stack.truncate(1);
+            result.map(|_| ())
+        })
+    }
+
+    // statements typed into the REPL share one scope, like the body of a main that never ends
+    pub fn check_statement(&mut self, expression: Expression, locals: &mut HashMap<String, ValueType>) -> MudResult<Statement> {
+        let mut declared = locals.clone();
+        let statement = self.or_restore(|compiler| {
+            let (statement, body) = compiler.statement(expression, &mut declared)?;
+            compiler.program.functions[statement.function].body = body?;
+            Ok(statement)
+        })?;
+
+        *locals = declared;
+        Ok(statement)
+    }
+
+    // the type of a statement, which is checked and then forgotten
+    pub fn statement_type(&mut self, expression: Expression, locals: &HashMap<String, ValueType>) -> MudResult<ValueType> {
+        let saved = self.clone();
+        let result = self.statement(expression, &mut locals.clone());
+        *self = saved;
+
+        result.map(|(statement, _)| statement.value_type)
+    }
+
+    // the body is apart, as types have a type but no value
+    fn statement(&mut self, expression: Expression, locals: &mut HashMap<String, ValueType>) -> MudResult<(Statement, MudResult<Expr>)> {
+        self.is_decl = false;
+        let function = self.declare_function(self.mangle("repl"), false, ValueType::Void);
+        self.current_function = Some(function);
+        self.scope_stack.push(HashMap::new());
+
+        let result = locals.clone().into_iter().try_for_each(|(name, value_type)| self.declare_local(&name, value_type).map(|_| ()))
+            .and_then(|_| self.convert(expression))
+            .and_then(|atom| Ok((self.resolve_type(&atom)?, self.value(atom))));
+        self.current_function = None;
+        self.scope_stack.truncate(2);
+
+        let scope = self.scope_stack.pop().unwrap();
+        let (value_type, body) = result?;
+        let depth = self.scope_stack.len();
+        let named = scope.keys().map(|name| (name.clone(), self.local_ids[&(depth, name.clone())])).collect();
+        *locals = scope;
+
+        Ok((Statement { function, value_type, locals: named }, body))
+    }
+
+    pub fn program(&self) -> &typed::Program {
+        &self.program
+    }
+
+    pub fn global_type(&self, name: &str) -> Option<ValueType> {
+        self.scope_stack[0].get(name).cloned()
+    }
+
+    // a type as it is written in Mud, named structs and interfaces go by their names
+    pub fn type_name(&self, value_type: &ValueType) -> String {
+        let list = |types: &[ValueType]| types.iter().map(|t| self.type_name(t)).collect::<Vec<_>>().join(", ");
+
+        match value_type {
+            ValueType::I32 => "i32".to_string(),
+            ValueType::U8 => "u8".to_string(),
+            ValueType::Usize => "usize".to_string(),
+            ValueType::Void | ValueType::Unknown => "void".to_string(),
+            ValueType::Str => "str".to_string(),
+            ValueType::Pointer(inner) => format!("*{}", self.type_name(inner)),
+            ValueType::Slice(element) => format!("[]{}", self.type_name(element)),
+            ValueType::Function { args, return_type, variadic } => {
+                let dots = if *variadic { ", ..." } else { "" };
+                format!("fn({}{dots}) -> {}", list(args), self.type_name(return_type))
+            }
+            ValueType::Closure { args, return_type } => format!("closure({}) -> {}", list(args), self.type_name(return_type)),
+            ValueType::Struct { fields, .. } => {
+                let named = self.program.struct_names.iter().find(|(t, _)| t == value_type).map(|(_, name)| name.clone());
+                named.unwrap_or_else(|| {
+                    let fields = fields.iter().map(|(name, t)| format!("{name}: {}", self.type_name(t))).collect::<Vec<_>>();
+                    format!("struct{{{}}}", fields.join(", "))
+                })
+            }
+            ValueType::Interface(key) => self.interfaces.get(key).map(|interface| interface.name.clone()).unwrap_or_else(|| key.clone()),
+            ValueType::Generic(key) => self.generics.get(key).map(|generic| generic.name.clone()).unwrap_or_else(|| key.clone()),
+            ValueType::Opaque(name) | ValueType::Module(name) | ValueType::Param(name) => name.clone(),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// C helpers the generated code calls into, they are part of every program
+pub const RUNTIME: &str = r#"typedef struct { char* ptr; i32 len; } mudrt_str;
+typedef struct { mudrt_str* ptr; i32 len; } mudrt_slice_mudrt_str;
+static inline void mudrt_exit(i32 code) {
+    fflush(stdout);
+    exit(code);
+}
+static inline i32 mudrt_bounds(i32 index, i32 len) {
+    if (index < 0 || index >= len) {
+        fflush(stdout);
+        fprintf(stderr, "index %d is out of bounds for length %d\n", index, len);
+        exit(101);
+    }
+    return index;
+}
+static inline i32 mudrt_range(i32 start, i32 end, i32 len) {
+    if (start < 0 || start > end || end > len) {
+        fflush(stdout);
+        fprintf(stderr, "range %d..%d is out of bounds for length %d\n", start, end, len);
+        exit(101);
+    }
+    return start;
+}
+static inline void mudrt_print_str(mudrt_str s) {
+    fwrite(s.ptr, 1, s.len, stdout);
+}
+static inline mudrt_slice_mudrt_str mudrt_args(int argc, char** argv) {
+    mudrt_slice_mudrt_str args = { calloc(argc, sizeof(mudrt_str)), argc };
+    for (int i = 0; i < argc; i++) {
+        args.ptr[i].ptr = argv[i];
+        args.ptr[i].len = strlen(argv[i]);
+    }
+    return args;
+}
+static inline i32 mudrt_read_line(mudrt_str* line) {
+    i32 capacity = 128, len = 0;
+    int c;
+    char* buffer = malloc(capacity);
+    while ((c = getchar()) != EOF && c != '\n') {
+        if (len + 1 == capacity) {
+            capacity *= 2;
+            buffer = realloc(buffer, capacity);
+        }
+        buffer[len++] = (char)c;
+    }
+    buffer[len] = 0;
+    line->ptr = buffer;
+    line->len = len;
+    return c != EOF || len > 0;
+}
+static inline mudrt_str mudrt_read_all(void) {
+    i32 capacity = 256, len = 0;
+    size_t n;
+    char* buffer = malloc(capacity);
+    while ((n = fread(buffer + len, 1, capacity - len - 1, stdin)) > 0) {
+        len += n;
+        if (len + 1 == capacity) {
+            capacity *= 2;
+            buffer = realloc(buffer, capacity);
+        }
+    }
+    buffer[len] = 0;
+    return (mudrt_str){ buffer, len };
+}
+static inline i32 mudrt_str_cmp(mudrt_str a, mudrt_str b) {
+    int c = memcmp(a.ptr, b.ptr, a.len < b.len ? a.len : b.len);
+    return c != 0 ? c : (a.len > b.len) - (a.len < b.len);
+}
+static inline i32 mudrt_str_eq(mudrt_str a, mudrt_str b) {
+    return a.len == b.len && memcmp(a.ptr, b.ptr, a.len) == 0;
+}
+static inline char* mudrt_str_to_cstr(mudrt_str s) {
+    char* c = malloc(s.len + 1);
+    memcpy(c, s.ptr, s.len);
+    c[s.len] = 0;
+    return c;
+}
+static inline mudrt_str mudrt_str_concat(mudrt_str a, mudrt_str b) {
+    char* c = malloc(a.len + b.len + 1);
+    memcpy(c, a.ptr, a.len);
+    memcpy(c + a.len, b.ptr, b.len);
+    c[a.len + b.len] = 0;
+    return (mudrt_str){ c, a.len + b.len };
+}
+static inline mudrt_str mudrt_str_slice(mudrt_str s, i32 start, i32 end) {
+    return (mudrt_str){ s.ptr + mudrt_range(start, end, s.len), end - start };
+}
+static inline mudrt_str mudrt_str_from_cstr(char* c) {
+    return (mudrt_str){ c, strlen(c) };
+}
+static inline mudrt_str mudrt_str_from_int(i32 i) {
+    char* c = malloc(12);
+    return (mudrt_str){ c, snprintf(c, 12, "%d", i) };
+}
+/* an optional sign and the digits that follow it, anything after them is ignored */
+static inline i32 mudrt_str_to_int(mudrt_str s) {
+    i32 i = 0, value = 0, sign = 1;
+    if (s.len > 0 && (s.ptr[0] == '-' || s.ptr[0] == '+')) {
+        sign = s.ptr[0] == '-' ? -1 : 1;
+        i = 1;
+    }
+    for (; i < s.len && s.ptr[i] >= '0' && s.ptr[i] <= '9'; i++) {
+        value = value * 10 + (s.ptr[i] - '0');
+    }
+    return sign * value;
+}
+"#;
+
+// the builtin read_file, the whole file in a buffer of its own
+pub const READ_FILE: &str = r#"char* read_file(char *filename){
+    char * buffer = 0;
+    long length;
+    FILE * f = fopen (filename, "rb");
+    if (f)
+    {
+        fseek (f, 0, SEEK_END);
+        length = ftell (f);
+        fseek (f, 0, SEEK_SET);
+        buffer = malloc (length);
+        if (buffer)
+        {
+            fread (buffer, 1, length, f);
+        }
+        fclose (f);
+    }
+    return buffer;
+}
+"#;
@@ -0,0 +1,57 @@
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, CompiledAtom, ValueType};
+use super::typed::ExprKind;
+
+// slices and strings are a pointer and a length, these are the fields Mud code can see
+pub fn view_fields(value_type: &ValueType) -> Option<Vec<(String, ValueType)>> {
+    let element = match value_type {
+        ValueType::Str => ValueType::U8,
+        ValueType::Slice(element) => (**element).clone(),
+        _ => return None,
+    };
+
+    Some(vec![("ptr".to_string(), ValueType::Pointer(Box::new(element))), ("len".to_string(), ValueType::I32)])
+}
+
+impl Compiler {
+    // `[]T`, a pointer to the elements and how many there are
+    pub(super) fn slice_type(&mut self, element: Expression) -> MudResult<CompiledAtom> {
+        let value_type = self.type_expr(element)?;
+
+        Ok(CompiledAtom::of_type(ValueType::Slice(Box::new(value_type))))
+    }
+
+    // indexing slices and strings is bounds checked, indexing pointers is not
+    pub(super) fn index(&mut self, target: Expression, index: Expression, location: Location) -> MudResult<CompiledAtom> {
+        let target = self.convert(target)?;
+        if let ValueType::Generic(key) = self.resolve_type(&target)? {
+            return self.instantiate_with(&key, vec![index]);
+        }
+        let index = self.convert(index)?;
+
+        let index_type = self.resolve_type(&index)?;
+        if !matches!(index_type, ValueType::I32 | ValueType::U8) {
+            return Err(ErrorType::CompileError(format!("Cannot index with a {}, indices must be integers", self.type_name(&index_type))));
+        }
+
+        let element = match self.resolve_type(&target)? {
+            ValueType::Slice(element) | ValueType::Pointer(element) => self.complete(*element),
+            ValueType::Str => ValueType::U8,
+            t => return Err(ErrorType::CompileError(format!("Cannot index type {}", self.type_name(&t)))),
+        };
+        let index = self.coerce(index, &ValueType::I32)?;
+
+        Ok(CompiledAtom::expr(ExprKind::Index { target: Box::new(self.value(target)?), index: Box::new(self.value(index)?), location }, element))
+    }
+
+    pub(super) fn len(&mut self, value: Expression) -> MudResult<CompiledAtom> {
+        let value = self.convert(value)?;
+
+        match self.resolve_type(&value)? {
+            ValueType::Slice(_) | ValueType::Str => Ok(CompiledAtom::expr(ExprKind::Field(Box::new(self.value(value)?), 1), ValueType::I32)),
+            t => Err(ErrorType::CompileError(format!("Cannot take the length of type {}", self.type_name(&t)))),
+        }
+    }
+}
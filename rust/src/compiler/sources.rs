@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// where Mud files are read from, a file given in memory hides any file on disk at the same path
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+// `a/./b/../c` as `a/c`, without asking the file system, which may not have the file
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+impl Sources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: impl AsRef<Path>, source: impl Into<Vec<u8>>) {
+        self.files.insert(normalize(path.as_ref()), source.into());
+    }
+
+    pub fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        match self.files.get(&normalize(path)) {
+            Some(source) => Some(source.clone()),
+            None => fs::read(path).ok(),
+        }
+    }
+
+    // the one path a file is known by, so a module imported twice is loaded once
+    pub fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        let normalized = normalize(path);
+        match self.files.contains_key(&normalized) {
+            true => Some(normalized),
+            false => path.canonicalize().ok(),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use std::path::PathBuf;
+
+// the standard library shipped with the compiler, MUD_STD_PATH overrides it for installed binaries
+pub fn std_dir() -> PathBuf {
+    std::env::var_os("MUD_STD_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/std")))
+}
+
+// `std/io` and `std/io.mud` both name a module of the standard library
+pub fn std_module(path: &str) -> Option<PathBuf> {
+    let name = path.strip_prefix("std/")?;
+    let file = if name.ends_with(".mud") { name.to_string() } else { format!("{name}.mud") };
+
+    Some(std_dir().join(file))
+}
@@ -0,0 +1,123 @@
+use crate::parser::*;
+use crate::lexer::error::{MudResult, ErrorType};
+
+use super::{Compiler, CompiledAtom, ExprType, ValueType};
+use super::typed::{Builtin, Expr, ExprKind};
+
+fn is_c_string(value_type: &ValueType) -> bool {
+    matches!(value_type, ValueType::Pointer(inner) if **inner == ValueType::U8)
+}
+
+pub fn is_literal(atom: &CompiledAtom) -> bool {
+    matches!(atom.atom_type.expr, ExprType::Literal) && is_c_string(&atom.atom_type.value)
+}
+
+// string literals become a str wherever one is expected, everything else is left alone
+pub fn coerce(atom: CompiledAtom, target: &ValueType) -> CompiledAtom {
+    if *target == ValueType::Str && is_literal(&atom) {
+        let node = atom.node.map(|node| Expr::new(node.kind, ValueType::Str));
+        return CompiledAtom::new(node, ValueType::Str, ExprType::Expression);
+    }
+
+    atom
+}
+
+// the bytes a string literal stands for, its escapes read as C reads them
+pub fn unescape(literal: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = literal.bytes();
+
+    while let Some(c) = chars.next() {
+        if c != b'\\' {
+            bytes.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b'0') => bytes.push(0),
+            Some(c) => bytes.push(c),
+            None => bytes.push(b'\\'),
+        }
+    }
+
+    bytes
+}
+
+impl Compiler {
+    // operators where one side is a str, a literal on the other side is coerced to match
+    pub(super) fn str_operation(&self, op: Operator, lhs: CompiledAtom, rhs: CompiledAtom) -> MudResult<CompiledAtom> {
+        let lhs = coerce(lhs, &ValueType::Str);
+        let rhs = coerce(rhs, &ValueType::Str);
+
+        let (l, r) = (self.resolve_type(&lhs)?, self.resolve_type(&rhs)?);
+        if l != ValueType::Str || r != ValueType::Str {
+            return Err(ErrorType::CompileError(format!("Cannot apply {op} to {} and {}, convert with str() first", self.type_name(&l), self.type_name(&r))));
+        }
+
+        let value_type = match op {
+            Operator::Plus => ValueType::Str,
+            Operator::DoubleEquals | Operator::ExclaimEquals | Operator::LessThan | Operator::GreaterThan => ValueType::I32,
+            op => return Err(ErrorType::CompileError(format!("Binary operator {op} cannot be applied to strings"))),
+        };
+
+        Ok(CompiledAtom::expr(ExprKind::Str(op, Box::new(self.value(lhs)?), Box::new(self.value(rhs)?)), value_type))
+    }
+
+    // `s[start..end]`, either bound can be left out
+    pub(super) fn subslice(&mut self, target: Expression, start: Expression, end: Expression, location: Location) -> MudResult<CompiledAtom> {
+        let target = self.convert(target)?;
+        let target_type = self.resolve_type(&target)?;
+
+        let start = match start {
+            Expression::Null => None,
+            start => Some(Box::new(self.index_value(start)?)),
+        };
+        let end = match end {
+            Expression::Null => None,
+            end => Some(Box::new(self.index_value(end)?)),
+        };
+
+        match &target_type {
+            ValueType::Str | ValueType::Slice(_) => {
+                Ok(CompiledAtom::expr(ExprKind::Slice { target: Box::new(self.value(target)?), start, end, location }, target_type))
+            }
+            t => Err(ErrorType::CompileError(format!("Cannot slice type {}", self.type_name(t)))),
+        }
+    }
+
+    fn index_value(&mut self, index: Expression) -> MudResult<Expr> {
+        let index = self.convert(index)?;
+
+        match self.resolve_type(&index)? {
+            ValueType::I32 | ValueType::U8 => {
+                let index = self.coerce(index, &ValueType::I32)?;
+                self.value(index)
+            }
+            t => Err(ErrorType::CompileError(format!("Slice bounds must be integers but got {}", self.type_name(&t)))),
+        }
+    }
+
+    // str(x) makes a str from an integer or a C string, i32(s) parses one and cstr(s) hands one to C
+    pub(super) fn str_conversion(&mut self, name: &str, value: Expression, location: Location) -> MudResult<CompiledAtom> {
+        let value = self.convert(value)?;
+        let value = coerce(value, &ValueType::Str);
+        let value_type = self.resolve_type(&value)?;
+
+        let (builtin, result_type) = match (name, &value_type) {
+            ("str", ValueType::I32 | ValueType::U8) => (Builtin::StrFromInt, ValueType::Str),
+            ("str", t) if is_c_string(t) => (Builtin::StrFromCstr, ValueType::Str),
+            ("str", ValueType::Str) => return Ok(value),
+            ("i32", ValueType::Str) => (Builtin::StrToInt, ValueType::I32),
+            ("cstr", ValueType::Str) => (Builtin::StrToCstr, ValueType::Pointer(Box::new(ValueType::U8))),
+            (name, t) => return Err(ErrorType::CompileError(format!("Cannot convert {} with {name}()", self.type_name(t)))),
+        };
+        let value = match builtin {
+            Builtin::StrFromInt => self.coerce(value, &ValueType::I32)?,
+            _ => value,
+        };
+
+        Ok(CompiledAtom::expr(ExprKind::Builtin { builtin, args: vec![self.value(value)?], location }, result_type))
+    }
+}
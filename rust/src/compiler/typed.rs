@@ -0,0 +1,174 @@
+use std::path::PathBuf;
+
+use crate::parser::{Location, Operator};
+
+use super::format::Piece;
+use super::ValueType;
+
+// a checked program as the lowering reads it, every name is resolved and every conversion is explicit
+
+pub type FunctionId = usize;
+pub type ExternId = usize;
+// the locals of a function are numbered from 0, its params first
+pub type LocalId = usize;
+
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub externs: Vec<Extern>,
+    // the methods of each impl of an interface, in the order the interface declares them
+    pub vtables: Vec<Vec<FunctionId>>,
+    pub main: Option<FunctionId>,
+    // the Mud name of each struct type, generic instances are named with their type arguments
+    pub struct_names: Vec<(ValueType, String)>,
+    // the functions and structs marked @export, in the order they are declared
+    pub exports: Vec<(String, ValueType)>,
+    // the functions with a name in Mud under that name, for the map of it to their symbols in the C
+    pub symbols: Vec<(String, FunctionId)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    // the symbol it is lowered as, unless another function has it already
+    pub name: String,
+    pub export: bool,
+    // the file it is written in, for runtime errors
+    pub file: String,
+    pub params: Vec<LocalId>,
+    pub locals: Vec<ValueType>,
+    pub return_type: ValueType,
+    // what a closure keeps in its environment, it takes the environment before its params
+    pub captures: Vec<Capture>,
+    // a nested fn sees itself under its own name
+    pub self_local: Option<LocalId>,
+    pub body: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub name: String,
+    pub value_type: ValueType,
+    pub by_ref: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Extern {
+    pub name: String,
+    pub value_type: ValueType,
+    // a header of the program's own, which may only define the function static
+    pub header: Option<PathBuf>,
+    // the header of the C library that declares it
+    pub include: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub value_type: ValueType,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Void,
+    Integer(i64),
+    // the bytes of a string literal, a *u8 to a copy with a NUL after it or a str of them
+    String(Vec<u8>),
+    Local(LocalId),
+    // a local of the functions around a closure, in its environment
+    Capture(usize),
+    Function(FunctionId),
+    Extern(ExternId),
+    // a fn literal that captures locals, with the places it captures in the order of its captures. Its environment is
+    // on the heap and owned by none of the copies of the closure, so like memory from alloc nothing frees it
+    Closure { function: FunctionId, captures: Vec<Expr> },
+    // `-` and `!` of an integer
+    Unary(Operator, Box<Expr>),
+    // integers, and pointers and fns which compare by address, `+` moves a pointer by elements
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    // `&&` and `||`, the right side only runs when the left does not decide
+    Logical(Operator, Box<Expr>, Box<Expr>),
+    Str(Operator, Box<Expr>, Box<Expr>),
+    Deref(Box<Expr>, Location),
+    // the address of a place, or of a copy of a value the checker made a receiver
+    AddressOf(Box<Expr>),
+    // the fields of a struct, and ptr and len of a str or slice
+    Field(Box<Expr>, usize),
+    // slices and str are bounds checked, pointers are not
+    Index { target: Box<Expr>, index: Box<Expr>, location: Location },
+    Slice { target: Box<Expr>, start: Option<Box<Expr>>, end: Option<Box<Expr>>, location: Location },
+    // a fn or closure, the arguments after the params of a variadic fn are as C promotes them
+    Call { callee: Box<Expr>, args: Vec<Expr>, location: Location },
+    // a method through the vtable of the interface value that is the first argument
+    Dynamic { method: usize, args: Vec<Expr>, location: Location },
+    Builtin { builtin: Builtin, args: Vec<Expr>, location: Location },
+    // each placeholder with the printf conversion of its argument
+    Print { pieces: Vec<Piece>, args: Vec<(&'static str, Expr)>, location: Location },
+    Convert(Conversion, Box<Expr>),
+    Sequence(Box<Expr>, Box<Expr>),
+    If { condition: Box<Expr>, then: Box<Expr>, otherwise: Box<Expr> },
+    While { condition: Box<Expr>, body: Box<Expr> },
+    Return(Box<Expr>),
+    // a new local, zeroed unless it is given a value
+    Declare(LocalId, Option<Box<Expr>>),
+    Assign(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    // the size of the element type, the count is the argument
+    Alloc(u64),
+    Free,
+    Exit,
+    ReadLine,
+    ReadAll,
+    StrFromInt,
+    StrFromCstr,
+    StrToInt,
+    StrToCstr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conversion {
+    // between integers, and from an integer to a pointer
+    Int,
+    // a closure without an environment
+    FunctionToClosure,
+    // a pointer to a value and the vtable of its type
+    Interface(usize),
+    // the same value as another type, such as a *void as a *T
+    Retype,
+    // evaluated for what it does, where nothing is wanted
+    Discard,
+}
+
+// what a closure keeps of each capture, the value or a pointer to it
+pub fn env_type(captures: &[Capture]) -> ValueType {
+    let fields = captures.iter().map(|capture| {
+        let value_type = match capture.by_ref {
+            true => ValueType::Pointer(Box::new(capture.value_type.clone())),
+            false => capture.value_type.clone(),
+        };
+        (capture.name.clone(), value_type)
+    }).collect();
+
+    ValueType::Struct { symbol: String::new(), fields }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, value_type: ValueType) -> Self {
+        Self { kind, value_type }
+    }
+
+    pub fn void() -> Self {
+        Self::new(ExprKind::Void, ValueType::Void)
+    }
+
+    // what can be assigned to and have its address taken
+    pub fn is_place(&self) -> bool {
+        match &self.kind {
+            ExprKind::Local(_) | ExprKind::Capture(_) | ExprKind::Deref(..) | ExprKind::Index { .. } => true,
+            ExprKind::Field(base, _) => base.is_place(),
+            _ => false,
+        }
+    }
+}
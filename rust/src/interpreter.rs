@@ -0,0 +1,705 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::compiler::typed::{self, Conversion, Expr, ExprKind, ExternId, FunctionId, LocalId};
+use crate::compiler::{self, OptLevel, Sources, ValueType};
+use crate::lexer::error::{MudResult, ErrorType};
+use crate::parser::{Location, Operator};
+
+mod builtins;
+mod memory;
+mod repl;
+
+use builtins::Stream;
+use memory::{Memory, Value, FUNCTION_BASE};
+pub use repl::Outcome;
+
+// mud calls nest Rust calls, the interpreter thread gets a stack deep enough for this many
+const MAX_CALL_DEPTH: usize = 10_000;
+pub const STACK_SIZE: usize = 1 << 30;
+
+// what stops evaluation early, a return unwinds to its call and the others to the top
+enum Unwind {
+    Return(Value),
+    Exit(i32),
+    Error(ErrorType),
+}
+
+impl From<ErrorType> for Unwind {
+    fn from(error: ErrorType) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type Flow<T> = Result<T, Unwind>;
+
+// where the program reads and writes, the terminal unless a caller captures it
+pub struct Io {
+    pub output: Box<dyn Write + Send>,
+    pub errors: Box<dyn Write + Send>,
+    pub input: Box<dyn BufRead + Send>,
+}
+
+impl Io {
+    pub fn std() -> Self {
+        Self {
+            output: Box::new(std::io::stdout()),
+            errors: Box::new(std::io::stderr()),
+            input: Box::new(std::io::BufReader::new(std::io::stdin())),
+        }
+    }
+}
+
+// memory a value lives in, the location is where the program reached it
+#[derive(Debug, Clone)]
+struct Place {
+    address: u64,
+    value_type: ValueType,
+    location: Location,
+}
+
+// something a function pointer can point at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Target {
+    Function(FunctionId),
+    Extern(ExternId),
+}
+
+struct Frame {
+    function: FunctionId,
+    // a local has memory from where it is first declared until its function returns
+    locals: HashMap<LocalId, Place>,
+    captures: Vec<Place>,
+    // copies of values that needed an address, such as receivers a method takes by pointer
+    temporaries: Vec<u64>,
+}
+
+impl Frame {
+    fn new(function: FunctionId) -> Self {
+        Self { function, locals: HashMap::new(), captures: Vec::new(), temporaries: Vec::new() }
+    }
+}
+
+// checks and runs the program at path, returning its exit code
+pub fn interpret(sources: &Sources, path: &str, args: Vec<String>, io: Io, level: OptLevel) -> MudResult<i32> {
+    let program = sources.read(Path::new(path)).ok_or_else(|| ErrorType::RuntimeError(format!("Unable to open file {path}")))?;
+    let mut compiler = compiler::Compiler::new();
+    compiler.set_sources(sources.clone());
+    compiler.set_file(path);
+    compiler.set_opt_level(level);
+    let program = compiler.check(program)?;
+    let Some(main) = program.main else {
+        return Err(ErrorType::RuntimeError(format!("{path} has no main function")));
+    };
+
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || Interpreter::new(io, args, program).run(main))
+        .map_err(|e| ErrorType::RuntimeError(format!("Unable to start the interpreter: {e}")))?
+        .join()
+        .unwrap_or_else(|_| Err(ErrorType::RuntimeError("The interpreter crashed".to_string())))
+}
+
+// runs the program the checker typed, every name in it is already resolved
+pub struct Interpreter {
+    program: Rc<typed::Program>,
+    memory: Memory,
+    callables: Vec<Target>,
+    callable_ids: HashMap<Target, usize>,
+    vtables: HashMap<usize, u64>,
+    strings: HashMap<Vec<u8>, u64>,
+    streams: HashMap<u64, Stream>,
+    frames: Vec<Frame>,
+    // the locals of a REPL session, by the names they were declared with
+    locals: HashMap<String, Place>,
+    location: Location,
+    io: Io,
+    args: Vec<String>,
+    exit_code: i32,
+}
+
+impl Interpreter {
+    pub fn new(io: Io, args: Vec<String>, program: typed::Program) -> Self {
+        let mut interpreter = Self {
+            program: Rc::new(program), memory: Memory::default(), callables: Vec::new(), callable_ids: HashMap::new(),
+            vtables: HashMap::new(), strings: HashMap::new(), streams: HashMap::new(), frames: Vec::new(), locals: HashMap::new(),
+            location: Location::default(), io, args, exit_code: 0,
+        };
+        interpreter.open_std_streams();
+        interpreter
+    }
+
+    fn run(&mut self, main: FunctionId) -> MudResult<i32> {
+        let result = self.run_main(main);
+        let _ = self.io.output.flush();
+
+        match result {
+            Ok(code) | Err(Unwind::Exit(code)) => Ok(code),
+            Err(Unwind::Return(_)) => unreachable!("returns stop at their call"),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+
+    fn run_main(&mut self, main: FunctionId) -> Flow<i32> {
+        // main(args: []str) gets the program arguments as strs
+        let mut args = Vec::new();
+        if !self.program.functions[main].params.is_empty() {
+            let strs = self.args.clone().into_iter().map(|arg| self.str_value(arg.as_bytes())).collect::<Vec<_>>();
+            let address = self.memory.alloc(16 * strs.len() as u64);
+            for (i, s) in strs.iter().enumerate() {
+                self.memory.write(address + 16 * i as u64, &s.bytes).unwrap();
+            }
+            args.push(Value::view(ValueType::Slice(Box::new(ValueType::Str)), address, strs.len() as i32));
+        }
+
+        Ok(self.call_function(main, args, None)?.as_int() as i32)
+    }
+
+    // a runtime error at the location the program has reached
+    fn fault(&self, message: impl std::fmt::Display) -> Unwind {
+        self.fault_at(self.location, message)
+    }
+
+    fn fault_at(&self, location: Location, message: impl std::fmt::Display) -> Unwind {
+        let file = self.frames.last().map(|frame| self.program.functions[frame.function].file.as_str()).unwrap_or_default();
+        Unwind::Error(ErrorType::RuntimeError(message.to_string()).at(location).in_file(file))
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("code only runs inside a frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("code only runs inside a frame")
+    }
+
+    // the frame of the function that is done, with the memory of its locals
+    fn leave(&mut self) {
+        let frame = self.frames.pop().expect("code only runs inside a frame");
+        for address in frame.locals.values().map(|place| place.address).chain(frame.temporaries) {
+            let _ = self.memory.free(address);
+        }
+    }
+
+    // function values
+
+    // fns and externs are told apart from data by addresses where nothing is ever allocated
+    fn function_address(&mut self, target: Target) -> u64 {
+        let callable = match self.callable_ids.get(&target) {
+            Some(&callable) => callable,
+            None => {
+                self.callables.push(target);
+                self.callable_ids.insert(target, self.callables.len() - 1);
+                self.callables.len() - 1
+            }
+        };
+        FUNCTION_BASE + 16 * callable as u64
+    }
+
+    fn callable_at(&self, address: u64) -> Flow<Target> {
+        let index = address.wrapping_sub(FUNCTION_BASE) / 16;
+        if address < FUNCTION_BASE || !address.is_multiple_of(16) || index as usize >= self.callables.len() {
+            return Err(match address {
+                0 => self.fault("call through a null function pointer"),
+                _ => self.fault(format!("call through invalid function pointer 0x{address:x}")),
+            });
+        }
+        Ok(self.callables[index as usize])
+    }
+
+    fn function_value(&mut self, target: Target, value_type: ValueType) -> Value {
+        let address = self.function_address(target);
+        Value::new(value_type, address.to_le_bytes().to_vec())
+    }
+
+    // a closure is the fn it calls with an environment, and the fn it calls without one
+    fn closure_value(&mut self, function: FunctionId, env: u64, value_type: ValueType) -> Value {
+        let address = self.function_address(Target::Function(function));
+        let bytes = [address.to_le_bytes(), env.to_le_bytes(), [0; 8]].concat();
+        Value::new(value_type, bytes)
+    }
+
+    // a fn literal that captures locals, its environment holds them or pointers to them
+    fn closure(&mut self, function: FunctionId, places: &[Expr], value_type: &ValueType) -> Flow<Value> {
+        let program = self.program.clone();
+        let captures = &program.functions[function].captures;
+
+        // the environment lives as long as the closure may, so it is on the heap and never freed
+        let env_type = typed::env_type(captures);
+        let env = self.memory.alloc(self.size(&env_type)?);
+        for (index, (capture, place)) in captures.iter().zip(places).enumerate() {
+            let (offset, field_type) = self.field(&env_type, index)?;
+            let value = match capture.by_ref {
+                true => Value::new(field_type, self.place(place)?.address.to_le_bytes().to_vec()),
+                false => self.eval(place)?,
+            };
+            self.memory.write(env + offset, &value.bytes).map_err(|e| self.fault(e))?;
+        }
+
+        Ok(self.closure_value(function, env, value_type.clone()))
+    }
+
+    // the methods of an impl in the order its interface declares them, laid out as C lays out the vtable
+    fn vtable(&mut self, implementation: usize) -> u64 {
+        if let Some(&vtable) = self.vtables.get(&implementation) {
+            return vtable;
+        }
+
+        let mut bytes = Vec::new();
+        for function in self.program.vtables[implementation].clone() {
+            bytes.extend(self.function_address(Target::Function(function)).to_le_bytes());
+        }
+        let vtable = self.memory.store(&bytes);
+        self.vtables.insert(implementation, vtable);
+        vtable
+    }
+
+    // types
+
+    fn size(&self, value_type: &ValueType) -> Flow<u64> {
+        match value_type {
+            // arithmetic on a *void moves by bytes, as it does in GNU C
+            ValueType::Void => Ok(1),
+            value_type => value_type.size().map_err(|_| self.fault(format!("{value_type:?} has no size"))),
+        }
+    }
+
+    // the offset and type of a field by its index, str and slices have ptr and len
+    fn field(&self, value_type: &ValueType, index: usize) -> Flow<(u64, ValueType)> {
+        let fields = match (compiler::view_fields(value_type), value_type) {
+            (Some(fields), _) => fields,
+            (None, ValueType::Struct { fields, .. }) => fields.clone(),
+            (None, t) => return Err(self.fault(format!("{t:?} has no fields"))),
+        };
+
+        let mut offset: u64 = 0;
+        for (i, (_, field_type)) in fields.into_iter().enumerate() {
+            let align = field_type.align()?;
+            offset = offset.div_ceil(align) * align;
+            if i == index {
+                return Ok((offset, field_type));
+            }
+            offset += field_type.size()?;
+        }
+
+        Err(self.fault(format!("{value_type:?} has no field {index}")))
+    }
+
+    // memory
+
+    fn load(&mut self, place: &Place) -> Flow<Value> {
+        let size = self.size(&place.value_type)?;
+        let bytes = self.memory.read(place.address, size).map_err(|e| self.fault_at(place.location, e))?;
+        Ok(Value::new(place.value_type.clone(), bytes))
+    }
+
+    fn store(&mut self, place: &Place, value: Value) -> Flow<()> {
+        self.memory.write(place.address, &value.bytes).map_err(|e| self.fault_at(place.location, e))
+    }
+
+    fn read_word(&self, address: u64) -> Flow<u64> {
+        let bytes = self.memory.read(address, 8).map_err(|e| self.fault(e))?;
+        Ok(Value::new(ValueType::Void, bytes).word(0, 8))
+    }
+
+    // a local of the function that is running, declaring it again reuses its memory as C reuses its stack slot
+    fn declare(&mut self, local: LocalId, value: Option<Value>) -> Flow<()> {
+        let value_type = self.program.functions[self.frame().function].locals[local].clone();
+        let value = value.unwrap_or_else(|| Value::zeroed(value_type.clone()));
+
+        match self.frame().locals.get(&local).cloned() {
+            Some(place) => self.store(&place, value),
+            None => {
+                let place = Place { address: self.memory.store(&value.bytes), value_type, location: self.location };
+                self.frame_mut().locals.insert(local, place);
+                Ok(())
+            }
+        }
+    }
+
+    // a value that needs an address, such as a receiver a method takes by pointer
+    fn temporary(&mut self, value: Value) -> u64 {
+        let address = self.memory.store(&value.bytes);
+        self.frame_mut().temporaries.push(address);
+        address
+    }
+
+    fn string_literal(&mut self, bytes: &[u8]) -> u64 {
+        if let Some(&address) = self.strings.get(bytes) {
+            return address;
+        }
+
+        let address = self.memory.store(&[bytes, &[0]].concat());
+        self.strings.insert(bytes.to_vec(), address);
+        address
+    }
+
+    // a str of new memory holding bytes, NUL terminated so C can read it too
+    fn str_value(&mut self, bytes: &[u8]) -> Value {
+        let address = self.memory.store(&[bytes, &[0]].concat());
+        Value::view(ValueType::Str, address, bytes.len() as i32)
+    }
+
+    fn str_bytes(&self, value: &Value) -> Flow<Vec<u8>> {
+        let (address, len) = value.view_parts();
+        self.memory.read(address, len.max(0) as u64).map_err(|e| self.fault(e))
+    }
+
+    // the conversions the checker made explicit where a value meets the type it is used as
+    fn coerce(&mut self, conversion: Conversion, value: Value, target: &ValueType) -> Value {
+        match conversion {
+            Conversion::Int => match value.value_type {
+                ValueType::I32 | ValueType::U8 => Value::integer(target.clone(), value.as_int()),
+                _ => Value::integer(target.clone(), value.as_address() as i64),
+            },
+            Conversion::FunctionToClosure => Value::new(target.clone(), [&[0; 16], &value.bytes[..]].concat()),
+            Conversion::Interface(implementation) => {
+                let vtable = self.vtable(implementation);
+                Value::new(target.clone(), [&value.bytes[..], &vtable.to_le_bytes()].concat())
+            }
+            Conversion::Retype => Value { value_type: target.clone(), ..value },
+            Conversion::Discard => Value::void(),
+        }
+    }
+
+    // evaluation
+
+    fn eval(&mut self, expr: &Expr) -> Flow<Value> {
+        match &expr.kind {
+            ExprKind::Void => Ok(Value::void()),
+            ExprKind::Integer(i) => Ok(Value::integer(expr.value_type.clone(), *i)),
+            // a literal used as a str is a view of its bytes without the NUL
+            ExprKind::String(bytes) => {
+                let address = self.string_literal(bytes);
+                match expr.value_type {
+                    ValueType::Str => Ok(Value::view(ValueType::Str, address, bytes.len() as i32)),
+                    _ => Ok(Value::new(expr.value_type.clone(), address.to_le_bytes().to_vec())),
+                }
+            }
+            ExprKind::Local(_) | ExprKind::Capture(_) | ExprKind::Deref(..) | ExprKind::Index { .. } => {
+                let place = self.place(expr)?;
+                self.load(&place)
+            }
+            ExprKind::Function(function) => Ok(self.function_value(Target::Function(*function), expr.value_type.clone())),
+            ExprKind::Extern(external) => Ok(self.function_value(Target::Extern(*external), expr.value_type.clone())),
+            ExprKind::Closure { function, captures } => self.closure(*function, captures, &expr.value_type),
+            ExprKind::Unary(op, oprand) => {
+                let value = self.eval(oprand)?;
+                match op {
+                    Operator::Minus => Ok(Value::int((value.as_int() as i32).wrapping_neg())),
+                    _ => Ok(Value::int(!value.is_true() as i32)),
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                self.arithmetic(*op, lhs, rhs)
+            }
+            // the right side only runs when the left does not decide the result
+            ExprKind::Logical(op, lhs, rhs) => {
+                let decided = *op == Operator::DoubleBar;
+                let result = match self.eval(lhs)?.is_true() == decided {
+                    true => decided,
+                    false => self.eval(rhs)?.is_true(),
+                };
+                Ok(Value::int(result as i32))
+            }
+            ExprKind::Str(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                let (l, r) = (self.str_bytes(&lhs)?, self.str_bytes(&rhs)?);
+
+                Ok(match op {
+                    Operator::Plus => self.str_value(&[l, r].concat()),
+                    Operator::DoubleEquals => Value::int((l == r) as i32),
+                    Operator::ExclaimEquals => Value::int((l != r) as i32),
+                    Operator::LessThan => Value::int((l < r) as i32),
+                    _ => Value::int((l > r) as i32),
+                })
+            }
+            ExprKind::AddressOf(value) => {
+                let address = match value.is_place() {
+                    true => self.place(value)?.address,
+                    false => {
+                        let value = self.eval(value)?;
+                        self.temporary(value)
+                    }
+                };
+                Ok(Value::new(expr.value_type.clone(), address.to_le_bytes().to_vec()))
+            }
+            ExprKind::Field(base, index) => {
+                if expr.is_place() {
+                    let place = self.place(expr)?;
+                    return self.load(&place);
+                }
+                // fields of values such as call results
+                let value = self.eval(base)?;
+                let (offset, field_type) = self.field(&value.value_type, *index)?;
+                let size = self.size(&field_type)?;
+                Ok(Value::new(field_type, value.bytes[offset as usize..(offset + size) as usize].to_vec()))
+            }
+            ExprKind::Slice { target, start, end, location } => self.subslice(target, start.as_deref(), end.as_deref(), *location),
+            ExprKind::Call { callee, args, location } => self.call(callee, args, *location),
+            ExprKind::Dynamic { method, args, location } => self.dynamic_call(*method, args, *location),
+            ExprKind::Builtin { builtin, args, location } => {
+                let values = self.eval_all(args)?;
+                self.location = *location;
+                self.builtin(*builtin, values, &expr.value_type)
+            }
+            ExprKind::Print { pieces, args, location } => {
+                let mut values = Vec::new();
+                for (conversion, arg) in args {
+                    values.push((*conversion, self.eval(arg)?));
+                }
+                self.location = *location;
+                self.print(pieces, values)?;
+                Ok(Value::void())
+            }
+            ExprKind::Convert(conversion, value) => {
+                let value = self.eval(value)?;
+                Ok(self.coerce(*conversion, value, &expr.value_type))
+            }
+            ExprKind::Sequence(first, second) => {
+                self.eval(first)?;
+                self.eval(second)?;
+                Ok(Value::void())
+            }
+            ExprKind::If { condition, then, otherwise } => {
+                match self.eval(condition)?.is_true() {
+                    true => self.eval(then)?,
+                    false => self.eval(otherwise)?,
+                };
+                Ok(Value::void())
+            }
+            ExprKind::While { condition, body } => {
+                while self.eval(condition)?.is_true() {
+                    self.eval(body)?;
+                }
+                Ok(Value::void())
+            }
+            ExprKind::Return(value) => {
+                let value = self.eval(value)?;
+                Err(Unwind::Return(value))
+            }
+            ExprKind::Declare(local, value) => {
+                let value = match value {
+                    Some(value) => Some(self.eval(value)?),
+                    None => None,
+                };
+                self.declare(*local, value)?;
+                Ok(Value::void())
+            }
+            ExprKind::Assign(place, value) => {
+                // the value goes first, so a fault in the target is reported at the target
+                let value = self.eval(value)?;
+                let place = self.place(place)?;
+                self.store(&place, value)?;
+                Ok(Value::void())
+            }
+        }
+    }
+
+    fn eval_all(&mut self, exprs: &[Expr]) -> Flow<Vec<Value>> {
+        exprs.iter().map(|expr| self.eval(expr)).collect()
+    }
+
+    fn arithmetic(&mut self, op: Operator, lhs: Value, rhs: Value) -> Flow<Value> {
+        if let (ValueType::Pointer(inner), Operator::Plus) = (&lhs.value_type, op) {
+            let offset = rhs.as_int().wrapping_mul(self.size(inner)? as i64);
+            return Ok(Value::pointer((**inner).clone(), lhs.as_address().wrapping_add(offset as u64)));
+        }
+
+        // pointers and functions compare by address, integers by value
+        let (l, r) = match (&lhs.value_type, &rhs.value_type) {
+            (ValueType::I32 | ValueType::U8, _) => (lhs.as_int(), rhs.as_int()),
+            _ => (lhs.as_address() as i64, rhs.as_address() as i64),
+        };
+        let (l32, r32) = (l as i32, r as i32);
+        let result = match op {
+            Operator::Plus => l32.wrapping_add(r32),
+            Operator::Minus => l32.wrapping_sub(r32),
+            Operator::Asterisk => l32.wrapping_mul(r32),
+            Operator::LessThan => (l < r) as i32,
+            Operator::GreaterThan => (l > r) as i32,
+            Operator::DoubleEquals => (l == r) as i32,
+            Operator::ExclaimEquals => (l != r) as i32,
+            op => return Err(self.fault(format!("Binary operator {op:?} cannot be evaluated"))),
+        };
+
+        Ok(Value::int(result))
+    }
+
+    fn place(&mut self, expr: &Expr) -> Flow<Place> {
+        match &expr.kind {
+            ExprKind::Local(local) => self.frame().locals.get(local).cloned().ok_or_else(|| self.fault("use of a local before it is declared")),
+            ExprKind::Capture(index) => Ok(self.frame().captures[*index].clone()),
+            ExprKind::Deref(pointer, location) => {
+                let pointer = self.eval(pointer)?;
+                self.location = *location;
+                Ok(Place { address: pointer.as_address(), value_type: expr.value_type.clone(), location: *location })
+            }
+            ExprKind::Index { target, index, location } => self.index_place(target, index, &expr.value_type, *location),
+            ExprKind::Field(base, index) => {
+                let base = self.place(base)?;
+                let (offset, value_type) = self.field(&base.value_type, *index)?;
+                Ok(Place { address: base.address + offset, value_type, location: base.location })
+            }
+            kind => Err(self.fault(format!("{kind:?} cannot be assigned to"))),
+        }
+    }
+
+    // indexing slices and str is bounds checked like the C runtime does, pointers only by the block they point into
+    fn index_place(&mut self, target: &Expr, index: &Expr, element: &ValueType, location: Location) -> Flow<Place> {
+        let target = self.eval(target)?;
+        let index = self.eval(index)?.as_int();
+        self.location = location;
+
+        let address = match &target.value_type {
+            ValueType::Slice(_) | ValueType::Str => {
+                let (address, len) = target.view_parts();
+                if index < 0 || index >= len as i64 {
+                    return Err(self.fault(format!("index {index} is out of bounds for length {len}")));
+                }
+                address
+            }
+            _ => target.as_address(),
+        };
+
+        let offset = index.wrapping_mul(self.size(element)? as i64);
+        Ok(Place { address: address.wrapping_add(offset as u64), value_type: element.clone(), location })
+    }
+
+    fn subslice(&mut self, target: &Expr, start: Option<&Expr>, end: Option<&Expr>, location: Location) -> Flow<Value> {
+        let target = self.eval(target)?;
+        let (address, len) = target.view_parts();
+        let start = match start {
+            Some(start) => self.eval(start)?.as_int(),
+            None => 0,
+        };
+        let end = match end {
+            Some(end) => self.eval(end)?.as_int(),
+            None => len as i64,
+        };
+        self.location = location;
+
+        if start < 0 || start > end || end > len as i64 {
+            return Err(self.fault(format!("range {start}..{end} is out of bounds for length {len}")));
+        }
+        let element = match &target.value_type {
+            ValueType::Slice(element) => self.size(element)?,
+            _ => 1,
+        };
+        Ok(Value::view(target.value_type.clone(), address + start as u64 * element, (end - start) as i32))
+    }
+
+    // calls
+
+    // fns and externs are called directly, anything else through its value
+    fn call(&mut self, callee: &Expr, args: &[Expr], location: Location) -> Flow<Value> {
+        let value = match &callee.kind {
+            ExprKind::Function(_) | ExprKind::Extern(_) => None,
+            _ => Some(self.eval(callee)?),
+        };
+        let values = self.eval_all(args)?;
+        self.location = location;
+
+        let (target, env) = match (&callee.kind, value) {
+            (ExprKind::Function(function), _) => (Target::Function(*function), None),
+            (ExprKind::Extern(external), _) => (Target::Extern(*external), None),
+            (_, Some(value)) => match &value.value_type {
+                ValueType::Closure { .. } => match value.word(0, 8) {
+                    0 => (self.callable_at(value.word(16, 8))?, None),
+                    call => (self.callable_at(call)?, Some(value.word(8, 8))),
+                },
+                _ => (self.callable_at(value.as_address())?, None),
+            },
+            (_, None) => unreachable!("only fns and externs are called without a value"),
+        };
+
+        let result = self.call_target(target, values, env);
+        self.location = location;
+        result
+    }
+
+    // the vtable has the methods in the order the interface declares them, each takes the pointer the interface holds
+    fn dynamic_call(&mut self, method: usize, args: &[Expr], location: Location) -> Flow<Value> {
+        let mut values = self.eval_all(args)?;
+        self.location = location;
+
+        let interface = values[0].clone();
+        let function = self.read_word(interface.word(8, 8).wrapping_add(8 * method as u64))?;
+        let target = self.callable_at(function)?;
+        values[0] = Value::pointer(ValueType::Void, interface.as_address());
+
+        let result = self.call_target(target, values, None);
+        self.location = location;
+        result
+    }
+
+    fn call_target(&mut self, target: Target, values: Vec<Value>, env: Option<u64>) -> Flow<Value> {
+        let external = match target {
+            Target::Function(function) => return self.call_function(function, values, env),
+            Target::Extern(external) => external,
+        };
+
+        let program = self.program.clone();
+        let external = &program.externs[external];
+        let ValueType::Function { return_type, .. } = &external.value_type else { unreachable!("externs are fns") };
+
+        // what C returns is read as the type the extern is declared with
+        let mut bytes = self.call_extern(&external.name, &values, return_type)?.bytes;
+        bytes.resize(return_type.size().unwrap_or(0) as usize, 0);
+        Ok(Value::new((**return_type).clone(), bytes))
+    }
+
+    fn call_function(&mut self, function: FunctionId, values: Vec<Value>, env: Option<u64>) -> Flow<Value> {
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(self.fault(format!("stack overflow, calls are nested more than {MAX_CALL_DEPTH} deep")));
+        }
+
+        let program = self.program.clone();
+        let definition = &program.functions[function];
+        self.frames.push(Frame::new(function));
+        let result = self.function_body(function, definition, values, env);
+        self.leave();
+
+        match result {
+            Ok(_) => Ok(Value::zeroed(definition.return_type.clone())),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    fn function_body(&mut self, function: FunctionId, definition: &typed::Function, values: Vec<Value>, env: Option<u64>) -> Flow<Value> {
+        for (&local, value) in definition.params.iter().zip(values) {
+            self.declare(local, Some(value))?;
+        }
+
+        // captures are reached through the environment, those captured by reference through a pointer in it
+        if let Some(env) = env {
+            let env_type = typed::env_type(&definition.captures);
+            for (index, capture) in definition.captures.iter().enumerate() {
+                let (offset, _) = self.field(&env_type, index)?;
+                let address = match capture.by_ref {
+                    true => self.read_word(env + offset)?,
+                    false => env + offset,
+                };
+                let place = Place { address, value_type: capture.value_type.clone(), location: self.location };
+                self.frame_mut().captures.push(place);
+            }
+        }
+
+        if let Some(local) = definition.self_local {
+            let value_type = definition.locals[local].clone();
+            let value = match env {
+                Some(env) => self.closure_value(function, env, value_type),
+                None => self.function_value(Target::Function(function), value_type),
+            };
+            self.declare(local, Some(value))?;
+        }
+
+        self.eval(&definition.body)
+    }
+}
@@ -0,0 +1,497 @@
+use std::fs;
+use std::io::{BufRead, Read, Write};
+
+use crate::compiler::typed::Builtin;
+use crate::compiler::{Piece, ValueType};
+
+use super::memory::Value;
+use super::{Flow, Interpreter, Place, Unwind};
+
+// a FILE the program has open, the address of its block is the *FILE
+pub enum Stream {
+    Input,
+    Output,
+    Errors,
+    Reader { bytes: Vec<u8>, position: usize },
+    Writer(fs::File),
+}
+
+// a C format directive, such as %-5d or %.*s
+struct Directive {
+    flags: String,
+    width: Option<usize>,
+    precision: Option<usize>,
+    conversion: u8,
+}
+
+fn pad(text: Vec<u8>, directive: &Directive) -> Vec<u8> {
+    let width = directive.width.unwrap_or(0);
+    if text.len() >= width {
+        return text;
+    }
+
+    let fill = vec![if directive.flags.contains('0') && !directive.flags.contains('-') { b'0' } else { b' ' }; width - text.len()];
+    match (directive.flags.contains('-'), fill[0], text.first()) {
+        (true, _, _) => [text, fill].concat(),
+        (false, b'0', Some(b'-')) => [&text[..1], &fill, &text[1..]].concat(),
+        _ => [fill, text].concat(),
+    }
+}
+
+// an optional sign and the digits that follow it, anything after them is ignored
+fn parse_int(bytes: &[u8]) -> i32 {
+    let (sign, digits) = match bytes.first() {
+        Some(b'-') => (-1i32, &bytes[1..]),
+        Some(b'+') => (1, &bytes[1..]),
+        _ => (1, bytes),
+    };
+    let value = digits.iter().take_while(|c| c.is_ascii_digit())
+        .fold(0i32, |value, c| value.wrapping_mul(10).wrapping_add((c - b'0') as i32));
+    sign.wrapping_mul(value)
+}
+
+impl Interpreter {
+    pub(super) fn open_std_streams(&mut self) {
+        for stream in [Stream::Input, Stream::Output, Stream::Errors] {
+            let address = self.memory.alloc(8);
+            self.streams.insert(address, stream);
+        }
+    }
+
+    fn std_stream(&self, wanted: fn(&Stream) -> bool) -> u64 {
+        self.streams.iter().find(|(_, stream)| wanted(stream)).map(|(&address, _)| address).unwrap_or(0)
+    }
+
+    fn stream(&mut self, address: u64) -> Flow<&mut Stream> {
+        match address {
+            0 => Err(self.fault("null pointer dereference of a FILE")),
+            address if !self.streams.contains_key(&address) => Err(self.fault(format!("0x{address:x} is not an open FILE"))),
+            address => Ok(self.streams.get_mut(&address).unwrap()),
+        }
+    }
+
+    fn write_stream(&mut self, address: u64, bytes: &[u8]) -> Flow<()> {
+        let result = match self.stream(address)? {
+            Stream::Output => self.io.output.write_all(bytes),
+            Stream::Errors => {
+                let _ = self.io.output.flush();
+                self.io.errors.write_all(bytes)
+            }
+            Stream::Writer(file) => file.write_all(bytes),
+            _ => return Err(self.fault("write to a FILE opened for reading")),
+        };
+        result.map_err(|e| self.fault(format!("write failed: {e}")))
+    }
+
+    // bytes up to and including a newline, at most limit of them
+    fn read_stream_line(&mut self, address: u64, limit: usize) -> Flow<Vec<u8>> {
+        let _ = self.io.output.flush();
+        let mut line = Vec::new();
+        match self.stream(address)? {
+            Stream::Input => {
+                let mut input = (&mut self.io.input).take(limit as u64);
+                input.read_until(b'\n', &mut line).map_err(|e| self.fault(format!("read failed: {e}")))?;
+            }
+            Stream::Reader { bytes, position } => {
+                while line.len() < limit && *position < bytes.len() {
+                    line.push(bytes[*position]);
+                    *position += 1;
+                    if line.last() == Some(&b'\n') {
+                        break;
+                    }
+                }
+            }
+            _ => return Err(self.fault("read from a FILE opened for writing")),
+        }
+        Ok(line)
+    }
+
+    fn read_stream_all(&mut self, address: u64) -> Flow<Vec<u8>> {
+        let _ = self.io.output.flush();
+        let mut all = Vec::new();
+        match self.stream(address)? {
+            Stream::Input => {
+                self.io.input.read_to_end(&mut all).map_err(|e| self.fault(format!("read failed: {e}")))?;
+            }
+            Stream::Reader { bytes, position } => {
+                all.extend(&bytes[*position..]);
+                *position = bytes.len();
+            }
+            _ => return Err(self.fault("read from a FILE opened for writing")),
+        }
+        Ok(all)
+    }
+
+    fn stdout(&mut self, bytes: &[u8]) -> Flow<()> {
+        self.io.output.write_all(bytes).map_err(|e| self.fault(format!("write failed: {e}")))
+    }
+
+    fn c_string(&self, address: u64) -> Flow<Vec<u8>> {
+        self.memory.c_string(address).map_err(|e| self.fault(e))
+    }
+
+    // a new NUL terminated buffer, as malloc and the C helpers return them
+    fn c_buffer(&mut self, bytes: &[u8]) -> Value {
+        let address = self.memory.store(&[bytes, &[0]].concat());
+        Value::pointer(ValueType::U8, address)
+    }
+
+    // print and println, each placeholder with the printf conversion the checker chose for its argument
+    pub(super) fn print(&mut self, pieces: &[Piece], values: Vec<(&str, Value)>) -> Flow<()> {
+        let mut output = Vec::new();
+        let mut values = values.into_iter();
+
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => output.extend(text.bytes()),
+                Piece::Placeholder(_) => {
+                    let (conversion, value) = values.next().unwrap();
+                    output.extend(self.convert(conversion, &value)?);
+                }
+            }
+        }
+
+        self.stdout(&output)
+    }
+
+    pub(super) fn builtin(&mut self, builtin: Builtin, values: Vec<Value>, value_type: &ValueType) -> Flow<Value> {
+        match builtin {
+            Builtin::Alloc(size) => {
+                let size = values[0].as_int().max(0) as u64 * size;
+                Ok(Value::new(value_type.clone(), self.memory.alloc(size).to_le_bytes().to_vec()))
+            }
+            Builtin::Free => {
+                self.memory.free(values[0].as_address()).map_err(|e| self.fault(e))?;
+                Ok(Value::void())
+            }
+            Builtin::Exit => Err(Unwind::Exit(values[0].as_int() as i32)),
+            Builtin::ReadLine => {
+                let input = self.std_stream(|stream| matches!(stream, Stream::Input));
+                let mut line = self.read_stream_line(input, usize::MAX)?;
+                let more = !line.is_empty();
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                }
+
+                let line = self.str_value(&line);
+                let place = Place { address: values[0].as_address(), value_type: ValueType::Str, location: self.location };
+                self.store(&place, line)?;
+                Ok(Value::int(more as i32))
+            }
+            Builtin::ReadAll => {
+                let input = self.std_stream(|stream| matches!(stream, Stream::Input));
+                let all = self.read_stream_all(input)?;
+                Ok(self.str_value(&all))
+            }
+            Builtin::StrFromInt => Ok(self.str_value(values[0].as_int().to_string().as_bytes())),
+            Builtin::StrFromCstr => {
+                let bytes = self.c_string(values[0].as_address())?;
+                Ok(Value::view(ValueType::Str, values[0].as_address(), bytes.len() as i32))
+            }
+            Builtin::StrToCstr => {
+                let bytes = self.str_bytes(&values[0])?;
+                Ok(self.c_buffer(&bytes))
+            }
+            Builtin::StrToInt => Ok(Value::int(parse_int(&self.str_bytes(&values[0])?))),
+        }
+    }
+
+    // one printf conversion of a value
+    fn convert(&mut self, conversion: &str, value: &Value) -> Flow<Vec<u8>> {
+        match conversion {
+            "%.*s" => self.str_bytes(value),
+            conversion => {
+                let directive = Directive { flags: String::new(), width: None, precision: None, conversion: conversion.as_bytes()[1] };
+                self.directive(&directive, value)
+            }
+        }
+    }
+
+    fn directive(&mut self, directive: &Directive, value: &Value) -> Flow<Vec<u8>> {
+        let text = match directive.conversion {
+            b'd' | b'i' => value.as_int().to_string().into_bytes(),
+            b'u' => (value.as_int() as u32).to_string().into_bytes(),
+            b'x' => format!("{:x}", value.as_int() as u32).into_bytes(),
+            b'X' => format!("{:X}", value.as_int() as u32).into_bytes(),
+            b'c' => vec![value.as_int() as u8],
+            b'p' => match value.as_address() {
+                0 => b"(nil)".to_vec(),
+                address => format!("0x{address:x}").into_bytes(),
+            },
+            b's' => {
+                let mut text = match value.as_address() {
+                    0 => b"(null)".to_vec(),
+                    address => match directive.precision {
+                        // a precision can stop before the end, which may not be NUL terminated
+                        Some(precision) => {
+                            let mut text = Vec::new();
+                            while text.len() < precision {
+                                match self.memory.read(address + text.len() as u64, 1).map_err(|e| self.fault(e))?[0] {
+                                    0 => break,
+                                    c => text.push(c),
+                                }
+                            }
+                            text
+                        }
+                        None => self.c_string(address)?,
+                    },
+                };
+                text.truncate(directive.precision.unwrap_or(usize::MAX));
+                text
+            }
+            c => return Err(self.fault(format!("%{} is not supported by the interpreter", c as char))),
+        };
+
+        Ok(pad(text, directive))
+    }
+
+    // printf and friends, with the conversions Mud programs pass them
+    fn format_c(&mut self, format: &[u8], values: &[Value]) -> Flow<Vec<u8>> {
+        let mut output = Vec::new();
+        let mut values = values.iter();
+        let mut next = |s: &Self| values.next().cloned().ok_or_else(|| s.fault("printf format has more conversions than arguments"));
+        let mut i = 0;
+
+        while i < format.len() {
+            if format[i] != b'%' {
+                output.push(format[i]);
+                i += 1;
+                continue;
+            }
+            i += 1;
+            if format.get(i) == Some(&b'%') {
+                output.push(b'%');
+                i += 1;
+                continue;
+            }
+
+            let mut directive = Directive { flags: String::new(), width: None, precision: None, conversion: 0 };
+            while let Some(&c @ (b'-' | b'0' | b'+' | b' ' | b'#')) = format.get(i) {
+                directive.flags.push(c as char);
+                i += 1;
+            }
+            let mut number = |i: &mut usize, s: &Self| -> Flow<Option<usize>> {
+                if format.get(*i) == Some(&b'*') {
+                    *i += 1;
+                    return Ok(Some(next(s)?.as_int().max(0) as usize));
+                }
+                let start = *i;
+                while format.get(*i).is_some_and(u8::is_ascii_digit) {
+                    *i += 1;
+                }
+                Ok(std::str::from_utf8(&format[start..*i]).unwrap().parse().ok())
+            };
+            directive.width = number(&mut i, self)?;
+            if format.get(i) == Some(&b'.') {
+                i += 1;
+                directive.precision = Some(number(&mut i, self)?.unwrap_or(0));
+            }
+            while let Some(b'l' | b'h' | b'z') = format.get(i) {
+                i += 1;
+            }
+
+            let Some(&conversion) = format.get(i) else {
+                return Err(self.fault("printf format ends in the middle of a conversion"));
+            };
+            i += 1;
+            directive.conversion = conversion;
+            let value = next(self)?;
+            output.extend(self.directive(&directive, &value)?);
+        }
+
+        Ok(output)
+    }
+
+    // the C functions the standard library and tests declare, the interpreter has no C to call
+    pub(super) fn call_extern(&mut self, name: &str, values: &[Value], return_type: &ValueType) -> Flow<Value> {
+        let int = |i: usize| values[i].as_int();
+        let address = |i: usize| values[i].as_address();
+
+        let result = match name {
+            "printf" => {
+                let format = self.c_string(address(0))?;
+                let output = self.format_c(&format, &values[1..])?;
+                self.stdout(&output)?;
+                Value::int(output.len() as i32)
+            }
+            "snprintf" | "sprintf" => {
+                let (size, format_arg) = match name {
+                    "snprintf" => (int(1).max(0) as usize, 2),
+                    _ => (usize::MAX, 1),
+                };
+                let format = self.c_string(address(format_arg))?;
+                let output = self.format_c(&format, &values[format_arg + 1..])?;
+                if size > 0 {
+                    let written = output.len().min(size - 1);
+                    self.memory.write(address(0), &[&output[..written], &[0]].concat()).map_err(|e| self.fault(e))?;
+                }
+                Value::int(output.len() as i32)
+            }
+            "puts" => {
+                let line = self.c_string(address(0))?;
+                self.stdout(&[&line[..], b"\n"].concat())?;
+                Value::int(0)
+            }
+            "putchar" => {
+                self.stdout(&[int(0) as u8])?;
+                Value::int(int(0) as i32)
+            }
+            "strlen" => Value::integer(return_type.clone(), self.c_string(address(0))?.len() as i64),
+            "strcmp" => {
+                let (a, b) = (self.c_string(address(0))?, self.c_string(address(1))?);
+                Value::int(a.cmp(&b) as i32)
+            }
+            "memcmp" => {
+                let n = int(2).max(0) as u64;
+                let a = self.memory.read(address(0), n).map_err(|e| self.fault(e))?;
+                let b = self.memory.read(address(1), n).map_err(|e| self.fault(e))?;
+                Value::int(a.cmp(&b) as i32)
+            }
+            "memcpy" | "memmove" => {
+                let bytes = self.memory.read(address(1), int(2).max(0) as u64).map_err(|e| self.fault(e))?;
+                self.memory.write(address(0), &bytes).map_err(|e| self.fault(e))?;
+                values[0].clone()
+            }
+            "memset" => {
+                let bytes = vec![int(1) as u8; int(2).max(0) as usize];
+                self.memory.write(address(0), &bytes).map_err(|e| self.fault(e))?;
+                values[0].clone()
+            }
+            "malloc" => Value::pointer(ValueType::Void, self.memory.alloc(int(0).max(0) as u64)),
+            "calloc" => Value::pointer(ValueType::Void, self.memory.alloc(int(0).max(0) as u64 * int(1).max(0) as u64)),
+            "read_file" => {
+                let path = String::from_utf8_lossy(&self.c_string(address(0))?).to_string();
+                match fs::read(path) {
+                    Ok(bytes) => self.c_buffer(&bytes),
+                    Err(_) => Value::pointer(ValueType::U8, 0),
+                }
+            }
+            "realloc" => {
+                let size = int(1).max(0) as u64;
+                let new = self.memory.alloc(size);
+                if address(0) != 0 {
+                    let old = self.memory.size_of(address(0)).ok_or_else(|| self.fault(format!("realloc of 0x{:x}, which was not allocated", address(0))))?;
+                    let bytes = self.memory.read(address(0), old.min(size)).map_err(|e| self.fault(e))?;
+                    self.memory.write(new, &bytes).map_err(|e| self.fault(e))?;
+                    self.memory.free(address(0)).map_err(|e| self.fault(e))?;
+                }
+                Value::pointer(ValueType::Void, new)
+            }
+            "free" => return self.builtin(Builtin::Free, values.to_vec(), &ValueType::Void),
+            "abs" => Value::int((int(0) as i32).wrapping_abs()),
+            "atoi" => Value::int(parse_int(&self.c_string(address(0))?)),
+            "exit" | "mudstd_exit" => return Err(Unwind::Exit(int(0) as i32)),
+            "div" => {
+                let (numer, denom) = (int(0) as i32, int(1) as i32);
+                if denom == 0 {
+                    return Err(self.fault("division by zero"));
+                }
+                Value::new(return_type.clone(), [numer.wrapping_div(denom).to_le_bytes(), numer.wrapping_rem(denom).to_le_bytes()].concat())
+            }
+            "mudstd_div" | "mudstd_mod" => {
+                let (a, b) = (int(0) as i32, int(1) as i32);
+                if b == 0 {
+                    return Err(self.fault("division by zero"));
+                }
+                Value::int(if name == "mudstd_div" { a.wrapping_div(b) } else { a.wrapping_rem(b) })
+            }
+            "isdigit" | "isalpha" | "isalnum" | "isspace" | "isupper" | "islower" => {
+                let c = int(0) as u8;
+                let is = match name {
+                    "isdigit" => c.is_ascii_digit(),
+                    "isalpha" => c.is_ascii_alphabetic(),
+                    "isalnum" => c.is_ascii_alphanumeric(),
+                    "isspace" => c.is_ascii_whitespace() || c == 0x0b,
+                    "isupper" => c.is_ascii_uppercase(),
+                    _ => c.is_ascii_lowercase(),
+                };
+                Value::int(is as i32)
+            }
+            "toupper" => Value::int((int(0) as u8).to_ascii_uppercase() as i32),
+            "tolower" => Value::int((int(0) as u8).to_ascii_lowercase() as i32),
+            "getenv" => {
+                let name = String::from_utf8_lossy(&self.c_string(address(0))?).to_string();
+                match std::env::var(name) {
+                    Ok(value) => self.c_buffer(value.as_bytes()),
+                    Err(_) => Value::pointer(ValueType::U8, 0),
+                }
+            }
+            "mudstd_is_null" => Value::int((address(0) == 0) as i32),
+            "mudstd_arg_count" => Value::int(self.args.len() as i32),
+            "mudstd_arg" => match self.args.get(int(0) as usize).cloned() {
+                Some(arg) if int(0) >= 0 => self.c_buffer(arg.as_bytes()),
+                _ => Value::pointer(ValueType::U8, 0),
+            },
+            "mudstd_stdin" => Value::new(return_type.clone(), self.std_stream(|s| matches!(s, Stream::Input)).to_le_bytes().to_vec()),
+            "mudstd_stdout" => Value::new(return_type.clone(), self.std_stream(|s| matches!(s, Stream::Output)).to_le_bytes().to_vec()),
+            "mudstd_stderr" => Value::new(return_type.clone(), self.std_stream(|s| matches!(s, Stream::Errors)).to_le_bytes().to_vec()),
+            "mudstd_read_all" => {
+                let all = self.read_stream_all(address(0))?;
+                self.c_buffer(&all)
+            }
+            "mudstd_read_line" => {
+                let mut line = self.read_stream_line(address(0), usize::MAX)?;
+                if line.is_empty() {
+                    return Ok(Value::pointer(ValueType::U8, 0));
+                }
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                }
+                self.c_buffer(&line)
+            }
+            "fopen" => {
+                let path = String::from_utf8_lossy(&self.c_string(address(0))?).to_string();
+                let mode = self.c_string(address(1))?;
+                let stream = match mode.first() {
+                    Some(b'r') => fs::read(&path).ok().map(|bytes| Stream::Reader { bytes, position: 0 }),
+                    Some(b'a') => fs::OpenOptions::new().append(true).create(true).open(&path).ok().map(Stream::Writer),
+                    _ => fs::File::create(&path).ok().map(Stream::Writer),
+                };
+                match stream {
+                    Some(stream) => {
+                        let file = self.memory.alloc(8);
+                        self.streams.insert(file, stream);
+                        Value::new(return_type.clone(), file.to_le_bytes().to_vec())
+                    }
+                    None => Value::new(return_type.clone(), vec![0; 8]),
+                }
+            }
+            "fclose" => {
+                self.stream(address(0))?;
+                self.streams.remove(&address(0));
+                self.memory.free(address(0)).map_err(|e| self.fault(e))?;
+                Value::int(0)
+            }
+            "fputs" => {
+                let s = self.c_string(address(0))?;
+                self.write_stream(address(1), &s)?;
+                Value::int(0)
+            }
+            "fwrite" => {
+                let count = int(1).max(0) as u64 * int(2).max(0) as u64;
+                let bytes = self.memory.read(address(0), count).map_err(|e| self.fault(e))?;
+                self.write_stream(address(3), &bytes)?;
+                Value::integer(return_type.clone(), int(2))
+            }
+            "fflush" => {
+                let _ = self.io.output.flush();
+                Value::int(0)
+            }
+            "fgets" => {
+                let line = self.read_stream_line(address(2), int(1).max(1) as usize - 1)?;
+                if line.is_empty() {
+                    return Ok(Value::pointer(ValueType::U8, 0));
+                }
+                self.memory.write(address(0), &[&line[..], &[0]].concat()).map_err(|e| self.fault(e))?;
+                values[0].clone()
+            }
+            "remove" => {
+                let path = String::from_utf8_lossy(&self.c_string(address(0))?).to_string();
+                Value::int(if fs::remove_file(path).is_ok() { 0 } else { -1 })
+            }
+            name => return Err(self.fault(format!("extern fn {name} is not available in the interpreter"))),
+        };
+
+        Ok(result)
+    }
+}
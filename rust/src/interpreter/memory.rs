@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use crate::compiler::ValueType;
+
+// addresses below this are null, so small offsets from a null pointer are caught too
+const NULL_PAGE: u64 = 0x1000;
+// blocks are spaced out, so running off the end of one never lands in the next
+const GAP: u64 = 64;
+// function pointers point here, where no block is ever allocated
+pub const FUNCTION_BASE: u64 = 1 << 48;
+
+// a value as the bytes C would store for it, laid out like the C backend lays it out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    pub value_type: ValueType,
+    pub bytes: Vec<u8>,
+}
+
+impl Value {
+    pub fn new(value_type: ValueType, bytes: Vec<u8>) -> Self {
+        Self { value_type, bytes }
+    }
+
+    pub fn void() -> Self {
+        Self::new(ValueType::Void, Vec::new())
+    }
+
+    pub fn int(i: i32) -> Self {
+        Self::new(ValueType::I32, i.to_le_bytes().to_vec())
+    }
+
+    // an integer as an integer or pointer type, cut to its size as C converts it
+    pub fn integer(value_type: ValueType, value: i64) -> Self {
+        let size = value_type.size().unwrap_or(0) as usize;
+        Self::new(value_type, value.to_le_bytes()[..size].to_vec())
+    }
+
+    pub fn pointer(target: ValueType, address: u64) -> Self {
+        Self::new(ValueType::Pointer(Box::new(target)), address.to_le_bytes().to_vec())
+    }
+
+    // str and slices are a pointer and an i32 length, padded to 16 bytes
+    pub fn view(value_type: ValueType, address: u64, len: i32) -> Self {
+        let mut bytes = address.to_le_bytes().to_vec();
+        bytes.extend(len.to_le_bytes());
+        bytes.extend([0; 4]);
+        Self::new(value_type, bytes)
+    }
+
+    pub fn zeroed(value_type: ValueType) -> Self {
+        let size = value_type.size().unwrap_or(0) as usize;
+        Self::new(value_type, vec![0; size])
+    }
+
+    // integers are read as C reads them, u8 is a signed char
+    pub fn as_int(&self) -> i64 {
+        match self.value_type {
+            ValueType::U8 => self.bytes[0] as i8 as i64,
+            ValueType::Usize => self.word(0, 8) as i64,
+            _ => self.word(0, 4) as i32 as i64,
+        }
+    }
+
+    pub fn as_address(&self) -> u64 {
+        self.word(0, 8)
+    }
+
+    pub fn word(&self, offset: usize, size: usize) -> u64 {
+        let mut word = [0; 8];
+        word[..size].copy_from_slice(&self.bytes[offset..offset + size]);
+        u64::from_le_bytes(word)
+    }
+
+    // the pointer and length of a str or slice
+    pub fn view_parts(&self) -> (u64, i32) {
+        (self.word(0, 8), self.word(8, 4) as i32)
+    }
+
+    pub fn is_true(&self) -> bool {
+        self.bytes.iter().any(|&b| b != 0)
+    }
+}
+
+struct Block {
+    bytes: Vec<u8>,
+    size: u64,
+    live: bool,
+}
+
+// memory is a set of separate blocks, so every access can be checked against the block it falls in
+pub struct Memory {
+    blocks: BTreeMap<u64, Block>,
+    next: u64,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self { blocks: BTreeMap::new(), next: NULL_PAGE }
+    }
+}
+
+impl Memory {
+    // addresses are never reused, so a freed block stays recognisable
+    pub fn alloc(&mut self, size: u64) -> u64 {
+        let address = self.next;
+        self.next += size.div_ceil(16) * 16 + GAP;
+        self.blocks.insert(address, Block { bytes: vec![0; size as usize], size, live: true });
+        address
+    }
+
+    pub fn free(&mut self, address: u64) -> Result<(), String> {
+        if address == 0 {
+            return Ok(());
+        }
+
+        match self.blocks.get_mut(&address) {
+            Some(block) if block.live => {
+                block.live = false;
+                block.bytes = Vec::new();
+                Ok(())
+            }
+            Some(_) => Err(format!("double free of 0x{address:x}")),
+            None => Err(format!("free of 0x{address:x}, which was not allocated")),
+        }
+    }
+
+    // the size of the block starting at address, for realloc
+    pub fn size_of(&self, address: u64) -> Option<u64> {
+        self.blocks.get(&address).filter(|block| block.live).map(|block| block.size)
+    }
+
+    fn block(&self, address: u64, len: u64) -> Result<(u64, &Block), String> {
+        if address < NULL_PAGE {
+            return Err("null pointer dereference".to_string());
+        }
+
+        let Some((&base, block)) = self.blocks.range(..=address).next_back() else {
+            return Err(format!("access of invalid pointer 0x{address:x}"));
+        };
+        if !block.live && address < base + block.size.max(1) {
+            return Err(format!("use of memory at 0x{address:x} after it was freed"));
+        }
+        if address + len > base + block.size {
+            return Err(match address - base < block.size {
+                true => format!("access of {len} bytes at offset {} is out of bounds of a {} byte allocation", address - base, block.size),
+                false => format!("access at offset {} is out of bounds of a {} byte allocation", address - base, block.size),
+            });
+        }
+
+        Ok((base, block))
+    }
+
+    pub fn read(&self, address: u64, len: u64) -> Result<Vec<u8>, String> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let (base, block) = self.block(address, len)?;
+        let start = (address - base) as usize;
+        Ok(block.bytes[start..start + len as usize].to_vec())
+    }
+
+    pub fn write(&mut self, address: u64, bytes: &[u8]) -> Result<(), String> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let (base, _) = self.block(address, bytes.len() as u64)?;
+        let block = self.blocks.get_mut(&base).unwrap();
+        let start = (address - base) as usize;
+        block.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // the bytes of a NUL terminated string, without the NUL
+    pub fn c_string(&self, address: u64) -> Result<Vec<u8>, String> {
+        let (base, block) = self.block(address, 1)?;
+        let start = (address - base) as usize;
+        match block.bytes[start..].iter().position(|&b| b == 0) {
+            Some(end) => Ok(block.bytes[start..start + end].to_vec()),
+            None => Err(format!("string at 0x{address:x} runs off the end of its {} byte allocation", block.size)),
+        }
+    }
+
+    // a new block holding bytes, such as a string literal or a NUL terminated copy of one
+    pub fn store(&mut self, bytes: &[u8]) -> u64 {
+        let address = self.alloc(bytes.len() as u64);
+        self.write(address, bytes).unwrap();
+        address
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::compiler::{typed, Statement, ValueType};
+use crate::lexer::error::MudResult;
+
+use super::memory::Value;
+use super::{Frame, Interpreter, Io, Unwind};
+
+// the name a session's program has in its args, as a compiled program has its path
+const REPL_PATH: &str = "<repl>";
+// slices longer than this are cut short when they are shown
+const SHOWN_ELEMENTS: i32 = 16;
+
+// what an input to the REPL did
+pub enum Outcome {
+    Void,
+    // the value of an expression, rendered for display, and its type
+    Value(String, ValueType),
+    Exit(i32),
+}
+
+impl Interpreter {
+    // an interpreter for the REPL, the program grows with its inputs and its locals live as long as the session
+    pub fn session(io: Io) -> Self {
+        Self::new(io, vec![REPL_PATH.to_string()], typed::Program::default())
+    }
+
+    // runs a statement the compiler checked, in the program as it is now
+    pub fn evaluate_input(&mut self, program: &typed::Program, statement: &Statement) -> MudResult<Outcome> {
+        self.program = Rc::new(program.clone());
+        let mut frame = Frame::new(statement.function);
+        for (name, local) in &statement.locals {
+            if let Some(place) = self.locals.get(name) {
+                frame.locals.insert(*local, place.clone());
+            }
+        }
+
+        self.frames.push(frame);
+        let body = self.program.clone();
+        let result = match self.eval(&body.functions[statement.function].body) {
+            Err(Unwind::Return(value)) => Ok(value),
+            result => result,
+        };
+        // the locals it declared stay, even if it stopped early, what else it allocated goes with its frame
+        for (name, local) in &statement.locals {
+            if let Some(place) = self.frame_mut().locals.remove(local) {
+                self.locals.insert(name.clone(), place);
+            }
+        }
+        self.leave();
+
+        match self.settle(result) {
+            Ok(Some(value)) if !value.bytes.is_empty() => Ok(Outcome::Value(self.render(&value), value.value_type)),
+            Ok(Some(_)) => Ok(Outcome::Void),
+            Ok(None) => Ok(Outcome::Exit(self.exit_code)),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn has_local(&self, name: &str) -> bool {
+        self.locals.contains_key(name)
+    }
+
+    fn settle<T>(&mut self, result: Result<T, Unwind>) -> MudResult<Option<T>> {
+        let _ = self.io.output.flush();
+
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(Unwind::Exit(code)) => {
+                self.exit_code = code;
+                Ok(None)
+            }
+            Err(Unwind::Return(_)) => unreachable!("returns stop at their call or the input"),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+
+    // a value the way Mud would write it, pointers are shown as addresses
+    fn render(&self, value: &Value) -> String {
+        match &value.value_type {
+            ValueType::I32 | ValueType::Usize => value.as_int().to_string(),
+            ValueType::U8 => format!("'{}'", value.bytes[0].escape_ascii()),
+            ValueType::Str => match self.str_bytes(value) {
+                Ok(bytes) => format!("\"{}\"", bytes.escape_ascii()),
+                Err(_) => "<invalid str>".to_string(),
+            },
+            ValueType::Pointer(inner) => match (value.as_address(), &**inner) {
+                (0, _) => "null".to_string(),
+                (address, ValueType::U8) => match self.memory.c_string(address) {
+                    Ok(bytes) => format!("0x{address:x} \"{}\"", bytes.escape_ascii()),
+                    Err(_) => format!("0x{address:x}"),
+                },
+                (address, _) => format!("0x{address:x}"),
+            },
+            ValueType::Struct { fields, .. } => {
+                let fields = fields.iter().enumerate().map(|(index, (name, _))| {
+                    let field = self.field(&value.value_type, index).ok().and_then(|(offset, field_type)| {
+                        let size = field_type.size().ok()? as usize;
+                        let bytes = value.bytes.get(offset as usize..offset as usize + size)?.to_vec();
+                        Some(self.render(&Value::new(field_type, bytes)))
+                    });
+                    format!("{name}: {}", field.unwrap_or_else(|| "?".to_string()))
+                }).collect::<Vec<_>>();
+                format!("{{{}}}", fields.join(", "))
+            }
+            ValueType::Slice(element) => {
+                let (address, len) = value.view_parts();
+                let size = element.size().unwrap_or(0);
+                let mut elements = (0..len.min(SHOWN_ELEMENTS)).map(|i| {
+                    match self.memory.read(address + i as u64 * size, size) {
+                        Ok(bytes) => self.render(&Value::new((**element).clone(), bytes)),
+                        Err(_) => "?".to_string(),
+                    }
+                }).collect::<Vec<_>>();
+                if len > SHOWN_ELEMENTS {
+                    elements.push("...".to_string());
+                }
+                format!("[{}]", elements.join(", "))
+            }
+            ValueType::Function { .. } if value.as_address() == 0 => "null".to_string(),
+            ValueType::Function { .. } => "<fn>".to_string(),
+            ValueType::Closure { .. } => "<closure>".to_string(),
+            // the value behind an interface is shown by its address, as a pointer to it would be
+            ValueType::Interface(_) => match value.as_address() {
+                0 => "null".to_string(),
+                address => format!("0x{address:x}"),
+            },
+            _ => "<value>".to_string(),
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::PathBuf;
+
+mod display;
+mod lower;
+
+pub use lower::{library, lower};
+
+// a checked program as the backends see it, functions of basic blocks over typed temporaries
+//
+// temporaries hold scalars and are assigned once, values of struct type live in memory
+// and are handled through their address
+
+pub type Temp = usize;
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    I8,
+    I32,
+    // pointers, function pointers and the offsets added to them
+    Ptr,
+    Struct(usize),
+}
+
+impl Type {
+    pub fn is_scalar(&self) -> bool {
+        !matches!(self, Type::Struct(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StructType {
+    // the Mud type it was made for, for readable output
+    pub name: String,
+    pub fields: Vec<Type>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    // 1 for zero and 0 for anything else
+    Not,
+}
+
+// comparisons are signed and give an I32 of 0 or 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Lt,
+    Gt,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone)]
+pub enum Callee {
+    Direct(String),
+    Indirect(Temp),
+}
+
+// where a call puts what it returns, a struct of the type is written to the memory at the address
+#[derive(Debug, Clone, Copy)]
+pub enum Dest {
+    None,
+    Value(Temp),
+    Memory(Temp, Type),
+}
+
+#[derive(Debug, Clone)]
+pub enum Inst {
+    Const { dest: Temp, value: i64 },
+    // the address of a function or data symbol
+    Symbol { dest: Temp, name: String },
+    Slot { dest: Temp, slot: usize },
+    // scalars of the type of dest or value
+    Load { dest: Temp, addr: Temp },
+    Store { addr: Temp, value: Temp },
+    // a whole value of type ty from src to dest, both addresses
+    Copy { dest: Temp, src: Temp, ty: Type },
+    Zero { dest: Temp, ty: Type },
+    // the address of field index of the struct at base
+    Field { dest: Temp, base: Temp, ty: usize, index: usize },
+    // the address of element index, a Ptr, of an array of ty at base
+    Element { dest: Temp, base: Temp, ty: Type, index: Temp },
+    Unary { dest: Temp, op: UnaryOp, value: Temp },
+    Binary { dest: Temp, op: BinaryOp, lhs: Temp, rhs: Temp },
+    // sign extends or truncates between scalar types
+    Cast { dest: Temp, value: Temp },
+    // struct arguments are passed by the address of their value, variadic calls know how many args are fixed
+    Call { dest: Dest, callee: Callee, args: Vec<(Temp, Type)>, fixed: Option<usize> },
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch { condition: Temp, then: BlockId, otherwise: BlockId },
+    // a struct is returned by its address
+    Return(Option<Temp>),
+    Unreachable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    // exported functions keep their Mud name and can be called from C
+    pub export: bool,
+    // a struct param is the address of the function's own copy
+    pub params: Vec<(Temp, Type)>,
+    pub return_type: Option<Type>,
+    pub temps: Vec<Type>,
+    pub slots: Vec<Type>,
+    pub blocks: Vec<Block>,
+}
+
+// a function defined outside the program, in libc or the runtime
+#[derive(Debug, Clone)]
+pub struct Extern {
+    pub name: String,
+    pub args: Vec<Type>,
+    pub return_type: Option<Type>,
+    pub variadic: bool,
+    // the C types the program gives its params and return type, the runtime's own functions take types only it has
+    pub prototype: Option<Prototype>,
+    // the header of the C library that declares it
+    pub include: Option<String>,
+    // a static function of a user header, reached through C glue that calls it by its own name
+    pub header: Option<(PathBuf, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prototype {
+    pub args: Vec<String>,
+    pub return_type: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum DataItem {
+    Bytes(Vec<u8>),
+    Symbol(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Data {
+    pub name: String,
+    pub items: Vec<DataItem>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub structs: Vec<StructType>,
+    pub functions: Vec<Function>,
+    pub externs: Vec<Extern>,
+    pub data: Vec<Data>,
+    // the Mud name of each function lowered that has one, and its symbol
+    pub symbols: Vec<(String, String)>,
+}
+
+impl Program {
+    pub fn size(&self, ty: Type) -> u64 {
+        match ty {
+            Type::I8 => 1,
+            Type::I32 => 4,
+            Type::Ptr => 8,
+            Type::Struct(id) => {
+                let mut size: u64 = 0;
+                for &field in &self.structs[id].fields {
+                    size = size.next_multiple_of(self.align(field)) + self.size(field);
+                }
+                size.next_multiple_of(self.align(ty))
+            }
+        }
+    }
+
+    pub fn align(&self, ty: Type) -> u64 {
+        match ty {
+            Type::Struct(id) => self.structs[id].fields.iter().map(|&field| self.align(field)).max().unwrap_or(1),
+            ty => self.size(ty),
+        }
+    }
+
+    pub fn offset(&self, id: usize, index: usize) -> u64 {
+        let mut offset: u64 = 0;
+        for (i, &field) in self.structs[id].fields.iter().enumerate() {
+            offset = offset.next_multiple_of(self.align(field));
+            if i == index {
+                break;
+            }
+            offset += self.size(field);
+        }
+        offset
+    }
+
+    pub fn external(&self, name: &str) -> Option<&Extern> {
+        self.externs.iter().find(|external| external.name == name)
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+
+use super::*;
+
+// the IR as text, for reading what a program lowers to
+
+impl Program {
+    fn type_name(&self, ty: Type) -> String {
+        match ty {
+            Type::I8 => "i8".to_string(),
+            Type::I32 => "i32".to_string(),
+            Type::Ptr => "ptr".to_string(),
+            Type::Struct(id) => format!("{}.{id}", self.structs[id].name),
+        }
+    }
+
+    fn args(&self, args: &[(Temp, Type)]) -> String {
+        args.iter().map(|&(temp, ty)| format!("t{temp}: {}", self.type_name(ty))).collect::<Vec<_>>().join(", ")
+    }
+
+    fn write_inst(&self, f: &mut fmt::Formatter, temps: &[Type], inst: &Inst) -> fmt::Result {
+        let ty = |temp: Temp| self.type_name(temps[temp]);
+        match inst {
+            Inst::Const { dest, value } => writeln!(f, "  t{dest}: {} = const {value}", ty(*dest)),
+            Inst::Symbol { dest, name } => writeln!(f, "  t{dest}: ptr = symbol {name}"),
+            Inst::Slot { dest, slot } => writeln!(f, "  t{dest}: ptr = slot s{slot}"),
+            Inst::Load { dest, addr } => writeln!(f, "  t{dest}: {} = load t{addr}", ty(*dest)),
+            Inst::Store { addr, value } => writeln!(f, "  store t{addr}, t{value}"),
+            Inst::Copy { dest, src, ty } => writeln!(f, "  copy t{dest}, t{src}, {}", self.type_name(*ty)),
+            Inst::Zero { dest, ty } => writeln!(f, "  zero t{dest}, {}", self.type_name(*ty)),
+            Inst::Field { dest, base, ty, index } => writeln!(f, "  t{dest}: ptr = field t{base}, {}.{index}", self.type_name(Type::Struct(*ty))),
+            Inst::Element { dest, base, ty, index } => writeln!(f, "  t{dest}: ptr = element t{base}, {}, t{index}", self.type_name(*ty)),
+            Inst::Unary { dest, op, value } => writeln!(f, "  t{dest}: {} = {op:?} t{value}", ty(*dest)),
+            Inst::Binary { dest, op, lhs, rhs } => writeln!(f, "  t{dest}: {} = {op:?} t{lhs}, t{rhs}", ty(*dest)),
+            Inst::Cast { dest, value } => writeln!(f, "  t{dest}: {} = cast t{value}", ty(*dest)),
+            Inst::Call { dest, callee, args, fixed } => {
+                let callee = match callee {
+                    Callee::Direct(name) => name.clone(),
+                    Callee::Indirect(temp) => format!("*t{temp}"),
+                };
+                // the args a variadic callee does not declare follow a ...
+                let args = match fixed {
+                    Some(fixed) => format!("{}, ... {}", self.args(&args[..*fixed]), self.args(&args[*fixed..])),
+                    None => self.args(args),
+                };
+                match dest {
+                    Dest::None => writeln!(f, "  call {callee}({args})"),
+                    Dest::Value(temp) => writeln!(f, "  t{temp}: {} = call {callee}({args})", ty(*temp)),
+                    Dest::Memory(addr, ty) => writeln!(f, "  t{addr} <- {} call {callee}({args})", self.type_name(*ty)),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (id, structure) in self.structs.iter().enumerate() {
+            let fields = structure.fields.iter().map(|&field| self.type_name(field)).collect::<Vec<_>>();
+            writeln!(f, "struct {} {{ {} }}", self.type_name(Type::Struct(id)), fields.join(", "))?;
+        }
+
+        for data in &self.data {
+            let items = data.items.iter().map(|item| match item {
+                DataItem::Bytes(bytes) => format!("{:?}", String::from_utf8_lossy(bytes)),
+                DataItem::Symbol(name) => name.clone(),
+            }).collect::<Vec<_>>();
+            writeln!(f, "data {} = {}", data.name, items.join(", "))?;
+        }
+
+        for external in &self.externs {
+            let mut args = external.args.iter().map(|&arg| self.type_name(arg)).collect::<Vec<_>>();
+            if external.variadic {
+                args.push("...".to_string());
+            }
+            let return_type = external.return_type.map(|ty| format!(" -> {}", self.type_name(ty))).unwrap_or_default();
+            let header = match &external.header {
+                Some((header, name)) => format!(" from {} as {name}", header.display()),
+                None => String::new(),
+            };
+            writeln!(f, "extern {}({}){return_type}{header}", external.name, args.join(", "))?;
+        }
+
+        for function in &self.functions {
+            let return_type = function.return_type.map(|ty| format!(" -> {}", self.type_name(ty))).unwrap_or_default();
+            let export = if function.export { "export " } else { "" };
+            writeln!(f, "\n{export}fn {}({}){return_type} {{", function.name, self.args(&function.params))?;
+            for (slot, &ty) in function.slots.iter().enumerate() {
+                writeln!(f, "  s{slot}: {}", self.type_name(ty))?;
+            }
+            for (index, block) in function.blocks.iter().enumerate() {
+                writeln!(f, "b{index}:")?;
+                for inst in &block.insts {
+                    self.write_inst(f, &function.temps, inst)?;
+                }
+                match block.terminator {
+                    Terminator::Jump(block) => writeln!(f, "  jump b{block}")?,
+                    Terminator::Branch { condition, then, otherwise } => writeln!(f, "  branch t{condition}, b{then}, b{otherwise}")?,
+                    Terminator::Return(Some(value)) => writeln!(f, "  return t{value}")?,
+                    Terminator::Return(None) => writeln!(f, "  return")?,
+                    Terminator::Unreachable => writeln!(f, "  unreachable")?,
+                }
+            }
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1148 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::compiler::{self, OptLevel, Piece, Sources, ValueType};
+use crate::compiler::typed::{self, Builtin, Conversion, Expr, ExprKind, ExternId, FunctionId, LocalId};
+use crate::lexer::error::{MudResult, ErrorType};
+use crate::parser::Operator;
+
+use super::{self as ir, *};
+
+type Signature = (Vec<ValueType>, ValueType, bool);
+
+#[derive(Debug, Clone)]
+struct Place {
+    addr: Temp,
+    value_type: ValueType,
+}
+
+// a scalar value is its temporary, a struct value the address of its own copy
+#[derive(Debug, Clone)]
+struct Value {
+    value_type: ValueType,
+    temp: Option<Temp>,
+}
+
+impl Value {
+    fn void() -> Self {
+        Self { value_type: ValueType::Void, temp: None }
+    }
+
+    fn new(value_type: ValueType, temp: Temp) -> Self {
+        Self { value_type, temp: Some(temp) }
+    }
+
+    fn temp(&self) -> Temp {
+        self.temp.expect("only void has no temporary")
+    }
+}
+
+struct Frame {
+    // the function being lowered, the C entry point is none
+    function: Option<FunctionId>,
+    locals: HashMap<LocalId, Place>,
+    captures: Vec<Place>,
+    return_type: ValueType,
+    temps: Vec<Type>,
+    slots: Vec<Type>,
+    blocks: Vec<(Vec<Inst>, Option<Terminator>)>,
+    block: BlockId,
+}
+
+impl Frame {
+    fn new(function: Option<FunctionId>, return_type: ValueType) -> Self {
+        Self { function, locals: HashMap::new(), captures: Vec::new(), return_type, temps: Vec::new(), slots: Vec::new(), blocks: vec![(Vec::new(), None)], block: 0 }
+    }
+}
+
+fn ptr(inner: ValueType) -> ValueType {
+    ValueType::Pointer(Box::new(inner))
+}
+
+fn signature(function_type: &ValueType) -> Signature {
+    match function_type {
+        ValueType::Function { args, return_type, variadic } => (args.clone(), (**return_type).clone(), *variadic),
+        ValueType::Closure { args, return_type } => (args.clone(), (**return_type).clone(), false),
+        t => unreachable!("the checker only calls fns and closures, not {t:?}"),
+    }
+}
+
+// the C runtime helpers and the libc functions the lowered code calls
+fn runtime_signature(name: &str) -> Signature {
+    use ValueType::*;
+
+    match name {
+        "mudrt_exit" => (vec![I32], Void, false),
+        "mudrt_bounds" => (vec![I32, I32], I32, false),
+        "mudrt_range" => (vec![I32, I32, I32], I32, false),
+        "mudrt_print_str" => (vec![Str], Void, false),
+        "mudrt_args" => (vec![I32, ptr(ptr(U8))], Slice(Box::new(Str)), false),
+        "mudrt_read_line" => (vec![ptr(Str)], I32, false),
+        "mudrt_read_all" => (vec![], Str, false),
+        "mudrt_str_cmp" | "mudrt_str_eq" => (vec![Str, Str], I32, false),
+        "mudrt_str_concat" => (vec![Str, Str], Str, false),
+        "mudrt_str_to_cstr" => (vec![Str], ptr(U8), false),
+        "mudrt_str_from_cstr" => (vec![ptr(U8)], Str, false),
+        "mudrt_str_from_int" => (vec![I32], Str, false),
+        "mudrt_str_to_int" => (vec![Str], I32, false),
+        "printf" => (vec![ptr(U8)], I32, true),
+        "calloc" => (vec![Usize, Usize], ptr(Void), false),
+        "free" => (vec![ptr(Void)], Void, false),
+        name => unreachable!("{name} is not a runtime function"),
+    }
+}
+
+fn check(sources: &Sources, path: &str, level: OptLevel) -> MudResult<typed::Program> {
+    let source = sources.read(Path::new(path)).ok_or_else(|| ErrorType::CompileError(format!("Unable to open file {path}")))?;
+    let mut compiler = compiler::Compiler::new();
+    compiler.set_sources(sources.clone());
+    compiler.set_file(path);
+    compiler.set_opt_level(level);
+    compiler.check(source)
+}
+
+// checks the program at path and lowers what main and the exported functions reach
+pub fn lower(sources: &Sources, path: &str, level: OptLevel) -> MudResult<Program> {
+    let typed = check(sources, path, level)?;
+    let Some(main) = typed.main else {
+        return Err(ErrorType::CompileError(format!("{path} has no main function")));
+    };
+
+    let mut lowering = Lowering::new(&typed, path);
+    lowering.exports();
+    lowering.entry(main)?;
+    lowering.pending()?;
+    lowering.symbols();
+    Ok(lowering.program)
+}
+
+// a library for C code to link against, what its exported functions reach and the header declaring them
+pub fn library(sources: &Sources, path: &str, level: OptLevel, module_name: &str) -> MudResult<(Program, String)> {
+    let typed = check(sources, path, level)?;
+
+    let mut lowering = Lowering::new(&typed, path);
+    lowering.exports();
+    lowering.pending()?;
+    lowering.symbols();
+    Ok((lowering.program, compiler::header(&typed, module_name)))
+}
+
+struct Lowering<'a> {
+    typed: &'a typed::Program,
+    path: &'a str,
+    program: Program,
+    symbols: HashSet<String>,
+    function_symbols: HashMap<FunctionId, String>,
+    pending: Vec<FunctionId>,
+    vtables: HashMap<usize, String>,
+    struct_ids: HashMap<String, usize>,
+    strings: HashMap<Vec<u8>, String>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Lowering<'a> {
+    fn new(typed: &'a typed::Program, path: &'a str) -> Self {
+        Self {
+            typed, path, program: Program::default(), symbols: HashSet::new(), function_symbols: HashMap::new(), pending: Vec::new(),
+            vtables: HashMap::new(), struct_ids: HashMap::new(), strings: HashMap::new(), frames: Vec::new(),
+        }
+    }
+
+    // C callers reach exported functions without main calling them
+    fn exports(&mut self) {
+        for function in 0..self.typed.functions.len() {
+            if self.typed.functions[function].export {
+                self.function_symbol(function);
+            }
+        }
+    }
+
+    fn pending(&mut self) -> MudResult<()> {
+        while let Some(function) = self.pending.pop() {
+            self.lower_function(function)?;
+        }
+        Ok(())
+    }
+
+    fn symbols(&mut self) {
+        for (name, function) in &self.typed.symbols {
+            if let Some(symbol) = self.function_symbols.get(function) {
+                self.program.symbols.push((name.clone(), symbol.clone()));
+            }
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> ErrorType {
+        ErrorType::CompileError(format!("{}: {message}", self.path))
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("code is only lowered inside a frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("code is only lowered inside a frame")
+    }
+
+    // a symbol no other function has taken, methods and closures join names with `__`, which a Mud name may also have
+    fn unique_symbol(&mut self, name: String) -> String {
+        let mut symbol = name.clone();
+        let mut n = 1;
+        while self.symbols.contains(&symbol) {
+            n += 1;
+            symbol = format!("{name}_{n}");
+        }
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    // the symbol a function is called by, it is lowered the first time it is asked for
+    fn function_symbol(&mut self, function: FunctionId) -> String {
+        if let Some(symbol) = self.function_symbols.get(&function) {
+            return symbol.clone();
+        }
+
+        let definition = &self.typed.functions[function];
+        let symbol = match definition.export {
+            true => {
+                self.symbols.insert(definition.name.clone());
+                definition.name.clone()
+            }
+            false => self.unique_symbol(definition.name.clone()),
+        };
+        self.function_symbols.insert(function, symbol.clone());
+        self.pending.push(function);
+        symbol
+    }
+
+    // a static of the program's own header is reached through glue the runtime defines under a name of its own
+    fn extern_symbol(&mut self, external: ExternId) -> MudResult<String> {
+        let external = &self.typed.externs[external];
+        let (symbol, header) = match &external.header {
+            Some(header) => (format!("mudglue_{}", external.name), Some((header.clone(), external.name.clone()))),
+            None => (external.name.clone(), None),
+        };
+        self.declare_extern(&symbol, &signature(&external.value_type), external.include.clone(), header)?;
+        Ok(symbol)
+    }
+
+    fn declare_extern(&mut self, name: &str, signature: &Signature, include: Option<String>, header: Option<(PathBuf, String)>) -> MudResult<()> {
+        if self.program.external(name).is_some() {
+            return Ok(());
+        }
+        let (args, return_type, variadic) = signature;
+        // the runtime's own functions take types only the runtime has, they are called by their IR types
+        let prototype = match name.starts_with("mudrt_") {
+            true => None,
+            false => args.iter().map(compiler::extern_c_type).collect::<Option<Vec<_>>>()
+                .zip(compiler::extern_c_type(return_type))
+                .map(|(args, return_type)| Prototype { args, return_type }),
+        };
+        let args = args.iter().map(|arg| self.value_ir_type(arg)).collect::<MudResult<Vec<_>>>()?;
+        let return_type = self.ir_type(return_type)?;
+        self.program.externs.push(Extern { name: name.to_string(), args, return_type, variadic: *variadic, prototype, include, header });
+        Ok(())
+    }
+
+    fn function_type(&self, function: FunctionId) -> ValueType {
+        let definition = &self.typed.functions[function];
+        let args = definition.params.iter().map(|&param| definition.locals[param].clone()).collect();
+        ValueType::Function { args, return_type: Box::new(definition.return_type.clone()), variadic: false }
+    }
+
+    fn function_value(&mut self, function: FunctionId) -> Value {
+        let symbol = self.function_symbol(function);
+        let temp = self.emit_symbol(&symbol);
+        Value::new(self.function_type(function), temp)
+    }
+
+    // a closure is the fn it calls with an environment, and the fn it calls without one
+    fn closure_value(&mut self, function: FunctionId, env: Option<Temp>) -> MudResult<Value> {
+        let (args, return_type, _) = signature(&self.function_type(function));
+        let value_type = ValueType::Closure { args, return_type: Box::new(return_type) };
+        let function = self.function_value(function).temp();
+        let null = self.constant_temp(0, Type::Ptr);
+
+        let fields = match env {
+            Some(env) => [function, env, null],
+            None => [null, null, function],
+        };
+        let closure = self.new_slot(&value_type)?;
+        for (index, value) in fields.into_iter().enumerate() {
+            let field = self.field_addr(closure, &value_type, index)?;
+            self.emit(Inst::Store { addr: field, value });
+        }
+        Ok(Value::new(value_type, closure))
+    }
+
+    // a fn literal that captures locals, its environment holds them or pointers to them
+    fn closure(&mut self, function: FunctionId, places: &[Expr]) -> MudResult<Value> {
+        let captures = &self.typed.functions[function].captures;
+
+        // the environment lives as long as the closure may, so it is on the heap and never freed
+        let env_type = typed::env_type(captures);
+        let size = self.integer(env_type.size()? as i32);
+        let one = self.integer(1);
+        let env = self.call_runtime("calloc", vec![one, size])?.temp();
+        for (index, (capture, place)) in captures.iter().zip(places).enumerate() {
+            let place = self.place(place)?;
+            let field = self.field_addr(env, &env_type, index)?;
+            match capture.by_ref {
+                true => self.emit(Inst::Store { addr: field, value: place.addr }),
+                false => {
+                    let value = self.load(&place)?;
+                    self.store(&Place { addr: field, value_type: place.value_type }, value)?;
+                }
+            }
+        }
+        self.closure_value(function, Some(env))
+    }
+
+    // the pointer to a value and the vtable of its type for an interface, the methods in the order the interface declares them
+    fn vtable(&mut self, implementation: usize) -> String {
+        if let Some(name) = self.vtables.get(&implementation) {
+            return name.clone();
+        }
+
+        let items = self.typed.vtables[implementation].iter().map(|&function| DataItem::Symbol(self.function_symbol(function))).collect();
+        let name = self.unique_symbol(format!("mud__vtable{}", self.vtables.len()));
+        self.program.data.push(Data { name: name.clone(), items });
+        self.vtables.insert(implementation, name.clone());
+        name
+    }
+}
+
+// types
+impl Lowering<'_> {
+    // the IR type of a Mud type, void has none
+    fn ir_type(&mut self, value_type: &ValueType) -> MudResult<Option<Type>> {
+        let (name, fields) = match value_type {
+            ValueType::Void => return Ok(None),
+            ValueType::I32 => return Ok(Some(Type::I32)),
+            ValueType::U8 => return Ok(Some(Type::I8)),
+            // size_t is as wide as a pointer on every target
+            ValueType::Usize | ValueType::Pointer(_) | ValueType::Function { .. } => return Ok(Some(Type::Ptr)),
+            ValueType::Str | ValueType::Slice(_) => ("view".to_string(), vec![Type::Ptr, Type::I32]),
+            ValueType::Interface(_) => ("interface".to_string(), vec![Type::Ptr, Type::Ptr]),
+            ValueType::Closure { .. } => ("closure".to_string(), vec![Type::Ptr, Type::Ptr, Type::Ptr]),
+            ValueType::Struct { fields, .. } => {
+                // instances of a generic struct are named after it, without their type arguments
+                let name = self.typed.struct_names.iter().find(|(t, _)| t == value_type)
+                    .map(|(_, name)| name.split('[').next().unwrap_or(name).to_string())
+                    .unwrap_or_else(|| "struct".to_string());
+                let mut types = Vec::new();
+                for (_, field) in fields {
+                    types.push(self.ir_type(field)?.ok_or_else(|| self.error("a field cannot be void"))?);
+                }
+                (name, types)
+            }
+            t => return Err(self.error(format!("{t:?} has no layout"))),
+        };
+
+        let key = format!("{name}{fields:?}");
+        if let Some(&id) = self.struct_ids.get(&key) {
+            return Ok(Some(Type::Struct(id)));
+        }
+        self.program.structs.push(StructType { name, fields });
+        self.struct_ids.insert(key, self.program.structs.len() - 1);
+        Ok(Some(Type::Struct(self.program.structs.len() - 1)))
+    }
+
+    fn value_ir_type(&mut self, value_type: &ValueType) -> MudResult<Type> {
+        self.ir_type(value_type)?.ok_or_else(|| self.error("void has no value"))
+    }
+
+    // the element type pointer arithmetic moves by, a *void moves by bytes as it does in GNU C
+    fn element_type(&mut self, value_type: &ValueType) -> MudResult<Type> {
+        match value_type {
+            ValueType::Void => Ok(Type::I8),
+            value_type => self.value_ir_type(value_type),
+        }
+    }
+}
+
+// emitting
+impl Lowering<'_> {
+    fn temp(&mut self, ty: Type) -> Temp {
+        let temps = &mut self.frame_mut().temps;
+        temps.push(ty);
+        temps.len() - 1
+    }
+
+    // code after a return is still lowered, into a block nothing jumps to
+    fn emit(&mut self, inst: Inst) {
+        if self.frame().blocks[self.frame().block].1.is_some() {
+            let block = self.new_block();
+            self.frame_mut().block = block;
+        }
+        let frame = self.frame_mut();
+        frame.blocks[frame.block].0.push(inst);
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        let blocks = &mut self.frame_mut().blocks;
+        blocks.push((Vec::new(), None));
+        blocks.len() - 1
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let frame = self.frame_mut();
+        let block = &mut frame.blocks[frame.block].1;
+        if block.is_none() {
+            *block = Some(terminator);
+        }
+    }
+
+    // ends the current block with a jump to block, and continues there
+    fn enter(&mut self, block: BlockId) {
+        self.terminate(Terminator::Jump(block));
+        self.frame_mut().block = block;
+    }
+
+    fn constant_temp(&mut self, value: i64, ty: Type) -> Temp {
+        let dest = self.temp(ty);
+        self.emit(Inst::Const { dest, value });
+        dest
+    }
+
+    fn integer(&mut self, value: i32) -> Value {
+        let temp = self.constant_temp(value as i64, Type::I32);
+        Value::new(ValueType::I32, temp)
+    }
+
+    fn emit_symbol(&mut self, name: &str) -> Temp {
+        let dest = self.temp(Type::Ptr);
+        self.emit(Inst::Symbol { dest, name: name.to_string() });
+        dest
+    }
+
+    // the address of new zeroed memory in the frame
+    fn new_slot(&mut self, value_type: &ValueType) -> MudResult<Temp> {
+        let ty = self.value_ir_type(value_type)?;
+        let frame = self.frame_mut();
+        frame.slots.push(ty);
+        let slot = frame.slots.len() - 1;
+
+        let dest = self.temp(Type::Ptr);
+        self.emit(Inst::Slot { dest, slot });
+        self.emit(Inst::Zero { dest, ty });
+        Ok(dest)
+    }
+
+    fn field_addr(&mut self, base: Temp, value_type: &ValueType, index: usize) -> MudResult<Temp> {
+        let Some(Type::Struct(ty)) = self.ir_type(value_type)? else {
+            return Err(self.error(format!("{value_type:?} has no fields")));
+        };
+        let dest = self.temp(Type::Ptr);
+        self.emit(Inst::Field { dest, base, ty, index });
+        Ok(dest)
+    }
+
+    fn cast(&mut self, value: Temp, ty: Type) -> Temp {
+        if self.frame().temps[value] == ty {
+            return value;
+        }
+        let dest = self.temp(ty);
+        self.emit(Inst::Cast { dest, value });
+        dest
+    }
+
+    fn binary_temp(&mut self, op: BinaryOp, lhs: Temp, rhs: Temp) -> Temp {
+        let ty = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => self.frame().temps[lhs],
+            _ => Type::I32,
+        };
+        let dest = self.temp(ty);
+        self.emit(Inst::Binary { dest, op, lhs, rhs });
+        dest
+    }
+
+    fn load(&mut self, place: &Place) -> MudResult<Value> {
+        match self.ir_type(&place.value_type)? {
+            None => Ok(Value::void()),
+            Some(ty) if ty.is_scalar() => {
+                let dest = self.temp(ty);
+                self.emit(Inst::Load { dest, addr: place.addr });
+                Ok(Value::new(place.value_type.clone(), dest))
+            }
+            // struct values are copied out, so a later store to the place cannot change them
+            Some(ty) => {
+                let dest = self.new_slot(&place.value_type)?;
+                self.emit(Inst::Copy { dest, src: place.addr, ty });
+                Ok(Value::new(place.value_type.clone(), dest))
+            }
+        }
+    }
+
+    fn store(&mut self, place: &Place, value: Value) -> MudResult<()> {
+        match self.ir_type(&place.value_type)? {
+            None => {}
+            Some(ty) if ty.is_scalar() => self.emit(Inst::Store { addr: place.addr, value: value.temp() }),
+            Some(ty) => self.emit(Inst::Copy { dest: place.addr, src: value.temp(), ty }),
+        }
+        Ok(())
+    }
+
+    // a value that needs an address, such as a receiver a method takes by pointer
+    fn temporary(&mut self, value: Value) -> MudResult<Temp> {
+        if !self.value_ir_type(&value.value_type)?.is_scalar() {
+            return Ok(value.temp());
+        }
+        let addr = self.new_slot(&value.value_type)?;
+        self.emit(Inst::Store { addr, value: value.temp() });
+        Ok(addr)
+    }
+
+    fn string_literal(&mut self, bytes: &[u8]) -> Temp {
+        let name = match self.strings.get(bytes) {
+            Some(name) => name.clone(),
+            None => {
+                let name = self.unique_symbol(format!("mud__str{}", self.strings.len()));
+                self.strings.insert(bytes.to_vec(), name.clone());
+                self.program.data.push(Data { name: name.clone(), items: vec![DataItem::Bytes([bytes, &[0]].concat())] });
+                name
+            }
+        };
+
+        self.emit_symbol(&name)
+    }
+
+    // a str or slice of the memory at addr
+    fn view(&mut self, value_type: ValueType, addr: Temp, len: Temp) -> MudResult<Value> {
+        let view = self.new_slot(&value_type)?;
+        let ptr_field = self.field_addr(view, &value_type, 0)?;
+        self.emit(Inst::Store { addr: ptr_field, value: addr });
+        let len_field = self.field_addr(view, &value_type, 1)?;
+        self.emit(Inst::Store { addr: len_field, value: len });
+        Ok(Value::new(value_type, view))
+    }
+
+    fn view_part(&mut self, view: &Value, index: usize) -> MudResult<Temp> {
+        let field = self.field_addr(view.temp(), &view.value_type, index)?;
+        let dest = self.temp([Type::Ptr, Type::I32][index]);
+        self.emit(Inst::Load { dest, addr: field });
+        Ok(dest)
+    }
+
+    // the conversions the checker made explicit where a value meets the type it is used as
+    fn convert(&mut self, conversion: Conversion, value: Value, target: &ValueType) -> MudResult<Value> {
+        match conversion {
+            Conversion::Int => {
+                let ty = self.value_ir_type(target)?;
+                let temp = self.cast(value.temp(), ty);
+                Ok(Value::new(target.clone(), temp))
+            }
+            Conversion::FunctionToClosure => {
+                let closure = self.new_slot(target)?;
+                let field = self.field_addr(closure, target, 2)?;
+                self.emit(Inst::Store { addr: field, value: value.temp() });
+                Ok(Value::new(target.clone(), closure))
+            }
+            Conversion::Interface(implementation) => {
+                let vtable = self.vtable(implementation);
+                let vtable = self.emit_symbol(&vtable);
+                let interface = self.new_slot(target)?;
+                for (index, value) in [value.temp(), vtable].into_iter().enumerate() {
+                    let field = self.field_addr(interface, target, index)?;
+                    self.emit(Inst::Store { addr: field, value });
+                }
+                Ok(Value::new(target.clone(), interface))
+            }
+            Conversion::Retype => Ok(Value { value_type: target.clone(), ..value }),
+            Conversion::Discard => Ok(Value::void()),
+        }
+    }
+}
+
+// expressions
+impl Lowering<'_> {
+    fn eval(&mut self, expr: &Expr) -> MudResult<Value> {
+        match &expr.kind {
+            ExprKind::Void => Ok(Value::void()),
+            ExprKind::Integer(i) => {
+                let ty = self.value_ir_type(&expr.value_type)?;
+                Ok(Value::new(expr.value_type.clone(), self.constant_temp(*i, ty)))
+            }
+            // a literal used as a str is a view of its bytes without the NUL
+            ExprKind::String(bytes) => {
+                let addr = self.string_literal(bytes);
+                match expr.value_type {
+                    ValueType::Str => {
+                        let len = self.integer(bytes.len() as i32).temp();
+                        self.view(ValueType::Str, addr, len)
+                    }
+                    _ => Ok(Value::new(expr.value_type.clone(), addr)),
+                }
+            }
+            ExprKind::Local(_) | ExprKind::Capture(_) | ExprKind::Deref(..) | ExprKind::Index { .. } => {
+                let place = self.place(expr)?;
+                self.load(&place)
+            }
+            ExprKind::Function(function) => Ok(self.function_value(*function)),
+            ExprKind::Extern(external) => {
+                let symbol = self.extern_symbol(*external)?;
+                let temp = self.emit_symbol(&symbol);
+                Ok(Value::new(expr.value_type.clone(), temp))
+            }
+            ExprKind::Closure { function, captures } => self.closure(*function, captures),
+            ExprKind::Unary(op, oprand) => {
+                let value = self.eval(oprand)?;
+                let value = self.cast(value.temp(), Type::I32);
+                let dest = self.temp(Type::I32);
+                let op = if *op == Operator::Minus { UnaryOp::Neg } else { UnaryOp::Not };
+                self.emit(Inst::Unary { dest, op, value });
+                Ok(Value::new(ValueType::I32, dest))
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                self.arithmetic(*op, lhs, rhs)
+            }
+            ExprKind::Logical(op, lhs, rhs) => self.logical(*op, lhs, rhs),
+            ExprKind::Str(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                self.str_operation(*op, lhs, rhs)
+            }
+            ExprKind::AddressOf(value) => {
+                let addr = match value.is_place() {
+                    true => self.place(value)?.addr,
+                    false => {
+                        let value = self.eval(value)?;
+                        self.temporary(value)?
+                    }
+                };
+                Ok(Value::new(expr.value_type.clone(), addr))
+            }
+            ExprKind::Field(base, index) => {
+                if expr.is_place() {
+                    let place = self.place(expr)?;
+                    return self.load(&place);
+                }
+                // fields of values such as call results
+                let value = self.eval(base)?;
+                let addr = self.field_addr(value.temp(), &value.value_type, *index)?;
+                self.load(&Place { addr, value_type: expr.value_type.clone() })
+            }
+            ExprKind::Slice { target, start, end, .. } => self.subslice(target, start.as_deref(), end.as_deref()),
+            ExprKind::Call { callee, args, .. } => self.call(callee, args),
+            ExprKind::Dynamic { method, args, .. } => self.dynamic_call(*method, args, &expr.value_type),
+            ExprKind::Builtin { builtin, args, .. } => self.builtin(*builtin, args, &expr.value_type),
+            ExprKind::Print { pieces, args, .. } => {
+                let mut values = Vec::new();
+                for (conversion, arg) in args {
+                    values.push((*conversion, self.eval(arg)?));
+                }
+                self.printf(pieces, values)?;
+                Ok(Value::void())
+            }
+            ExprKind::Convert(conversion, value) => {
+                let value = self.eval(value)?;
+                self.convert(*conversion, value, &expr.value_type)
+            }
+            ExprKind::Sequence(first, second) => {
+                self.eval(first)?;
+                self.eval(second)?;
+                Ok(Value::void())
+            }
+            ExprKind::If { condition, then, otherwise } => {
+                let condition = self.eval(condition)?.temp();
+                let (then_block, otherwise_block, join) = (self.new_block(), self.new_block(), self.new_block());
+                self.terminate(Terminator::Branch { condition, then: then_block, otherwise: otherwise_block });
+
+                self.frame_mut().block = then_block;
+                self.eval(then)?;
+                self.enter(join);
+                self.frame_mut().block = otherwise_block;
+                self.eval(otherwise)?;
+                self.enter(join);
+                Ok(Value::void())
+            }
+            ExprKind::While { condition, body } => {
+                let (header, looped, exit) = (self.new_block(), self.new_block(), self.new_block());
+                self.enter(header);
+                let condition = self.eval(condition)?.temp();
+                self.terminate(Terminator::Branch { condition, then: looped, otherwise: exit });
+
+                self.frame_mut().block = looped;
+                self.eval(body)?;
+                self.enter(header);
+                self.frame_mut().block = exit;
+                Ok(Value::void())
+            }
+            ExprKind::Return(value) => {
+                let value = self.eval(value)?;
+                self.terminate(Terminator::Return(value.temp));
+                Ok(Value::void())
+            }
+            ExprKind::Declare(local, value) => {
+                let value = match value {
+                    Some(value) => Some(self.eval(value)?),
+                    None => None,
+                };
+                self.declare(*local, value)?;
+                Ok(Value::void())
+            }
+            ExprKind::Assign(place, value) => {
+                let value = self.eval(value)?;
+                let place = self.place(place)?;
+                self.store(&place, value)?;
+                Ok(Value::void())
+            }
+        }
+    }
+
+    // a new local of the function being lowered, zeroed unless it is given a value
+    fn declare(&mut self, local: LocalId, value: Option<Value>) -> MudResult<()> {
+        let function = self.frame().function.expect("locals are only declared inside a function");
+        let value_type = self.typed.functions[function].locals[local].clone();
+        let addr = self.new_slot(&value_type)?;
+        let place = Place { addr, value_type };
+        if let Some(value) = value {
+            self.store(&place, value)?;
+        }
+        self.frame_mut().locals.insert(local, place);
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, op: Operator, lhs: Value, rhs: Value) -> MudResult<Value> {
+        if let (ValueType::Pointer(inner), Operator::Plus) = (&lhs.value_type, op) {
+            let ty = self.element_type(inner)?;
+            let index = self.cast(rhs.temp(), Type::Ptr);
+            let dest = self.temp(Type::Ptr);
+            self.emit(Inst::Element { dest, base: lhs.temp(), ty, index });
+            return Ok(Value::new(lhs.value_type, dest));
+        }
+
+        // pointers and functions compare by address, integers by value
+        let ty = match &lhs.value_type {
+            ValueType::I32 | ValueType::U8 => Type::I32,
+            _ => Type::Ptr,
+        };
+        let (l, r) = (self.cast(lhs.temp(), ty), self.cast(rhs.temp(), ty));
+        let op = match op {
+            Operator::Plus => BinaryOp::Add,
+            Operator::Minus => BinaryOp::Sub,
+            Operator::Asterisk => BinaryOp::Mul,
+            Operator::LessThan => BinaryOp::Lt,
+            Operator::GreaterThan => BinaryOp::Gt,
+            Operator::DoubleEquals => BinaryOp::Eq,
+            Operator::ExclaimEquals => BinaryOp::Ne,
+            op => return Err(self.error(format!("Binary operator {op:?} cannot be lowered"))),
+        };
+        let result = self.binary_temp(op, l, r);
+        Ok(Value::new(ValueType::I32, self.cast(result, Type::I32)))
+    }
+
+    // the right side only runs when the left does not decide the result
+    fn logical(&mut self, op: Operator, lhs: &Expr, rhs: &Expr) -> MudResult<Value> {
+        let result = self.new_slot(&ValueType::I32)?;
+        let lhs = self.eval(lhs)?.temp();
+        let (right, done) = (self.new_block(), self.new_block());
+        let decided = self.constant_temp((op == Operator::DoubleBar) as i64, Type::I32);
+        self.emit(Inst::Store { addr: result, value: decided });
+        let (then, otherwise) = if op == Operator::DoubleBar { (done, right) } else { (right, done) };
+        self.terminate(Terminator::Branch { condition: lhs, then, otherwise });
+
+        self.frame_mut().block = right;
+        let rhs = self.eval(rhs)?.temp();
+        let zero = self.constant_temp(0, self.frame().temps[rhs]);
+        let rhs = self.binary_temp(BinaryOp::Ne, rhs, zero);
+        self.emit(Inst::Store { addr: result, value: rhs });
+        self.enter(done);
+
+        self.load(&Place { addr: result, value_type: ValueType::I32 })
+    }
+
+    fn str_operation(&mut self, op: Operator, lhs: Value, rhs: Value) -> MudResult<Value> {
+        match op {
+            Operator::Plus => self.call_runtime("mudrt_str_concat", vec![lhs, rhs]),
+            Operator::DoubleEquals => self.call_runtime("mudrt_str_eq", vec![lhs, rhs]),
+            Operator::ExclaimEquals => {
+                let equal = self.call_runtime("mudrt_str_eq", vec![lhs, rhs])?.temp();
+                let dest = self.temp(Type::I32);
+                self.emit(Inst::Unary { dest, op: UnaryOp::Not, value: equal });
+                Ok(Value::new(ValueType::I32, dest))
+            }
+            _ => {
+                let order = self.call_runtime("mudrt_str_cmp", vec![lhs, rhs])?.temp();
+                let zero = self.constant_temp(0, Type::I32);
+                let op = if op == Operator::LessThan { BinaryOp::Lt } else { BinaryOp::Gt };
+                Ok(Value::new(ValueType::I32, self.binary_temp(op, order, zero)))
+            }
+        }
+    }
+
+    fn place(&mut self, expr: &Expr) -> MudResult<Place> {
+        match &expr.kind {
+            ExprKind::Local(local) => Ok(self.frame().locals[local].clone()),
+            ExprKind::Capture(index) => Ok(self.frame().captures[*index].clone()),
+            ExprKind::Deref(pointer, _) => {
+                let pointer = self.eval(pointer)?;
+                Ok(Place { addr: pointer.temp(), value_type: expr.value_type.clone() })
+            }
+            ExprKind::Index { target, index, .. } => self.index_place(target, index, &expr.value_type),
+            ExprKind::Field(base, index) => {
+                let base = self.place(base)?;
+                let addr = self.field_addr(base.addr, &base.value_type, *index)?;
+                Ok(Place { addr, value_type: expr.value_type.clone() })
+            }
+            kind => Err(self.error(format!("{kind:?} cannot be assigned to"))),
+        }
+    }
+
+    // indexing slices and str is bounds checked by the runtime, pointers are not
+    fn index_place(&mut self, target: &Expr, index: &Expr, element: &ValueType) -> MudResult<Place> {
+        let target = self.eval(target)?;
+        let index = self.eval(index)?;
+
+        let (base, index) = match &target.value_type {
+            ValueType::Slice(_) | ValueType::Str => {
+                let len = self.view_part(&target, 1)?;
+                let len = Value::new(ValueType::I32, len);
+                let index = self.call_runtime("mudrt_bounds", vec![index, len])?.temp();
+                (self.view_part(&target, 0)?, index)
+            }
+            _ => (target.temp(), index.temp()),
+        };
+
+        let ty = self.element_type(element)?;
+        let index = self.cast(index, Type::Ptr);
+        let addr = self.temp(Type::Ptr);
+        self.emit(Inst::Element { dest: addr, base, ty, index });
+        Ok(Place { addr, value_type: element.clone() })
+    }
+
+    fn subslice(&mut self, target: &Expr, start: Option<&Expr>, end: Option<&Expr>) -> MudResult<Value> {
+        let target = self.eval(target)?;
+        let len = self.view_part(&target, 1)?;
+        let start = match start {
+            Some(start) => self.eval(start)?,
+            None => self.integer(0),
+        };
+        let end = match end {
+            Some(end) => self.eval(end)?,
+            None => Value::new(ValueType::I32, len),
+        };
+
+        let checked = self.call_runtime("mudrt_range", vec![start, end.clone(), Value::new(ValueType::I32, len)])?.temp();
+        let element = match &target.value_type {
+            ValueType::Slice(element) => self.element_type(element)?,
+            _ => Type::I8,
+        };
+        let base = self.view_part(&target, 0)?;
+        let index = self.cast(checked, Type::Ptr);
+        let addr = self.temp(Type::Ptr);
+        self.emit(Inst::Element { dest: addr, base, ty: element, index });
+        let len = self.binary_temp(BinaryOp::Sub, end.temp(), checked);
+        self.view(target.value_type.clone(), addr, len)
+    }
+}
+
+// calls
+impl Lowering<'_> {
+    // fns and externs are called by their symbol, anything else through its value
+    fn call(&mut self, callee: &Expr, args: &[Expr]) -> MudResult<Value> {
+        let symbol = match &callee.kind {
+            ExprKind::Function(function) => Some(self.function_symbol(*function)),
+            ExprKind::Extern(external) => Some(self.extern_symbol(*external)?),
+            _ => None,
+        };
+        let function = match symbol {
+            Some(_) => None,
+            None => Some(self.eval(callee)?),
+        };
+
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.eval(arg)?);
+        }
+
+        match (symbol, function) {
+            (Some(symbol), _) => self.call_signature(ir::Callee::Direct(symbol), &signature(&callee.value_type), values),
+            (None, Some(function)) => self.call_value(function, values),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    // variadic arguments are promoted as C does
+    fn call_signature(&mut self, callee: ir::Callee, signature: &Signature, values: Vec<Value>) -> MudResult<Value> {
+        let (arg_types, return_type, variadic) = signature;
+
+        let mut args = Vec::new();
+        for (i, value) in values.into_iter().enumerate() {
+            let Some(ty) = self.ir_type(&value.value_type)? else { continue };
+            let param = match arg_types.get(i) {
+                Some(param) => self.ir_type(param)?,
+                None => None,
+            };
+            // integers the lowering passes itself are widened to the params they are for
+            let (temp, ty) = match (param, ty) {
+                (None, Type::I8) => (self.cast(value.temp(), Type::I32), Type::I32),
+                (Some(param), ty) if param != ty && param.is_scalar() && ty.is_scalar() => (self.cast(value.temp(), param), param),
+                _ => (value.temp(), ty),
+            };
+            args.push((temp, ty));
+        }
+
+        let fixed = variadic.then_some(arg_types.len());
+        let (dest, value) = match self.ir_type(return_type)? {
+            None => (Dest::None, Value::void()),
+            Some(ty) if ty.is_scalar() => {
+                let temp = self.temp(ty);
+                (Dest::Value(temp), Value::new(return_type.clone(), temp))
+            }
+            Some(ty) => {
+                let slot = self.new_slot(return_type)?;
+                (Dest::Memory(slot, ty), Value::new(return_type.clone(), slot))
+            }
+        };
+        self.emit(Inst::Call { dest, callee, args, fixed });
+        Ok(value)
+    }
+
+    fn call_runtime(&mut self, name: &str, values: Vec<Value>) -> MudResult<Value> {
+        let signature = runtime_signature(name);
+        self.declare_extern(name, &signature, compiler::libc_header(name).map(String::from), None)?;
+        self.call_signature(ir::Callee::Direct(name.to_string()), &signature, values)
+    }
+
+    // a call through a function pointer, or a closure which is called with its environment if it has one
+    fn call_value(&mut self, value: Value, values: Vec<Value>) -> MudResult<Value> {
+        match value.value_type.clone() {
+            ValueType::Function { args, return_type, variadic } => self.call_signature(ir::Callee::Indirect(value.temp()), &(args, *return_type, variadic), values),
+            ValueType::Closure { args, return_type } => {
+                let load_field = |s: &mut Self, index| -> MudResult<Temp> {
+                    let field = s.field_addr(value.temp(), &value.value_type, index)?;
+                    let dest = s.temp(Type::Ptr);
+                    s.emit(Inst::Load { dest, addr: field });
+                    Ok(dest)
+                };
+                let (call, env, function) = (load_field(self, 0)?, load_field(self, 1)?, load_field(self, 2)?);
+
+                let result = match *return_type {
+                    ValueType::Void => None,
+                    ref return_type => Some(self.new_slot(return_type)?),
+                };
+                let (with_env, without_env, done) = (self.new_block(), self.new_block(), self.new_block());
+                self.terminate(Terminator::Branch { condition: call, then: with_env, otherwise: without_env });
+
+                for (block, callee, env) in [(with_env, call, Some(env)), (without_env, function, None)] {
+                    self.frame_mut().block = block;
+                    let env_arg = env.map(|env| Value::new(ptr(ValueType::Void), env));
+                    let arg_types = env_arg.iter().map(|env| env.value_type.clone()).chain(args.iter().cloned()).collect();
+                    let values = env_arg.into_iter().chain(values.iter().cloned()).collect();
+                    let value = self.call_signature(ir::Callee::Indirect(callee), &(arg_types, (*return_type).clone(), false), values)?;
+                    if let Some(result) = result {
+                        self.store(&Place { addr: result, value_type: (*return_type).clone() }, value)?;
+                    }
+                    self.enter(done);
+                }
+
+                match result {
+                    Some(result) => self.load(&Place { addr: result, value_type: *return_type }),
+                    None => Ok(Value::void()),
+                }
+            }
+            t => Err(self.error(format!("Cannot call a {t:?}"))),
+        }
+    }
+
+    // the vtable has the methods in the order the interface declares them, each takes the pointer the interface holds
+    fn dynamic_call(&mut self, method: usize, args: &[Expr], return_type: &ValueType) -> MudResult<Value> {
+        let interface = self.eval(&args[0])?;
+        let data = self.view_part(&interface, 0)?;
+        let vtable_field = self.field_addr(interface.temp(), &interface.value_type, 1)?;
+        let vtable = self.temp(Type::Ptr);
+        self.emit(Inst::Load { dest: vtable, addr: vtable_field });
+        let index = self.constant_temp(method as i64, Type::Ptr);
+        let entry = self.temp(Type::Ptr);
+        self.emit(Inst::Element { dest: entry, base: vtable, ty: Type::Ptr, index });
+        let function = self.temp(Type::Ptr);
+        self.emit(Inst::Load { dest: function, addr: entry });
+
+        let mut values = vec![Value::new(ptr(ValueType::Void), data)];
+        for arg in &args[1..] {
+            values.push(self.eval(arg)?);
+        }
+        let arg_types = values.iter().map(|value| value.value_type.clone()).collect();
+        self.call_signature(ir::Callee::Indirect(function), &(arg_types, return_type.clone(), false), values)
+    }
+}
+
+// builtins
+impl Lowering<'_> {
+    fn builtin(&mut self, builtin: Builtin, args: &[Expr], value_type: &ValueType) -> MudResult<Value> {
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.eval(arg)?);
+        }
+
+        let name = match builtin {
+            Builtin::Alloc(size) => {
+                let size = self.integer(size as i32);
+                values.push(size);
+                let memory = self.call_runtime("calloc", values)?;
+                return Ok(Value { value_type: value_type.clone(), ..memory });
+            }
+            Builtin::Free => "free",
+            Builtin::Exit => "mudrt_exit",
+            Builtin::ReadLine => "mudrt_read_line",
+            Builtin::ReadAll => "mudrt_read_all",
+            Builtin::StrFromInt => "mudrt_str_from_int",
+            Builtin::StrFromCstr => "mudrt_str_from_cstr",
+            Builtin::StrToInt => "mudrt_str_to_int",
+            Builtin::StrToCstr => "mudrt_str_to_cstr",
+        };
+        self.call_runtime(name, values)
+    }
+
+    // output as printf calls, strs are written on their own as they are not NUL terminated
+    fn printf(&mut self, pieces: &[Piece], values: Vec<(&str, Value)>) -> MudResult<()> {
+        let mut format = Vec::new();
+        let mut args = Vec::new();
+        let mut values = values.into_iter();
+
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => format.extend(text.replace('%', "%%").bytes()),
+                Piece::Placeholder(_) => {
+                    let (conversion, value) = values.next().unwrap();
+                    match conversion {
+                        "%.*s" => {
+                            self.flush_printf(&mut format, &mut args)?;
+                            self.call_runtime("mudrt_print_str", vec![value])?;
+                        }
+                        conversion => {
+                            format.extend(conversion.bytes());
+                            args.push(value);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.flush_printf(&mut format, &mut args)
+    }
+
+    fn flush_printf(&mut self, format: &mut Vec<u8>, args: &mut Vec<Value>) -> MudResult<()> {
+        if format.is_empty() {
+            return Ok(());
+        }
+        let format = self.string_literal(&std::mem::take(format));
+        let format = Value::new(ptr(ValueType::U8), format);
+        let values = std::iter::once(format).chain(args.drain(..)).collect();
+        self.call_runtime("printf", values)?;
+        Ok(())
+    }
+}
+
+// functions
+impl Lowering<'_> {
+    fn lower_function(&mut self, function: FunctionId) -> MudResult<()> {
+        let symbol = self.function_symbols[&function].clone();
+        let definition = &self.typed.functions[function];
+
+        self.frames.push(Frame::new(Some(function), definition.return_type.clone()));
+        let result = self.function_body(function, definition);
+        let frame = self.frames.pop().unwrap();
+        let params = result?;
+
+        let return_type = self.ir_type(&definition.return_type)?;
+        let blocks = frame.blocks.into_iter().map(|(insts, terminator)| Block { insts, terminator: terminator.unwrap_or(Terminator::Unreachable) }).collect();
+        self.program.functions.push(Function { name: symbol, export: definition.export, params, return_type, temps: frame.temps, slots: frame.slots, blocks });
+        Ok(())
+    }
+
+    fn function_body(&mut self, function: FunctionId, definition: &typed::Function) -> MudResult<Vec<(Temp, Type)>> {
+        let mut params = Vec::new();
+        let env = match definition.captures.is_empty() {
+            true => None,
+            false => {
+                let env = self.temp(Type::Ptr);
+                params.push((env, Type::Ptr));
+                Some(env)
+            }
+        };
+
+        for &local in &definition.params {
+            let arg_type = &definition.locals[local];
+            let ty = self.value_ir_type(arg_type)?;
+            let param = self.temp(if ty.is_scalar() { ty } else { Type::Ptr });
+            params.push((param, ty));
+            let place = match ty.is_scalar() {
+                true => {
+                    let addr = self.new_slot(arg_type)?;
+                    self.emit(Inst::Store { addr, value: param });
+                    Place { addr, value_type: arg_type.clone() }
+                }
+                false => Place { addr: param, value_type: arg_type.clone() },
+            };
+            self.frame_mut().locals.insert(local, place);
+        }
+
+        // captures are reached through the environment, those captured by reference through a pointer in it
+        if let Some(env) = env {
+            let env_type = typed::env_type(&definition.captures);
+            for (index, capture) in definition.captures.iter().enumerate() {
+                let field = self.field_addr(env, &env_type, index)?;
+                let addr = match capture.by_ref {
+                    true => {
+                        let addr = self.temp(Type::Ptr);
+                        self.emit(Inst::Load { dest: addr, addr: field });
+                        addr
+                    }
+                    false => field,
+                };
+                self.frame_mut().captures.push(Place { addr, value_type: capture.value_type.clone() });
+            }
+        }
+
+        if let Some(local) = definition.self_local {
+            let value = match env {
+                Some(_) => self.closure_value(function, env)?,
+                None => self.function_value(function),
+            };
+            self.declare(local, Some(value))?;
+        }
+
+        self.eval(&definition.body)?;
+
+        // falling off the end returns a zero value
+        let return_type = self.frame().return_type.clone();
+        let value = match self.ir_type(&return_type)? {
+            None => None,
+            Some(ty) if ty.is_scalar() => Some(self.constant_temp(0, ty)),
+            Some(_) => Some(self.new_slot(&return_type)?),
+        };
+        self.terminate(Terminator::Return(value));
+        Ok(params)
+    }
+
+    // the C entry point, which builds main's args from argc and argv
+    fn entry(&mut self, main: FunctionId) -> MudResult<()> {
+        let main_type = self.function_type(main);
+        let (args, return_type, _) = signature(&main_type);
+        let symbol = self.function_symbol(main);
+
+        self.frames.push(Frame::new(None, return_type.clone()));
+        let (argc, argv) = (self.temp(Type::I32), self.temp(Type::Ptr));
+        let values = match args.is_empty() {
+            true => Vec::new(),
+            false => {
+                let (argc, argv) = (Value::new(ValueType::I32, argc), Value::new(ptr(ptr(ValueType::U8)), argv));
+                vec![self.call_runtime("mudrt_args", vec![argc, argv])?]
+            }
+        };
+        let code = self.call_signature(ir::Callee::Direct(symbol), &(args, return_type, false), values)?;
+        self.terminate(Terminator::Return(code.temp));
+        let frame = self.frames.pop().unwrap();
+
+        let blocks = frame.blocks.into_iter().map(|(insts, terminator)| Block { insts, terminator: terminator.unwrap_or(Terminator::Unreachable) }).collect();
+        self.program.functions.push(Function {
+            name: "main".to_string(), export: true, params: vec![(argc, Type::I32), (argv, Type::Ptr)], return_type: Some(Type::I32),
+            temps: frame.temps, slots: frame.slots, blocks,
+        });
+        Ok(())
+    }
+}
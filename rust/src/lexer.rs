@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 pub mod error;
 use error::{MudResult, ErrorType};
+use crate::trace::trace;
 
 use once_cell::sync::Lazy; 
 
@@ -26,6 +27,9 @@ pub enum Operator {
     OpenBrace,
     CloseBrace,
 
+    OpenBracket,
+    CloseBracket,
+
     Arrow,
     Comma,
     ColonEquals,
@@ -41,6 +45,10 @@ pub enum Operator {
     DoubleBar,
 
     Exclaim,
+
+    DotDot,
+    Ellipsis,
+    At,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -51,6 +59,12 @@ pub enum Keyword {
     Function,
     Struct,
     Return,
+    Extern,
+    Import,
+    Pub,
+    Interface,
+    Impl,
+    Closure,
 }
 
 
@@ -66,6 +80,8 @@ static OPERATORS: Lazy<HashMap<&'static str, Operator>> = Lazy::new(|| {
     operator_map.insert(")", Operator::CloseParenthesis);
     operator_map.insert("{", Operator::OpenBrace);
     operator_map.insert("}", Operator::CloseBrace);
+    operator_map.insert("[", Operator::OpenBracket);
+    operator_map.insert("]", Operator::CloseBracket);
 
 
     operator_map.insert("<", Operator::LessThan);
@@ -88,10 +104,21 @@ static OPERATORS: Lazy<HashMap<&'static str, Operator>> = Lazy::new(|| {
     operator_map.insert("!", Operator::Exclaim);
 
     operator_map.insert(".", Operator::Dot);
+    operator_map.insert("..", Operator::DotDot);
+    operator_map.insert("...", Operator::Ellipsis);
+    operator_map.insert("@", Operator::At);
 
     operator_map
 });
 
+// an operator as it is written, for diagnostics
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = OPERATORS.iter().find(|(_, op)| *op == self).map(|(symbol, _)| *symbol).unwrap_or("?");
+        write!(f, "{symbol}")
+    }
+}
+
 static KEYWORDS: Lazy<HashMap<&'static str, Keyword>> = Lazy::new(|| {
     let mut keyword_map: HashMap<&'static str, Keyword> = HashMap::new();
     // let mut operators = [false; 256];
@@ -102,6 +129,12 @@ static KEYWORDS: Lazy<HashMap<&'static str, Keyword>> = Lazy::new(|| {
     keyword_map.insert("fn", Keyword::Function);
     keyword_map.insert("struct", Keyword::Struct);
     keyword_map.insert("return", Keyword::Return);
+    keyword_map.insert("extern", Keyword::Extern);
+    keyword_map.insert("import", Keyword::Import);
+    keyword_map.insert("pub", Keyword::Pub);
+    keyword_map.insert("interface", Keyword::Interface);
+    keyword_map.insert("impl", Keyword::Impl);
+    keyword_map.insert("closure", Keyword::Closure);
 
     keyword_map
 });
@@ -128,26 +161,54 @@ pub enum Lexeme {
     Eof,
 }
 
+// where a lexeme starts in the program, both counted from 1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 pub struct Lexer {
     program: Vec<u8>,
     index: usize,
+    start: usize,
+    line_starts: Vec<usize>,
 }
 
 impl Lexer {
     pub fn new(program: Vec<u8>) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(program.iter().enumerate().filter(|(_, &c)| c == b'\n').map(|(i, _)| i + 1))
+            .collect();
+
         Self {
             program,
             index: 0,
+            start: 0,
+            line_starts,
          }
     }
 
+    // the location of the lexeme returned by the last call to next
+    pub fn location(&self) -> Location {
+        let line = self.line_starts.partition_point(|&start| start <= self.start);
+        Location { line, column: self.start - self.line_starts[line - 1] + 1 }
+    }
+
     pub fn next(&mut self) -> MudResult<Lexeme> {
         while self.peek().is_ascii_whitespace() {
             self.index += 1;
         }
 
-        if self.peek() == '#' as u8 {
-            while self.peek() != '\n' as u8 {
+        if self.peek() == b'#' {
+            // a comment on the last line ends with the input
+            while self.peek() != b'\n' && self.peek() != 0 {
                 self.index += 1;
             }
 
@@ -157,6 +218,7 @@ impl Lexer {
             return self.next();
         }
 
+        self.start = self.index;
 
         // if self.peek() == '#' as u8{
         //     while self.peek() != '\n' as u8{
@@ -164,14 +226,16 @@ impl Lexer {
         //     }
         // }
 
-        match self.peek() {
+        let lexeme = match self.peek() {
             c if c.is_ascii_digit() => self.integer(),
             c if c.is_ascii_alphabetic() => self.identifier(),
             c if c as char == '"' => self.string_literal(),
             c if OP_CHARS[c as usize] => self.operator(),
             0 => Ok(Lexeme::Eof),
             c => Err(ErrorType::LexError(format!("Invalid character: {}", c as char))),
-        }
+        }?;
+        trace!(Lex, Trace, "{} {lexeme:?}", self.location());
+        Ok(lexeme)
     }
 
 
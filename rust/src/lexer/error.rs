@@ -1,10 +1,93 @@
+use std::fmt;
 use std::result;
 
+use super::Location;
+
 pub type MudResult<T> = result::Result<T, ErrorType>;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names, dead_code)]
 pub enum ErrorType {
     ParseError(String),
     LexError(String),
     CompileError(String),
+    // a checked program that failed while the interpreter ran it
+    RuntimeError(String),
+    // an error and where in the source it happened
+    Located(ErrorLocation, Box<ErrorType>),
+}
+
+// the file is filled in once the error reaches code that knows which file was being read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ErrorType {
+    // the error at location, unless it already has a closer one
+    pub fn at(self, location: Location) -> Self {
+        match self {
+            located @ ErrorType::Located(..) => located,
+            error => ErrorType::Located(ErrorLocation { file: None, line: location.line, column: location.column }, Box::new(error)),
+        }
+    }
+
+    pub fn in_file(self, file: &str) -> Self {
+        match self {
+            ErrorType::Located(location, error) if location.file.is_none() => {
+                ErrorType::Located(ErrorLocation { file: Some(file.to_string()), ..location }, error)
+            }
+            error => error,
+        }
+    }
+
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            ErrorType::Located(location, _) => Some(location),
+            _ => None,
+        }
+    }
+
+    // the error without where it happened
+    pub fn unlocated(&self) -> &ErrorType {
+        match self {
+            ErrorType::Located(_, error) => error.unlocated(),
+            error => error,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self.unlocated() {
+            ErrorType::LexError(message) | ErrorType::ParseError(message) | ErrorType::CompileError(message) | ErrorType::RuntimeError(message) => message,
+            ErrorType::Located(..) => unreachable!("unlocated takes the location off"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
+
+impl fmt::Display for ErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.unlocated() {
+            ErrorType::LexError(_) | ErrorType::ParseError(_) => write!(f, "syntax error: ")?,
+            ErrorType::CompileError(_) => write!(f, "error: ")?,
+            ErrorType::RuntimeError(_) => write!(f, "runtime error: ")?,
+            ErrorType::Located(..) => unreachable!("unlocated takes the location off"),
+        }
+        if let Some(location) = self.location() {
+            write!(f, "{location}: ")?;
+        }
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for ErrorType {}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::path::Path;
+
+#[cfg(test)]
+mod test;
+mod lexer;
+mod parser;
+mod compiler;
+mod interpreter;
+mod repl;
+mod ir;
+mod backend;
+pub mod cli;
+pub mod trace;
+
+pub use backend::Target;
+pub use compiler::{OptLevel, Sources};
+pub use interpreter::Io;
+pub use lexer::error::{ErrorLocation, ErrorType, MudResult};
+
+// a file a compilation gives back, named by the extension it takes after the program's name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub extension: String,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    Syntax,
+    Compile,
+    Runtime,
+}
+
+// an error with where it happened taken apart, for tools that show it in place
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl From<ErrorType> for Diagnostic {
+    fn from(error: ErrorType) -> Self {
+        let kind = match error.unlocated() {
+            ErrorType::LexError(_) | ErrorType::ParseError(_) => DiagnosticKind::Syntax,
+            ErrorType::CompileError(_) => DiagnosticKind::Compile,
+            ErrorType::RuntimeError(_) => DiagnosticKind::Runtime,
+            ErrorType::Located(..) => unreachable!("unlocated takes the location off"),
+        };
+        let location = error.location();
+        Self {
+            kind,
+            file: location.and_then(|location| location.file.clone()),
+            line: location.map(|location| location.line),
+            column: location.map(|location| location.column),
+            message: error.message().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            DiagnosticKind::Syntax => write!(f, "syntax error: ")?,
+            DiagnosticKind::Compile => write!(f, "error: ")?,
+            DiagnosticKind::Runtime => write!(f, "runtime error: ")?,
+        }
+        match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => write!(f, "{file}:{line}:{column}: ")?,
+            (None, Some(line), Some(column)) => write!(f, "{line}:{column}: ")?,
+            _ => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+// one program to compile, its files read from memory before the disk
+#[derive(Debug, Clone)]
+pub struct Compilation {
+    sources: Sources,
+    path: String,
+    level: OptLevel,
+}
+
+impl Compilation {
+    // path is the entry file, which add_file can give without it being on disk
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { sources: Sources::new(), path: path.into(), level: OptLevel::default() }
+    }
+
+    pub fn add_file(&mut self, path: impl AsRef<Path>, source: impl Into<Vec<u8>>) -> &mut Self {
+        self.sources.insert(path, source);
+        self
+    }
+
+    pub fn set_opt_level(&mut self, level: OptLevel) -> &mut Self {
+        self.level = level;
+        self
+    }
+
+    fn read(&self) -> MudResult<Vec<u8>> {
+        self.sources.read(Path::new(&self.path))
+            .ok_or_else(|| ErrorType::CompileError(format!("Unable to open file {}", self.path)))
+    }
+
+    fn compiler(&self) -> compiler::Compiler {
+        let mut compiler = compiler::Compiler::new();
+        compiler.set_sources(self.sources.clone());
+        compiler.set_file(&self.path);
+        compiler.set_opt_level(self.level);
+        compiler
+    }
+
+    pub fn check(&self) -> Result<(), Diagnostic> {
+        self.compiler().check(self.read()?)?;
+        Ok(())
+    }
+
+    // the program as the files a backend writes, nothing is written to disk
+    pub fn emit(&self, target: Target) -> Result<Vec<Artifact>, Diagnostic> {
+        let program = ir::lower(&self.sources, &self.path, self.level)?;
+        Ok(backend::emit(&program, target).into_iter()
+            .map(|(extension, contents)| Artifact { extension: extension.to_string(), contents: contents.into_bytes() })
+            .collect())
+    }
+
+    // the typed IR the backends are built from, as text
+    pub fn ir(&self) -> Result<String, Diagnostic> {
+        Ok(ir::lower(&self.sources, &self.path, self.level)?.to_string())
+    }
+
+    // a library for C code to link against, its C and the header declaring what it exports
+    pub fn library(&self) -> Result<Vec<Artifact>, Diagnostic> {
+        let module_name = Path::new(&self.path).file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let (program, header) = ir::library(&self.sources, &self.path, self.level, &module_name)?;
+        let artifacts = backend::emit(&program, Target::C).into_iter().map(|(extension, contents)| (extension, contents.into_bytes()));
+        Ok(artifacts.chain([("h", header.into_bytes())])
+            .map(|(extension, contents)| Artifact { extension: extension.to_string(), contents })
+            .collect())
+    }
+
+    // runs the program in the interpreter with args after its own path, giving its exit code
+    pub fn interpret(&self, args: &[String], io: Io) -> Result<i32, Diagnostic> {
+        let args = std::iter::once(self.path.clone()).chain(args.iter().cloned()).collect();
+        Ok(interpreter::interpret(&self.sources, &self.path, args, io, self.level)?)
+    }
+}
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
 
 use crate::lexer::{error::{ErrorType, MudResult}, Keyword};
-pub use crate::lexer::{Lexeme, Lexer, Operator};
+pub use crate::lexer::{Lexeme, Lexer, Location, Operator};
+use crate::trace::trace;
 use once_cell::sync::Lazy; // TODO: figure out why it cannot be unsync
 
 #[derive(Debug, Clone)]
@@ -11,19 +12,43 @@ pub enum Expression {
     Identifier(String),
     String(String),
     BinaryOperation { op: Operator, lhs: Box<Expression>, rhs: Box<Expression> }, // TODO: probably get rid of expression composition as a binary operation
-    UnaryOperation { op: Operator, oprand: Box<Expression> },
-    FunctionCall { function: Box<Expression>, args: Vec<Expression> },
+    // the operations that can fail at runtime keep where they are in the source
+    UnaryOperation { op: Operator, oprand: Box<Expression>, location: Location },
+    FunctionCall { function: Box<Expression>, args: Vec<Expression>, location: Location },
+    Index { target: Box<Expression>, index: Box<Expression>, location: Location },
+    Slice { target: Box<Expression>, start: Box<Expression>, end: Box<Expression>, location: Location },
+    Instantiate { target: Box<Expression>, args: Vec<Expression> },
+    Generic { params: Vec<(String, Option<Expression>)>, body: Box<Expression> },
+    SliceType(Box<Expression>),
     Return(Box<Expression>),
     Block(Box<Expression>),
     IfElse { condition: Box<Expression>, on_if: Box<Expression>, on_else: Box<Expression> },
     While { condition: Box<Expression>, body: Box<Expression> },
     Function { args: Vec<Expression>, return_type: Box<Expression>, body: Box<Expression> },
+    FunctionType { args: Vec<Expression>, return_type: Box<Expression> },
+    ClosureType { args: Vec<Expression>, return_type: Box<Expression> },
     Struct {fields: Vec<Expression>},
+    Interface { methods: Vec<MethodSignature> },
+    Impl { target: Box<Expression>, interface: Option<Box<Expression>>, methods: Vec<(String, Expression)> },
+    ExternFunction { name: String, args: Vec<Expression>, variadic: bool, return_type: Box<Expression> },
+    ExternStruct { name: String, fields: Option<Vec<Expression>> },
+    Attribute { name: String, args: Vec<Expression>, target: Box<Expression> },
+    Import(String),
+    Pub(Box<Expression>),
+}
+
+// a method an interface requires, the receiver is implicit
+#[derive(Debug, Clone)]
+pub struct MethodSignature {
+    pub name: String,
+    pub args: Vec<Expression>,
+    pub return_type: Expression,
 }
 
 pub struct Parser {
     lexer: Lexer,
     lexeme: Lexeme,
+    location: Location,
 }
 
 static PRECEDENCE_LOOKUP: Lazy<HashMap<Operator, u8>> = Lazy::new(|| {
@@ -72,7 +97,7 @@ fn is_decl(expr: &Expression) -> bool {
         }
     }
 
-    return false;
+    false
 }
 
 impl Parser {
@@ -80,10 +105,16 @@ impl Parser {
         Self {
             lexer: Lexer::new(program),
             lexeme: Lexeme::Eof,
+            location: Location::default(),
         }
     }
 
+    // errors are at the lexeme the parser stopped at, the file is left to the caller
     pub fn parse(&mut self) -> MudResult<Expression> {
+        self.parse_program().map_err(|e| e.at(self.location))
+    }
+
+    fn parse_program(&mut self) -> MudResult<Expression> {
         self.advance()?;
         let expr = self.expression()?;
         if let Lexeme::Eof = self.lexeme {
@@ -103,7 +134,7 @@ impl Parser {
             return true;
         }
 
-        return false;
+        false
     }
 
     fn ifelse(&mut self) -> MudResult<Expression> {
@@ -117,27 +148,25 @@ impl Parser {
                 return true;
             }
 
-            return Parser::is_block(expr);
+            Parser::is_block(expr)
         }
 
+        let location = self.location;
         let condition = self.expression()?;
-        let on_if = self.expression()?;
-        dbg!(&on_if);
+        let on_if = self.term()?;
 
         let on_else = if let Lexeme::Keyword(crate::lexer::Keyword::Else) = self.lexeme {
             self.advance()?;
-            self.expression()?
+            self.term()?
         }
         else {
             Expression::Null
         };
 
-        dbg!(1, &self.lexeme);
-
         if !Self::is_block(&on_if) { return Err(ErrorType::ParseError("Expected block after `if`".to_string())); }
         if !is_valid_else(&on_else) { return Err(ErrorType::ParseError("Expected block after `else`".to_string())); }
 
-        dbg!(2, &self.lexeme);
+        trace!(Parse, Trace, "if at {location}: {on_if:?} else {on_else:?}");
 
         Ok(Expression::IfElse { condition: Box::new(condition), on_if: Box::new(on_if), on_else: Box::new(on_else) })
     }
@@ -145,19 +174,70 @@ impl Parser {
     fn while_loop(&mut self) -> MudResult<Expression> {
         // assume `while` has already been consumed
 
+        let location = self.location;
         let condition = self.expression()?;
-        let body = self.expression()?;
-
-        dbg!(&body);
+        let body = self.term()?;
 
         if !Self::is_block(&body) { return Err(ErrorType::ParseError("Expected block after `while`".to_string())); }
 
+        trace!(Parse, Trace, "while at {location}: {body:?}");
+
         Ok(Expression::While { condition: Box::new(condition), body: Box::new(body) })
     }
 
+    // the `[T, U: Writer]` after `fn` or `struct` that makes a definition generic
+    fn type_params(&mut self) -> MudResult<Vec<(String, Option<Expression>)>> {
+        let mut params = Vec::new();
+
+        if let Lexeme::Operator(Operator::OpenBracket) = self.lexeme {
+            self.advance()?;
+
+            loop {
+                if let Lexeme::Operator(Operator::CloseBracket) = self.lexeme {
+                    self.advance()?;
+                    break;
+                }
+
+                if !params.is_empty() {
+                    expect_lexeme!(self, Lexeme::Operator(Operator::Comma));
+                }
+
+                let param = match self.advance()? {
+                    Lexeme::Identifier(param) => param,
+                    t => return Err(ErrorType::ParseError(format!("Expected type parameter but got {:?}", t))),
+                };
+
+                // a parameter can be bounded by an interface its type arguments must implement
+                let bound = if let Lexeme::Operator(Operator::Colon) = self.lexeme {
+                    self.advance()?;
+                    Some(self.type_name()?)
+                } else {
+                    None
+                };
+
+                params.push((param, bound));
+            }
+
+            if params.is_empty() {
+                return Err(ErrorType::ParseError("Expected at least one type parameter".to_string()));
+            }
+        }
+
+        Ok(params)
+    }
+
+    fn generic(params: Vec<(String, Option<Expression>)>, body: Expression) -> Expression {
+        if params.is_empty() {
+            body
+        } else {
+            Expression::Generic { params, body: Box::new(body) }
+        }
+    }
+
     fn r#struct(&mut self) -> MudResult<Expression> {
         let mut fields = Vec::new();
 
+        let params = self.type_params()?;
         expect_lexeme!(self, Lexeme::Operator(Operator::OpenBrace));
 
         loop {
@@ -166,7 +246,7 @@ impl Parser {
                 break;
             }
 
-            if fields.len() != 0 {
+            if !fields.is_empty() {
                 expect_lexeme!(self, Lexeme::Operator(Operator::Comma))
             }
 
@@ -178,12 +258,97 @@ impl Parser {
             fields.push(field)
         }
 
-        Ok(Expression::Struct{fields})
+        Ok(Self::generic(params, Expression::Struct{fields}))
     }
 
-    fn function(&mut self) -> MudResult<Expression> {
+    // a possibly module qualified type name such as `io.Writer` or `List[i32]`, without what follows it
+    fn type_name(&mut self) -> MudResult<Expression> {
+        self.binary_operation(PRECEDENCE_LOOKUP[&Operator::Dot])
+    }
 
-        // assume `fn` has already been consumed
+    fn interface(&mut self) -> MudResult<Expression> {
+        // assume `interface` has already been consumed
+        let mut methods = Vec::new();
+
+        expect_lexeme!(self, Lexeme::Operator(Operator::OpenBrace));
+
+        loop {
+            if let Lexeme::Operator(Operator::CloseBrace) = self.lexeme {
+                self.advance()?;
+                break;
+            }
+
+            if !methods.is_empty() {
+                expect_lexeme!(self, Lexeme::Operator(Operator::Comma))
+            }
+
+            let name = match self.advance()? {
+                Lexeme::Identifier(name) => name,
+                t => return Err(ErrorType::ParseError(format!("Expected method name in interface but got {:?}", t))),
+            };
+
+            let args = self.args()?;
+            expect_lexeme!(self, Lexeme::Operator(Operator::Arrow));
+            let return_type = self.expression()?;
+
+            methods.push(MethodSignature { name, args, return_type });
+        }
+
+        Ok(Expression::Interface { methods })
+    }
+
+    fn r#impl(&mut self) -> MudResult<Expression> {
+        // assume `impl` has already been consumed, the rest is `Type { name := fn... }` or `Type: Interface { ... }`
+        let target = self.type_name()?;
+        let interface = if let Lexeme::Operator(Operator::Colon) = self.lexeme {
+            self.advance()?;
+            Some(Box::new(self.type_name()?))
+        } else {
+            None
+        };
+
+        let body = match self.term()? {
+            Expression::Block(body) => *body,
+            _ => return Err(ErrorType::ParseError("Expected block of methods after impl".to_string())),
+        };
+
+        fn collect(expr: Expression, methods: &mut Vec<(String, Expression)>) -> MudResult<()> {
+            match expr {
+                Expression::Null => Ok(()),
+                Expression::BinaryOperation { op: Operator::Semicolon, lhs, rhs } => {
+                    collect(*lhs, methods)?;
+                    collect(*rhs, methods)
+                }
+                Expression::BinaryOperation { op: Operator::ColonEquals, lhs, rhs } => match *lhs {
+                    Expression::Identifier(name) => {
+                        methods.push((name, *rhs));
+                        Ok(())
+                    }
+                    lhs => Err(ErrorType::ParseError(format!("Expected method name but got {:?}", lhs))),
+                },
+                e => Err(ErrorType::ParseError(format!("Only method definitions are allowed in impl, got {:?}", e))),
+            }
+        }
+
+        let mut methods = Vec::new();
+        collect(body, &mut methods)?;
+
+        Ok(Expression::Impl { target: Box::new(target), interface, methods })
+    }
+
+    // `(a: T, b: U)`, the declarations a function or method takes
+    fn args(&mut self) -> MudResult<Vec<Expression>> {
+        let args = self.arg_list()?;
+
+        if !args.iter().all(is_decl) {
+            return Err(ErrorType::ParseError("Malformed arguments in function type".to_string()));
+        }
+
+        Ok(args)
+    }
+
+    // arguments of a function type may leave out their names, as in `fn(i32) -> i32`
+    fn arg_list(&mut self) -> MudResult<Vec<Expression>> {
         let mut args = Vec::new();
 
         expect_lexeme!(self, Lexeme::Operator(Operator::OpenParenthesis));
@@ -194,27 +359,181 @@ impl Parser {
                 break;
             }
 
-            if args.len() != 0 {
+            if !args.is_empty() {
                 expect_lexeme!(self, Lexeme::Operator(Operator::Comma))
             }
 
-            let arg = self.expression()?;
-            if !is_decl(&arg) {
-                return Err(ErrorType::ParseError("Malformed arguments in function type".to_string()));
-            }
+            args.push(self.expression()?);
+        }
+
+        Ok(args)
+    }
 
-            args.push(arg)
+    // a function type after `->` never has a body, the brace that follows belongs to the outer function
+    fn return_type(&mut self) -> MudResult<Expression> {
+        if let Lexeme::Keyword(Keyword::Function) = self.lexeme {
+            self.advance()?;
+            let args = self.arg_list()?;
+            expect_lexeme!(self, Lexeme::Operator(Operator::Arrow));
+            let return_type = Box::new(self.return_type()?);
+
+            return Ok(Expression::FunctionType { args, return_type });
+        }
+        if let Lexeme::Keyword(Keyword::Closure) = self.lexeme {
+            self.advance()?;
+            return self.closure_type();
         }
 
+        self.type_name()
+    }
+
+    // `closure(i32) -> i32`, a function that may carry the variables it captured
+    fn closure_type(&mut self) -> MudResult<Expression> {
+        // assume `closure` has already been consumed
+        let args = self.arg_list()?;
         expect_lexeme!(self, Lexeme::Operator(Operator::Arrow));
+        let return_type = Box::new(self.return_type()?);
+
+        Ok(Expression::ClosureType { args, return_type })
+    }
+
+    fn function(&mut self) -> MudResult<Expression> {
+
+        // assume `fn` has already been consumed
+        let location = self.location;
+        let params = self.type_params()?;
+        let args = self.arg_list()?;
+
+        expect_lexeme!(self, Lexeme::Operator(Operator::Arrow));
+
+        let return_type = Box::new(self.return_type()?);
+
+        // without a body this is the type of a function pointer
+        if !matches!(self.lexeme, Lexeme::Operator(Operator::OpenBrace)) {
+            if !params.is_empty() {
+                return Err(ErrorType::ParseError("A function type cannot be generic".to_string()));
+            }
+            return Ok(Expression::FunctionType { args, return_type });
+        }
+
+        if !args.iter().all(is_decl) {
+            return Err(ErrorType::ParseError("Malformed arguments in function type".to_string()));
+        }
 
-        let return_type = Box::new(dbg!(self.expression()?));
-        dbg!(&self.lexeme);
-        let body = Box::new(dbg!(self.expression()?));
+        let body = Box::new(self.term()?);
 
         if !Self::is_block(&body) { return Err(ErrorType::ParseError("Expected block as function body".to_string())); }
 
-        Ok(Expression::Function { args, return_type, body })
+        trace!(Parse, Debug, "fn at {location} -> {return_type:?}");
+        trace!(Parse, Trace, "fn body {body:?}");
+
+        Ok(Self::generic(params, Expression::Function { args, return_type, body }))
+    }
+
+    fn r#extern(&mut self) -> MudResult<Expression> {
+        // assume `extern` has already been consumed
+        match self.advance()? {
+            Lexeme::Keyword(Keyword::Function) => {
+                let name = match self.advance()? {
+                    Lexeme::Identifier(name) => name,
+                    t => return Err(ErrorType::ParseError(format!("Expected extern function name but got {:?}", t))),
+                };
+
+                let mut args = Vec::new();
+                let mut variadic = false;
+
+                expect_lexeme!(self, Lexeme::Operator(Operator::OpenParenthesis));
+
+                loop {
+                    if let Lexeme::Operator(Operator::CloseParenthesis) = self.lexeme {
+                        self.advance()?;
+                        break;
+                    }
+
+                    if variadic {
+                        return Err(ErrorType::ParseError("`...` must be the last argument".to_string()));
+                    }
+
+                    if !args.is_empty() {
+                        expect_lexeme!(self, Lexeme::Operator(Operator::Comma))
+                    }
+
+                    if let Lexeme::Operator(Operator::Ellipsis) = self.lexeme {
+                        self.advance()?;
+                        variadic = true;
+                        continue;
+                    }
+
+                    let arg = self.expression()?;
+                    if !is_decl(&arg) {
+                        return Err(ErrorType::ParseError("Malformed arguments in extern function".to_string()));
+                    }
+
+                    args.push(arg)
+                }
+
+                expect_lexeme!(self, Lexeme::Operator(Operator::Arrow));
+
+                let return_type = Box::new(self.term()?);
+
+                Ok(Expression::ExternFunction { name, args, variadic, return_type })
+            }
+            Lexeme::Keyword(Keyword::Struct) => {
+                let name = match self.advance()? {
+                    Lexeme::Identifier(name) => name,
+                    t => return Err(ErrorType::ParseError(format!("Expected extern struct name but got {:?}", t))),
+                };
+
+                // an extern struct without a body is opaque
+                let fields = if let Lexeme::Operator(Operator::OpenBrace) = self.lexeme {
+                    match self.r#struct()? {
+                        Expression::Struct { fields } => Some(fields),
+                        _ => unreachable!(),
+                    }
+                } else {
+                    None
+                };
+
+                Ok(Expression::ExternStruct { name, fields })
+            }
+            t => Err(ErrorType::ParseError(format!("Expected `fn` or `struct` after `extern` but got {:?}", t))),
+        }
+    }
+
+    fn attribute(&mut self) -> MudResult<Expression> {
+        // assume `@` has already been consumed
+        let name = match self.advance()? {
+            Lexeme::Identifier(name) => name,
+            t => return Err(ErrorType::ParseError(format!("Expected attribute name but got {:?}", t))),
+        };
+
+        let mut args = Vec::new();
+
+        if let Lexeme::Operator(Operator::OpenParenthesis) = self.lexeme {
+            self.advance()?;
+
+            loop {
+                if let Lexeme::Operator(Operator::CloseParenthesis) = self.lexeme {
+                    self.advance()?;
+                    break;
+                }
+
+                if !args.is_empty() {
+                    expect_lexeme!(self, Lexeme::Operator(Operator::Comma));
+                }
+
+                args.push(self.expression()?);
+            }
+        }
+
+        let target = self.declaration()?;
+
+        Ok(Expression::Attribute { name, args, target: Box::new(target) })
+    }
+
+    // attributes and `pub` apply to a whole declaration, but not to the rest of the sequence
+    fn declaration(&mut self) -> MudResult<Expression> {
+        self.binary_operation(PRECEDENCE_LOOKUP[&Operator::ColonEquals])
     }
 
     fn binary_operation(&mut self, precedence: u8) -> MudResult<Expression> {
@@ -228,7 +547,33 @@ impl Parser {
             if let Some(&op_precedence) = PRECEDENCE_LOOKUP.get(&op) {
                 if op_precedence == precedence {
                     self.advance()?;
-                    expr = Expression::BinaryOperation { op, lhs: Box::new(expr), rhs: Box::new(self.binary_operation(precedence - 1)?) };
+                    let rhs = self.binary_operation(precedence - 1)?;
+
+                    // the call in `a.b(c)` applies to `a.b`, not just `b`
+                    expr = match (op, rhs) {
+                        (Operator::Dot, Expression::FunctionCall { function, args, location }) => Expression::FunctionCall {
+                            function: Box::new(Expression::BinaryOperation { op, lhs: Box::new(expr), rhs: function }),
+                            args,
+                            location,
+                        },
+                        // and so does the index in `a.b[c]`
+                        (Operator::Dot, Expression::Index { target, index, location }) => Expression::Index {
+                            target: Box::new(Expression::BinaryOperation { op, lhs: Box::new(expr), rhs: target }),
+                            index,
+                            location,
+                        },
+                        (Operator::Dot, Expression::Slice { target, start, end, location }) => Expression::Slice {
+                            target: Box::new(Expression::BinaryOperation { op, lhs: Box::new(expr), rhs: target }),
+                            start,
+                            end,
+                            location,
+                        },
+                        (Operator::Dot, Expression::Instantiate { target, args }) => Expression::Instantiate {
+                            target: Box::new(Expression::BinaryOperation { op, lhs: Box::new(expr), rhs: target }),
+                            args,
+                        },
+                        (op, rhs) => Expression::BinaryOperation { op, lhs: Box::new(expr), rhs: Box::new(rhs) },
+                    };
                 } else {
                     break;
                 }
@@ -242,6 +587,7 @@ impl Parser {
     }
 
     fn term(&mut self) -> MudResult<Expression> {
+        let location = self.location;
         let term = match self.advance()? {
             Lexeme::Integer(i) => {
                 Ok(Expression::Integer(i))
@@ -257,7 +603,7 @@ impl Parser {
 
             //negate
             Lexeme::Operator(Operator::Minus) => {
-                Ok(Expression::UnaryOperation { op: Operator::Minus, oprand: Box::new(self.term()?) })
+                Ok(Expression::UnaryOperation { op: Operator::Minus, oprand: Box::new(self.term()?), location })
             }
 
             //deref
@@ -265,6 +611,7 @@ impl Parser {
                 Ok(Expression::UnaryOperation {
                     op: Operator::Asterisk,
                     oprand: Box::new(self.term()?),
+                    location,
                 })
             }
 
@@ -272,6 +619,7 @@ impl Parser {
                 Ok(Expression::UnaryOperation {
                     op: Operator::Exclaim,
                     oprand: Box::new(self.term()?),
+                    location,
                 })
             }
 
@@ -280,12 +628,13 @@ impl Parser {
                 Ok(Expression::UnaryOperation {
                     op: Operator::Ampersand,
                     oprand: Box::new(self.term()?),
+                    location,
                 })
             }
 
             //print
             Lexeme::Operator(Operator::LessThan) => {
-                Ok(Expression::UnaryOperation { op: Operator::LessThan, oprand: Box::new(self.term()?) })
+                Ok(Expression::UnaryOperation { op: Operator::LessThan, oprand: Box::new(self.term()?), location })
             }
 
             Lexeme::Operator(Operator::OpenParenthesis) => {
@@ -315,6 +664,12 @@ impl Parser {
             }
 
 
+            // slice type `[]T`
+            Lexeme::Operator(Operator::OpenBracket) => {
+                expect_lexeme!(self, Lexeme::Operator(Operator::CloseBracket));
+                Ok(Expression::SliceType(Box::new(self.term()?)))
+            }
+
             Lexeme::Keyword(Keyword::If) => {
                 self.ifelse()
             }
@@ -331,8 +686,40 @@ impl Parser {
                 self.function()
             }
 
+            Lexeme::Keyword(Keyword::Closure) => {
+                self.closure_type()
+            }
+
+            Lexeme::Keyword(Keyword::Interface) => {
+                self.interface()
+            }
+
+            Lexeme::Keyword(Keyword::Impl) => {
+                self.r#impl()
+            }
+
+            // the value of a return ends at the next `;`, rather than taking the statements after it along
             Lexeme::Keyword(Keyword::Return) => {
-                Ok(Expression::Return(Box::new(self.expression()?)))
+                Ok(Expression::Return(Box::new(self.binary_operation(*MAX_PRECEDENCE - 1)?)))
+            }
+
+            Lexeme::Keyword(Keyword::Extern) => {
+                self.r#extern()
+            }
+
+            Lexeme::Operator(Operator::At) => {
+                self.attribute()
+            }
+
+            Lexeme::Keyword(Keyword::Pub) => {
+                Ok(Expression::Pub(Box::new(self.declaration()?)))
+            }
+
+            Lexeme::Keyword(Keyword::Import) => {
+                match self.advance()? {
+                    Lexeme::String(path) => Ok(Expression::Import(path)),
+                    t => Err(ErrorType::ParseError(format!("Expected module path after `import` but got {:?}", t))),
+                }
             }
 
             Lexeme::Eof => Ok(Expression::Null),
@@ -343,6 +730,11 @@ impl Parser {
             ))),
         }?;
 
+        self.postfix(term, location)
+    }
+
+    // calls and indexing after a term, they can follow each other as in `f[i32](x)` or `ops(1)(2, 3)`
+    fn postfix(&mut self, term: Expression, location: Location) -> MudResult<Expression> {
         match &self.lexeme {
             Lexeme::Operator(Operator::OpenParenthesis) => {
                 self.advance()?;
@@ -355,20 +747,50 @@ impl Parser {
                         break;
                     }
 
-                    if args.len() != 0 {
+                    if !args.is_empty() {
                         expect_lexeme!(self, Lexeme::Operator(Operator::Comma));
                     }
 
                     args.push(self.expression()?);
                 }
 
-                Ok(Expression::FunctionCall { function: Box::new(term), args })
+                self.postfix(Expression::FunctionCall { function: Box::new(term), args, location }, location)
+            }
+            Lexeme::Operator(Operator::OpenBracket) => {
+                self.advance()?;
+                let index = if let Lexeme::Operator(Operator::DotDot) = self.lexeme { Expression::Null } else { self.expression()? };
+
+                // `a[start..end]`, where either bound may be left out
+                if let Lexeme::Operator(Operator::DotDot) = self.lexeme {
+                    self.advance()?;
+                    let end = if let Lexeme::Operator(Operator::CloseBracket) = self.lexeme { Expression::Null } else { self.expression()? };
+                    expect_lexeme!(self, Lexeme::Operator(Operator::CloseBracket));
+
+                    return Ok(Expression::Slice { target: Box::new(term), start: Box::new(index), end: Box::new(end), location });
+                }
+
+                // `List[K, V]` can only be type arguments, a single one is told apart from an index by the compiler
+                if let Lexeme::Operator(Operator::Comma) = self.lexeme {
+                    let mut args = vec![index];
+                    while let Lexeme::Operator(Operator::Comma) = self.lexeme {
+                        self.advance()?;
+                        args.push(self.expression()?);
+                    }
+                    expect_lexeme!(self, Lexeme::Operator(Operator::CloseBracket));
+
+                    return self.postfix(Expression::Instantiate { target: Box::new(term), args }, location);
+                }
+                expect_lexeme!(self, Lexeme::Operator(Operator::CloseBracket));
+
+                self.postfix(Expression::Index { target: Box::new(term), index: Box::new(index), location }, location)
             }
             _ => Ok(term),
         }
     }
 
     fn advance(&mut self) -> MudResult<Lexeme> {
-        Ok(std::mem::replace(&mut self.lexeme, self.lexer.next()?))
+        let next = self.lexer.next().map_err(|e| e.at(self.lexer.location()))?;
+        self.location = self.lexer.location();
+        Ok(std::mem::replace(&mut self.lexeme, next))
     }
 }
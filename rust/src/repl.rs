@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::compiler::{Compiler, ValueType};
+use crate::interpreter::{self, Interpreter, Io, Outcome};
+use crate::parser::{Expression, Operator, Parser};
+
+const HELP: &str = "\
+Declarations such as `Point := struct{x: i32, y: i32}` or `square := fn(x: i32) -> i32 {return x * x}` are kept,
+and so are locals such as `p : Point`. Any other input is run and its value is shown with its type.
+Input continues on the next line until its braces and parentheses are closed.
+  :type <expr>   show the type of an expression without running it
+  :ast <input>   show the syntax tree of an input
+  :help          show this help
+  :quit          leave, as does end of input";
+
+// what the REPL says back to an input
+pub enum Reply {
+    Text(String),
+    Quit(i32),
+}
+
+// a session keeps both the compiler's scopes, to check inputs, and the interpreter's, to run them
+pub struct Repl {
+    compiler: Compiler,
+    locals: HashMap<String, ValueType>,
+    interpreter: Interpreter,
+}
+
+// an input is complete once its braces and parentheses close, those in strings and comments do not count
+pub fn is_complete(input: &str) -> bool {
+    let mut depth = 0;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => for c in chars.by_ref() {
+                if c == '"' {
+                    break;
+                }
+            },
+            '#' => for c in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            },
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth <= 0
+}
+
+// `a; b` as the list of its parts, so declarations and statements can be told apart
+fn flatten(expression: Expression, items: &mut Vec<Expression>) {
+    match expression {
+        Expression::BinaryOperation { op: Operator::Semicolon, lhs, rhs } => {
+            flatten(*lhs, items);
+            flatten(*rhs, items);
+        }
+        Expression::Null => {}
+        expression => items.push(expression),
+    }
+}
+
+// what belongs at the top level of a file, anything else runs as if inside main
+fn is_declaration(expression: &Expression) -> bool {
+    matches!(expression,
+        Expression::BinaryOperation { op: Operator::ColonEquals, .. } | Expression::Import(_) | Expression::ExternFunction { .. }
+        | Expression::ExternStruct { .. } | Expression::Impl { .. } | Expression::Pub(_) | Expression::Attribute { .. })
+}
+
+fn parse(input: &str) -> Result<Expression, String> {
+    Parser::new(input.as_bytes().to_vec()).parse().map_err(|error| error.to_string())
+}
+
+impl Repl {
+    pub fn new(io: Io) -> Self {
+        let mut compiler = Compiler::new();
+        compiler.set_file("<repl>");
+
+        Self { compiler, locals: HashMap::new(), interpreter: Interpreter::session(io) }
+    }
+
+    pub fn respond(&mut self, input: &str) -> Reply {
+        let input = input.trim();
+        let (command, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+
+        let reply = match command {
+            "" => Ok(String::new()),
+            ":help" | ":h" => Ok(HELP.to_string()),
+            ":quit" | ":q" => return Reply::Quit(0),
+            ":ast" => parse(rest).map(|expression| format!("{expression:#?}")),
+            ":type" | ":t" => self.type_of(rest),
+            command if command.starts_with(':') => Err(format!("unknown command {command}, :help lists them")),
+            _ => match self.run(input) {
+                Ok(reply) => return reply,
+                Err(error) => Err(error),
+            },
+        };
+
+        Reply::Text(reply.unwrap_or_else(|error| error))
+    }
+
+    // each part of the input is checked and then declared or run, stopping at the first error
+    fn run(&mut self, input: &str) -> Result<Reply, String> {
+        let mut items = Vec::new();
+        flatten(parse(input)?, &mut items);
+
+        let mut reply = String::new();
+        for item in items {
+            reply = match is_declaration(&item) {
+                true => self.declare(item)?,
+                false => match self.evaluate(item)? {
+                    Outcome::Value(value, value_type) => format!("{value} : {}", self.compiler.type_name(&value_type)),
+                    Outcome::Void => String::new(),
+                    Outcome::Exit(code) => return Ok(Reply::Quit(code)),
+                },
+            };
+        }
+
+        Ok(Reply::Text(reply))
+    }
+
+    fn declare(&mut self, declaration: Expression) -> Result<String, String> {
+        let name = match &declaration {
+            Expression::BinaryOperation { op: Operator::ColonEquals, lhs, .. } => match &**lhs {
+                Expression::Identifier(name) => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        self.compiler.check_declaration(declaration).map_err(|error| error.to_string())?;
+
+        // a struct is shown with its fields, anywhere else it goes by its name
+        let declared = name.and_then(|name| self.compiler.global_type(&name).map(|t| match t {
+            ValueType::Struct { fields, .. } => {
+                let fields = fields.iter().map(|(field, t)| format!("{field}: {}", self.compiler.type_name(t))).collect::<Vec<_>>();
+                format!("{name} : struct{{{}}}", fields.join(", "))
+            }
+            t => format!("{name} : {}", self.compiler.type_name(&t)),
+        }));
+        Ok(declared.unwrap_or_default())
+    }
+
+    fn evaluate(&mut self, statement: Expression) -> Result<Outcome, String> {
+        let statement = self.compiler.check_statement(statement, &mut self.locals).map_err(|error| error.to_string())?;
+
+        match self.interpreter.evaluate_input(self.compiler.program(), &statement) {
+            Ok(outcome) => Ok(outcome),
+            Err(error) => {
+                // the locals the input did not get to declare are forgotten again
+                self.locals.retain(|name, _| self.interpreter.has_local(name));
+                Err(error.to_string())
+            }
+        }
+    }
+
+    fn type_of(&mut self, input: &str) -> Result<String, String> {
+        let expression = parse(input)?;
+        let value_type = self.compiler.statement_type(expression, &self.locals).map_err(|error| error.to_string())?;
+
+        Ok(self.compiler.type_name(&value_type))
+    }
+}
+
+// reads inputs from stdin until :quit or the end of input, and returns the exit code
+pub fn run() -> i32 {
+    let session = || {
+        // the program reads stdin a byte at a time, so the lines after its input are left for the REPL
+        let io = Io {
+            output: Box::new(std::io::stdout()),
+            errors: Box::new(std::io::stderr()),
+            input: Box::new(std::io::BufReader::with_capacity(1, std::io::stdin())),
+        };
+        let mut repl = Repl::new(io);
+        let stdin = std::io::stdin();
+        println!("Mud {}, :help for help", env!("CARGO_PKG_VERSION"));
+
+        loop {
+            let mut input = String::new();
+            loop {
+                print!("{}", if input.is_empty() { "> " } else { ". " });
+                let _ = std::io::stdout().flush();
+
+                let mut line = String::new();
+                if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                    if input.is_empty() {
+                        println!();
+                        return 0;
+                    }
+                    break;
+                }
+                input.push_str(&line);
+                if is_complete(&input) {
+                    break;
+                }
+            }
+
+            match repl.respond(&input) {
+                Reply::Text(text) if text.is_empty() => {}
+                Reply::Text(text) => println!("{text}"),
+                Reply::Quit(code) => return code,
+            }
+        }
+    };
+
+    // calls nest as deeply as they do when a file is interpreted
+    std::thread::Builder::new()
+        .stack_size(interpreter::STACK_SIZE)
+        .spawn(session)
+        .expect("Unable to start the REPL")
+        .join()
+        .unwrap_or(101)
+}
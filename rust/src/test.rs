@@ -1,9 +1,24 @@
 use crate::lexer::{Lexeme, Lexer};
 use crate::*;
-use std::process::Command;
+use crate::cli::*;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
 
 use crate::parser::Parser;
 
+
+mod wasm_host;
+
+// where tests write what they build, so the sources in mud_tests stay alone
+const OUT: &str = "target/mud_tests";
+
+fn out(name: &str) -> String {
+    fs::create_dir_all(OUT).unwrap();
+    format!("{OUT}/{name}")
+}
+
 fn parse_file(input_filename: &str) {
     let input_filename = "mud_tests/".to_owned() + input_filename;
 
@@ -31,22 +46,56 @@ fn lex_file(input_filename: &str) {
 
 fn test_compile(test_name: &str){
     let input_filepath = "mud_tests/".to_string() + test_name;
-    compile_file(&input_filepath, "");
+    let output_filename: String = test_name.split(".").take(1).collect();
+    compile_file(&input_filepath, &out(&format!("{output_filename}.c")), false, OptLevel::O0)
+        .unwrap_or_else(|e| panic!("Error compiling {input_filepath}! {e:?}"));
 }
 
-fn test_transpile(test_name: &str){
+// the error has to be the one the test is about, not any error on the way to it
+fn test_compile_error(test_name: &str, expected: &str){
     let input_filepath = "mud_tests/".to_string() + test_name;
-    transpile_file(&input_filepath);
+    let file = fs::read(&input_filepath).expect("Unable to open file!");
+    let mut comp = compiler::Compiler::new();
+    comp.set_file(input_filepath);
+    let error = comp.check(file).expect_err("Expected a compile error but compiled successfully").to_string();
+    assert!(error.contains(expected), "Expected an error containing {expected:?} but got {error:?}");
 }
 
+// the IR a test lowers to, which every backend is built from
+fn lowered(test_name: &str) -> String {
+    ir::lower(&Sources::default(), &format!("mud_tests/{test_name}"), OptLevel::O0).unwrap().to_string()
+}
 
-fn test_run(test_name: &str, expected_out: Option<&str>){
+// whether the IR has the text of pattern, where $name stands for a temporary and the same name for the same one,
+// so the tests do not hang on how many temporaries the lowering takes before what they check
+fn contains_ir(ir: &str, pattern: &str) -> bool {
+    ir.char_indices().any(|(start, _)| matches_ir(&ir[start..], pattern, &mut std::collections::HashMap::new()))
+}
+
+fn matches_ir<'a>(ir: &'a str, pattern: &'a str, temps: &mut std::collections::HashMap<&'a str, &'a str>) -> bool {
+    let Some((literal, rest)) = pattern.split_once('$') else { return ir.starts_with(pattern) };
+    let Some(ir) = ir.strip_prefix(literal) else { return false };
+    let (name, pattern) = rest.split_at(rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len()));
+    let digits = ir.strip_prefix('t').map_or(0, |number| number.find(|c: char| !c.is_ascii_digit()).unwrap_or(number.len()));
+    if digits == 0 {
+        return false;
+    }
+    let (temp, ir) = ir.split_at(1 + digits);
+    *temps.entry(name).or_insert(temp) == temp && matches_ir(ir, pattern, temps)
+}
+
+fn test_transpile(test_name: &str){
     let input_filepath = "mud_tests/".to_string() + test_name;
+    let output_filename: String = test_name.split(".").take(1).collect();
+    backend::build_c(&input_filepath, &out(&format!("{output_filename}.exe")), &backend::Options::default())
+        .unwrap_or_else(|e| panic!("Error compiling {input_filepath}! {e:?}"));
+}
+
 
-    transpile_file(&input_filepath);
+fn test_run(test_name: &str, expected_out: Option<&str>){
+    test_transpile(test_name);
     let output_filename: String = test_name.split(".").take(1).collect();
-    let output = Command::new("./".to_string() +
-                              &"mud_tests/" + &output_filename + &".exe")
+    let output = Command::new(out(&format!("{output_filename}.exe")))
         .output()
         .expect("Failed to run program");
 
@@ -67,6 +116,100 @@ fn test_run(test_name: &str, expected_out: Option<&str>){
     );
 }
 
+// runs a compiled test with arguments and stdin, and returns its exit code, stdout and stderr
+fn run_with_input(test_name: &str, args: &[&str], input: &str) -> (i32, String, String) {
+    let output_filename: String = test_name.split(".").take(1).collect();
+    let mut child = Command::new(out(&format!("{output_filename}.exe")))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run program");
+
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    (output.status.code().unwrap_or(-1), String::from_utf8_lossy(&output.stdout).to_string(), String::from_utf8_lossy(&output.stderr).to_string())
+}
+
+// output the interpreter writes, shared with the test that reads it back
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Capture {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+    }
+}
+
+type Build = fn(&str, &str, &backend::Options) -> lexer::error::MudResult<()>;
+
+// builds a test with one of the IR backends and runs it like run_with_input, apart from the C build of the same test
+fn build_with_input(build: Build, backend: &str, level: OptLevel, test_name: &str, args: &[&str], input: &str) -> (i32, String, String) {
+    let output_filename: String = test_name.split(".").take(1).collect();
+    build(&format!("mud_tests/{test_name}"), &out(&format!("{output_filename}_{backend}.exe")), &backend::Options { level, ..Default::default() })
+        .unwrap_or_else(|e| panic!("{test_name} did not build: {e:?}"));
+
+    run_with_input(&format!("{output_filename}_{backend}.mud"), args, input)
+}
+
+// the programs print through a backend what they print through C
+fn test_backend(build: Build, backend: &str, level: OptLevel) {
+    for (filename, expected) in PROGRAMS {
+        let (code, stdout, stderr) = build_with_input(build, backend, level, filename, &[], "");
+        assert_eq!((stdout.as_str(), code), (expected, 0), "{filename} printed {stderr}");
+    }
+
+    let (code, stdout, _) = build_with_input(build, backend, level, "input.mud", &["x", "y"], "one\ntwo\n\nlast");
+    assert_eq!(stdout, "3 args, first is x\n1: one (3)\n2: two (3)\n3:  (0)\n4: last (4)\n");
+    assert_eq!(code, 100);
+
+    let (code, stdout, stderr) = build_with_input(build, backend, level, "strings.mud", &[], "");
+    assert_eq!(stdout, "hello mud! 10\n1 0 1\n1 1 1\n1\n[mud] [hello] [mud!]\n1\nh\n-1234 -1233\n50\nmud and C\nfrom C.\n7\n01234 5\n");
+    assert_eq!(stderr, "range 8..12 is out of bounds for length 10\n");
+    assert_eq!(code, 101);
+
+    let (code, stdout, _) = build_with_input(build, backend, level, "extern.mud", &[], "");
+    assert_eq!((stdout.as_str(), code), ("5 3 12\n3 r 2\nsome text\n", 0));
+}
+
+// runs a test in the interpreter like run_with_input runs the compiled program, runtime errors exit with 101
+fn interpret_with_input(test_name: &str, args: &[&str], input: &str) -> (i32, String, String) {
+    interpret_at(OptLevel::O0, test_name, args, input)
+}
+
+fn interpret_at(level: OptLevel, test_name: &str, args: &[&str], input: &str) -> (i32, String, String) {
+    let input_filepath = "mud_tests/".to_string() + test_name;
+    let (output, errors) = (Capture::default(), Capture::default());
+    let io = interpreter::Io {
+        output: Box::new(output.clone()),
+        errors: Box::new(errors.clone()),
+        input: Box::new(std::io::Cursor::new(input.as_bytes().to_vec())),
+    };
+    let args = std::iter::once(input_filepath.clone()).chain(args.iter().map(|arg| arg.to_string())).collect();
+
+    match interpreter::interpret(&Sources::default(), &input_filepath, args, io, level) {
+        Ok(code) => (code, output.text(), errors.text()),
+        Err(error) if matches!(error.unlocated(), ErrorType::RuntimeError(_)) => {
+            let location = error.location().map(|location| format!("{location}: ")).unwrap_or_default();
+            (101, output.text(), errors.text() + &location + error.message())
+        }
+        Err(e) => panic!("{test_name} did not compile: {e:?}"),
+    }
+}
+
 
 #[test]
 fn add_mul() {
@@ -159,6 +302,10 @@ fn comment(){
     let filename = "comment.mud";
     lex_file(filename);
     test_run(filename, Some("print this\nand this\n"));
+
+    let mut lexer = Lexer::new(b"1 # no newline after this".to_vec());
+    assert!(matches!(lexer.next(), Ok(Lexeme::Integer(1))));
+    assert!(matches!(lexer.next(), Ok(Lexeme::Eof)));
 }
 
 #[test]
@@ -181,8 +328,597 @@ fn r#const(){
     test_run(filename, Some("100"))
 }
 
+#[test]
+fn const_expr(){
+    let filename = "const_expr.mud";
+    test_run(filename, Some("8mud123"));
+    test_compile_error("const_assign.mud", "Cannot assign to constant LIMIT");
+}
+
+#[test]
+fn alloc(){
+    let filename = "alloc.mud";
+    test_run(filename, Some("84340"));
+}
+
+#[test]
+fn r#extern(){
+    let filename = "extern.mud";
+    parse_file(filename);
+    test_run(filename, Some("5 3 12\n3 r 2\nsome text\n"));
+
+    // an extern declared with types its C function does not take is not built
+    let error = backend::build_c("mud_tests/extern_mismatch.mud", &out("extern_mismatch.exe"), &backend::Options::default())
+        .expect_err("Expected strlen returning i32 to fail the build").to_string();
+    assert!(error.contains("strlen") && error.contains("-Werror=conversion"), "{error}");
+}
+
+#[test]
+fn export(){
+    compile_file("mud_tests/export.mud", &out("export.c"), true, OptLevel::O0).unwrap();
+
+    let output = Command::new("gcc")
+        .args(["mud_tests/export_driver.c", &out("export.c"), "-I", OUT, "-o", &out("export_driver.exe")])
+        .output()
+        .expect("Failed to run compiler");
+    println!("compiler error/warnings: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success(), "The C driver failed to compile against the exported header");
+
+    let output = Command::new(out("export_driver.exe"))
+        .output()
+        .expect("Failed to run program");
+    assert_eq!("7 1 7\n", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn mangle(){
+    let filename = "mangle.mud";
+    test_run(filename, Some("A5"));
+
+    // the C says which Mud function each symbol is
+    let output = Compilation::new("mud_tests/mangle.mud").emit(Target::C).unwrap().remove(0).contents;
+    assert!(String::from_utf8(output).unwrap().starts_with("/* mud symbol map:\n * printf -> mud_printf\n * main -> mud_main\n */\n"));
+}
+
+#[test]
+fn import(){
+    let filename = "import.mud";
+    parse_file(filename);
+    test_run(filename, Some("7 4 3 10 101\n"));
+    test_compile_error("import_private.mud", "length_squared is not a pub member of module vector");
+    test_compile_error("import_cycle.mud", "Cyclic import: cycle_a.mud -> cycle_b.mud -> cycle_a.mud");
+}
+
+#[test]
+fn stdlib(){
+    let filename = "stdlib.mud";
+    parse_file(filename);
+    test_run(filename, Some("hello world\n11642\n1081\n22317-11\n1first second\n11\n10.1.0\n"));
+
+    // only the parts of the standard library the program uses are emitted
+    let output = fs::read_to_string(out("stdlib.c")).unwrap();
+    assert!(output.contains("mud_3vec_push("));
+    assert!(!output.contains("mud_3vec_set("));
+    assert!(!output.contains("mud_6string_compare("));
+}
+
+#[test]
+fn format(){
+    let filename = "format.mud";
+    parse_file(filename);
+    test_run(filename, Some("a = 42\nM mud 84\n(3, -4)\n77 ff A mud\n{} 100% {42}\nhi mud, you are 42 years old\nno newline 1\n42\n1\n"));
+
+    // pointers other than *u8 print as addresses
+    let output = lowered(filename);
+    assert!(output.contains("data mud__str9 = \"%p\\n\\0\"\n"));
+    assert!(output.contains("data mud__str10 = \"%p\\0\"\n"));
+
+    test_compile_error("format_mismatch.mud", "Placeholder {s} cannot print argument 1 of type i32");
+    test_compile_error("format_count.mud", "Format \"{} {}\" has 2 placeholders but println got 1 arguments");
+}
+
+#[test]
+fn input(){
+    let filename = "input.mud";
+    parse_file(filename);
+    test_transpile(filename);
+
+    // main's return value is the exit code
+    let (code, stdout, _) = run_with_input(filename, &["x", "y"], "one\ntwo\n\nlast");
+    assert_eq!(stdout, "3 args, first is x\n1: one (3)\n2: two (3)\n3:  (0)\n4: last (4)\n");
+    assert_eq!(code, 100);
+
+    let (code, stdout, _) = run_with_input(filename, &["z"], "a\nb\n");
+    assert_eq!(stdout, "2 args, first is z\n1: a (1)\n2: b (1)\n");
+    assert_eq!(code, 2);
+
+    test_transpile("input_all.mud");
+    let (code, stdout, stderr) = run_with_input("input_all.mud", &[], "xyz");
+    assert_eq!(stdout, "[xyz]x");
+    assert!(stderr.contains("index 3 is out of bounds for length 3"));
+    assert_eq!(code, 101);
+
+    test_compile_error("main_signature.mud", "main must be `fn() -> i32` or `fn(args: []str) -> i32`, but is fn(i32, **u8) -> i32");
+}
+
+#[test]
+fn strings(){
+    let filename = "strings.mud";
+    parse_file(filename);
+    test_transpile(filename);
+
+    let (code, stdout, stderr) = run_with_input(filename, &[], "");
+    assert_eq!(stdout, "hello mud! 10\n1 0 1\n1 1 1\n1\n[mud] [hello] [mud!]\n1\nh\n-1234 -1233\n50\nmud and C\nfrom C.\n7\n01234 5\n");
+    assert!(stderr.contains("range 8..12 is out of bounds for length 10"));
+    assert_eq!(code, 101);
+
+    test_compile_error("strings_mismatch.mud", "Cannot apply + to str and i32, convert with str() first");
+}
+
+#[test]
+fn generics(){
+    let filename = "generics.mud";
+    parse_file(filename);
+    test_run(filename, Some("3 5 3\nmud 1\nanswer=42\n42=answer\nanswer\nsame\n7\n"));
+
+    // every set of type arguments gets its own copy, reused between calls
+    let output = lowered(filename);
+    assert!(contains_ir(&output, "\nfn mud_push($v: ptr, $x: i32) -> i32 {\n"));
+    assert!(contains_ir(&output, "\nfn mud_push_2($v: ptr, $x: view.1) -> i32 {\n"));
+    assert_eq!(output.matches("\nfn mud_push").count(), 2);
+    assert_eq!(output.matches("struct List.").count(), 1);
+
+    test_compile_error("generics_body.mud", "In generic add: Cannot add types T and T");
+
+    // a generic fn can call itself and a struct can point to itself
+    test_run("generics_recursive.mud", Some("2 4\n2\n"));
+}
+
+#[test]
+fn interfaces(){
+    let filename = "interfaces.mud";
+    parse_file(filename);
+    test_run(filename, Some("9 rect\nsquare 9\nrect 10\ntotal 19\nlargest rect\nrect 10\nto stdout\na line\nthrough the writer\nto a file\n"));
+
+    // generics call methods directly, interface values go through the vtable
+    let output = lowered(filename);
+    assert!(contains_ir(&output, "\nfn mud_describe($shape: ptr) -> i32 {\n"));
+    assert!(contains_ir(&output, "  $area: i32 = call mud_Square__area($square: ptr)\n"));
+    assert!(contains_ir(&output, "  $entry: ptr = element $table, ptr, $index\n  $method: ptr = load $entry\n  $area: i32 = call *$method($data: ptr)\n"));
+    assert!(output.contains("data mud__vtable0 = mud_Square__area, mud_Square__name\n"));
+
+    // types with the same fields have impls and vtables of their own
+    test_run("interfaces_same_shape.mud", Some("hello cat\nhello dog\ncat dog\n"));
+    let output = lowered("interfaces_same_shape.mud");
+    assert!(output.contains("data mud__vtable0 = mud_Cat__name\n"));
+    assert!(output.contains("data mud__vtable1 = mud_Dog__name\n"));
+
+    test_compile_error("interfaces_missing.mud", "impl Square: Shape: name is missing or is not a fn");
+    test_compile_error("interfaces_bound.mud", "Circle does not implement Shape, which T of describe requires");
+}
+
+#[test]
+fn methods(){
+    let filename = "methods.mud";
+    parse_file(filename);
+    test_run(filename, Some("6\n12 12\n18\n18\nm\n42\n2 5\n"));
+
+    // methods are plain functions, and the receiver has its address taken or is dereferenced as needed
+    let output = lowered(filename);
+    assert!(contains_ir(&output, "\nfn mud_Counter__tick($self: ptr) -> i32 {\n"));
+    assert!(contains_ir(&output, "  $count: i32 = call mud_Counter__tick($counter: ptr)\n"));
+    assert!(contains_ir(&output, "  $pointer: ptr = load $slot\n  $counter: ptr = load $pointer\n  $count: i32 = call mud_Counter__tick($counter: ptr)\n"));
+    assert!(contains_ir(&output, "  copy $copy, $counter, Counter.0\n  $count: i32 = call mud_Counter__get($copy: Counter.0)\n"));
+
+    // structs with the same fields each have methods of their own
+    test_run("methods_same_shape.mud", Some("3m\n10ft\n"));
+
+    test_compile_error("methods_missing.mud", "field \"new\" not found on struct Counter");
+}
+
+#[test]
+fn function_pointers(){
+    let filename = "function_pointers.mud";
+    parse_file(filename);
+    test_run(filename, Some("10 16\n36 12\n1 0\nok 42\n9\n36\n20 hi hi mud\nhi mud\n"));
+
+    // fn values are plain pointers, and are called through them
+    let output = lowered(filename);
+    assert!(contains_ir(&output, "\nfn mud_pick($which: i32) -> ptr {\n"));
+    assert!(output.contains("struct Button.1 { view.0, ptr }\n"));
+    assert!(contains_ir(&output, "\nfn mud_apply($f: ptr, $x: i32) -> i32 {\n"));
+    assert!(contains_ir(&output, "  $result: i32 = call *$f($x: i32)\n"));
+
+    test_compile_error("function_pointers_mismatch.mud", "Expected a function of type fn(i32) -> i32 but got fn(str) -> str");
+}
+
+#[test]
+fn closures(){
+    let filename = "closures.mud";
+    parse_file(filename);
+    test_run(filename, Some("16 25 120\n6 11 42\n9\n6\n101\n5 4 3 1 1 = 14\n1 1 3 4 5 \nfig pear apple\n"));
+
+    // fns that capture nothing stay plain functions, the rest take an environment and are called with it
+    let output = lowered(filename);
+    assert!(contains_ir(&output, "\nfn mud_main__square($x: i32) -> i32 {\n"));
+    assert!(output.contains("struct struct.1 { ptr, i32 }\n"));
+    assert!(contains_ir(&output, "\nfn mud_main__count($env: ptr, $x: i32) -> i32 {\n"));
+    assert!(contains_ir(&output, "  $result: i32 = call *$code($env: ptr, $x: i32)\n"));
+    assert!(contains_ir(&output, "  $result: i32 = call *$code($x: i32)\n"));
+    assert!(contains_ir(&output, "  $field: ptr = field $closure, closure.0.2\n  store $field, $env\n"));
+
+    test_compile_error("closures_escape.mud", "f outlives x, so it cannot hold a closure that captures x by reference");
+    test_compile_error("closures_return.mud", "Cannot return a closure that captures n by reference, n does not outlive the call");
+    // a closure argument may borrow the caller's locals, so the callee cannot keep it
+    test_compile_error("closures_argument.mud", "Only a local variable can hold a closure passed as f");
+}
+
 #[test]
 fn casting(){
     let filename = "casting.mud";
     test_run(filename, Some("42"));
 }
+
+// programs and what they print, whichever way they are run
+const PROGRAMS: [(&str, &str); 19] = [
+    ("while.mud", "109876543210"),
+    ("str_literal.mud", "testcatcat"),
+    ("operators.mud", "Passed"),
+    ("pointer.mud", "67"),
+    ("char.mud", "A"),
+    ("struct.mud", "tom the cat is 7 years old.\n"),
+    ("read_file.mud", "some text\n"),
+    ("const_expr.mud", "8mud123"),
+    ("alloc.mud", "84340"),
+    ("mangle.mud", "A5"),
+    ("import.mud", "7 4 3 10 101\n"),
+    ("stdlib.mud", "hello world\n11642\n1081\n22317-11\n1first second\n11\n10.1.0\n"),
+    ("format.mud", "a = 42\nM mud 84\n(3, -4)\n77 ff A mud\n{} 100% {42}\nhi mud, you are 42 years old\nno newline 1\n42\n1\n"),
+    ("generics.mud", "3 5 3\nmud 1\nanswer=42\n42=answer\nanswer\nsame\n7\n"),
+    ("interfaces.mud", "9 rect\nsquare 9\nrect 10\ntotal 19\nlargest rect\nrect 10\nto stdout\na line\nthrough the writer\nto a file\n"),
+    ("methods.mud", "6\n12 12\n18\n18\nm\n42\n2 5\n"),
+    ("function_pointers.mud", "10 16\n36 12\n1 0\nok 42\n9\n36\n20 hi hi mud\nhi mud\n"),
+    ("closures.mud", "16 25 120\n6 11 42\n9\n6\n101\n5 4 3 1 1 = 14\n1 1 3 4 5 \nfig pear apple\n"),
+    ("casting.mud", "42"),
+];
+
+#[test]
+fn interpreter(){
+    // the interpreter prints what the compiled programs print
+    for (filename, expected) in PROGRAMS {
+        let (code, stdout, stderr) = interpret_with_input(filename, &[], "");
+        assert_eq!((stdout.as_str(), code), (expected, 0), "{filename} printed {stderr}");
+    }
+
+    let (code, stdout, _) = interpret_with_input("input.mud", &["x", "y"], "one\ntwo\n\nlast");
+    assert_eq!(stdout, "3 args, first is x\n1: one (3)\n2: two (3)\n3:  (0)\n4: last (4)\n");
+    assert_eq!(code, 100);
+
+    let (code, stdout, stderr) = interpret_with_input("strings.mud", &[], "");
+    assert_eq!(stdout, "hello mud! 10\n1 0 1\n1 1 1\n1\n[mud] [hello] [mud!]\n1\nh\n-1234 -1233\n50\nmud and C\nfrom C.\n7\n01234 5\n");
+    assert!(stderr.contains("mud_tests/strings.mud:50:19: range 8..12 is out of bounds for length 10"), "{stderr}");
+    assert_eq!(code, 101);
+
+    // externs the interpreter cannot emulate stop the program instead of guessing
+    let (code, _, stderr) = interpret_with_input("extern.mud", &[], "");
+    assert!(stderr.contains("extern fn triple is not available in the interpreter"), "{stderr}");
+    assert_eq!(code, 101);
+}
+
+#[test]
+fn c(){
+    test_backend(backend::build_c, "c", OptLevel::O0);
+
+    // externs are called through their header with the types the program gives them
+    let output = fs::read_to_string(out("struct_c.c")).unwrap();
+    assert!(output.contains("struct mudty_Cat_0 { intptr_t f0; i32 f1; };"));
+    assert!(output.contains("{ i32 r = printf((char*)t"));
+    assert!(!output.contains("mudext_printf"));
+    assert!(output.contains("int main(int argc, char** argv)"));
+}
+
+#[test]
+fn optimize(){
+    // optimized programs print what they print as written
+    for (filename, expected) in PROGRAMS {
+        let (code, stdout, stderr) = interpret_at(OptLevel::O2, filename, &[], "");
+        assert_eq!((stdout.as_str(), code), (expected, 0), "{filename} printed {stderr}");
+    }
+    test_backend(backend::build_c, "c_o2", OptLevel::O2);
+
+    let (code, stdout, _) = interpret_at(OptLevel::O1, "optimize.mud", &[], "");
+    assert_eq!((stdout.as_str(), code), ("4 2\n", 0));
+
+    // constants are folded into the code, and what follows a return or sits behind a false condition is gone
+    let program = ir::lower(&Sources::default(), "mud_tests/optimize.mud", OptLevel::O1).unwrap().to_string();
+    assert!(contains_ir(&program, "  $two: i32 = const 2\n  $result: i32 = call mud_twice($two: i32)\n"));
+    assert!(!program.contains("never") && !program.contains("small") && !program.contains("loop"));
+
+    // -O2 leaves out the functions and structs nothing uses
+    let optimized = |level| {
+        let program = Parser::new(fs::read("mud_tests/optimize.mud").unwrap()).parse().unwrap();
+        format!("{:?}", compiler::optimize(program, level))
+    };
+    let output = optimized(OptLevel::O0);
+    assert!(output.contains("\"unused\"") && output.contains("\"Unused\""));
+    let output = optimized(OptLevel::O2);
+    assert!(!output.contains("\"unused\"") && !output.contains("\"Unused\"") && output.contains("\"twice\""));
+
+    // the IR only holds what main reaches, so the read_file prelude only comes along when it is called
+    let program = ir::lower(&Sources::default(), "mud_tests/optimize.mud", OptLevel::O0).unwrap().to_string();
+    assert!(!program.contains("mud_unused") && !program.contains("read_file"));
+
+    // code the optimizer drops is still checked
+    for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        let mut compilation = Compilation::new("mud_tests/optimize_reject.mud");
+        compilation.set_opt_level(level);
+        assert!(compilation.check().is_err(), "{level:?} accepted optimize_reject.mud");
+        assert!(compilation.ir().is_err(), "{level:?} lowered optimize_reject.mud");
+    }
+}
+
+#[test]
+fn ir_dump(){
+    let program = ir::lower(&Sources::default(), "mud_tests/struct.mud", OptLevel::O0).unwrap().to_string();
+    assert!(program.contains("struct Cat.0 { ptr, i32 }\n"));
+    assert!(program.contains("data mud__str0 = \"tom\\0\"\n"));
+    assert!(program.contains("extern printf(ptr, ...) -> i32\n"));
+    assert!(program.contains("\nfn mud_main() -> i32 {\n  s0: Cat.0\n"));
+    assert!(contains_ir(&program, "  $field: ptr = field $cat, Cat.0.1\n  store $field, $age\n"));
+    assert!(contains_ir(&program, "  $result: i32 = call printf($format: ptr, ... $age: i32)\n"));
+}
+
+#[test]
+fn native(){
+    test_backend(backend::build_asm, "asm", OptLevel::O0);
+
+    // static functions of the program's own headers are called through pointers the C runtime sets up
+    let runtime = fs::read_to_string(out("extern_asm.rt.c")).unwrap();
+    assert!(runtime.contains("__typeof__(triple)* mudglue_triple = triple;"));
+
+    // the program is its own assembly, C is only the runtime it links with
+    let output = fs::read_to_string(out("closures_asm.s")).unwrap();
+    assert!(output.contains(".globl main"));
+    assert!(output.contains("call mud_main"));
+    assert!(output.contains("call printf@PLT"));
+}
+
+#[test]
+fn llvm(){
+    if Command::new("llc").arg("--version").output().is_err() {
+        println!("llc is not installed, skipping the LLVM backend");
+        return;
+    }
+    test_backend(backend::build_llvm, "llvm", OptLevel::O0);
+
+    // structs are named types, fields are reached with getelementptr and strings are constants
+    let output = fs::read_to_string(out("struct_llvm.ll")).unwrap();
+    assert!(output.contains("%Cat.0 = type { ptr, i32 }"));
+    assert!(output.contains("getelementptr %Cat.0, ptr %t0, i32 0, i32 1"));
+    assert!(output.contains("@mud__str0 = private unnamed_addr constant [4 x i8] c\"tom\\00\""));
+
+    // strs cross calls as C passes them, so the C runtime can take them
+    let output = fs::read_to_string(out("strings_llvm.ll")).unwrap();
+    assert!(output.contains("declare void @mudrt_print_str({ i64, i64 })"));
+}
+
+// builds a test as a wasm module and runs it in the test host
+fn wasm_with_input(test_name: &str, args: &[&str], input: &str) -> (i32, String, String) {
+    let output_filename: String = test_name.split(".").take(1).collect();
+    let output = out(&format!("{output_filename}_wasm.wat"));
+    backend::build_wat(&format!("mud_tests/{test_name}"), &output, &backend::Options::default()).unwrap_or_else(|e| panic!("{test_name} did not build: {e:?}"));
+
+    wasm_host::run(&output, args, input)
+}
+
+#[test]
+fn wasm(){
+    for (filename, expected) in PROGRAMS {
+        let (code, stdout, stderr) = wasm_with_input(filename, &[], "");
+        assert_eq!((stdout.as_str(), code), (expected, 0), "{filename} printed {stderr}");
+    }
+
+    let (code, stdout, _) = wasm_with_input("input.mud", &["x", "y"], "one\ntwo\n\nlast");
+    assert_eq!(stdout, "3 args, first is x\n1: one (3)\n2: two (3)\n3:  (0)\n4: last (4)\n");
+    assert_eq!(code, 100);
+
+    let (code, stdout, stderr) = wasm_with_input("strings.mud", &[], "");
+    assert_eq!(stdout, "hello mud! 10\n1 0 1\n1 1 1\n1\n[mud] [hello] [mud!]\n1\nh\n-1234 -1233\n50\nmud and C\nfrom C.\n7\n01234 5\n");
+    assert_eq!(stderr, "range 8..12 is out of bounds for length 10\n");
+    assert_eq!(code, 101);
+
+    // a static of the program's own header is an import the host provides by its name
+    backend::build_wat("mud_tests/extern.mud", &out("extern_wasm.wat"), &backend::Options::default()).unwrap();
+    let output = fs::read_to_string(out("extern_wasm.wat")).unwrap();
+    assert!(output.contains("(import \"env\" \"triple\" (func $mudglue_triple (param i32) (result i32)))"));
+    assert!(output.contains("(export \"main\")"));
+}
+
+#[test]
+fn cli(){
+    let options = |args: &str| parse_args(args.split_whitespace().map(String::from).collect());
+
+    let parsed = options("build mud_tests/casting.mud -o mud_tests/casting_cli.exe -O2 --cc=gcc --cflags -O1 --asm").unwrap();
+    assert_eq!((parsed.output.as_deref(), parsed.build.level, parsed.build.cflags.clone()), (Some("mud_tests/casting_cli.exe"), OptLevel::O2, vec!["-O1".to_string()]));
+    assert!(parsed.target == Target::Asm);
+    assert_eq!(options("run x.mud -- a -o").unwrap().program_args, ["a", "-o"]);
+    assert_eq!(options("build").err().unwrap(), "build expects a file to compile");
+    assert_eq!(options("build x.mud --fast").err().unwrap(), "unknown option --fast");
+    assert_eq!(options("build x.mud -O3").err().unwrap(), "unknown optimization level -O3");
+
+    // diagnostics and faults give exit codes rather than panics
+    assert_eq!(run(options("check mud_tests/struct.mud").unwrap()), 0);
+    assert_eq!(run(options("check mud_tests/format_mismatch.mud").unwrap()), EXIT_ERROR);
+    assert_eq!(run(options("check mud_tests/missing.mud").unwrap()), EXIT_ERROR);
+    assert_eq!(run(options("frobnicate x.mud").unwrap()), EXIT_USAGE);
+    assert_eq!(run(options("emit mud_tests/struct.mud --emit=bytecode").unwrap()), EXIT_USAGE);
+    assert_eq!(run(options("run mud_tests/null_deref.mud --interpret").unwrap()), EXIT_RUNTIME_ERROR);
+
+    assert_eq!(run(options(&format!("build mud_tests/casting.mud -o {} --cflags=-O2", out("casting_cli.exe"))).unwrap()), 0);
+    let output = Command::new(out("casting_cli.exe")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42");
+    assert_eq!(run(options(&format!("run mud_tests/main_signature.mud -o {}", out("main_signature_cli.exe"))).unwrap()), EXIT_ERROR);
+    // a program killed by SIGABRT, not a program that failed to build
+    assert_eq!(run(options(&format!("run mud_tests/abort.mud -o {}", out("abort_cli.exe"))).unwrap()), 128 + 6);
+
+    assert_eq!(run(options(&format!("emit mud_tests/struct.mud --emit=ir -o {}", out("struct_cli.ir"))).unwrap()), 0);
+    assert!(fs::read_to_string(out("struct_cli.ir")).unwrap().contains("struct Cat.0 { ptr, i32 }\n"));
+    assert_eq!(run(options(&format!("emit mud_tests/struct.mud --emit=tokens -o {}", out("struct_cli.tokens"))).unwrap()), 0);
+    assert!(fs::read_to_string(out("struct_cli.tokens")).unwrap().ends_with("Eof\n"));
+}
+
+#[test]
+fn library_api(){
+    // a program and the module it imports given in memory, nothing of it is on disk
+    let mut compilation = Compilation::new("virtual/main.mud");
+    compilation
+        .add_file("virtual/main.mud", "import \"lib/math.mud\";\n\nmain := fn() -> i32 {\n  println(\"{}\", math.double(21));\n  return 3\n}")
+        .add_file("virtual/lib/./math.mud", "pub double := fn(x: i32) -> i32 {\n  return x * 2\n};");
+    assert!(compilation.check().is_ok());
+
+    let output = Capture::default();
+    let io = Io { output: Box::new(output.clone()), errors: Box::new(Capture::default()), input: Box::new(std::io::empty()) };
+    assert_eq!(compilation.interpret(&[], io).unwrap(), 3);
+    assert_eq!(output.text(), "42\n");
+
+    let artifacts = compilation.emit(Target::Asm).unwrap();
+    assert_eq!(artifacts.iter().map(|artifact| &artifact.extension[..]).collect::<Vec<_>>(), ["s", "rt.c"]);
+    assert!(String::from_utf8_lossy(&compilation.emit(Target::C).unwrap()[0].contents).contains("int main("));
+    assert!(compilation.ir().unwrap().contains("fn mud_main() -> i32 {"));
+
+    // errors come back with where they happened taken apart
+    let mut faulty = Compilation::new("virtual/fault.mud");
+    faulty.add_file("virtual/fault.mud", fs::read("mud_tests/null_deref.mud").unwrap());
+    let io = Io { output: Box::new(Capture::default()), errors: Box::new(Capture::default()), input: Box::new(std::io::empty()) };
+    let diagnostic = faulty.interpret(&[], io).unwrap_err();
+    assert_eq!((diagnostic.kind, diagnostic.file.as_deref(), diagnostic.line, diagnostic.column), (DiagnosticKind::Runtime, Some("virtual/fault.mud"), Some(11), Some(12)));
+    assert_eq!(diagnostic.to_string(), "runtime error: virtual/fault.mud:11:12: null pointer dereference");
+
+    let diagnostic = Compilation::new("mud_tests/format_mismatch.mud").check().unwrap_err();
+    assert_eq!((diagnostic.kind, diagnostic.file.as_deref(), diagnostic.line, diagnostic.column), (DiagnosticKind::Compile, Some("mud_tests/format_mismatch.mud"), Some(4), Some(5)));
+    let mut unclosed = Compilation::new("virtual/unclosed.mud");
+    unclosed.add_file("virtual/unclosed.mud", "main := fn() -> i32 {\n  return (1 + 2\n}");
+    assert_eq!(unclosed.check().unwrap_err().to_string(), "syntax error: virtual/unclosed.mud:3:1: Unclosed parenthesis");
+    let diagnostic = Compilation::new("virtual/missing.mud").check().unwrap_err();
+    assert_eq!(diagnostic.to_string(), "error: Unable to open file virtual/missing.mud");
+    assert_eq!(ErrorType::ParseError("expected ;".to_string()).to_string(), "syntax error: expected ;");
+    // the kind is the located error's, however deep the locations go
+    let location = ErrorLocation { file: None, line: 1, column: 2 };
+    let nested = ErrorType::Located(location.clone(), Box::new(ErrorType::Located(location, Box::new(ErrorType::LexError("bad".to_string())))));
+    assert_eq!(Diagnostic::from(nested).kind, DiagnosticKind::Syntax);
+}
+
+#[test]
+fn check_diagnostics(){
+    let check = |source: &str| {
+        let mut compilation = Compilation::new("virtual/check.mud");
+        compilation.add_file("virtual/check.mud", source);
+        compilation.check().map_err(|diagnostic| diagnostic.message)
+    };
+
+    // check rejects what build would, including the bodies of nested fns
+    assert_eq!(check("main := fn() -> i32 {\n  g := fn() -> i32 {\n    return nope\n  };\n  return g()\n}").unwrap_err(), "Undefined variable: nope");
+
+    // types are named as they are written in Mud
+    assert_eq!(check("Cat := struct { name: str, age: i32 };\nmain := fn() -> i32 {\n  c: Cat;\n  return c + 1\n}").unwrap_err(), "Cannot add types Cat and i32");
+    assert_eq!(check("main := fn(x: *i32) -> i32 {\n  return 0\n}").unwrap_err(),
+        "main must be `fn() -> i32` or `fn(args: []str) -> i32`, but is fn(*i32) -> i32");
+    assert_eq!(check("main := fn(args: []str) -> i32 {\n  return -args[0]\n}").unwrap_err(), "Cannot negate type str");
+    assert_eq!(check("main := fn() -> i32 {\n  x : i32;\n  x = { 1 };\n  return x\n}").unwrap_err(), "Cannot use a block as the value of x");
+}
+
+#[test]
+fn tracing(){
+    use crate::trace::{parse, Category, Level};
+
+    // specs are only read here, the levels they set are global and other tests run alongside
+    assert_eq!(parse("parse,resolve=bogus").err().unwrap(), "unknown trace level bogus");
+    assert_eq!(parse("typeck").err().unwrap(), "unknown trace category typeck");
+    assert_eq!(parse("lex=info,codegen").unwrap(), [(Category::Lex, Some(Level::Info)), (Category::Codegen, Some(Level::Debug))]);
+    assert_eq!(parse("trace").unwrap().len(), 4);
+    assert!(parse("trace").unwrap().iter().all(|&(_, level)| level == Some(Level::Trace)));
+    assert_eq!(parse("resolve=off, ").unwrap(), [(Category::Resolve, None)]);
+
+    let parsed = parse_args(["check", "x.mud", "--trace=parse=trace"].map(String::from).to_vec()).unwrap();
+    assert_eq!(parsed.trace.as_deref(), Some("parse=trace"));
+    assert_eq!(crate::cli::main(["check", "mud_tests/struct.mud", "--trace", "parse=loud"].map(String::from).to_vec()), EXIT_USAGE);
+}
+
+#[test]
+fn interpreter_runtime_errors(){
+    // faults C would leave undefined are reported with where they happened in the Mud source
+    let (code, stdout, stderr) = interpret_with_input("null_deref.mud", &[], "");
+    assert_eq!(stdout, "before\n");
+    assert_eq!(stderr, "mud_tests/null_deref.mud:11:12: null pointer dereference");
+    assert_eq!(code, 101);
+
+    let (code, stdout, stderr) = interpret_with_input("out_of_bounds.mud", &[], "");
+    assert_eq!(stdout, "0\n1\n4\n9\n");
+    assert_eq!(stderr, "mud_tests/out_of_bounds.mud:8:9: access at offset 16 is out of bounds of a 16 byte allocation");
+    assert_eq!(code, 101);
+
+    let (code, _, stderr) = interpret_with_input("use_after_free.mud", &[], "");
+    assert!(stderr.starts_with("mud_tests/use_after_free.mud:7:19: use of memory at"), "{stderr}");
+    assert_eq!(code, 101);
+
+    let (code, stdout, stderr) = interpret_with_input("input_all.mud", &[], "xyz");
+    assert_eq!(stdout, "[xyz]x");
+    assert_eq!(stderr, "mud_tests/input_all.mud:6:6: index 3 is out of bounds for length 3");
+    assert_eq!(code, 101);
+}
+
+#[test]
+fn repl(){
+    let output = Capture::default();
+    let io = interpreter::Io {
+        output: Box::new(output.clone()),
+        errors: Box::new(Capture::default()),
+        input: Box::new(std::io::Cursor::new(b"typed in\n".to_vec())),
+    };
+    let mut session = repl::Repl::new(io);
+    let mut respond = |input: &str| match session.respond(input) {
+        repl::Reply::Text(text) => text,
+        repl::Reply::Quit(code) => format!("quit {code}"),
+    };
+
+    // declarations and locals stay around for the inputs after them
+    assert_eq!(respond("Point := struct{x: i32, y: i32}"), "Point : struct{x: i32, y: i32}");
+    assert_eq!(respond("square := fn(n: i32) -> i32 {\n    return n * n\n}"), "square : fn(i32) -> i32");
+    assert_eq!(respond("p : Point"), "");
+    assert_eq!(respond("p.x = 3; p.y = square(4)"), "");
+    assert_eq!(respond("p"), "{x: 3, y: 16} : Point");
+    assert_eq!(respond("p.x + p.y"), "19 : i32");
+    assert_eq!(respond("p.x + 1 # a comment"), "4 : i32");
+    assert_eq!(respond("\"mud\" + str(p.x)"), "\"mud3\" : str");
+    assert_eq!(respond(":type &p"), "*Point");
+
+    assert_eq!(respond("println(\"{} {}\", p.x, p.y)"), "");
+    assert_eq!(output.text(), "3 16\n");
+    assert_eq!(respond("line : str; read_line(&line); line"), "\"typed in\" : str");
+
+    // an error leaves the session as it was
+    assert_eq!(respond("q : *Point"), "");
+    assert_eq!(respond("(*q).x"), "runtime error: <repl>:1:2: null pointer dereference");
+    assert_eq!(respond("missing + 1"), "error: Undefined variable: missing");
+    assert!(respond("p.x = = 1").starts_with("syntax error"));
+    assert_eq!(respond("p.x"), "3 : i32");
+    assert_eq!(respond("f := fn() -> i32 { return nope }"), "error: Undefined variable: nope");
+    assert_eq!(respond(":type f"), "error: Undefined variable: f");
+    assert_eq!(respond("g := fn() -> i32 { x : i32; return nope }"), "error: Undefined variable: nope");
+    assert_eq!(respond("g := fn() -> i32 { return 2 }"), "g : fn() -> i32");
+
+    // a name can be defined again, a failed definition keeps the one before
+    assert_eq!(respond("square := fn(n: i32) -> i32 {\n    return n + n\n}"), "square : fn(i32) -> i32");
+    assert_eq!(respond("square(5)"), "10 : i32");
+    assert_eq!(respond("square := fn(n: i32) -> i32 { return nope }"), "error: Undefined variable: nope");
+    assert_eq!(respond("square(5) + g()"), "12 : i32");
+
+    assert!(respond(":ast 1 + 2").starts_with("BinaryOperation {\n    op: Plus,"));
+    assert_eq!(respond("exit(4)"), "quit 4");
+
+    // input continues until its braces and parentheses are closed
+    assert!(!repl::is_complete("square := fn(n: i32) -> i32 {"));
+    assert!(!repl::is_complete("println(\"{}\",\n  square(2)"));
+    assert!(repl::is_complete("s := \"{ (\" # ( {"));
+    assert!(repl::is_complete("square := fn(n: i32) -> i32 {\n    return n * n\n}"));
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::fs;
+
+use wasmi::{Caller, Engine, Extern, Linker, Memory, Module, Store};
+
+// a host for the modules the wasm backend writes, running them as a browser playground would with the
+// imports it provides: the "mud" ones the runtime needs and the "env" externs the test programs use
+
+// the FILE* handles of the standard streams, files the program opens come after them
+const STDIN: i32 = 1;
+const STDOUT: i32 = 2;
+const STDERR: i32 = 3;
+
+enum File {
+    Reader { bytes: Vec<u8>, position: usize },
+    Writer(fs::File),
+}
+
+#[derive(Default)]
+struct Host {
+    input: Vec<u8>,
+    position: usize,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    args: usize,
+    files: HashMap<i32, File>,
+    next_file: i32,
+}
+
+impl Host {
+    fn write(&mut self, file: i32, bytes: &[u8]) {
+        match file {
+            STDOUT => self.stdout.extend(bytes),
+            STDERR => self.stderr.extend(bytes),
+            file => if let Some(File::Writer(file)) = self.files.get_mut(&file) {
+                std::io::Write::write_all(file, bytes).unwrap();
+            },
+        }
+    }
+
+    // up to max bytes of a line from a stream, with its newline
+    fn read_line(&mut self, file: i32, max: usize) -> Vec<u8> {
+        let (bytes, position) = match (file, self.files.get_mut(&file)) {
+            (STDIN, _) => (&self.input, &mut self.position),
+            (_, Some(File::Reader { bytes, position })) => (&*bytes, position),
+            _ => return Vec::new(),
+        };
+        let rest = &bytes[*position..];
+        let len = rest.iter().position(|&b| b == b'\n').map_or(rest.len(), |end| end + 1).min(max);
+        *position += len;
+        rest[..len].to_vec()
+    }
+
+    fn read_all(&mut self, file: i32) -> Vec<u8> {
+        let mut all = Vec::new();
+        loop {
+            let line = self.read_line(file, usize::MAX);
+            if line.is_empty() {
+                return all;
+            }
+            all.extend(line);
+        }
+    }
+}
+
+fn memory(caller: &Caller<'_, Host>) -> Memory {
+    caller.get_export("memory").and_then(Extern::into_memory).unwrap()
+}
+
+fn read(caller: &Caller<'_, Host>, address: i32, len: i32) -> Vec<u8> {
+    memory(caller).data(caller)[address as usize..][..len.max(0) as usize].to_vec()
+}
+
+fn write(caller: &mut Caller<'_, Host>, address: i32, bytes: &[u8]) {
+    memory(caller).data_mut(caller)[address as usize..][..bytes.len()].copy_from_slice(bytes);
+}
+
+fn c_string(caller: &Caller<'_, Host>, address: i32) -> Vec<u8> {
+    let data = &memory(caller).data(caller)[address as usize..];
+    data[..data.iter().position(|&b| b == 0).unwrap()].to_vec()
+}
+
+// a copy of bytes with a NUL after them, in memory the program's malloc gives
+fn alloc(caller: &mut Caller<'_, Host>, bytes: &[u8]) -> i32 {
+    let malloc = caller.get_export("malloc").and_then(Extern::into_func).unwrap().typed::<i32, i32>(&*caller).unwrap();
+    let address = malloc.call(&mut *caller, bytes.len() as i32 + 1).unwrap();
+    write(caller, address, &[bytes, &[0]].concat());
+    address
+}
+
+// printf's format with the 8 byte args at args
+fn format(caller: &Caller<'_, Host>, format: i32, args: i32) -> Vec<u8> {
+    let format = c_string(caller, format);
+    let mut output = Vec::new();
+    let mut next = 0;
+    let mut arg = || {
+        next += 1;
+        i64::from_le_bytes(read(caller, args + (next - 1) * 8, 8).try_into().unwrap())
+    };
+
+    let mut bytes = format.iter();
+    while let Some(&b) = bytes.next() {
+        if b != b'%' {
+            output.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'd' | b'i') => output.extend((arg() as i32).to_string().bytes()),
+            Some(b'x') => output.extend(format!("{:x}", arg() as u32).bytes()),
+            Some(b'c') => output.push(arg() as u8),
+            Some(b's') => output.extend(c_string(caller, arg() as i32)),
+            Some(b'p') => output.extend(format!("0x{:x}", arg() as u32).bytes()),
+            Some(&c) => output.push(c),
+            None => {}
+        }
+    }
+    output
+}
+
+// runs the module at path as the program would run natively, returning its exit code and output
+pub fn run(path: &str, args: &[&str], input: &str) -> (i32, String, String) {
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wat::parse_file(path).unwrap()[..]).unwrap_or_else(|e| panic!("{path} is not valid: {e}"));
+    let host = Host { input: input.as_bytes().to_vec(), args: args.len() + 1, next_file: STDERR + 1, ..Host::default() };
+    let mut store = Store::new(&engine, host);
+    let mut linker = <Linker<Host>>::new(&engine);
+
+    linker.func_wrap("mud", "write", |mut caller: Caller<'_, Host>, fd: i32, address: i32, len: i32| {
+        let bytes = read(&caller, address, len);
+        caller.data_mut().write(if fd == 2 { STDERR } else { STDOUT }, &bytes);
+    }).unwrap();
+    linker.func_wrap("mud", "read", |mut caller: Caller<'_, Host>, address: i32, len: i32| -> i32 {
+        let host = caller.data_mut();
+        let bytes = host.input[host.position..].iter().take(len as usize).copied().collect::<Vec<_>>();
+        host.position += bytes.len();
+        write(&mut caller, address, &bytes);
+        bytes.len() as i32
+    }).unwrap();
+    linker.func_wrap("mud", "exit", |_: Caller<'_, Host>, code: i32| -> Result<(), wasmi::Error> { Err(wasmi::Error::i32_exit(code)) }).unwrap();
+    linker.func_wrap("mud", "file_size", |caller: Caller<'_, Host>, path: i32| -> i32 {
+        let path = String::from_utf8(c_string(&caller, path)).unwrap();
+        fs::metadata(path).map_or(-1, |metadata| metadata.len() as i32)
+    }).unwrap();
+    linker.func_wrap("mud", "file_read", |mut caller: Caller<'_, Host>, path: i32, buffer: i32| {
+        let bytes = fs::read(String::from_utf8(c_string(&caller, path)).unwrap()).unwrap();
+        write(&mut caller, buffer, &bytes);
+    }).unwrap();
+
+    linker.func_wrap("env", "printf", |mut caller: Caller<'_, Host>, format_address: i32, args: i32| -> i32 {
+        let output = format(&caller, format_address, args);
+        caller.data_mut().write(STDOUT, &output);
+        output.len() as i32
+    }).unwrap();
+    linker.func_wrap("env", "snprintf", |mut caller: Caller<'_, Host>, buffer: i32, size: i32, format_address: i32, args: i32| -> i32 {
+        let output = format(&caller, format_address, args);
+        if size > 0 {
+            let written = output.len().min(size as usize - 1);
+            write(&mut caller, buffer, &[&output[..written], &[0]].concat());
+        }
+        output.len() as i32
+    }).unwrap();
+    linker.func_wrap("env", "puts", |mut caller: Caller<'_, Host>, s: i32| -> i32 {
+        let line = c_string(&caller, s);
+        caller.data_mut().write(STDOUT, &[&line[..], b"\n"].concat());
+        0
+    }).unwrap();
+    linker.func_wrap("env", "abs", |_: Caller<'_, Host>, i: i32| -> i32 { i.wrapping_abs() }).unwrap();
+    linker.func_wrap("env", "atoi", |caller: Caller<'_, Host>, s: i32| -> i32 {
+        let s = String::from_utf8(c_string(&caller, s)).unwrap();
+        let end = s.char_indices().find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && (c == '-' || c == '+')))).map_or(s.len(), |(i, _)| i);
+        s[..end].parse().unwrap_or(0)
+    }).unwrap();
+    linker.func_wrap("env", "div", |mut caller: Caller<'_, Host>, result: i32, numer: i32, denom: i32| {
+        write(&mut caller, result, &[numer.wrapping_div(denom).to_le_bytes(), numer.wrapping_rem(denom).to_le_bytes()].concat());
+    }).unwrap();
+
+    linker.func_wrap("env", "mudstd_stdout", |_: Caller<'_, Host>| -> i32 { STDOUT }).unwrap();
+    linker.func_wrap("env", "mudstd_is_null", |_: Caller<'_, Host>, pointer: i32| -> i32 { (pointer == 0) as i32 }).unwrap();
+    linker.func_wrap("env", "mudstd_mod", |_: Caller<'_, Host>, a: i32, b: i32| -> i32 { a.wrapping_rem(b) }).unwrap();
+    linker.func_wrap("env", "mudstd_exit", |_: Caller<'_, Host>, code: i32| -> Result<(), wasmi::Error> { Err(wasmi::Error::i32_exit(code)) }).unwrap();
+    linker.func_wrap("env", "mudstd_arg_count", |caller: Caller<'_, Host>| -> i32 { caller.data().args as i32 }).unwrap();
+    linker.func_wrap("env", "mudstd_read_all", |mut caller: Caller<'_, Host>, file: i32| -> i32 {
+        let all = caller.data_mut().read_all(file);
+        alloc(&mut caller, &all)
+    }).unwrap();
+    linker.func_wrap("env", "fopen", |mut caller: Caller<'_, Host>, path: i32, mode: i32| -> i32 {
+        let path = String::from_utf8(c_string(&caller, path)).unwrap();
+        let file = match c_string(&caller, mode).first() {
+            Some(b'r') => fs::read(&path).ok().map(|bytes| File::Reader { bytes, position: 0 }),
+            Some(b'a') => fs::OpenOptions::new().append(true).create(true).open(&path).ok().map(File::Writer),
+            _ => fs::File::create(&path).ok().map(File::Writer),
+        };
+        let Some(file) = file else { return 0 };
+        let host = caller.data_mut();
+        host.next_file += 1;
+        host.files.insert(host.next_file, file);
+        host.next_file
+    }).unwrap();
+    linker.func_wrap("env", "fclose", |mut caller: Caller<'_, Host>, file: i32| -> i32 {
+        caller.data_mut().files.remove(&file);
+        0
+    }).unwrap();
+    linker.func_wrap("env", "fputs", |mut caller: Caller<'_, Host>, s: i32, file: i32| -> i32 {
+        let s = c_string(&caller, s);
+        caller.data_mut().write(file, &s);
+        0
+    }).unwrap();
+    linker.func_wrap("env", "fwrite", |mut caller: Caller<'_, Host>, data: i32, size: i32, count: i32, file: i32| -> i32 {
+        let bytes = read(&caller, data, size * count);
+        caller.data_mut().write(file, &bytes);
+        count
+    }).unwrap();
+    linker.func_wrap("env", "fgets", |mut caller: Caller<'_, Host>, buffer: i32, size: i32, file: i32| -> i32 {
+        let line = caller.data_mut().read_line(file, size.max(1) as usize - 1);
+        if line.is_empty() {
+            return 0;
+        }
+        write(&mut caller, buffer, &[&line[..], &[0]].concat());
+        buffer
+    }).unwrap();
+    linker.func_wrap("env", "remove", |caller: Caller<'_, Host>, path: i32| -> i32 {
+        let path = String::from_utf8(c_string(&caller, path)).unwrap();
+        if fs::remove_file(path).is_ok() { 0 } else { -1 }
+    }).unwrap();
+
+    let instance = linker.instantiate(&mut store, &module).and_then(|instance| instance.start(&mut store))
+        .unwrap_or_else(|e| panic!("{path} did not instantiate: {e}"));
+    let malloc = instance.get_typed_func::<i32, i32>(&store, "malloc").unwrap();
+    let memory = instance.get_memory(&store, "memory").unwrap();
+
+    // argv is an array of 8 byte pointers, like every pointer in memory
+    let args = std::iter::once(path).chain(args.iter().copied()).collect::<Vec<_>>();
+    let argv = malloc.call(&mut store, args.len() as i32 * 8).unwrap();
+    for (index, arg) in args.iter().enumerate() {
+        let address = malloc.call(&mut store, arg.len() as i32 + 1).unwrap();
+        memory.write(&mut store, address as usize, &[arg.as_bytes(), &[0]].concat()).unwrap();
+        memory.write(&mut store, argv as usize + index * 8, &(address as u64).to_le_bytes()).unwrap();
+    }
+
+    let main = instance.get_typed_func::<(i32, i32), i32>(&store, "main").unwrap();
+    let code = match main.call(&mut store, (args.len() as i32, argv)) {
+        Ok(code) => code,
+        Err(e) => e.i32_exit_status().unwrap_or_else(|| panic!("{path} trapped: {e}")),
+    };
+    let host = store.data();
+    (code, String::from_utf8_lossy(&host.stdout).to_string(), String::from_utf8_lossy(&host.stderr).to_string())
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// what the compiler reports about itself while it works, nothing unless asked for with --trace or MUD_TRACE
+
+// the phases tracing can be enabled for one at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Lex,
+    Parse,
+    Resolve,
+    Codegen,
+}
+
+// how much is said, each level includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Info = 1,
+    Debug = 2,
+    Trace = 3,
+}
+
+const CATEGORIES: [Category; 4] = [Category::Lex, Category::Parse, Category::Resolve, Category::Codegen];
+
+// the most detailed level enabled for each category, 0 when it is off
+static ENABLED: [AtomicU8; 4] = [AtomicU8::new(0), AtomicU8::new(0), AtomicU8::new(0), AtomicU8::new(0)];
+
+impl Category {
+    fn from_name(name: &str) -> Option<Category> {
+        CATEGORIES.into_iter().find(|category| category.to_string() == name)
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Category::Lex => "lex",
+            Category::Parse => "parse",
+            Category::Resolve => "resolve",
+            Category::Codegen => "codegen",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl Level {
+    fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        write!(f, "{name}")
+    }
+}
+
+pub fn enabled(category: Category, level: Level) -> bool {
+    ENABLED[category as usize].load(Ordering::Relaxed) >= level as u8
+}
+
+pub fn set(category: Category, level: Option<Level>) {
+    ENABLED[category as usize].store(level.map_or(0, |level| level as u8), Ordering::Relaxed);
+}
+
+// the level each category is set to by a spec like `parse,codegen=trace`, a category alone is traced at debug.
+// `all` or a bare level stands for every category, and `off` turns a category off
+pub fn parse(spec: &str) -> Result<Vec<(Category, Option<Level>)>, String> {
+    let mut settings = Vec::new();
+    for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let (name, level) = match item.split_once('=') {
+            Some((name, level)) => (name, level),
+            None if Level::from_name(item).is_some() => ("all", item),
+            None => (item, "debug"),
+        };
+        let level = match level {
+            "off" => None,
+            level => Some(Level::from_name(level).ok_or(format!("unknown trace level {level}"))?),
+        };
+        let categories = match name {
+            "all" => CATEGORIES.to_vec(),
+            name => vec![Category::from_name(name).ok_or(format!("unknown trace category {name}"))?],
+        };
+        settings.extend(categories.into_iter().map(|category| (category, level)));
+    }
+
+    Ok(settings)
+}
+
+// turns tracing on from a spec, nothing changes unless the whole spec is understood
+pub fn enable(spec: &str) -> Result<(), String> {
+    for (category, level) in parse(spec)? {
+        set(category, level);
+    }
+    Ok(())
+}
+
+pub fn write(category: Category, level: Level, message: fmt::Arguments) {
+    eprintln!("[{category} {level}] {message}");
+}
+
+// traces a message for a phase, the arguments are only formatted when it is enabled
+macro_rules! trace {
+    ($category:ident, $level:ident, $($arg:tt)+) => {
+        if $crate::trace::enabled($crate::trace::Category::$category, $crate::trace::Level::$level) {
+            $crate::trace::write($crate::trace::Category::$category, $crate::trace::Level::$level, format_args!($($arg)+));
+        }
+    };
+}
+
+pub(crate) use trace;
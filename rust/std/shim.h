@@ -0,0 +1,65 @@
+/* C helpers for the parts of the Mud standard library that Mud cannot express yet */
+#ifndef MUD_STD_SHIM_H
+#define MUD_STD_SHIM_H
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static int mudstd_argc;
+static char** mudstd_argv;
+
+/* glibc passes the program arguments to constructors as well as to main */
+__attribute__((constructor)) static void mudstd_init_args(int argc, char** argv) {
+    mudstd_argc = argc;
+    mudstd_argv = argv;
+}
+
+static inline int mudstd_arg_count(void) { return mudstd_argc; }
+static inline char* mudstd_arg(int index) { return index >= 0 && index < mudstd_argc ? mudstd_argv[index] : 0; }
+static inline void mudstd_exit(int code) { fflush(stdout); exit(code); }
+
+static inline FILE* mudstd_stdin(void) { return stdin; }
+static inline FILE* mudstd_stdout(void) { return stdout; }
+static inline FILE* mudstd_stderr(void) { return stderr; }
+
+static inline int mudstd_is_null(void* pointer) { return pointer == 0; }
+static inline int mudstd_div(int a, int b) { return a / b; }
+static inline int mudstd_mod(int a, int b) { return a % b; }
+
+/* reads the rest of a file into a NUL terminated buffer */
+static inline char* mudstd_read_all(FILE* f) {
+    size_t capacity = 256, length = 0, n;
+    char* buffer = malloc(capacity);
+    while ((n = fread(buffer + length, 1, capacity - length - 1, f)) > 0) {
+        length += n;
+        if (capacity - length == 1) {
+            capacity *= 2;
+            buffer = realloc(buffer, capacity);
+        }
+    }
+    buffer[length] = 0;
+    return buffer;
+}
+
+/* one line without its newline, or NULL at the end of the input */
+static inline char* mudstd_read_line(FILE* f) {
+    size_t capacity = 128, length = 0;
+    char* buffer = malloc(capacity);
+    int c;
+    while ((c = fgetc(f)) != EOF && c != '\n') {
+        if (length + 1 == capacity) {
+            capacity *= 2;
+            buffer = realloc(buffer, capacity);
+        }
+        buffer[length++] = (char)c;
+    }
+    if (c == EOF && length == 0) {
+        free(buffer);
+        return 0;
+    }
+    buffer[length] = 0;
+    return buffer;
+}
+
+#endif